@@ -9,12 +9,16 @@ use std::{
 };
 
 use flamingo_downloader::{
+    commands::from_ui,
     events::EventEmitter,
     init_backend,
     models::{
-        AddTaskOptions, AppUpdateStrategy, Aria2UpdateApplyResult, Aria2UpdateInfo, GlobalSettings,
-        ImportTaskListResult, LinkParseInput, LinkParseResult, MediaMergeJob, OperationLog,
-        StartupNotice, Task, TaskFile, TaskStatus, TaskType,
+        AddTaskOptions, AddTaskOutcome, AppUpdateStrategy, Aria2UpdateApplyResult, Aria2UpdateInfo,
+        BandwidthHistoryQuery, BandwidthSample, BatchAddRequest, BatchAddResult,
+        BulkTaskActionResult, GlobalSettings, ImportTaskListResult, LinkParseInput,
+        LinkParseResult, MediaMergeJob, OperationLog, StartupNotice, Task, TaskFile, TaskMirror,
+        TaskQueueStatus, TaskSeeding, TaskStatus, TaskType, TaskWindow, TorrentInfo,
+        TrackerListStatus,
     },
 };
 use serde::Serialize;
@@ -561,10 +565,10 @@ async fn add_url(
     state: State<'_, AppState>,
     url: String,
     options: AddTaskOptions,
-) -> Result<String, String> {
+) -> Result<AddTaskOutcome, String> {
     state
         .service
-        .add_url(&url, options)
+        .add_url_outcome(&url, from_ui(options))
         .await
         .map_err(|e| e.to_string())
 }
@@ -574,10 +578,10 @@ async fn add_magnet(
     state: State<'_, AppState>,
     magnet: String,
     options: AddTaskOptions,
-) -> Result<String, String> {
+) -> Result<AddTaskOutcome, String> {
     state
         .service
-        .add_magnet(&magnet, options)
+        .add_magnet_outcome(&magnet, from_ui(options))
         .await
         .map_err(|e| e.to_string())
 }
@@ -592,20 +596,87 @@ async fn add_torrent(
     if let Some(path) = torrent_file_path {
         return state
             .service
-            .add_torrent_from_file(&path, options)
+            .add_torrent_from_file(&path, from_ui(options))
             .await
             .map_err(|e| e.to_string());
     }
     if let Some(base64) = torrent_base64 {
         return state
             .service
-            .add_torrent_base64(base64, options, None)
+            .add_torrent_base64(base64, from_ui(options), None)
             .await
             .map_err(|e| e.to_string());
     }
     Err("missing torrent input".to_string())
 }
 
+#[tauri::command]
+async fn inspect_torrent(
+    state: State<'_, AppState>,
+    torrent_file_path: Option<String>,
+    torrent_base64: Option<String>,
+) -> Result<TorrentInfo, String> {
+    if let Some(path) = torrent_file_path {
+        return state
+            .service
+            .inspect_torrent_from_file(&path)
+            .await
+            .map_err(|e| e.to_string());
+    }
+    if let Some(base64) = torrent_base64 {
+        return state
+            .service
+            .inspect_torrent_base64(&base64)
+            .map_err(|e| e.to_string());
+    }
+    Err("missing torrent input".to_string())
+}
+
+#[tauri::command]
+async fn add_metalink(
+    state: State<'_, AppState>,
+    metalink_file_path: Option<String>,
+    metalink_base64: Option<String>,
+    options: AddTaskOptions,
+) -> Result<Vec<String>, String> {
+    if let Some(path) = metalink_file_path {
+        return state
+            .service
+            .add_metalink_from_file(&path, from_ui(options))
+            .await
+            .map_err(|e| e.to_string());
+    }
+    if let Some(base64) = metalink_base64 {
+        return state
+            .service
+            .add_metalink_base64(base64, from_ui(options), None)
+            .await
+            .map_err(|e| e.to_string());
+    }
+    Err("missing metalink input".to_string())
+}
+
+#[tauri::command]
+async fn add_batch(
+    state: State<'_, AppState>,
+    mut request: BatchAddRequest,
+) -> Result<BatchAddResult, String> {
+    request.options = from_ui(request.options);
+    state
+        .service
+        .add_batch(request)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn preview_url_pattern(state: State<'_, AppState>, url: String) -> Result<Vec<String>, String> {
+    state
+        .service
+        .preview_url_pattern(&url)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn parse_link_candidates(
     state: State<'_, AppState>,
@@ -701,6 +772,43 @@ async fn remove_task(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn pause_tasks(
+    state: State<'_, AppState>,
+    task_ids: Vec<String>,
+) -> Result<BulkTaskActionResult, String> {
+    state
+        .service
+        .pause_tasks(&task_ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn resume_tasks(
+    state: State<'_, AppState>,
+    task_ids: Vec<String>,
+) -> Result<BulkTaskActionResult, String> {
+    state
+        .service
+        .resume_tasks(&task_ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn remove_tasks(
+    state: State<'_, AppState>,
+    task_ids: Vec<String>,
+    delete_files: bool,
+) -> Result<BulkTaskActionResult, String> {
+    state
+        .service
+        .remove_tasks(&task_ids, delete_files)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn open_task_file(state: State<'_, AppState>, task_id: String) -> Result<(), String> {
     state
@@ -743,6 +851,25 @@ async fn list_tasks(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn set_task_schedule(
+    state: State<'_, AppState>,
+    task_id: String,
+    start_at: Option<i64>,
+    window: Option<TaskWindow>,
+) -> Result<(), String> {
+    state
+        .service
+        .set_task_schedule(&task_id, start_at, window)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_task_queues(state: State<'_, AppState>) -> Result<Vec<TaskQueueStatus>, String> {
+    state.service.list_task_queues().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn set_task_category(
     state: State<'_, AppState>,
@@ -801,6 +928,86 @@ async fn set_task_file_selection(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_task_torrent(state: State<'_, AppState>, task_id: String) -> Result<Option<String>, String> {
+    state
+        .service
+        .get_task_torrent(&task_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_task_mirrors(
+    state: State<'_, AppState>,
+    task_id: String,
+) -> Result<Vec<TaskMirror>, String> {
+    state
+        .service
+        .list_task_mirrors(&task_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_tracker_lists(state: State<'_, AppState>) -> Result<Vec<TrackerListStatus>, String> {
+    state
+        .service
+        .list_tracker_lists()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn refresh_tracker_lists(
+    state: State<'_, AppState>,
+) -> Result<Vec<TrackerListStatus>, String> {
+    state
+        .service
+        .refresh_tracker_lists()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_task_trackers(
+    state: State<'_, AppState>,
+    task_id: String,
+    trackers: Vec<String>,
+) -> Result<(), String> {
+    state
+        .service
+        .add_task_trackers(&task_id, &trackers)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn remove_task_trackers(
+    state: State<'_, AppState>,
+    task_id: String,
+    trackers: Vec<String>,
+) -> Result<(), String> {
+    state
+        .service
+        .remove_task_trackers(&task_id, &trackers)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_task_seeding(state: State<'_, AppState>) -> Result<Vec<TaskSeeding>, String> {
+    state.service.list_task_seeding().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_bandwidth_history(
+    state: State<'_, AppState>,
+    query: BandwidthHistoryQuery,
+) -> Result<Vec<BandwidthSample>, String> {
+    state
+        .service
+        .get_bandwidth_history(query)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn set_task_runtime_options(
     state: State<'_, AppState>,
@@ -872,10 +1079,11 @@ async fn suggest_save_dir_detail(
     state: State<'_, AppState>,
     task_type: TaskType,
     source: Option<String>,
+    out: Option<String>,
 ) -> Result<flamingo_downloader::models::SaveDirSuggestion, String> {
     state
         .service
-        .suggest_save_dir_detail(task_type, source.as_deref())
+        .suggest_save_dir_detail(task_type, source.as_deref(), out.as_deref())
         .map_err(|e| e.to_string())
 }
 
@@ -1416,6 +1624,10 @@ fn main() {
             add_url,
             add_magnet,
             add_torrent,
+            inspect_torrent,
+            add_metalink,
+            add_batch,
+            preview_url_pattern,
             parse_link_candidates,
             scan_page_resources,
             pause_task,
@@ -1426,14 +1638,27 @@ fn main() {
             pause_all,
             resume_all,
             remove_task,
+            pause_tasks,
+            resume_tasks,
+            remove_tasks,
             open_task_file,
             open_task_dir,
             get_task_primary_path,
             list_tasks,
             set_task_category,
+            set_task_schedule,
+            list_task_queues,
             get_task_detail,
             get_task_runtime_status,
             set_task_file_selection,
+            get_task_torrent,
+            list_task_mirrors,
+            list_tracker_lists,
+            refresh_tracker_lists,
+            add_task_trackers,
+            remove_task_trackers,
+            list_task_seeding,
+            get_bandwidth_history,
             set_task_runtime_options,
             set_global_settings,
             get_global_settings,
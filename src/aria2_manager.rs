@@ -1,25 +1,49 @@
 use std::{
+    collections::HashMap,
     fs,
     io::Read,
     net::TcpListener,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
 use anyhow::{Context, Result, anyhow};
 use async_trait::async_trait;
+use futures_util::StreamExt;
 use reqwest::Client;
 use serde::de::DeserializeOwned;
 use serde_json::{Value, json};
 use tokio::{
     process::{Child, Command},
-    sync::{Mutex, RwLock},
+    sync::{Mutex, RwLock, broadcast},
     time,
 };
+use tokio_tungstenite::{connect_async, tungstenite::Message};
 use uuid::Uuid;
 
-use crate::models::{Aria2FileSnapshot, Aria2TaskSnapshot};
+use crate::models::{Aria2FileSnapshot, Aria2TaskSnapshot, GlobalSettings};
+
+const SNAPSHOT_KEYS: [&str; 12] = [
+    "gid",
+    "status",
+    "totalLength",
+    "completedLength",
+    "downloadSpeed",
+    "uploadSpeed",
+    "uploadLength",
+    "connections",
+    "errorCode",
+    "errorMessage",
+    "files",
+    "bittorrent",
+];
+const MULTICALL_CHUNK_SIZE: usize = 200;
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 1024;
+const WS_RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
 
 #[async_trait]
 pub trait Aria2Api: Send + Sync {
@@ -34,15 +58,57 @@ pub trait Aria2Api: Send + Sync {
         uris: Vec<String>,
         options: Option<Value>,
     ) -> Result<String>;
+    /// aria2 returns one gid per file it was asked to download.
+    async fn add_metalink(
+        &self,
+        _metalink_base64: String,
+        _options: Option<Value>,
+    ) -> Result<Vec<String>> {
+        Err(anyhow!("this engine cannot download metalinks"))
+    }
     async fn pause(&self, gid: &str) -> Result<String>;
     async fn unpause(&self, gid: &str) -> Result<String>;
     async fn pause_all(&self) -> Result<String>;
     async fn unpause_all(&self) -> Result<String>;
     async fn remove(&self, gid: &str, force: bool) -> Result<String>;
     async fn remove_download_result(&self, gid: &str) -> Result<String>;
+    async fn pause_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        let mut out = Vec::with_capacity(gids.len());
+        for gid in gids {
+            out.push(self.pause(gid).await);
+        }
+        Ok(out)
+    }
+    async fn unpause_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        let mut out = Vec::with_capacity(gids.len());
+        for gid in gids {
+            out.push(self.unpause(gid).await);
+        }
+        Ok(out)
+    }
+    async fn remove_many(&self, gids: &[String], force: bool) -> Result<Vec<Result<String>>> {
+        let mut out = Vec::with_capacity(gids.len());
+        for gid in gids {
+            out.push(self.remove(gid, force).await);
+        }
+        Ok(out)
+    }
+    async fn remove_download_result_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        let mut out = Vec::with_capacity(gids.len());
+        for gid in gids {
+            out.push(self.remove_download_result(gid).await);
+        }
+        Ok(out)
+    }
     async fn tell_status(&self, gid: &str) -> Result<Value>;
     async fn get_peers(&self, gid: &str) -> Result<Vec<Value>>;
     async fn tell_all(&self) -> Result<Vec<Aria2TaskSnapshot>>;
+    async fn tell_active(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        self.tell_all().await
+    }
+    async fn tell_snapshot(&self, gid: &str) -> Result<Option<Aria2TaskSnapshot>> {
+        Ok(parse_snapshot(self.tell_status(gid).await?))
+    }
     async fn change_position(&self, gid: &str, pos: i64, how: &str) -> Result<i64>;
     async fn change_option(&self, gid: &str, options: Value) -> Result<String>;
     async fn change_global_option(&self, options: Value) -> Result<String>;
@@ -51,6 +117,63 @@ pub trait Aria2Api: Send + Sync {
     async fn get_version(&self) -> Result<Value>;
     async fn save_session(&self) -> Result<String>;
     fn stderr_tail(&self) -> Option<String>;
+    fn subscribe_notifications(&self) -> Option<broadcast::Receiver<Aria2Notification>> {
+        None
+    }
+    fn notifications_connected(&self) -> bool {
+        false
+    }
+    fn set_external(&self, _external: Option<ExternalAria2Config>) {}
+    fn external_config(&self) -> Option<ExternalAria2Config> {
+        None
+    }
+    fn is_external(&self) -> bool {
+        self.external_config().is_some()
+    }
+    /// The `engine_mode` an engine running inside Flamingo stands for
+    /// (`demo`, `native`); `None` when an aria2 process does the work.
+    fn in_process_mode(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// A download event pushed by aria2 over its WebSocket RPC endpoint,
+/// e.g. `aria2.onDownloadComplete`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aria2Notification {
+    pub method: String,
+    pub gid: String,
+}
+
+/// An aria2 instance Flamingo attaches to instead of spawning its own,
+/// e.g. one running on a NAS or in a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalAria2Config {
+    pub rpc_url: String,
+    pub secret: String,
+}
+
+impl ExternalAria2Config {
+    pub fn from_settings(settings: &GlobalSettings) -> Option<Self> {
+        if settings.engine_mode.as_deref().map(str::trim) != Some("external") {
+            return None;
+        }
+        let rpc_url = settings.aria2_rpc_url.as_deref()?.trim().to_string();
+        if rpc_url.is_empty() {
+            return None;
+        }
+        Some(Self {
+            rpc_url,
+            secret: settings.aria2_rpc_secret.clone().unwrap_or_default(),
+        })
+    }
+
+    fn ws_url(&self) -> Option<String> {
+        let mut url = reqwest::Url::parse(&self.rpc_url).ok()?;
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme).ok()?;
+        Some(url.to_string())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -192,6 +315,7 @@ pub struct Aria2Endpoint {
     pub secret: String,
     pub port: u16,
     pub compat_mode: bool,
+    pub external: bool,
 }
 
 #[derive(Clone)]
@@ -215,12 +339,106 @@ impl Aria2Client {
         }
     }
 
-    pub async fn call<T: DeserializeOwned>(
-        &self,
-        method: &str,
-        mut params: Vec<Value>,
-    ) -> Result<T> {
-        params.insert(0, json!(format!("token:{}", self.secret)));
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Vec<Value>) -> Result<T> {
+        let result = self.request(method, self.with_token(params)).await?;
+        serde_json::from_value(result).map_err(Into::into)
+    }
+
+    /// Runs several calls in one round trip via `system.multicall`, falling back
+    /// to a JSON-RPC batch array when the server lacks it. Results keep the order
+    /// of `calls`.
+    pub async fn multicall(&self, calls: &[(&str, Vec<Value>)]) -> Result<Vec<Result<Value>>> {
+        let mut out = Vec::with_capacity(calls.len());
+        for chunk in calls.chunks(MULTICALL_CHUNK_SIZE) {
+            let results = match self.system_multicall(chunk).await {
+                Ok(v) => v,
+                // Any other failure may come after some calls already ran; retrying
+                // them as a batch could pause, remove or add twice.
+                Err(e)
+                    if e.downcast_ref::<RpcFault>()
+                        .is_some_and(RpcFault::is_method_not_found) =>
+                {
+                    self.batch(chunk).await?
+                }
+                Err(e) => return Err(e),
+            };
+            out.extend(results);
+        }
+        Ok(out)
+    }
+
+    async fn system_multicall(&self, calls: &[(&str, Vec<Value>)]) -> Result<Vec<Result<Value>>> {
+        let entries = calls
+            .iter()
+            .map(|(method, params)| {
+                json!({"methodName": method, "params": self.with_token(params.clone())})
+            })
+            .collect::<Vec<_>>();
+        // system.multicall itself takes no token; each nested call carries one.
+        let raw = self
+            .request("system.multicall", vec![json!(entries)])
+            .await?;
+        let items = match raw {
+            Value::Array(items) if items.len() == calls.len() => items,
+            other => return Err(anyhow!("aria2 rpc invalid multicall response: {other}")),
+        };
+        Ok(items
+            .into_iter()
+            .map(|item| match item {
+                Value::Array(mut wrapped) if wrapped.len() == 1 => Ok(wrapped.remove(0)),
+                fault => Err(anyhow!("aria2 rpc error: {fault}")),
+            })
+            .collect())
+    }
+
+    async fn batch(&self, calls: &[(&str, Vec<Value>)]) -> Result<Vec<Result<Value>>> {
+        let ids = calls
+            .iter()
+            .map(|_| Uuid::new_v4().to_string())
+            .collect::<Vec<_>>();
+        let body = calls
+            .iter()
+            .zip(&ids)
+            .map(|((method, params), id)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": method,
+                    "params": self.with_token(params.clone())
+                })
+            })
+            .collect::<Vec<_>>();
+        let response = self.http.post(&self.endpoint).json(&body).send().await?;
+        let payload: Vec<Value> = response.json().await?;
+        let mut by_id = payload
+            .into_iter()
+            .filter_map(|item| Some((item.get("id")?.as_str()?.to_string(), item)))
+            .collect::<HashMap<_, _>>();
+        Ok(ids
+            .iter()
+            .map(|id| {
+                let item = by_id
+                    .remove(id)
+                    .ok_or_else(|| anyhow!("aria2 rpc invalid response: missing batch entry"))?;
+                if let Some(err) = item.get("error") {
+                    return Err(anyhow!("aria2 rpc error: {err}"));
+                }
+                item.get("result")
+                    .cloned()
+                    .ok_or_else(|| anyhow!("aria2 rpc invalid response: missing result"))
+            })
+            .collect())
+    }
+
+    fn with_token(&self, mut params: Vec<Value>) -> Vec<Value> {
+        // An external aria2 without --rpc-secret would read the token as a real parameter.
+        if !self.secret.is_empty() {
+            params.insert(0, json!(format!("token:{}", self.secret)));
+        }
+        params
+    }
+
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Value> {
         let body = json!({
             "jsonrpc": "2.0",
             "id": Uuid::new_v4().to_string(),
@@ -232,15 +450,32 @@ impl Aria2Client {
         let payload: Value = response.json().await?;
 
         if let Some(err) = payload.get("error") {
-            return Err(anyhow!("aria2 rpc error: {err}"));
+            return Err(RpcFault { body: err.clone() }.into());
         }
 
-        let result = payload
+        payload
             .get("result")
             .cloned()
-            .ok_or_else(|| anyhow!("aria2 rpc invalid response: missing result"))?;
+            .ok_or_else(|| anyhow!("aria2 rpc invalid response: missing result"))
+    }
+}
 
-        serde_json::from_value(result).map_err(Into::into)
+/// An error object the RPC server answered with, as opposed to a transport failure.
+#[derive(Debug, thiserror::Error)]
+#[error("aria2 rpc error: {body}")]
+struct RpcFault {
+    body: Value,
+}
+
+impl RpcFault {
+    /// aria2 reports unknown methods as "No such method"; other JSON-RPC servers use -32601.
+    fn is_method_not_found(&self) -> bool {
+        self.body.get("code").and_then(Value::as_i64) == Some(-32601)
+            || self
+                .body
+                .get("message")
+                .and_then(Value::as_str)
+                .is_some_and(|m| m.starts_with("No such method"))
     }
 }
 
@@ -250,6 +485,11 @@ pub struct Aria2Manager {
     endpoint: RwLock<Option<Aria2Endpoint>>,
     client: RwLock<Option<Aria2Client>>,
     lifecycle_lock: Mutex<()>,
+    shutting_down: AtomicBool,
+    notifications: broadcast::Sender<Aria2Notification>,
+    ws_connected: Arc<AtomicBool>,
+    ws_generation: Arc<AtomicU64>,
+    external: std::sync::RwLock<Option<ExternalAria2Config>>,
 }
 
 impl Aria2Manager {
@@ -260,14 +500,58 @@ impl Aria2Manager {
             endpoint: RwLock::new(None),
             client: RwLock::new(None),
             lifecycle_lock: Mutex::new(()),
+            shutting_down: AtomicBool::new(false),
+            notifications: broadcast::channel(NOTIFICATION_CHANNEL_CAPACITY).0,
+            ws_connected: Arc::new(AtomicBool::new(false)),
+            ws_generation: Arc::new(AtomicU64::new(0)),
+            external: std::sync::RwLock::new(None),
         }
     }
 
+    /// A manager that only ever attaches to `external`, used for extra engine profiles.
+    pub fn attached(external: ExternalAria2Config) -> Self {
+        let manager = Self::new(Aria2RuntimeConfig {
+            aria2_bin: PathBuf::new(),
+            work_dir: PathBuf::new(),
+            default_download_dir: PathBuf::new(),
+            session_file: PathBuf::new(),
+            max_concurrent_downloads: 5,
+            split: 16,
+            max_connection_per_server: 8,
+            bt_tracker: None,
+            enable_upnp: true,
+        });
+        manager.set_external(Some(external));
+        manager
+    }
+
+    /// Switches between spawning aria2 and attaching to an external RPC endpoint.
+    /// Takes effect on the next start.
+    pub fn set_external(&self, external: Option<ExternalAria2Config>) {
+        *self
+            .external
+            .write()
+            .expect("external config lock poisoned") = external;
+    }
+
+    pub fn external_config(&self) -> Option<ExternalAria2Config> {
+        self.external
+            .read()
+            .expect("external config lock poisoned")
+            .clone()
+    }
+
     pub async fn start(&self) -> Result<Aria2Endpoint> {
         let _lifecycle_guard = self.lifecycle_lock.lock().await;
         if let Some(ep) = self.endpoint().await {
             return Ok(ep);
         }
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(anyhow!("aria2 manager is shutting down"));
+        }
+        if let Some(external) = self.external_config() {
+            return self.attach_external(external).await;
+        }
 
         tokio::fs::create_dir_all(&self.cfg.work_dir).await?;
         tokio::fs::create_dir_all(&self.cfg.default_download_dir).await?;
@@ -398,22 +682,58 @@ impl Aria2Manager {
                 secret: secret.clone(),
                 port,
                 compat_mode,
+                external: false,
             });
             let mut client_guard = self.client.write().await;
             *client_guard = Some(client);
         }
+        self.spawn_notification_listener(format!("ws://127.0.0.1:{port}/jsonrpc"));
 
         Ok(Aria2Endpoint {
             endpoint,
             secret,
             port,
             compat_mode,
+            external: false,
         })
     }
 
+    async fn attach_external(&self, external: ExternalAria2Config) -> Result<Aria2Endpoint> {
+        let url = reqwest::Url::parse(&external.rpc_url)
+            .with_context(|| format!("invalid aria2 rpc url: {}", external.rpc_url))?;
+        let client = Aria2Client::new(external.rpc_url.clone(), external.secret.clone());
+        client
+            .call::<Value>("aria2.getVersion", vec![])
+            .await
+            .map_err(|e| anyhow!("external aria2 unreachable at {}: {e}", external.rpc_url))?;
+
+        let endpoint = Aria2Endpoint {
+            endpoint: external.rpc_url.clone(),
+            secret: external.secret.clone(),
+            port: url.port_or_known_default().unwrap_or_default(),
+            compat_mode: false,
+            external: true,
+        };
+        *self.endpoint.write().await = Some(endpoint.clone());
+        *self.client.write().await = Some(client);
+        if let Some(ws_url) = external.ws_url() {
+            self.spawn_notification_listener(ws_url);
+        }
+        Ok(endpoint)
+    }
+
     pub async fn stop(&self) -> Result<()> {
         let _lifecycle_guard = self.lifecycle_lock.lock().await;
-        if let Ok(client) = self.client().await {
+        self.ws_generation.fetch_add(1, Ordering::SeqCst);
+        self.ws_connected.store(false, Ordering::SeqCst);
+        let attached = self
+            .endpoint
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|ep| ep.external);
+        // Never shut down an aria2 we did not spawn; just detach from it.
+        if !attached && let Ok(client) = self.client().await {
             let _: Result<String> = client.call("aria2.shutdown", vec![]).await;
         }
 
@@ -426,6 +746,12 @@ impl Aria2Manager {
         Ok(())
     }
 
+    /// Stops aria2 for good: the health guard exits and later starts are refused.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        self.stop().await
+    }
+
     pub async fn endpoint(&self) -> Option<Aria2Endpoint> {
         self.endpoint.read().await.clone()
     }
@@ -438,13 +764,72 @@ impl Aria2Manager {
     }
 
     pub fn stderr_tail(&self) -> Option<String> {
+        if self.external_config().is_some() {
+            return None;
+        }
         read_aria2_stderr_tail(&self.cfg.work_dir)
     }
 
+    pub fn subscribe_notifications(&self) -> broadcast::Receiver<Aria2Notification> {
+        self.notifications.subscribe()
+    }
+
+    pub fn notifications_connected(&self) -> bool {
+        self.ws_connected.load(Ordering::SeqCst)
+    }
+
+    /// Keeps a WebSocket open to aria2 for push notifications until the next
+    /// stop/start, reconnecting with backoff while the socket is down.
+    fn spawn_notification_listener(&self, ws_url: String) {
+        let generation = self.ws_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let current_generation = self.ws_generation.clone();
+        let connected = self.ws_connected.clone();
+        let sender = self.notifications.clone();
+        tokio::spawn(async move {
+            let is_current = || current_generation.load(Ordering::SeqCst) == generation;
+            let mut backoff = Duration::from_secs(1);
+            while is_current() {
+                if let Ok((mut socket, _)) = connect_async(ws_url.as_str()).await {
+                    connected.store(true, Ordering::SeqCst);
+                    backoff = Duration::from_secs(1);
+                    while is_current() {
+                        // Wake up periodically so a stop/restart retires this listener.
+                        let next = match time::timeout(Duration::from_secs(5), socket.next()).await
+                        {
+                            Ok(next) => next,
+                            Err(_) => continue,
+                        };
+                        match next {
+                            Some(Ok(Message::Text(text))) => {
+                                for notification in parse_notifications(text.as_str()) {
+                                    let _ = sender.send(notification);
+                                }
+                            }
+                            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                            Some(Ok(_)) => {}
+                        }
+                    }
+                    if is_current() {
+                        connected.store(false, Ordering::SeqCst);
+                    }
+                }
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(WS_RECONNECT_MAX_BACKOFF);
+            }
+        });
+    }
+
     pub async fn start_health_guard(self: Arc<Self>) {
         tokio::spawn(async move {
             loop {
                 time::sleep(Duration::from_secs(2)).await;
+                if self.shutting_down.load(Ordering::SeqCst) {
+                    break;
+                }
+                // There is no child process to babysit; requests reconnect on demand.
+                if self.external_config().is_some() {
+                    continue;
+                }
 
                 let exited = {
                     let mut child_guard = self.child.lock().await;
@@ -511,6 +896,19 @@ impl Aria2Manager {
         client.call("aria2.addTorrent", params).await
     }
 
+    pub async fn add_metalink(
+        &self,
+        metalink_base64: String,
+        options: Option<Value>,
+    ) -> Result<Vec<String>> {
+        let client = self.client().await?;
+        let mut params = vec![json!(metalink_base64)];
+        if let Some(o) = options {
+            params.push(o);
+        }
+        client.call("aria2.addMetalink", params).await
+    }
+
     pub async fn pause(&self, gid: &str) -> Result<String> {
         self.client()
             .await?
@@ -549,6 +947,50 @@ impl Aria2Manager {
             .await
     }
 
+    pub async fn pause_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        self.multicall_per_gid("aria2.pause", gids).await
+    }
+
+    pub async fn unpause_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        self.multicall_per_gid("aria2.unpause", gids).await
+    }
+
+    pub async fn remove_many(&self, gids: &[String], force: bool) -> Result<Vec<Result<String>>> {
+        let method = if force {
+            "aria2.forceRemove"
+        } else {
+            "aria2.remove"
+        };
+        self.multicall_per_gid(method, gids).await
+    }
+
+    pub async fn remove_download_result_many(
+        &self,
+        gids: &[String],
+    ) -> Result<Vec<Result<String>>> {
+        self.multicall_per_gid("aria2.removeDownloadResult", gids)
+            .await
+    }
+
+    async fn multicall_per_gid(
+        &self,
+        method: &str,
+        gids: &[String],
+    ) -> Result<Vec<Result<String>>> {
+        if gids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let calls = gids
+            .iter()
+            .map(|gid| (method, vec![json!(gid)]))
+            .collect::<Vec<_>>();
+        let results = self.client().await?.multicall(&calls).await?;
+        Ok(results
+            .into_iter()
+            .map(|r| r.and_then(|v| serde_json::from_value(v).map_err(Into::into)))
+            .collect())
+    }
+
     pub async fn tell_status(&self, gid: &str) -> Result<Value> {
         self.client()
             .await?
@@ -563,6 +1005,7 @@ impl Aria2Manager {
                         "completedLength",
                         "downloadSpeed",
                         "uploadSpeed",
+                        "uploadLength",
                         "connections",
                         "errorCode",
                         "errorMessage",
@@ -583,84 +1026,37 @@ impl Aria2Manager {
     }
 
     pub async fn tell_all(&self) -> Result<Vec<Aria2TaskSnapshot>> {
-        let active: Vec<Value> = self
+        let results = self
             .client()
             .await?
-            .call(
-                "aria2.tellActive",
-                vec![json!([
-                    "gid",
-                    "status",
-                    "totalLength",
-                    "completedLength",
-                    "downloadSpeed",
-                    "uploadSpeed",
-                    "connections",
-                    "errorCode",
-                    "errorMessage",
-                    "files",
-                    "bittorrent"
-                ])],
-            )
+            .multicall(&[
+                ("aria2.tellActive", vec![json!(SNAPSHOT_KEYS)]),
+                (
+                    "aria2.tellWaiting",
+                    vec![json!(0), json!(1000), json!(SNAPSHOT_KEYS)],
+                ),
+                (
+                    "aria2.tellStopped",
+                    vec![json!(0), json!(1000), json!(SNAPSHOT_KEYS)],
+                ),
+            ])
             .await?;
 
-        let waiting: Vec<Value> = self
-            .client()
-            .await?
-            .call(
-                "aria2.tellWaiting",
-                vec![
-                    json!(0),
-                    json!(1000),
-                    json!([
-                        "gid",
-                        "status",
-                        "totalLength",
-                        "completedLength",
-                        "downloadSpeed",
-                        "uploadSpeed",
-                        "connections",
-                        "errorCode",
-                        "errorMessage",
-                        "files",
-                        "bittorrent"
-                    ]),
-                ],
-            )
-            .await?;
+        let mut merged = Vec::new();
+        for result in results {
+            let items: Vec<Value> = serde_json::from_value(result?)?;
+            merged.extend(items.into_iter().filter_map(parse_snapshot));
+        }
+        Ok(merged)
+    }
 
-        let stopped: Vec<Value> = self
+    pub async fn tell_active(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        let active: Vec<Value> = self
             .client()
             .await?
-            .call(
-                "aria2.tellStopped",
-                vec![
-                    json!(0),
-                    json!(1000),
-                    json!([
-                        "gid",
-                        "status",
-                        "totalLength",
-                        "completedLength",
-                        "downloadSpeed",
-                        "uploadSpeed",
-                        "connections",
-                        "errorCode",
-                        "errorMessage",
-                        "files",
-                        "bittorrent"
-                    ]),
-                ],
-            )
+            .call("aria2.tellActive", vec![json!(SNAPSHOT_KEYS)])
             .await?;
-
-        let mut merged = Vec::with_capacity(active.len() + waiting.len() + stopped.len());
-        for item in active.into_iter().chain(waiting).chain(stopped) {
-            if let Some(snapshot) = parse_snapshot(item) {
-                merged.push(snapshot);
-            }
-        }
-        Ok(merged)
+        Ok(active.into_iter().filter_map(parse_snapshot).collect())
     }
 
     pub async fn change_position(&self, gid: &str, pos: i64, how: &str) -> Result<i64> {
@@ -753,6 +1149,14 @@ impl Aria2Api for Aria2Manager {
         Aria2Manager::add_torrent(self, torrent_base64, uris, options).await
     }
 
+    async fn add_metalink(
+        &self,
+        metalink_base64: String,
+        options: Option<Value>,
+    ) -> Result<Vec<String>> {
+        Aria2Manager::add_metalink(self, metalink_base64, options).await
+    }
+
     async fn pause(&self, gid: &str) -> Result<String> {
         Aria2Manager::pause(self, gid).await
     }
@@ -777,6 +1181,22 @@ impl Aria2Api for Aria2Manager {
         Aria2Manager::remove_download_result(self, gid).await
     }
 
+    async fn pause_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        Aria2Manager::pause_many(self, gids).await
+    }
+
+    async fn unpause_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        Aria2Manager::unpause_many(self, gids).await
+    }
+
+    async fn remove_many(&self, gids: &[String], force: bool) -> Result<Vec<Result<String>>> {
+        Aria2Manager::remove_many(self, gids, force).await
+    }
+
+    async fn remove_download_result_many(&self, gids: &[String]) -> Result<Vec<Result<String>>> {
+        Aria2Manager::remove_download_result_many(self, gids).await
+    }
+
     async fn tell_status(&self, gid: &str) -> Result<Value> {
         Aria2Manager::tell_status(self, gid).await
     }
@@ -789,6 +1209,10 @@ impl Aria2Api for Aria2Manager {
         Aria2Manager::tell_all(self).await
     }
 
+    async fn tell_active(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        Aria2Manager::tell_active(self).await
+    }
+
     async fn change_position(&self, gid: &str, pos: i64, how: &str) -> Result<i64> {
         Aria2Manager::change_position(self, gid, pos, how).await
     }
@@ -820,9 +1244,52 @@ impl Aria2Api for Aria2Manager {
     fn stderr_tail(&self) -> Option<String> {
         Aria2Manager::stderr_tail(self)
     }
+
+    fn subscribe_notifications(&self) -> Option<broadcast::Receiver<Aria2Notification>> {
+        Some(Aria2Manager::subscribe_notifications(self))
+    }
+
+    fn notifications_connected(&self) -> bool {
+        Aria2Manager::notifications_connected(self)
+    }
+
+    fn set_external(&self, external: Option<ExternalAria2Config>) {
+        Aria2Manager::set_external(self, external)
+    }
+
+    fn external_config(&self) -> Option<ExternalAria2Config> {
+        Aria2Manager::external_config(self)
+    }
 }
 
-fn parse_snapshot(value: Value) -> Option<Aria2TaskSnapshot> {
+fn parse_notifications(text: &str) -> Vec<Aria2Notification> {
+    let Ok(payload) = serde_json::from_str::<Value>(text) else {
+        return Vec::new();
+    };
+    let Some(method) = payload
+        .get("method")
+        .and_then(Value::as_str)
+        .filter(|m| m.starts_with("aria2.on"))
+    else {
+        return Vec::new();
+    };
+    payload
+        .get("params")
+        .and_then(Value::as_array)
+        .map(|params| {
+            params
+                .iter()
+                .filter_map(|p| p.get("gid").and_then(Value::as_str))
+                .map(|gid| Aria2Notification {
+                    method: method.to_string(),
+                    gid: gid.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn parse_snapshot(value: Value) -> Option<Aria2TaskSnapshot> {
     let gid = value.get("gid")?.as_str()?.to_string();
     let status = value
         .get("status")
@@ -877,6 +1344,7 @@ fn parse_snapshot(value: Value) -> Option<Aria2TaskSnapshot> {
         completed_length: parse_i64(&value, "completedLength"),
         download_speed: parse_i64(&value, "downloadSpeed"),
         upload_speed: parse_i64(&value, "uploadSpeed"),
+        upload_length: parse_i64(&value, "uploadLength"),
         connections: parse_i64(&value, "connections"),
         error_code: value
             .get("errorCode")
@@ -965,3 +1433,66 @@ fn read_aria2_stderr_tail(work_dir: &Path) -> Option<String> {
     };
     Some(tail.replace('\n', " | "))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Aria2Notification, ExternalAria2Config, RpcFault, parse_notifications};
+    use crate::models::GlobalSettings;
+    use serde_json::json;
+
+    #[test]
+    fn only_missing_multicall_counts_as_method_not_found() {
+        let fault = |body| RpcFault { body };
+        assert!(
+            fault(json!({"code": 1, "message": "No such method: system.multicall"}))
+                .is_method_not_found()
+        );
+        assert!(
+            fault(json!({"code": -32601, "message": "Method not found"})).is_method_not_found()
+        );
+        assert!(!fault(json!({"code": 1, "message": "Unauthorized"})).is_method_not_found());
+        assert!(!fault(json!({"code": -32700, "message": "Parse error."})).is_method_not_found());
+    }
+
+    #[test]
+    fn parse_notifications_reads_download_events_only() {
+        let parsed = parse_notifications(
+            r#"{"jsonrpc":"2.0","method":"aria2.onDownloadComplete","params":[{"gid":"2089b05ecca3d829"}]}"#,
+        );
+        assert_eq!(
+            parsed,
+            vec![Aria2Notification {
+                method: "aria2.onDownloadComplete".to_string(),
+                gid: "2089b05ecca3d829".to_string(),
+            }]
+        );
+        assert!(parse_notifications(r#"{"jsonrpc":"2.0","id":"1","result":"OK"}"#).is_empty());
+        assert!(parse_notifications("not json").is_empty());
+    }
+
+    #[test]
+    fn external_config_follows_engine_mode_and_derives_ws_url() {
+        let mut settings = GlobalSettings {
+            engine_mode: Some("managed".to_string()),
+            aria2_rpc_url: Some("https://nas.local:6800/jsonrpc".to_string()),
+            aria2_rpc_secret: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ExternalAria2Config::from_settings(&settings), None);
+
+        settings.engine_mode = Some("external".to_string());
+        let external = ExternalAria2Config::from_settings(&settings).expect("external config");
+        assert_eq!(external.secret, "s3cret");
+        assert_eq!(
+            external.ws_url().as_deref(),
+            Some("wss://nas.local:6800/jsonrpc")
+        );
+
+        settings.aria2_rpc_url = Some("http://10.0.0.2:6800/jsonrpc".to_string());
+        let external = ExternalAria2Config::from_settings(&settings).expect("external config");
+        assert_eq!(
+            external.ws_url().as_deref(),
+            Some("ws://10.0.0.2:6800/jsonrpc")
+        );
+    }
+}
@@ -0,0 +1,14 @@
+#[tokio::main]
+async fn main() {
+    let args = match flamingo_downloader::cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{}", flamingo_downloader::cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = flamingo_downloader::cli::run(args).await {
+        eprintln!("error: {e:#}");
+        std::process::exit(1);
+    }
+}
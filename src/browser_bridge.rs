@@ -10,9 +10,14 @@ use serde_json::json;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
+    sync::broadcast::error::RecvError,
+    time,
 };
 
-use crate::download_service::DownloadService;
+use crate::{
+    download_service::DownloadService,
+    models::{BandwidthHistoryQuery, BatchAddRequest, EngineProfile, GlobalSettings},
+};
 
 static BRIDGE_RATE_BUCKETS: LazyLock<Mutex<HashMap<String, VecDeque<Instant>>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
@@ -21,6 +26,8 @@ const RATE_LIMIT_ADD: usize = 40;
 const RATE_LIMIT_HEALTH: usize = 80;
 const MAX_BODY_ADD: usize = 256 * 1024;
 const MAX_BODY_HEALTH: usize = 8 * 1024;
+const MAX_BODY_IMPORT: usize = 16 * 1024 * 1024;
+const SSE_KEEPALIVE: Duration = Duration::from_secs(15);
 
 #[derive(Debug, Clone)]
 pub struct BrowserBridgeConfig {
@@ -42,11 +49,44 @@ struct BridgeAddRequest {
 struct LocalApiAddRequest {
     url: Option<String>,
     magnet: Option<String>,
+    torrent_base64: Option<String>,
+    metalink_base64: Option<String>,
     save_dir: Option<String>,
     category: Option<String>,
+    out: Option<String>,
+    engine: Option<String>,
     referer: Option<String>,
     user_agent: Option<String>,
     headers: Option<Vec<String>>,
+    depends_on: Option<Vec<String>>,
+    on_dependency_failure: Option<String>,
+    start_at: Option<i64>,
+    window: Option<crate::models::TaskWindow>,
+    queue: Option<String>,
+    collision_policy: Option<String>,
+    duplicate_policy: Option<String>,
+    url_expansion: Option<String>,
+    mirrors: Option<Vec<String>>,
+    select_file: Option<String>,
+    file_priorities: Option<Vec<crate::models::TorrentFilePriority>>,
+    metadata_only: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LocalApiInspectTorrentRequest {
+    torrent_base64: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LocalApiExpandRequest {
+    url: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LocalApiBulkActionRequest {
+    task_ids: Vec<String>,
+    action: String,
+    delete_files: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -54,6 +94,10 @@ struct LocalApiTaskActionRequest {
     action: String,
     delete_files: Option<bool>,
     category: Option<String>,
+    start_at: Option<i64>,
+    window: Option<crate::models::TaskWindow>,
+    files: Option<Vec<usize>>,
+    trackers: Option<Vec<String>>,
 }
 
 pub fn start_browser_bridge(service: Arc<DownloadService>, cfg: BrowserBridgeConfig) {
@@ -146,6 +190,12 @@ async fn handle_connection(
             }
         }
     }
+    // EventSource cannot set custom headers, so the event stream also accepts ?token=.
+    if req_token.is_empty() && path == "/api/events" {
+        req_token = parse_query_params(query)
+            .remove("token")
+            .unwrap_or_default();
+    }
     let settings = service.get_global_settings().ok();
     if !allow_request_for_path(path) {
         service.append_operation_log("bridge_activity", format!("rate_limited path={path}"));
@@ -161,7 +211,9 @@ async fn handle_connection(
         .and_then(|s| s.browser_bridge_allowed_origins.clone())
         .unwrap_or_default();
     let ext_origin = is_extension_origin(&origin);
-    let relax_origin_check = origin.is_empty() && (path == "/add" || path == "/health");
+    // Non-browser clients (CLI, scripts) send no Origin; /api/ still requires the token below.
+    let relax_origin_check =
+        origin.is_empty() && (path == "/add" || path == "/health" || path.starts_with("/api/"));
     if !relax_origin_check && !origin_allowed(&origin, &allowed_origins) {
         service.append_operation_log(
             "bridge_activity",
@@ -193,10 +245,10 @@ async fn handle_connection(
         .await;
     }
 
-    let body_limit = if path == "/health" {
-        MAX_BODY_HEALTH
-    } else {
-        MAX_BODY_ADD
+    let body_limit = match path {
+        "/health" => MAX_BODY_HEALTH,
+        "/api/import" | "/api/tasks/batch" => MAX_BODY_IMPORT,
+        _ => MAX_BODY_ADD,
     };
     if content_length > body_limit {
         service.append_operation_log(
@@ -341,12 +393,16 @@ fn classify_bridge_error(detail: &str) -> &'static str {
     if lower.contains("unauthorized") {
         return "unauthorized";
     }
+    if lower.contains("file already exists") {
+        return "file_exists";
+    }
     if lower.contains("forbidden origin") {
         return "forbidden_origin";
     }
     "bridge_add_failed"
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_local_api(
     stream: &mut TcpStream,
     service: Arc<DownloadService>,
@@ -370,9 +426,15 @@ async fn handle_local_api(
     let settings = service.get_global_settings()?;
     let scopes = parse_scopes(settings.local_api_scopes.as_deref());
     let required_scope = match (method, path) {
-        ("GET", "/api/health") | ("GET", "/api/stats") => "read",
+        ("GET", "/api/health") | ("GET", "/api/stats") | ("GET", "/api/queues") => "read",
+        ("GET", "/api/trackers") | ("GET", "/api/seeding") => "read",
+        ("GET", "/api/stats/history") => "read",
+        ("POST", "/api/tasks/expand") | ("POST", "/api/torrents/inspect") => "read",
+        ("GET", "/api/settings") | ("GET", "/api/export") | ("GET", "/api/events") => "read",
         ("GET", p) if p == "/api/tasks" || p.starts_with("/api/tasks/") => "read",
-        ("POST", "/api/tasks") => "add",
+        ("POST", "/api/tasks") | ("POST", "/api/tasks/batch") | ("POST", "/api/import") => "add",
+        ("POST", "/api/settings") | ("POST", "/api/tasks/actions") => "control",
+        ("POST", "/api/trackers/refresh") => "control",
         ("POST", p) if p.starts_with("/api/tasks/") && p.ends_with("/actions") => "control",
         _ => "",
     };
@@ -400,11 +462,64 @@ async fn handle_local_api(
             );
             return write_json(stream, 200, &json!({"ok": true, "stats": stats})).await;
         }
+        ("GET", "/api/events") => {
+            service.append_operation_log(
+                "local_api_activity",
+                format!("events_open origin={origin} ua={user_agent}"),
+            );
+            return stream_events(stream, &service, query).await;
+        }
         ("GET", "/api/stats") => {
             let stats = service.get_task_stats()?;
             service.append_operation_log("local_api_activity", "stats_ok".to_string());
             return write_json(stream, 200, &json!({"ok": true, "data": stats})).await;
         }
+        ("GET", "/api/queues") => {
+            let queues = service.list_task_queues()?;
+            service.append_operation_log("local_api_activity", "list_queues_ok".to_string());
+            return write_json(stream, 200, &json!({"ok": true, "data": queues})).await;
+        }
+        ("GET", "/api/trackers") => {
+            let lists = service.list_tracker_lists()?;
+            let trackers = service.effective_trackers()?;
+            return write_json(
+                stream,
+                200,
+                &json!({"ok": true, "data": {"lists": lists, "trackers": trackers}}),
+            )
+            .await;
+        }
+        ("GET", "/api/stats/history") => {
+            let params = parse_query_params(query);
+            let number = |key: &str| -> Result<Option<i64>> {
+                params
+                    .get(key)
+                    .map(|v| {
+                        v.parse::<i64>()
+                            .map_err(|_| anyhow!("invalid {key}={v}, expected unix seconds"))
+                    })
+                    .transpose()
+            };
+            let history = service.get_bandwidth_history(BandwidthHistoryQuery {
+                resolution: params.get("resolution").cloned(),
+                group_by: params.get("group_by").cloned(),
+                from: number("from")?,
+                to: number("to")?,
+                task_id: params.get("task_id").cloned(),
+                category: params.get("category").cloned(),
+                domain: params.get("domain").cloned(),
+            })?;
+            return write_json(stream, 200, &json!({"ok": true, "data": history})).await;
+        }
+        ("GET", "/api/seeding") => {
+            let seeding = service.list_task_seeding()?;
+            return write_json(stream, 200, &json!({"ok": true, "data": seeding})).await;
+        }
+        ("POST", "/api/trackers/refresh") => {
+            let lists = service.refresh_tracker_lists().await?;
+            service.append_operation_log("local_api_activity", "refresh_trackers_ok".to_string());
+            return write_json(stream, 200, &json!({"ok": true, "data": lists})).await;
+        }
         ("GET", "/api/tasks") => {
             let params = parse_query_params(query);
             let status = params.get("status").and_then(|value| match value.as_str() {
@@ -437,38 +552,229 @@ async fn handle_local_api(
             let options = crate::models::AddTaskOptions {
                 save_dir: payload.save_dir,
                 category: payload.category,
+                out: payload.out,
+                engine: payload.engine,
                 referer: payload.referer,
                 user_agent: payload.user_agent,
                 headers: payload.headers.unwrap_or_default(),
+                depends_on: payload.depends_on.unwrap_or_default(),
+                on_dependency_failure: payload.on_dependency_failure,
+                start_at: payload.start_at,
+                window: payload.window,
+                queue: payload.queue,
+                collision_policy: payload.collision_policy,
+                duplicate_policy: payload.duplicate_policy,
+                url_expansion: payload.url_expansion,
+                mirrors: payload.mirrors.unwrap_or_default(),
+                select_file: payload.select_file,
+                file_priorities: payload.file_priorities.unwrap_or_default(),
+                metadata_only: payload.metadata_only,
+                origin: Some("api".to_string()),
                 ..Default::default()
             };
-            let task_id = if let Some(magnet) = payload
+            let mut task_ids = Vec::new();
+            let outcome = if let Some(magnet) = payload
                 .magnet
                 .as_deref()
                 .map(str::trim)
                 .filter(|v| !v.is_empty())
             {
-                service.add_magnet(magnet, options).await?
+                service.add_magnet_outcome(magnet, options).await?
+            } else if let Some(torrent) = payload
+                .torrent_base64
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+            {
+                crate::models::AddTaskOutcome {
+                    task_id: service.add_torrent_base64(torrent, options, None).await?,
+                    action: "created".to_string(),
+                    duplicate: None,
+                }
+            } else if let Some(metalink) = payload
+                .metalink_base64
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+            {
+                task_ids = service.add_metalink_base64(metalink, options, None).await?;
+                crate::models::AddTaskOutcome {
+                    task_id: task_ids.first().cloned().unwrap_or_default(),
+                    action: "created".to_string(),
+                    duplicate: None,
+                }
             } else if let Some(url) = payload
                 .url
                 .as_deref()
                 .map(str::trim)
                 .filter(|v| !v.is_empty())
             {
-                service.add_url(url, options).await?
+                service.add_url_outcome(url, options).await?
             } else {
                 return write_json(
                     stream,
                     400,
-                    &json!({"ok": false, "error": "missing url or magnet"}),
+                    &json!({"ok": false, "error": "missing url, magnet, torrent or metalink"}),
                 )
                 .await;
             };
             service.append_operation_log(
                 "local_api_activity",
-                format!("add_task_ok task_id={task_id}"),
+                format!(
+                    "add_task_ok task_id={} action={}",
+                    outcome.task_id, outcome.action
+                ),
+            );
+            return write_json(
+                stream,
+                200,
+                &json!({
+                    "ok": true,
+                    "task_id": outcome.task_id,
+                    "action": outcome.action,
+                    "duplicate_of": outcome.duplicate,
+                    "task_ids": task_ids,
+                }),
+            )
+            .await;
+        }
+        ("POST", "/api/tasks/expand") => {
+            let payload: LocalApiExpandRequest = serde_json::from_str(body_raw)?;
+            let urls = service.preview_url_pattern(&payload.url)?;
+            return write_json(
+                stream,
+                200,
+                &json!({"ok": true, "data": {"count": urls.len(), "urls": urls}}),
+            )
+            .await;
+        }
+        ("POST", "/api/torrents/inspect") => {
+            let payload: LocalApiInspectTorrentRequest = serde_json::from_str(body_raw)?;
+            let info = service.inspect_torrent_base64(&payload.torrent_base64)?;
+            return write_json(stream, 200, &json!({"ok": true, "data": info})).await;
+        }
+        ("POST", "/api/tasks/batch") => {
+            let mut payload: BatchAddRequest = serde_json::from_str(body_raw)?;
+            payload
+                .options
+                .origin
+                .get_or_insert_with(|| "api".to_string());
+            let result = service.add_batch(payload).await?;
+            service.append_operation_log(
+                "local_api_activity",
+                format!(
+                    "add_batch_ok created={} failed={}",
+                    result.created, result.failed
+                ),
+            );
+            return write_json(stream, 200, &json!({"ok": true, "data": result})).await;
+        }
+        ("POST", "/api/tasks/actions") => {
+            let payload: LocalApiBulkActionRequest = serde_json::from_str(body_raw)?;
+            let action = payload.action.trim().to_ascii_lowercase();
+            let result = match action.as_str() {
+                "pause" => service.pause_tasks(&payload.task_ids).await?,
+                "resume" => service.resume_tasks(&payload.task_ids).await?,
+                "remove" => {
+                    service
+                        .remove_tasks(&payload.task_ids, payload.delete_files.unwrap_or(false))
+                        .await?
+                }
+                _ => {
+                    return write_json(
+                        stream,
+                        400,
+                        &json!({"ok": false, "error": "unsupported action"}),
+                    )
+                    .await;
+                }
+            };
+            service.append_operation_log(
+                "local_api_activity",
+                format!(
+                    "bulk_action_ok action={action} succeeded={} failed={}",
+                    result.succeeded.len(),
+                    result.failed.len()
+                ),
             );
-            return write_json(stream, 200, &json!({"ok": true, "task_id": task_id})).await;
+            return write_json(stream, 200, &json!({"ok": true, "data": result})).await;
+        }
+        ("GET", "/api/settings") => {
+            let settings = redact_settings(service.get_global_settings()?);
+            service.append_operation_log("local_api_activity", "get_settings_ok".to_string());
+            return write_json(stream, 200, &json!({"ok": true, "data": settings})).await;
+        }
+        ("POST", "/api/settings") => {
+            let patch: serde_json::Value = serde_json::from_str(body_raw)?;
+            let keys = patch
+                .as_object()
+                .map(|m| m.keys().cloned().collect::<Vec<_>>().join(","))
+                .unwrap_or_default();
+            let protected = patch
+                .as_object()
+                .map(|m| {
+                    m.keys()
+                        .filter(|k| PROTECTED_SETTING_KEYS.contains(&k.as_str()))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            if !protected.is_empty() {
+                service.append_operation_log(
+                    "local_api_activity",
+                    format!(
+                        "patch_settings_forbidden keys={} origin={origin}",
+                        protected.join(",")
+                    ),
+                );
+                return write_json(
+                    stream,
+                    403,
+                    &json!({"ok": false, "error": "protected_settings", "keys": protected}),
+                )
+                .await;
+            }
+            let settings = match service.patch_global_settings(patch).await {
+                Ok(v) => redact_settings(v),
+                Err(e) => {
+                    return write_json(
+                        stream,
+                        400,
+                        &json!({"ok": false, "error": "invalid_settings", "detail": e.to_string()}),
+                    )
+                    .await;
+                }
+            };
+            service.append_operation_log(
+                "local_api_activity",
+                format!("patch_settings_ok keys={keys}"),
+            );
+            return write_json(stream, 200, &json!({"ok": true, "data": settings})).await;
+        }
+        ("GET", "/api/export") => {
+            let snapshot: serde_json::Value =
+                serde_json::from_str(&service.export_task_list_json()?)?;
+            service.append_operation_log("local_api_activity", "export_ok".to_string());
+            return write_json(stream, 200, &json!({"ok": true, "data": snapshot})).await;
+        }
+        ("POST", "/api/import") => {
+            let result = match service.import_task_list_json(body_raw) {
+                Ok(v) => v,
+                Err(e) => {
+                    return write_json(
+                        stream,
+                        400,
+                        &json!({"ok": false, "error": "invalid_snapshot", "detail": e.to_string()}),
+                    )
+                    .await;
+                }
+            };
+            service.append_operation_log(
+                "local_api_activity",
+                format!(
+                    "import_ok tasks={} files={}",
+                    result.imported_tasks, result.imported_files
+                ),
+            );
+            return write_json(stream, 200, &json!({"ok": true, "data": result})).await;
         }
         _ => {}
     }
@@ -513,6 +819,23 @@ async fn handle_local_api(
             "open_dir" => service.open_task_dir(task_id).await?,
             "open_file" => service.open_task_file(task_id).await?,
             "set_category" => service.set_task_category(task_id, payload.category.as_deref())?,
+            "schedule" => {
+                service
+                    .set_task_schedule(task_id, payload.start_at, payload.window)
+                    .await?
+            }
+            "select_files" => {
+                let files = payload.files.unwrap_or_default();
+                service.set_task_file_selection(task_id, &files).await?
+            }
+            "add_trackers" => {
+                let trackers = payload.trackers.unwrap_or_default();
+                service.add_task_trackers(task_id, &trackers).await?
+            }
+            "remove_trackers" => {
+                let trackers = payload.trackers.unwrap_or_default();
+                service.remove_task_trackers(task_id, &trackers).await?
+            }
             _ => {
                 return write_json(
                     stream,
@@ -536,6 +859,111 @@ async fn handle_local_api(
     write_json(stream, 404, &json!({"ok": false, "error": "not found"})).await
 }
 
+async fn stream_events(
+    stream: &mut TcpStream,
+    service: &DownloadService,
+    query: &str,
+) -> Result<()> {
+    let Some(mut events) = service.subscribe_events() else {
+        return write_json(
+            stream,
+            404,
+            &json!({"ok": false, "error": "events_unavailable"}),
+        )
+        .await;
+    };
+    let wanted_types = parse_query_params(query)
+        .get("types")
+        .map(|raw| {
+            raw.split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+        )
+        .await?;
+    let tasks = service.list_tasks(None, 5000, 0)?;
+    write_sse(
+        stream,
+        "snapshot",
+        &json!({"type": "snapshot", "tasks": tasks}),
+    )
+    .await?;
+
+    let mut keepalive = time::interval(SSE_KEEPALIVE);
+    keepalive.tick().await;
+    loop {
+        tokio::select! {
+            received = events.recv() => match received {
+                Ok(event) => {
+                    let name = event.name();
+                    if !wanted_types.is_empty() && !wanted_types.iter().any(|t| t == name) {
+                        continue;
+                    }
+                    write_sse(stream, name, &serde_json::to_value(&event)?).await?;
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    write_sse(stream, "lagged", &json!({"type": "lagged", "skipped": skipped}))
+                        .await?;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            _ = keepalive.tick() => {
+                stream.write_all(b": keepalive\n\n").await?;
+                stream.flush().await?;
+            }
+        }
+    }
+}
+
+async fn write_sse(stream: &mut TcpStream, event: &str, data: &serde_json::Value) -> Result<()> {
+    let frame = format!("event: {event}\ndata: {}\n\n", serde_json::to_string(data)?);
+    stream.write_all(frame.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Settings that control API access, credentials, spawned programs or local files read
+/// (tracker lists accept `file://` sources); only the desktop app may change them.
+const PROTECTED_SETTING_KEYS: &[&str] = &[
+    "browser_bridge_enabled",
+    "browser_bridge_port",
+    "browser_bridge_token",
+    "browser_bridge_allowed_origins",
+    "local_api_scopes",
+    "aria2_rpc_url",
+    "aria2_rpc_secret",
+    "aria2_bin_path",
+    "ffmpeg_bin_path",
+    "engine_mode",
+    "engine_profiles",
+    "github_token",
+    "completion_webhook_url",
+    "completion_command",
+    "tracker_lists",
+];
+
+fn redact_settings(mut settings: GlobalSettings) -> GlobalSettings {
+    settings.browser_bridge_token = None;
+    settings.github_token = None;
+    settings.aria2_rpc_secret = None;
+    settings.engine_profiles = settings.engine_profiles.map(|raw| {
+        let Ok(mut profiles) = serde_json::from_str::<Vec<EngineProfile>>(&raw) else {
+            return raw;
+        };
+        for profile in &mut profiles {
+            profile.secret.clear();
+        }
+        serde_json::to_string(&profiles).unwrap_or(raw)
+    });
+    settings
+}
+
 fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     haystack.windows(needle.len()).position(|w| w == needle)
 }
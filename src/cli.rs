@@ -0,0 +1,1357 @@
+use std::{
+    collections::HashSet,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use reqwest::Client;
+use serde_json::{Value, json};
+use tokio::time;
+
+use crate::{
+    BackendHandles, BackendOptions,
+    db::Database,
+    error::AppError,
+    events::{NullEventEmitter, SharedEmitter},
+    init_backend_with_options,
+    models::{
+        AddTaskOptions, BatchAddRequest, BatchAddResult, BulkTaskActionResult, Task, TaskStatus,
+        TaskWindow,
+    },
+};
+
+const DEFAULT_API_PORT: u16 = 16789;
+/// The local API allows 20 requests per 10 seconds; faster polling gets throttled.
+const REMOTE_WATCH_MIN_INTERVAL_MS: u64 = 500;
+
+pub const USAGE: &str = "\
+flamingo - headless control for Flamingo Downloader
+
+Usage: flamingo [global options] <command> [args]
+
+Global options:
+  --data-dir <dir>   Flamingo data dir (default: $FLAMINGO_DATA_DIR or current dir)
+  --local            Drive the download service in-process
+  --remote           Talk to the running app over the local API
+  --port <port>      Local API port (default: saved setting or 16789)
+  --token <token>    Local API token (default: $FLAMINGO_TOKEN or saved setting)
+  --json             Print machine-readable JSON
+
+Commands:
+  add <url|magnet|file.torrent|file.meta4>... [--dir <dir>] [--category <name>] [--out <name>]
+      [--engine <profile>] [--referer <url>] [--user-agent <ua>] [--header <k: v>]... [--split <n>]
+      [--connections <n>] [--limit <speed>] [--after <id>]... [--on-dep-failure cancel|wait|run]
+      [--start-at <unix-secs|rfc3339>] [--window <HH:MM-HH:MM>] [--days <1-7,...>]
+      [--queue <name>] [--collision rename|overwrite|skip|resume|ask]
+      [--duplicate warn|skip|redownload|open] [--expand tasks|mirrors|off] [--mirror <url>]...
+      [--select <1,3-5>] [--metadata-only] [--wait]
+  ls [--status <status>] [--limit <n>]
+  pause <id>...
+  resume <id>...
+  retry <id>...
+  rm <id>... [--delete-files]
+  watch [id...] [--interval <ms>]
+  settings get [key]
+  settings set <key> <value>
+  export [file]
+  import <file>
+
+Task ids may be abbreviated to any unique prefix.
+Without --local/--remote the running app is used when reachable.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CliMode {
+    Auto,
+    Local,
+    Remote,
+}
+
+#[derive(Debug, Clone)]
+pub struct CliArgs {
+    pub data_dir: Option<PathBuf>,
+    pub mode: CliMode,
+    pub port: Option<u16>,
+    pub token: Option<String>,
+    pub json: bool,
+    pub command: CliCommand,
+}
+
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    Add {
+        sources: Vec<String>,
+        options: Box<AddTaskOptions>,
+        wait: bool,
+    },
+    List {
+        status: Option<TaskStatus>,
+        limit: u32,
+    },
+    Pause {
+        task_ids: Vec<String>,
+    },
+    Resume {
+        task_ids: Vec<String>,
+    },
+    Retry {
+        task_ids: Vec<String>,
+    },
+    Remove {
+        task_ids: Vec<String>,
+        delete_files: bool,
+    },
+    Watch {
+        task_ids: Vec<String>,
+        interval_ms: u64,
+    },
+    SettingsGet {
+        key: Option<String>,
+    },
+    SettingsSet {
+        key: String,
+        value: String,
+    },
+    Export {
+        path: Option<PathBuf>,
+    },
+    Import {
+        path: PathBuf,
+    },
+    Help,
+}
+
+impl CliCommand {
+    fn needs_engine(&self) -> bool {
+        matches!(
+            self,
+            Self::Add { .. }
+                | Self::Pause { .. }
+                | Self::Resume { .. }
+                | Self::Retry { .. }
+                | Self::Watch { .. }
+        )
+    }
+}
+
+pub fn parse_args<I>(args: I) -> Result<CliArgs>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut data_dir = None;
+    let mut mode = CliMode::Auto;
+    let mut port = None;
+    let mut token = None;
+    let mut json_output = false;
+    let mut rest = Vec::new();
+
+    // Global options only come before the command, so command arguments that
+    // look like one (`settings set k --json`) reach the command untouched.
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--data-dir" => data_dir = Some(PathBuf::from(take_value(&mut iter, &arg)?)),
+            "--local" => mode = CliMode::Local,
+            "--remote" => mode = CliMode::Remote,
+            "--port" => {
+                let raw = take_value(&mut iter, &arg)?;
+                port = Some(
+                    raw.parse::<u16>()
+                        .map_err(|_| anyhow!("invalid --port value: {raw}"))?,
+                );
+            }
+            "--token" => token = Some(take_value(&mut iter, &arg)?),
+            "--json" => json_output = true,
+            "--" => break,
+            "-h" | "--help" => {
+                rest.push("help".to_string());
+                break;
+            }
+            _ => {
+                rest.push(arg);
+                break;
+            }
+        }
+    }
+    rest.extend(iter);
+
+    let command = parse_command(rest)?;
+    Ok(CliArgs {
+        data_dir,
+        mode,
+        port,
+        token,
+        json: json_output,
+        command,
+    })
+}
+
+fn parse_command(args: Vec<String>) -> Result<CliCommand> {
+    let mut iter = args.into_iter();
+    let Some(name) = iter.next() else {
+        return Ok(CliCommand::Help);
+    };
+    match name.as_str() {
+        "help" => Ok(CliCommand::Help),
+        "add" => {
+            let mut sources = Vec::new();
+            let mut options = AddTaskOptions::default();
+            let mut wait = false;
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "--dir" => options.save_dir = Some(take_value(&mut iter, &arg)?),
+                    "--category" => options.category = Some(take_value(&mut iter, &arg)?),
+                    "--out" => options.out = Some(take_value(&mut iter, &arg)?),
+                    "--engine" => options.engine = Some(take_value(&mut iter, &arg)?),
+                    "--queue" => options.queue = Some(take_value(&mut iter, &arg)?),
+                    "--collision" => options.collision_policy = Some(take_value(&mut iter, &arg)?),
+                    "--duplicate" => options.duplicate_policy = Some(take_value(&mut iter, &arg)?),
+                    "--expand" => options.url_expansion = Some(take_value(&mut iter, &arg)?),
+                    "--mirror" => options.mirrors.push(take_value(&mut iter, &arg)?),
+                    "--select" => options.select_file = Some(take_value(&mut iter, &arg)?),
+                    "--referer" => options.referer = Some(take_value(&mut iter, &arg)?),
+                    "--user-agent" => options.user_agent = Some(take_value(&mut iter, &arg)?),
+                    "--header" => options.headers.push(take_value(&mut iter, &arg)?),
+                    "--split" => options.split = Some(take_u32(&mut iter, &arg)?),
+                    "--connections" => {
+                        options.max_connection_per_server = Some(take_u32(&mut iter, &arg)?)
+                    }
+                    "--limit" => options.max_download_limit = Some(take_value(&mut iter, &arg)?),
+                    "--after" => options.depends_on.push(take_value(&mut iter, &arg)?),
+                    "--on-dep-failure" => {
+                        options.on_dependency_failure = Some(take_value(&mut iter, &arg)?)
+                    }
+                    "--start-at" => options.start_at = Some(take_timestamp(&mut iter, &arg)?),
+                    "--window" => {
+                        let raw = take_value(&mut iter, &arg)?;
+                        let (start, end) = raw
+                            .split_once('-')
+                            .ok_or_else(|| anyhow!("invalid value for --window: {raw}"))?;
+                        let window = options.window.get_or_insert_with(TaskWindow::default);
+                        window.start = start.trim().to_string();
+                        window.end = end.trim().to_string();
+                    }
+                    "--days" => {
+                        options.window.get_or_insert_with(TaskWindow::default).days =
+                            Some(take_value(&mut iter, &arg)?)
+                    }
+                    "--wait" => wait = true,
+                    "--metadata-only" => options.metadata_only = Some(true),
+                    flag if flag.starts_with("--") => {
+                        return Err(anyhow!("unknown option for add: {flag}"));
+                    }
+                    _ => sources.push(arg),
+                }
+            }
+            if sources.is_empty() {
+                return Err(anyhow!(
+                    "add requires at least one url, magnet, torrent or metalink file"
+                ));
+            }
+            if options.window.as_ref().is_some_and(|w| w.start.is_empty()) {
+                return Err(anyhow!("--days requires --window"));
+            }
+            Ok(CliCommand::Add {
+                sources,
+                options: Box::new(options),
+                wait,
+            })
+        }
+        "ls" | "list" => {
+            let mut status = None;
+            let mut limit = 200;
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "--status" => {
+                        let raw = take_value(&mut iter, &arg)?;
+                        status = Some(
+                            parse_task_status(&raw)
+                                .ok_or_else(|| anyhow!("unknown task status: {raw}"))?,
+                        );
+                    }
+                    "--limit" => limit = take_u32(&mut iter, &arg)?,
+                    other => return Err(anyhow!("unknown option for ls: {other}")),
+                }
+            }
+            Ok(CliCommand::List { status, limit })
+        }
+        "pause" | "resume" | "retry" => {
+            let task_ids = iter.collect::<Vec<_>>();
+            if task_ids.is_empty() {
+                return Err(anyhow!("{name} requires at least one task id"));
+            }
+            Ok(match name.as_str() {
+                "pause" => CliCommand::Pause { task_ids },
+                "resume" => CliCommand::Resume { task_ids },
+                _ => CliCommand::Retry { task_ids },
+            })
+        }
+        "rm" | "remove" => {
+            let mut task_ids = Vec::new();
+            let mut delete_files = false;
+            for arg in iter {
+                match arg.as_str() {
+                    "--delete-files" => delete_files = true,
+                    flag if flag.starts_with("--") => {
+                        return Err(anyhow!("unknown option for rm: {flag}"));
+                    }
+                    _ => task_ids.push(arg),
+                }
+            }
+            if task_ids.is_empty() {
+                return Err(anyhow!("rm requires at least one task id"));
+            }
+            Ok(CliCommand::Remove {
+                task_ids,
+                delete_files,
+            })
+        }
+        "watch" => {
+            let mut task_ids = Vec::new();
+            let mut interval_ms = 1000;
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "--interval" => interval_ms = u64::from(take_u32(&mut iter, &arg)?.max(200)),
+                    flag if flag.starts_with("--") => {
+                        return Err(anyhow!("unknown option for watch: {flag}"));
+                    }
+                    _ => task_ids.push(arg),
+                }
+            }
+            Ok(CliCommand::Watch {
+                task_ids,
+                interval_ms,
+            })
+        }
+        "settings" => match iter.next().as_deref() {
+            Some("get") => Ok(CliCommand::SettingsGet { key: iter.next() }),
+            Some("set") => {
+                let key = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("settings set requires <key> <value>"))?;
+                let value = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("settings set requires <key> <value>"))?;
+                Ok(CliCommand::SettingsSet { key, value })
+            }
+            _ => Err(anyhow!(
+                "usage: settings get [key] | settings set <key> <value>"
+            )),
+        },
+        "export" => Ok(CliCommand::Export {
+            path: iter.next().map(PathBuf::from),
+        }),
+        "import" => {
+            let path = iter
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("import requires a snapshot file"))?;
+            Ok(CliCommand::Import { path })
+        }
+        other => Err(anyhow!("unknown command: {other}")),
+    }
+}
+
+fn take_value(iter: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    iter.next()
+        .ok_or_else(|| anyhow!("missing value for {flag}"))
+}
+
+fn take_timestamp(iter: &mut impl Iterator<Item = String>, flag: &str) -> Result<i64> {
+    let raw = take_value(iter, flag)?;
+    raw.parse::<i64>()
+        .ok()
+        .or_else(|| {
+            chrono::DateTime::parse_from_rfc3339(raw.trim())
+                .ok()
+                .map(|v| v.timestamp())
+        })
+        .ok_or_else(|| anyhow!("invalid value for {flag}: {raw}"))
+}
+
+fn take_u32(iter: &mut impl Iterator<Item = String>, flag: &str) -> Result<u32> {
+    let raw = take_value(iter, flag)?;
+    raw.parse::<u32>()
+        .map_err(|_| anyhow!("invalid value for {flag}: {raw}"))
+}
+
+fn parse_task_status(value: &str) -> Option<TaskStatus> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "queued" => Some(TaskStatus::Queued),
+        "active" => Some(TaskStatus::Active),
+        "paused" => Some(TaskStatus::Paused),
+        "completed" => Some(TaskStatus::Completed),
+        "error" => Some(TaskStatus::Error),
+        "removed" => Some(TaskStatus::Removed),
+        "metadata" => Some(TaskStatus::Metadata),
+        _ => None,
+    }
+}
+
+pub async fn run(args: CliArgs) -> Result<()> {
+    if matches!(args.command, CliCommand::Help) {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let data_dir = resolve_data_dir(args.data_dir.clone())?;
+    let backend = CliBackend::connect(&args, &data_dir).await?;
+    if args.command.needs_engine() {
+        backend.ensure_engine().await?;
+    }
+    let result = execute(&backend, &args).await;
+    backend.shutdown().await;
+    result
+}
+
+fn resolve_data_dir(explicit: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(dir) = explicit {
+        return Ok(dir);
+    }
+    if let Some(dir) = std::env::var_os("FLAMINGO_DATA_DIR").filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    Ok(std::env::current_dir()?)
+}
+
+fn db_path_for(data_dir: &Path) -> PathBuf {
+    data_dir.join("runtime").join("app.db")
+}
+
+async fn execute(backend: &CliBackend, args: &CliArgs) -> Result<()> {
+    match &args.command {
+        CliCommand::Help => {
+            println!("{USAGE}");
+        }
+        CliCommand::Add {
+            sources,
+            options,
+            wait,
+        } => {
+            let mut options = options.as_ref().clone();
+            options.depends_on = backend.resolve_task_ids(&options.depends_on).await?;
+            options.origin = Some("api".to_string());
+            let (files, links): (Vec<_>, Vec<_>) = sources
+                .iter()
+                .map(|source| source.trim().to_string())
+                .partition(|source| is_torrent_file(source) || is_metalink_file(source));
+            let mut task_ids = Vec::new();
+            for source in &files {
+                for task_id in backend.add(source, options.clone()).await? {
+                    if args.json {
+                        println!("{}", json!({ "source": source, "task_id": task_id }));
+                    } else {
+                        println!("{task_id}  {source}");
+                    }
+                    task_ids.push(task_id);
+                }
+            }
+            let mut failed = 0;
+            if !links.is_empty() {
+                let result = backend.add_batch(links, options.clone()).await?;
+                failed = result.failed;
+                for item in result.items {
+                    if args.json {
+                        println!("{}", serde_json::to_string(&item)?);
+                    } else if let Some(error) = &item.error {
+                        eprintln!("failed  {}: {error}", item.source);
+                    } else if item.action.as_deref() == Some("skipped") {
+                        println!(
+                            "skipped  {}  (duplicate of {})",
+                            item.source,
+                            item.task_id.as_deref().unwrap_or_default()
+                        );
+                    } else {
+                        println!(
+                            "{}  {}",
+                            item.task_id.as_deref().unwrap_or_default(),
+                            item.source
+                        );
+                    }
+                    if item.action.as_deref() != Some("skipped")
+                        && let Some(task_id) = item.task_id
+                    {
+                        task_ids.push(task_id);
+                    }
+                }
+            }
+            if *wait {
+                watch_tasks(backend, &task_ids, 1000, args.json, true).await?;
+            } else if matches!(backend, CliBackend::Local(_)) && !args.json {
+                println!(
+                    "aria2 session saved; downloads continue the next time Flamingo runs (or use `flamingo watch`)."
+                );
+            }
+            if failed > 0 {
+                return Err(anyhow!("{failed} of {} source(s) failed", sources.len()));
+            }
+        }
+        CliCommand::List { status, limit } => {
+            let tasks = backend.list(status.clone(), *limit).await?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&tasks)?);
+            } else {
+                print_task_table(&tasks);
+            }
+        }
+        CliCommand::Pause { task_ids } => {
+            let ids = backend.resolve_task_ids(task_ids).await?;
+            let result = backend.bulk_action(&ids, "pause", false).await?;
+            report_bulk_result(&result, "paused", args.json)?;
+        }
+        CliCommand::Resume { task_ids } => {
+            let ids = backend.resolve_task_ids(task_ids).await?;
+            let result = backend.bulk_action(&ids, "resume", false).await?;
+            report_bulk_result(&result, "resumed", args.json)?;
+        }
+        CliCommand::Retry { task_ids } => {
+            for id in backend.resolve_task_ids(task_ids).await? {
+                backend.retry(&id).await?;
+                println!("retried {id}");
+            }
+        }
+        CliCommand::Remove {
+            task_ids,
+            delete_files,
+        } => {
+            let ids = backend.resolve_task_ids(task_ids).await?;
+            let result = backend.bulk_action(&ids, "remove", *delete_files).await?;
+            report_bulk_result(&result, "removed", args.json)?;
+        }
+        CliCommand::Watch {
+            task_ids,
+            interval_ms,
+        } => {
+            let resolved = backend.resolve_task_ids(task_ids).await?;
+            let interval_ms = match backend {
+                CliBackend::Remote(_) => (*interval_ms).max(REMOTE_WATCH_MIN_INTERVAL_MS),
+                CliBackend::Local(_) => *interval_ms,
+            };
+            watch_tasks(backend, &resolved, interval_ms, args.json, false).await?;
+        }
+        CliCommand::SettingsGet { key } => {
+            let settings = backend.settings().await?;
+            let value = match key {
+                Some(key) => settings
+                    .get(key)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("unknown setting: {key}"))?,
+                None => settings,
+            };
+            match value {
+                Value::String(v) if !args.json => println!("{v}"),
+                Value::Null if !args.json => {}
+                other => println!("{}", serde_json::to_string_pretty(&other)?),
+            }
+        }
+        CliCommand::SettingsSet { key, value } => {
+            let parsed = coerce_setting_value(value);
+            let updated = match backend
+                .patch_settings(json!({ key.as_str(): parsed }))
+                .await
+            {
+                Ok(v) => v,
+                // Values like `0` or `true` may target string settings; retry verbatim.
+                Err(_) if !parsed.is_string() => {
+                    backend
+                        .patch_settings(json!({ key.as_str(): value.as_str() }))
+                        .await?
+                }
+                Err(e) => return Err(e),
+            };
+            let current = updated.get(key).cloned().unwrap_or(Value::Null);
+            if args.json {
+                println!("{}", json!({ key.as_str(): current }));
+            } else {
+                println!("{key} = {current}");
+            }
+        }
+        CliCommand::Export { path } => {
+            let payload = backend.export().await?;
+            match path {
+                Some(path) => {
+                    std::fs::write(path, payload)?;
+                    println!("exported task list to {}", path.display());
+                }
+                None => println!("{payload}"),
+            }
+        }
+        CliCommand::Import { path } => {
+            let payload = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("read snapshot {} failed: {e}", path.display()))?;
+            let result = backend.import(payload).await?;
+            if args.json {
+                println!("{result}");
+            } else {
+                println!(
+                    "imported tasks={}, files={}",
+                    result.get("imported_tasks").unwrap_or(&Value::Null),
+                    result.get("imported_files").unwrap_or(&Value::Null)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn report_bulk_result(result: &BulkTaskActionResult, verb: &str, json_output: bool) -> Result<()> {
+    if json_output {
+        println!("{}", serde_json::to_string(result)?);
+    } else {
+        for id in &result.succeeded {
+            println!("{verb} {id}");
+        }
+        for failure in &result.failed {
+            eprintln!("failed {}: {}", failure.task_id, failure.error);
+        }
+    }
+    if result.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} task(s) failed", result.failed.len()))
+    }
+}
+
+fn coerce_setting_value(raw: &str) -> Value {
+    serde_json::from_str::<Value>(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+async fn watch_tasks(
+    backend: &CliBackend,
+    task_ids: &[String],
+    interval_ms: u64,
+    json_output: bool,
+    stop_when_stalled: bool,
+) -> Result<()> {
+    let interactive = !json_output && std::io::stdout().is_terminal();
+    let mut interval = time::interval(Duration::from_millis(interval_ms));
+    loop {
+        interval.tick().await;
+        let tasks = backend.list(None, 5000).await?;
+        let watched = if task_ids.is_empty() {
+            tasks
+                .into_iter()
+                .filter(|t| !is_finished(&t.status))
+                .collect::<Vec<_>>()
+        } else {
+            let wanted = task_ids.iter().collect::<HashSet<_>>();
+            tasks
+                .into_iter()
+                .filter(|t| wanted.contains(&t.id))
+                .collect::<Vec<_>>()
+        };
+
+        if json_output {
+            println!("{}", serde_json::to_string(&watched)?);
+        } else {
+            if interactive {
+                print!("\x1b[2J\x1b[H");
+            }
+            print_task_table(&watched);
+            if !interactive {
+                println!();
+            }
+        }
+
+        // `add --wait` cannot finish a task that sits paused or on its dependencies.
+        if stop_when_stalled
+            && !watched
+                .iter()
+                .any(|t| !is_finished(&t.status) && !is_stalled(t))
+            && let Some(stalled) = watched.iter().find(|t| is_stalled(t))
+        {
+            return Err(anyhow!(
+                "{} task(s) are paused or waiting on dependencies, first: {} ({})",
+                watched.iter().filter(|t| is_stalled(t)).count(),
+                stalled.id,
+                stalled.status.as_str()
+            ));
+        }
+        if watched.iter().all(|t| is_finished(&t.status)) {
+            let failed = watched
+                .iter()
+                .filter(|t| t.status == TaskStatus::Error)
+                .collect::<Vec<_>>();
+            if let Some(first) = failed.first() {
+                return Err(anyhow!(
+                    "{} task(s) failed, first: {} ({})",
+                    failed.len(),
+                    first.id,
+                    first.error_message.as_deref().unwrap_or("unknown error")
+                ));
+            }
+            return Ok(());
+        }
+    }
+}
+
+fn is_finished(status: &TaskStatus) -> bool {
+    matches!(
+        status,
+        TaskStatus::Completed | TaskStatus::Error | TaskStatus::Removed
+    )
+}
+
+fn is_stalled(task: &Task) -> bool {
+    task.status == TaskStatus::Paused || (!task.depends_on.is_empty() && task.aria2_gid.is_none())
+}
+
+fn print_task_table(tasks: &[Task]) {
+    if tasks.is_empty() {
+        println!("no tasks");
+        return;
+    }
+    println!(
+        "{:<8}  {:<9}  {:>6}  {:>10}  {:>10}  NAME",
+        "ID", "STATUS", "DONE", "SIZE", "SPEED"
+    );
+    for task in tasks {
+        let progress = if task.total_length > 0 {
+            format!(
+                "{:.1}%",
+                task.completed_length as f64 * 100.0 / task.total_length as f64
+            )
+        } else {
+            "-".to_string()
+        };
+        let speed = if task.status == TaskStatus::Active {
+            format!("{}/s", format_bytes(task.download_speed))
+        } else {
+            "-".to_string()
+        };
+        println!(
+            "{:<8}  {:<9}  {:>6}  {:>10}  {:>10}  {}",
+            task.id.chars().take(8).collect::<String>(),
+            task.status.as_str(),
+            progress,
+            format_bytes(task.total_length),
+            speed,
+            task.name.as_deref().unwrap_or(&task.source)
+        );
+    }
+}
+
+fn format_bytes(value: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = value.max(0) as f64;
+    let mut unit = 0usize;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size as i64, UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn is_torrent_file(source: &str) -> bool {
+    source.to_ascii_lowercase().ends_with(".torrent") && Path::new(source).is_file()
+}
+
+fn is_metalink_file(source: &str) -> bool {
+    let lower = source.to_ascii_lowercase();
+    (lower.ends_with(".meta4") || lower.ends_with(".metalink")) && Path::new(source).is_file()
+}
+
+fn resolve_task_id_prefix(tasks: &[Task], input: &str) -> Result<String> {
+    let needle = input.trim();
+    if needle.is_empty() {
+        return Err(anyhow!("empty task id"));
+    }
+    if let Some(task) = tasks.iter().find(|t| t.id == needle) {
+        return Ok(task.id.clone());
+    }
+    let matches = tasks
+        .iter()
+        .filter(|t| t.id.starts_with(needle))
+        .collect::<Vec<_>>();
+    match matches.len() {
+        0 => Err(anyhow!("task not found: {needle}")),
+        1 => Ok(matches[0].id.clone()),
+        n => Err(anyhow!(
+            "task id prefix `{needle}` is ambiguous ({n} matches)"
+        )),
+    }
+}
+
+enum CliBackend {
+    Local(BackendHandles),
+    Remote(LocalApiClient),
+}
+
+impl CliBackend {
+    async fn connect(args: &CliArgs, data_dir: &Path) -> Result<Self> {
+        let db_path = db_path_for(data_dir);
+        if args.mode != CliMode::Local {
+            let client = LocalApiClient::from_args(args, &db_path)?;
+            match args.mode {
+                CliMode::Remote => return Ok(Self::Remote(client)),
+                _ => {
+                    if client.is_reachable().await {
+                        return Ok(Self::Remote(client));
+                    }
+                }
+            }
+        }
+
+        std::fs::create_dir_all(data_dir.join("runtime"))?;
+        let emitter = Arc::new(NullEventEmitter) as SharedEmitter;
+        let handles = init_backend_with_options(
+            data_dir,
+            &db_path,
+            emitter,
+            BackendOptions {
+                autostart_aria2: false,
+                start_browser_bridge: false,
+            },
+        )
+        .await?;
+        Ok(Self::Local(handles))
+    }
+
+    async fn ensure_engine(&self) -> Result<()> {
+        let Self::Local(handles) = self else {
+            return Ok(());
+        };
+        if handles.engine.endpoint().await.is_some() {
+            return Ok(());
+        }
+        handles.engine.ensure_started().await.map_err(|e| {
+            match handles.aria2.external_config() {
+                Some(external) => anyhow!("aria2 unreachable at {}: {e}", external.rpc_url),
+                None => anyhow!(
+                    "aria2 failed to start from {}: {e}",
+                    handles.config.aria2_bin.display()
+                ),
+            }
+        })?;
+        let _ = handles.service.apply_saved_runtime_global_options().await;
+        let _ = handles.service.reconcile_with_aria2().await;
+        Ok(())
+    }
+
+    async fn shutdown(&self) {
+        let Self::Local(handles) = self else {
+            return;
+        };
+        let _ = handles.shutdown("cli exit").await;
+    }
+
+    async fn add_batch(
+        &self,
+        sources: Vec<String>,
+        options: AddTaskOptions,
+    ) -> Result<BatchAddResult> {
+        let request = BatchAddRequest {
+            urls: sources,
+            options,
+            ..Default::default()
+        };
+        match self {
+            Self::Local(handles) => handles.service.add_batch(request).await,
+            Self::Remote(client) => {
+                let response = client
+                    .post("/api/tasks/batch", &serde_json::to_value(&request)?)
+                    .await?;
+                let data = response.get("data").cloned().unwrap_or(Value::Null);
+                serde_json::from_value(data).map_err(|e| anyhow!("invalid batch result: {e}"))
+            }
+        }
+    }
+
+    async fn add(&self, source: &str, options: AddTaskOptions) -> Result<Vec<String>> {
+        let source = source.trim();
+        let file_path = Path::new(source);
+        let is_torrent_file = is_torrent_file(source);
+        let is_metalink_file = is_metalink_file(source);
+        match self {
+            Self::Local(handles) => {
+                let service = &handles.service;
+                if source.starts_with("magnet:?") {
+                    Ok(vec![service.add_magnet(source, options).await?])
+                } else if is_torrent_file {
+                    Ok(vec![service.add_torrent_from_file(source, options).await?])
+                } else if is_metalink_file {
+                    service.add_metalink_from_file(source, options).await
+                } else {
+                    Ok(vec![service.add_url(source, options).await?])
+                }
+            }
+            Self::Remote(client) => {
+                let mut body = json!({
+                    "save_dir": options.save_dir,
+                    "category": options.category,
+                    "out": options.out,
+                    "engine": options.engine,
+                    "referer": options.referer,
+                    "user_agent": options.user_agent,
+                    "headers": options.headers,
+                    "depends_on": options.depends_on,
+                    "on_dependency_failure": options.on_dependency_failure,
+                    "start_at": options.start_at,
+                    "window": options.window,
+                    "queue": options.queue,
+                    "collision_policy": options.collision_policy,
+                    "duplicate_policy": options.duplicate_policy,
+                    "url_expansion": options.url_expansion,
+                    "mirrors": options.mirrors,
+                    "select_file": options.select_file,
+                    "metadata_only": options.metadata_only,
+                });
+                if source.starts_with("magnet:?") {
+                    body["magnet"] = json!(source);
+                } else if is_torrent_file {
+                    let bytes = std::fs::read(file_path)
+                        .map_err(|e| anyhow!("read torrent file failed: {e}"))?;
+                    body["torrent_base64"] = json!(STANDARD.encode(bytes));
+                } else if is_metalink_file {
+                    let bytes = std::fs::read(file_path)
+                        .map_err(|e| anyhow!("read metalink file failed: {e}"))?;
+                    body["metalink_base64"] = json!(STANDARD.encode(bytes));
+                } else {
+                    body["url"] = json!(source);
+                }
+                let response = client.post("/api/tasks", &body).await?;
+                let task_id = response
+                    .get("task_id")
+                    .and_then(Value::as_str)
+                    .map(ToString::to_string)
+                    .ok_or_else(|| anyhow!("local api response missing task_id"))?;
+                if response.get("action").and_then(Value::as_str) == Some("skipped") {
+                    return Err(AppError::DuplicateTask(task_id).into());
+                }
+                let task_ids: Vec<String> = response
+                    .get("task_ids")
+                    .and_then(Value::as_array)
+                    .map(|ids| {
+                        ids.iter()
+                            .filter_map(Value::as_str)
+                            .map(ToString::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if task_ids.is_empty() {
+                    Ok(vec![task_id])
+                } else {
+                    Ok(task_ids)
+                }
+            }
+        }
+    }
+
+    async fn list(&self, status: Option<TaskStatus>, limit: u32) -> Result<Vec<Task>> {
+        match self {
+            Self::Local(handles) => handles.service.list_tasks(status, limit, 0),
+            Self::Remote(client) => {
+                let mut path = format!("/api/tasks?limit={limit}");
+                if let Some(status) = status {
+                    path.push_str(&format!("&status={}", status.as_str()));
+                }
+                let response = client.get(&path).await?;
+                let data = response.get("data").cloned().unwrap_or(Value::Null);
+                serde_json::from_value(data).map_err(|e| anyhow!("invalid task list: {e}"))
+            }
+        }
+    }
+
+    async fn resolve_task_ids(&self, inputs: &[String]) -> Result<Vec<String>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let tasks = self.list(None, 5000).await?;
+        inputs
+            .iter()
+            .map(|input| resolve_task_id_prefix(&tasks, input))
+            .collect()
+    }
+
+    async fn retry(&self, task_id: &str) -> Result<()> {
+        match self {
+            Self::Local(handles) => handles.service.retry_task(task_id).await,
+            Self::Remote(client) => {
+                client
+                    .post(
+                        &format!("/api/tasks/{task_id}/actions"),
+                        &json!({ "action": "retry" }),
+                    )
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn bulk_action(
+        &self,
+        task_ids: &[String],
+        action: &str,
+        delete_files: bool,
+    ) -> Result<BulkTaskActionResult> {
+        match self {
+            Self::Local(handles) => {
+                let service = &handles.service;
+                match action {
+                    "pause" => service.pause_tasks(task_ids).await,
+                    "resume" => service.resume_tasks(task_ids).await,
+                    "remove" => service.remove_tasks(task_ids, delete_files).await,
+                    _ => Err(anyhow!("unsupported action: {action}")),
+                }
+            }
+            Self::Remote(client) => {
+                let response = client
+                    .post(
+                        "/api/tasks/actions",
+                        &json!({
+                            "task_ids": task_ids,
+                            "action": action,
+                            "delete_files": delete_files,
+                        }),
+                    )
+                    .await?;
+                let data = response.get("data").cloned().unwrap_or(Value::Null);
+                serde_json::from_value(data).map_err(|e| anyhow!("invalid bulk result: {e}"))
+            }
+        }
+    }
+
+    async fn settings(&self) -> Result<Value> {
+        match self {
+            Self::Local(handles) => Ok(serde_json::to_value(
+                handles.service.get_global_settings()?,
+            )?),
+            Self::Remote(client) => Ok(client
+                .get("/api/settings")
+                .await?
+                .get("data")
+                .cloned()
+                .unwrap_or(Value::Null)),
+        }
+    }
+
+    async fn patch_settings(&self, patch: Value) -> Result<Value> {
+        match self {
+            Self::Local(handles) => Ok(serde_json::to_value(
+                handles.service.patch_global_settings(patch).await?,
+            )?),
+            Self::Remote(client) => Ok(client
+                .post("/api/settings", &patch)
+                .await?
+                .get("data")
+                .cloned()
+                .unwrap_or(Value::Null)),
+        }
+    }
+
+    async fn export(&self) -> Result<String> {
+        match self {
+            Self::Local(handles) => handles.service.export_task_list_json(),
+            Self::Remote(client) => {
+                let data = client
+                    .get("/api/export")
+                    .await?
+                    .get("data")
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                Ok(serde_json::to_string_pretty(&data)?)
+            }
+        }
+    }
+
+    async fn import(&self, payload: String) -> Result<Value> {
+        match self {
+            Self::Local(handles) => Ok(serde_json::to_value(
+                handles.service.import_task_list_json(&payload)?,
+            )?),
+            Self::Remote(client) => {
+                let snapshot: Value = serde_json::from_str(&payload)
+                    .map_err(|e| anyhow!("invalid snapshot json: {e}"))?;
+                Ok(client
+                    .post("/api/import", &snapshot)
+                    .await?
+                    .get("data")
+                    .cloned()
+                    .unwrap_or(Value::Null))
+            }
+        }
+    }
+}
+
+struct LocalApiClient {
+    base_url: String,
+    token: String,
+    http: Client,
+}
+
+impl LocalApiClient {
+    fn from_args(args: &CliArgs, db_path: &Path) -> Result<Self> {
+        let saved = if db_path.exists() {
+            Database::new(db_path).ok()
+        } else {
+            None
+        };
+        let saved_setting = |key: &str| {
+            saved
+                .as_ref()
+                .and_then(|db| db.get_setting(key).ok().flatten())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let port = args
+            .port
+            .or_else(|| {
+                std::env::var("FLAMINGO_PORT")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+            })
+            .or_else(|| saved_setting("browser_bridge_port").and_then(|v| v.parse().ok()))
+            .unwrap_or(DEFAULT_API_PORT);
+        let token = args
+            .token
+            .clone()
+            .or_else(|| {
+                std::env::var("FLAMINGO_TOKEN")
+                    .ok()
+                    .filter(|v| !v.trim().is_empty())
+            })
+            .or_else(|| saved_setting("browser_bridge_token"))
+            .unwrap_or_default();
+        let http = Client::builder()
+            .connect_timeout(Duration::from_secs(2))
+            .timeout(Duration::from_secs(60))
+            .build()?;
+        Ok(Self {
+            base_url: format!("http://127.0.0.1:{port}"),
+            token,
+            http,
+        })
+    }
+
+    async fn is_reachable(&self) -> bool {
+        if self.token.is_empty() {
+            return false;
+        }
+        let request = self
+            .http
+            .get(format!("{}/api/health", self.base_url))
+            .header("X-Token", &self.token)
+            .timeout(Duration::from_millis(800));
+        matches!(request.send().await, Ok(resp) if resp.status().is_success())
+    }
+
+    async fn get(&self, path: &str) -> Result<Value> {
+        let request = self.http.get(format!("{}{path}", self.base_url));
+        self.send(request).await
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let request = self
+            .http
+            .post(format!("{}{path}", self.base_url))
+            .json(body);
+        self.send(request).await
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value> {
+        if self.token.is_empty() {
+            return Err(anyhow!(
+                "local api token unknown: pass --token or set FLAMINGO_TOKEN"
+            ));
+        }
+        let response = request
+            .header("X-Token", &self.token)
+            .send()
+            .await
+            .map_err(|e| anyhow!("local api request failed: {e}"))?;
+        let status = response.status();
+        let payload: Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("local api returned an invalid response ({status}): {e}"))?;
+        if payload.get("ok").and_then(Value::as_bool) != Some(true) {
+            let error = payload
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or("request_failed");
+            return match payload.get("detail").and_then(Value::as_str) {
+                Some(detail) => Err(anyhow!("local api error: {error}: {detail}")),
+                None => Err(anyhow!("local api error: {error}")),
+            };
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Task, TaskStatus, TaskType, TaskWindow};
+
+    use super::{
+        CliCommand, CliMode, coerce_setting_value, format_bytes, parse_args, resolve_task_id_prefix,
+    };
+
+    fn args(items: &[&str]) -> Vec<String> {
+        items.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            aria2_gid: None,
+            engine_id: None,
+            task_type: TaskType::Http,
+            source: "https://example.com/a.bin".to_string(),
+            status: TaskStatus::Queued,
+            name: None,
+            category: None,
+            save_dir: "/tmp".to_string(),
+            total_length: 0,
+            completed_length: 0,
+            download_speed: 0,
+            upload_speed: 0,
+            connections: 0,
+            health: None,
+            error_code: None,
+            error_message: None,
+            remediation: None,
+            retry_count: 0,
+            last_retry_at: None,
+            checksum_algorithm: None,
+            checksum_expected: None,
+            checksum_actual: None,
+            checksum_status: None,
+            created_at: 0,
+            updated_at: 0,
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
+        }
+    }
+
+    #[test]
+    fn parse_add_collects_sources_and_options() {
+        let parsed = parse_args(args(&[
+            "--remote",
+            "--json",
+            "add",
+            "https://example.com/a.iso",
+            "--dir",
+            "/tmp/isos",
+            "--header",
+            "Cookie: a=b",
+            "magnet:?xt=urn:btih:abc",
+            "--split",
+            "4",
+            "--after",
+            "abc",
+            "--on-dep-failure",
+            "wait",
+            "--start-at",
+            "2026-01-02T03:04:05Z",
+            "--window",
+            "01:00-07:00",
+            "--days",
+            "1,2,3,4,5",
+            "--queue",
+            "bulk",
+            "--collision",
+            "skip",
+            "--duplicate",
+            "open",
+            "--expand",
+            "mirrors",
+            "--mirror",
+            "https://mirror.example.com/a.iso",
+        ]))
+        .expect("parse add");
+        assert_eq!(parsed.mode, CliMode::Remote);
+        assert!(parsed.json);
+        let CliCommand::Add {
+            sources,
+            options,
+            wait,
+        } = parsed.command
+        else {
+            panic!("expected add command");
+        };
+        assert_eq!(sources.len(), 2);
+        assert_eq!(options.save_dir.as_deref(), Some("/tmp/isos"));
+        assert_eq!(options.headers, vec!["Cookie: a=b".to_string()]);
+        assert_eq!(options.split, Some(4));
+        assert_eq!(options.depends_on, vec!["abc".to_string()]);
+        assert_eq!(options.on_dependency_failure.as_deref(), Some("wait"));
+        assert_eq!(options.start_at, Some(1_767_323_045));
+        assert_eq!(
+            options.window,
+            Some(TaskWindow {
+                days: Some("1,2,3,4,5".to_string()),
+                start: "01:00".to_string(),
+                end: "07:00".to_string(),
+            })
+        );
+        assert_eq!(options.queue.as_deref(), Some("bulk"));
+        assert_eq!(options.collision_policy.as_deref(), Some("skip"));
+        assert_eq!(options.duplicate_policy.as_deref(), Some("open"));
+        assert_eq!(options.url_expansion.as_deref(), Some("mirrors"));
+        assert_eq!(
+            options.mirrors,
+            vec!["https://mirror.example.com/a.iso".to_string()]
+        );
+        assert!(!wait);
+    }
+
+    #[test]
+    fn global_options_stop_at_the_command() {
+        let parsed = parse_args(args(&["--local", "settings", "set", "ui_theme", "--json"]))
+            .expect("parse settings set");
+        assert_eq!(parsed.mode, CliMode::Local);
+        assert!(!parsed.json);
+        assert!(matches!(
+            parsed.command,
+            CliCommand::SettingsSet { ref key, ref value } if key == "ui_theme" && value == "--json"
+        ));
+
+        let parsed = parse_args(args(&["--json", "--", "ls"])).expect("parse after --");
+        assert!(parsed.json);
+        assert!(matches!(parsed.command, CliCommand::List { .. }));
+    }
+
+    #[test]
+    fn parse_rejects_missing_values_and_unknown_commands() {
+        assert!(parse_args(args(&["add"])).is_err());
+        assert!(parse_args(args(&["ls", "--status", "bogus"])).is_err());
+        assert!(parse_args(args(&["settings", "set", "ui_theme"])).is_err());
+        assert!(parse_args(args(&["frobnicate"])).is_err());
+        assert!(parse_args(args(&["add", "https://example.com/a.iso", "--json"])).is_err());
+        assert!(matches!(
+            parse_args(Vec::<String>::new())
+                .expect("empty args")
+                .command,
+            CliCommand::Help
+        ));
+    }
+
+    #[test]
+    fn task_id_prefix_must_be_unique() {
+        let tasks = vec![task("abc123"), task("abd456")];
+        assert_eq!(
+            resolve_task_id_prefix(&tasks, "abc").expect("unique prefix"),
+            "abc123"
+        );
+        assert!(resolve_task_id_prefix(&tasks, "ab").is_err());
+        assert!(resolve_task_id_prefix(&tasks, "zzz").is_err());
+    }
+
+    #[test]
+    fn setting_values_fall_back_to_strings() {
+        assert_eq!(coerce_setting_value("5"), serde_json::json!(5));
+        assert_eq!(coerce_setting_value("true"), serde_json::json!(true));
+        assert_eq!(coerce_setting_value("2M"), serde_json::json!("2M"));
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+    }
+}
@@ -5,18 +5,27 @@ use anyhow::Result;
 use crate::{
     download_service::DownloadService,
     models::{
-        AddTaskOptions, GlobalSettings, ImportTaskListResult, OperationLog, Task, TaskFile,
-        TaskStatus,
+        AddTaskOptions, AddTaskOutcome, BandwidthHistoryQuery, BandwidthSample, BatchAddRequest,
+        BatchAddResult, BulkTaskActionResult, GlobalSettings, ImportTaskListResult, OperationLog,
+        Task, TaskFile, TaskMirror, TaskQueueStatus, TaskSeeding, TaskStatus, TaskWindow,
+        TorrentInfo, TrackerListStatus,
     },
 };
 
+/// Tasks added from the app count as `ui` for `source` rules unless the UI says
+/// otherwise, e.g. `clipboard`.
+pub fn from_ui(mut options: AddTaskOptions) -> AddTaskOptions {
+    options.origin.get_or_insert_with(|| "ui".to_string());
+    options
+}
+
 #[allow(dead_code)]
 pub async fn add_url(
     service: Arc<DownloadService>,
     url: String,
     options: AddTaskOptions,
-) -> Result<String> {
-    service.add_url(&url, options).await
+) -> Result<AddTaskOutcome> {
+    service.add_url_outcome(&url, from_ui(options)).await
 }
 
 #[allow(dead_code)]
@@ -24,8 +33,22 @@ pub async fn add_magnet(
     service: Arc<DownloadService>,
     magnet: String,
     options: AddTaskOptions,
-) -> Result<String> {
-    service.add_magnet(&magnet, options).await
+) -> Result<AddTaskOutcome> {
+    service.add_magnet_outcome(&magnet, from_ui(options)).await
+}
+
+#[allow(dead_code)]
+pub async fn add_batch(
+    service: Arc<DownloadService>,
+    mut request: BatchAddRequest,
+) -> Result<BatchAddResult> {
+    request.options = from_ui(request.options);
+    service.add_batch(request).await
+}
+
+#[allow(dead_code)]
+pub fn preview_url_pattern(service: Arc<DownloadService>, url: String) -> Result<Vec<String>> {
+    service.preview_url_pattern(&url)
 }
 
 #[allow(dead_code)]
@@ -35,6 +58,7 @@ pub async fn add_torrent(
     torrent_base64: Option<String>,
     options: AddTaskOptions,
 ) -> Result<String> {
+    let options = from_ui(options);
     if let Some(path) = torrent_file_path {
         return service.add_torrent_from_file(&path, options).await;
     }
@@ -44,6 +68,94 @@ pub async fn add_torrent(
     anyhow::bail!("missing torrent input")
 }
 
+#[allow(dead_code)]
+pub async fn inspect_torrent(
+    service: Arc<DownloadService>,
+    torrent_file_path: Option<String>,
+    torrent_base64: Option<String>,
+) -> Result<TorrentInfo> {
+    if let Some(path) = torrent_file_path {
+        return service.inspect_torrent_from_file(&path).await;
+    }
+    if let Some(base64) = torrent_base64 {
+        return service.inspect_torrent_base64(&base64);
+    }
+    anyhow::bail!("missing torrent input")
+}
+
+#[allow(dead_code)]
+pub fn get_task_torrent(service: Arc<DownloadService>, task_id: String) -> Result<Option<String>> {
+    service.get_task_torrent(&task_id)
+}
+
+#[allow(dead_code)]
+pub async fn add_metalink(
+    service: Arc<DownloadService>,
+    metalink_file_path: Option<String>,
+    metalink_base64: Option<String>,
+    options: AddTaskOptions,
+) -> Result<Vec<String>> {
+    let options = from_ui(options);
+    if let Some(path) = metalink_file_path {
+        return service.add_metalink_from_file(&path, options).await;
+    }
+    if let Some(base64) = metalink_base64 {
+        return service.add_metalink_base64(base64, options, None).await;
+    }
+    anyhow::bail!("missing metalink input")
+}
+
+#[allow(dead_code)]
+pub fn list_task_mirrors(
+    service: Arc<DownloadService>,
+    task_id: String,
+) -> Result<Vec<TaskMirror>> {
+    service.list_task_mirrors(&task_id)
+}
+
+#[allow(dead_code)]
+pub fn get_bandwidth_history(
+    service: Arc<DownloadService>,
+    query: BandwidthHistoryQuery,
+) -> Result<Vec<BandwidthSample>> {
+    service.get_bandwidth_history(query)
+}
+
+#[allow(dead_code)]
+pub fn list_task_seeding(service: Arc<DownloadService>) -> Result<Vec<TaskSeeding>> {
+    service.list_task_seeding()
+}
+
+#[allow(dead_code)]
+pub fn list_tracker_lists(service: Arc<DownloadService>) -> Result<Vec<TrackerListStatus>> {
+    service.list_tracker_lists()
+}
+
+#[allow(dead_code)]
+pub async fn refresh_tracker_lists(
+    service: Arc<DownloadService>,
+) -> Result<Vec<TrackerListStatus>> {
+    service.refresh_tracker_lists().await
+}
+
+#[allow(dead_code)]
+pub async fn add_task_trackers(
+    service: Arc<DownloadService>,
+    task_id: String,
+    trackers: Vec<String>,
+) -> Result<()> {
+    service.add_task_trackers(&task_id, &trackers).await
+}
+
+#[allow(dead_code)]
+pub async fn remove_task_trackers(
+    service: Arc<DownloadService>,
+    task_id: String,
+    trackers: Vec<String>,
+) -> Result<()> {
+    service.remove_task_trackers(&task_id, &trackers).await
+}
+
 #[allow(dead_code)]
 pub async fn pause_task(service: Arc<DownloadService>, task_id: String) -> Result<()> {
     service.pause_task(&task_id).await
@@ -78,6 +190,31 @@ pub async fn remove_task(
     service.remove_task(&task_id, delete_files).await
 }
 
+#[allow(dead_code)]
+pub async fn pause_tasks(
+    service: Arc<DownloadService>,
+    task_ids: Vec<String>,
+) -> Result<BulkTaskActionResult> {
+    service.pause_tasks(&task_ids).await
+}
+
+#[allow(dead_code)]
+pub async fn resume_tasks(
+    service: Arc<DownloadService>,
+    task_ids: Vec<String>,
+) -> Result<BulkTaskActionResult> {
+    service.resume_tasks(&task_ids).await
+}
+
+#[allow(dead_code)]
+pub async fn remove_tasks(
+    service: Arc<DownloadService>,
+    task_ids: Vec<String>,
+    delete_files: bool,
+) -> Result<BulkTaskActionResult> {
+    service.remove_tasks(&task_ids, delete_files).await
+}
+
 #[allow(dead_code)]
 pub fn list_tasks(
     service: Arc<DownloadService>,
@@ -97,6 +234,21 @@ pub fn set_task_category(
     service.set_task_category(&task_id, category.as_deref())
 }
 
+#[allow(dead_code)]
+pub async fn set_task_schedule(
+    service: Arc<DownloadService>,
+    task_id: String,
+    start_at: Option<i64>,
+    window: Option<TaskWindow>,
+) -> Result<()> {
+    service.set_task_schedule(&task_id, start_at, window).await
+}
+
+#[allow(dead_code)]
+pub fn list_task_queues(service: Arc<DownloadService>) -> Result<Vec<TaskQueueStatus>> {
+    service.list_task_queues()
+}
+
 #[allow(dead_code)]
 pub async fn get_task_detail(
     service: Arc<DownloadService>,
@@ -148,8 +300,9 @@ pub fn suggest_save_dir_detail(
     service: Arc<DownloadService>,
     task_type: crate::models::TaskType,
     source: Option<String>,
+    out: Option<String>,
 ) -> Result<crate::models::SaveDirSuggestion> {
-    service.suggest_save_dir_detail(task_type, source.as_deref())
+    service.suggest_save_dir_detail(task_type, source.as_deref(), out.as_deref())
 }
 
 #[allow(dead_code)]
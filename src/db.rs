@@ -1,4 +1,5 @@
 use std::{
+    collections::HashSet,
     path::Path,
     path::PathBuf,
     sync::{Arc, Mutex},
@@ -9,17 +10,23 @@ use rusqlite::{Connection, OptionalExtension, params};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 
+use crate::link_parser::source_dedupe_key;
 use crate::models::{
-    Aria2TaskSnapshot, CategoryRule, DownloadDirRule, GlobalSettings, MediaMergeJob, Task,
-    TaskFile, TaskHealth, TaskStatus, TaskType,
+    Aria2TaskSnapshot, BandwidthSample, CategoryRule, DEFAULT_ENGINE_ID, DownloadDirRule,
+    DuplicateMatch, EngineProfile, GlobalSettings, MediaMergeJob, PendingSubmission, PolicyRule,
+    RuleCondition, SeedingRule, Task, TaskFile, TaskHealth, TaskMirror, TaskQueue, TaskSeeding,
+    TaskStatus, TaskType, TrackerHealth, TrackerListStatus,
 };
+use crate::native_engine::parse_size_range;
+use crate::path_template::validate_template;
 
 pub struct Database {
     conn: Arc<Mutex<Connection>>,
     db_path: PathBuf,
 }
 
-const SCHEMA_VERSION: i64 = 8;
+const SCHEMA_VERSION: i64 = 20;
+const HISTORY_RESOLUTIONS: [(&str, i64); 3] = [("minute", 60), ("hour", 3600), ("day", 86_400)];
 
 #[derive(Debug, serde::Deserialize)]
 struct StoredSpeedPlanRule {
@@ -123,10 +130,12 @@ impl Database {
                 total_length, completed_length, download_speed, upload_speed,
                 connections, health, error_code, error_message, remediation,
                 retry_count, last_retry_at, checksum_algorithm, checksum_expected,
-                checksum_actual, checksum_status, created_at, updated_at
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)
+                checksum_actual, checksum_status, created_at, updated_at, engine_id,
+                start_at, schedule_window, schedule_held, queue, queue_held, dedupe_key
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)
             ON CONFLICT(id) DO UPDATE SET
               aria2_gid=excluded.aria2_gid,
+              engine_id=excluded.engine_id,
               type=excluded.type,
               source=excluded.source,
               status=excluded.status,
@@ -148,7 +157,13 @@ impl Database {
               checksum_expected=excluded.checksum_expected,
               checksum_actual=excluded.checksum_actual,
               checksum_status=excluded.checksum_status,
-              updated_at=excluded.updated_at
+              updated_at=excluded.updated_at,
+              start_at=excluded.start_at,
+              schedule_window=excluded.schedule_window,
+              schedule_held=excluded.schedule_held,
+              queue=excluded.queue,
+              queue_held=excluded.queue_held,
+              dedupe_key=excluded.dedupe_key
             "#,
             params![
                 task.id,
@@ -176,11 +191,90 @@ impl Database {
                 task.checksum_status,
                 task.created_at,
                 task.updated_at,
+                task.engine_id,
+                task.start_at,
+                task.window
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                task.schedule_held,
+                task.queue,
+                task.queue_held,
+                source_dedupe_key(&task.source),
             ],
         )?;
         Ok(())
     }
 
+    pub fn set_task_final_url(&self, task_id: &str, final_url: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "UPDATE tasks SET final_url = ?2 WHERE id = ?1",
+            params![task_id, source_dedupe_key(final_url)],
+        )?;
+        Ok(())
+    }
+
+    /// Finds the newest task that fetched the same source, ended up at the same
+    /// final URL, or produced a file with the same name and size.
+    pub fn find_duplicate_task(
+        &self,
+        source: &str,
+        final_url: Option<&str>,
+        name_size: Option<(&str, i64)>,
+    ) -> Result<Option<DuplicateMatch>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let lookup = |sql: &str, params: &[&dyn rusqlite::ToSql], matched_by: &str| {
+            conn.query_row(
+                &format!(
+                    "SELECT id, status, name, save_dir FROM tasks WHERE {sql} \
+                     ORDER BY created_at DESC, rowid DESC LIMIT 1"
+                ),
+                params,
+                |row| {
+                    Ok(DuplicateMatch {
+                        task_id: row.get(0)?,
+                        matched_by: matched_by.to_string(),
+                        status: parse_task_status(&row.get::<_, String>(1)?)?,
+                        name: row.get(2)?,
+                        save_dir: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+        };
+        let key = source_dedupe_key(source);
+        if let Some(key) = &key {
+            let matched_by = if key.starts_with("magnet:") {
+                "infohash"
+            } else {
+                "url"
+            };
+            if let Some(found) = lookup("dedupe_key = ?1", &[key], matched_by)? {
+                return Ok(Some(found));
+            }
+        }
+        let final_key = final_url.and_then(source_dedupe_key);
+        for candidate in [&final_key, &key].into_iter().flatten() {
+            if let Some(found) = lookup(
+                "final_url = ?1 OR dedupe_key = ?1",
+                &[candidate],
+                "final_url",
+            )? {
+                return Ok(Some(found));
+            }
+        }
+        if let Some((name, size)) = name_size.filter(|(name, size)| !name.is_empty() && *size > 0) {
+            return lookup(
+                "name = ?1 AND total_length = ?2",
+                &[&name, &size],
+                "name_size",
+            )
+            .map_err(Into::into);
+        }
+        Ok(None)
+    }
+
     pub fn list_tasks(
         &self,
         status: Option<TaskStatus>,
@@ -193,7 +287,9 @@ impl Database {
                 r#"SELECT id, aria2_gid, type, source, status, name, save_dir, category, total_length,
                    completed_length, download_speed, upload_speed, connections, health, error_code,
                    error_message, remediation, retry_count, last_retry_at, checksum_algorithm,
-                   checksum_expected, checksum_actual, checksum_status, created_at, updated_at
+                   checksum_expected, checksum_actual, checksum_status, created_at, updated_at, engine_id,
+                   (SELECT group_concat(depends_on) FROM task_dependencies d WHERE d.task_id = tasks.id),
+                   start_at, schedule_window, schedule_held, queue, queue_held
                    FROM tasks WHERE status = ?1 ORDER BY created_at DESC LIMIT ?2 OFFSET ?3"#,
             )?;
             stmt.query_map(params![status.as_str(), limit, offset], row_to_task)?
@@ -203,7 +299,9 @@ impl Database {
                 r#"SELECT id, aria2_gid, type, source, status, name, save_dir, category, total_length,
                    completed_length, download_speed, upload_speed, connections, health, error_code,
                    error_message, remediation, retry_count, last_retry_at, checksum_algorithm,
-                   checksum_expected, checksum_actual, checksum_status, created_at, updated_at
+                   checksum_expected, checksum_actual, checksum_status, created_at, updated_at, engine_id,
+                   (SELECT group_concat(depends_on) FROM task_dependencies d WHERE d.task_id = tasks.id),
+                   start_at, schedule_window, schedule_held, queue, queue_held
                    FROM tasks ORDER BY created_at DESC LIMIT ?1 OFFSET ?2"#,
             )?;
             stmt.query_map(params![limit, offset], row_to_task)?
@@ -219,7 +317,9 @@ impl Database {
             r#"SELECT id, aria2_gid, type, source, status, name, save_dir, category, total_length,
                completed_length, download_speed, upload_speed, connections, health, error_code,
                error_message, remediation, retry_count, last_retry_at, checksum_algorithm,
-               checksum_expected, checksum_actual, checksum_status, created_at, updated_at
+               checksum_expected, checksum_actual, checksum_status, created_at, updated_at, engine_id,
+               (SELECT group_concat(depends_on) FROM task_dependencies d WHERE d.task_id = tasks.id),
+               start_at, schedule_window, schedule_held, queue, queue_held
                FROM tasks WHERE id = ?1"#,
             params![task_id],
             row_to_task,
@@ -228,15 +328,18 @@ impl Database {
         .map_err(Into::into)
     }
 
-    pub fn get_task_by_gid(&self, gid: &str) -> Result<Option<Task>> {
+    /// Looks a gid up within one engine; `None` is the primary engine.
+    pub fn get_task_by_gid(&self, engine_id: Option<&str>, gid: &str) -> Result<Option<Task>> {
         let conn = self.conn.lock().expect("db mutex poisoned");
         conn.query_row(
             r#"SELECT id, aria2_gid, type, source, status, name, save_dir, category, total_length,
                completed_length, download_speed, upload_speed, connections, health, error_code,
                error_message, remediation, retry_count, last_retry_at, checksum_algorithm,
-               checksum_expected, checksum_actual, checksum_status, created_at, updated_at
-               FROM tasks WHERE aria2_gid = ?1"#,
-            params![gid],
+               checksum_expected, checksum_actual, checksum_status, created_at, updated_at, engine_id,
+               (SELECT group_concat(depends_on) FROM task_dependencies d WHERE d.task_id = tasks.id),
+               start_at, schedule_window, schedule_held, queue, queue_held
+               FROM tasks WHERE aria2_gid = ?1 AND IFNULL(engine_id, '') = IFNULL(?2, '')"#,
+            params![gid, engine_id],
             row_to_task,
         )
         .optional()
@@ -245,12 +348,13 @@ impl Database {
 
     pub fn update_from_snapshots(
         &self,
+        engine_id: Option<&str>,
         snapshots: &[Aria2TaskSnapshot],
         now_ts: i64,
     ) -> Result<Vec<Task>> {
         let mut changed = Vec::new();
         for snapshot in snapshots {
-            if let Some(mut task) = self.get_task_by_gid(&snapshot.gid)? {
+            if let Some(mut task) = self.get_task_by_gid(engine_id, &snapshot.gid)? {
                 task.status = TaskStatus::from_aria2_status(
                     &snapshot.status,
                     snapshot.has_metadata,
@@ -259,6 +363,7 @@ impl Database {
                 if task.checksum_status.as_deref() == Some("mismatch") {
                     task.status = TaskStatus::Error;
                 }
+                let downloaded = (snapshot.completed_length - task.completed_length).max(0);
                 task.total_length = snapshot.total_length;
                 task.completed_length = snapshot.completed_length;
                 task.download_speed = snapshot.download_speed;
@@ -270,6 +375,20 @@ impl Database {
                 }
                 task.updated_at = now_ts;
                 self.upsert_task(&task)?;
+                let mut uploaded = 0;
+                if matches!(task.task_type, TaskType::Torrent | TaskType::Magnet) {
+                    let seeding = snapshot.status == "active"
+                        && snapshot.total_length > 0
+                        && snapshot.completed_length >= snapshot.total_length;
+                    uploaded = self.record_task_upload(
+                        &task.id,
+                        &snapshot.gid,
+                        snapshot.upload_length,
+                        seeding,
+                        now_ts,
+                    )?;
+                }
+                self.record_transfer(&task, downloaded, uploaded, now_ts)?;
                 changed.push(task);
             }
         }
@@ -365,6 +484,15 @@ impl Database {
         if let Some(v) = &settings.aria2_bin_path {
             set("manual_aria2_bin_path", v)?;
         }
+        if let Some(v) = &settings.engine_mode {
+            set("engine_mode", v)?;
+        }
+        if let Some(v) = &settings.aria2_rpc_url {
+            set("aria2_rpc_url", v)?;
+        }
+        if let Some(v) = &settings.aria2_rpc_secret {
+            set("aria2_rpc_secret", v)?;
+        }
         if let Some(v) = &settings.download_dir {
             set("download_dir", v)?;
         }
@@ -434,6 +562,39 @@ impl Database {
         if let Some(v) = &settings.task_option_presets {
             set("task_option_presets", v)?;
         }
+        if let Some(v) = &settings.engine_profiles {
+            set("engine_profiles", v)?;
+        }
+        if let Some(v) = &settings.task_queues {
+            set("task_queues", v)?;
+        }
+        if let Some(v) = &settings.collision_policy {
+            set("collision_policy", v)?;
+        }
+        if let Some(v) = &settings.duplicate_policy {
+            set("duplicate_policy", v)?;
+        }
+        if let Some(v) = &settings.tracker_lists {
+            set("tracker_lists", v)?;
+        }
+        if let Some(v) = settings.tracker_refresh_hours {
+            set("tracker_refresh_hours", &v.to_string())?;
+        }
+        if let Some(v) = &settings.seeding_rules {
+            set("seeding_rules", v)?;
+        }
+        if let Some(v) = settings.max_active_seeds {
+            set("max_active_seeds", &v.to_string())?;
+        }
+        if let Some(v) = settings.transfer_history_days {
+            set("transfer_history_days", &v.to_string())?;
+        }
+        if let Some(v) = settings.pause_seeding_in_speed_plan {
+            set(
+                "pause_seeding_in_speed_plan",
+                if v { "true" } else { "false" },
+            )?;
+        }
         if let Some(v) = &settings.post_complete_action {
             set("post_complete_action", v)?;
         }
@@ -473,6 +634,9 @@ impl Database {
         let category_rules_json =
             serde_json::to_string(&settings.category_rules).context("serialize category_rules")?;
         set("category_rules", &category_rules_json)?;
+        let policy_rules_json =
+            serde_json::to_string(&settings.policy_rules).context("serialize policy_rules")?;
+        set("policy_rules", &policy_rules_json)?;
         validate_runtime_settings_with_conn(
             &tx,
             &[
@@ -494,8 +658,15 @@ impl Database {
             .get_setting("category_rules")?
             .and_then(|v| serde_json::from_str::<Vec<CategoryRule>>(&v).ok())
             .unwrap_or_default();
+        let policy_rules = self
+            .get_setting("policy_rules")?
+            .and_then(|v| serde_json::from_str::<Vec<PolicyRule>>(&v).ok())
+            .unwrap_or_default();
         Ok(GlobalSettings {
             aria2_bin_path: self.get_setting("manual_aria2_bin_path")?,
+            engine_mode: self.get_setting("engine_mode")?,
+            aria2_rpc_url: self.get_setting("aria2_rpc_url")?,
+            aria2_rpc_secret: self.get_setting("aria2_rpc_secret")?,
             download_dir: self.get_setting("download_dir")?,
             max_concurrent_downloads: self
                 .get_setting("max_concurrent_downloads")?
@@ -516,6 +687,7 @@ impl Database {
             github_token: self.get_setting("github_token")?,
             download_dir_rules: rules,
             category_rules,
+            policy_rules,
             browser_bridge_enabled: self.get_setting("browser_bridge_enabled")?.and_then(
                 |v| match v.as_str() {
                     "true" => Some(true),
@@ -557,6 +729,28 @@ impl Database {
                 .and_then(|v| v.parse::<u32>().ok()),
             speed_plan: self.get_setting("speed_plan")?,
             task_option_presets: self.get_setting("task_option_presets")?,
+            engine_profiles: self.get_setting("engine_profiles")?,
+            task_queues: self.get_setting("task_queues")?,
+            collision_policy: self.get_setting("collision_policy")?,
+            duplicate_policy: self.get_setting("duplicate_policy")?,
+            tracker_lists: self.get_setting("tracker_lists")?,
+            tracker_refresh_hours: self
+                .get_setting("tracker_refresh_hours")?
+                .and_then(|v| v.parse::<u32>().ok()),
+            seeding_rules: self.get_setting("seeding_rules")?,
+            max_active_seeds: self
+                .get_setting("max_active_seeds")?
+                .and_then(|v| v.parse::<u32>().ok()),
+            transfer_history_days: self
+                .get_setting("transfer_history_days")?
+                .and_then(|v| v.parse::<u32>().ok()),
+            pause_seeding_in_speed_plan: self.get_setting("pause_seeding_in_speed_plan")?.and_then(
+                |v| match v.as_str() {
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    _ => None,
+                },
+            ),
             post_complete_action: self.get_setting("post_complete_action")?,
             completion_webhook_url: self.get_setting("completion_webhook_url")?,
             completion_command: self.get_setting("completion_command")?,
@@ -618,6 +812,40 @@ impl Database {
             "DELETE FROM media_merge_jobs WHERE task_id = ?1",
             params![task_id],
         )?;
+        conn.execute(
+            "DELETE FROM task_dependencies WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        conn.execute(
+            "DELETE FROM pending_submissions WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        conn.execute(
+            "DELETE FROM task_mirrors WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        conn.execute(
+            "DELETE FROM task_file_stages WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        conn.execute(
+            "DELETE FROM magnet_prefetches WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        conn.execute(
+            "DELETE FROM task_trackers WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        conn.execute(
+            "DELETE FROM task_seeding WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        // Dependents of a finished task must not read its removal as a failure.
+        conn.execute(
+            "UPDATE task_dependencies SET satisfied = 1
+             WHERE depends_on = ?1 AND EXISTS (SELECT 1 FROM tasks WHERE id = ?1 AND status = 'completed')",
+            params![task_id],
+        )?;
         conn.execute("DELETE FROM tasks WHERE id = ?1", params![task_id])?;
         Ok(())
     }
@@ -672,6 +900,30 @@ impl Database {
             "DELETE FROM task_files WHERE task_id IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
             params![cutoff_ts],
         )?;
+        tx.execute(
+            "DELETE FROM task_mirrors WHERE task_id IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
+            params![cutoff_ts],
+        )?;
+        tx.execute(
+            "DELETE FROM task_file_stages WHERE task_id IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
+            params![cutoff_ts],
+        )?;
+        tx.execute(
+            "DELETE FROM magnet_prefetches WHERE task_id IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
+            params![cutoff_ts],
+        )?;
+        tx.execute(
+            "DELETE FROM task_trackers WHERE task_id IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
+            params![cutoff_ts],
+        )?;
+        tx.execute(
+            "DELETE FROM task_seeding WHERE task_id IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
+            params![cutoff_ts],
+        )?;
+        tx.execute(
+            "UPDATE task_dependencies SET satisfied = 1 WHERE depends_on IN (SELECT id FROM tasks WHERE status='completed' AND updated_at < ?1)",
+            params![cutoff_ts],
+        )?;
         let deleted = tx.execute(
             "DELETE FROM tasks WHERE status='completed' AND updated_at < ?1",
             params![cutoff_ts],
@@ -794,22 +1046,618 @@ impl Database {
             WHERE task_id = ?1
             "#,
             params![task_id],
-            |row| {
-                Ok(MediaMergeJob {
-                    task_id: row.get(0)?,
-                    input_url: row.get(1)?,
-                    output_path: row.get(2)?,
-                    ffmpeg_bin: row.get(3)?,
-                    ffmpeg_args: row.get(4)?,
-                    status: row.get(5)?,
-                    error_message: row.get(6)?,
-                    created_at: row.get(7)?,
-                    updated_at: row.get(8)?,
-                })
-            },
-        )
-        .optional()
-        .map_err(Into::into)
+            |row| {
+                Ok(MediaMergeJob {
+                    task_id: row.get(0)?,
+                    input_url: row.get(1)?,
+                    output_path: row.get(2)?,
+                    ffmpeg_bin: row.get(3)?,
+                    ffmpeg_args: row.get(4)?,
+                    status: row.get(5)?,
+                    error_message: row.get(6)?,
+                    created_at: row.get(7)?,
+                    updated_at: row.get(8)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
+    pub fn set_task_dependencies(&self, task_id: &str, depends_on: &[String]) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "DELETE FROM task_dependencies WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        for prerequisite in depends_on {
+            conn.execute(
+                "INSERT OR IGNORE INTO task_dependencies (task_id, depends_on) VALUES (?1, ?2)",
+                params![task_id, prerequisite],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Prerequisites of `task_id` that completed and were removed afterwards.
+    pub fn satisfied_dependencies(&self, task_id: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT depends_on FROM task_dependencies WHERE task_id = ?1 AND satisfied = 1",
+        )?;
+        let rows = stmt.query_map(params![task_id], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(Into::into)
+    }
+
+    pub fn set_task_mirrors(&self, task_id: &str, mirrors: &[TaskMirror]) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "DELETE FROM task_mirrors WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        for (position, mirror) in mirrors.iter().enumerate() {
+            conn.execute(
+                "INSERT OR IGNORE INTO task_mirrors (task_id, uri, location, priority, position)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    task_id,
+                    mirror.uri,
+                    mirror.location,
+                    mirror.priority,
+                    position as i64
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn list_task_mirrors(&self, task_id: &str) -> Result<Vec<TaskMirror>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT uri, location, priority FROM task_mirrors WHERE task_id = ?1 ORDER BY position",
+        )?;
+        let rows = stmt
+            .query_map(params![task_id], |row| {
+                Ok(TaskMirror {
+                    uri: row.get(0)?,
+                    location: row.get(1)?,
+                    priority: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// The full `select-file` a task moves to once its high-priority files are done.
+    pub fn set_task_file_stage(&self, task_id: &str, select_file: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        match select_file {
+            Some(select_file) => conn.execute(
+                "INSERT INTO task_file_stages (task_id, select_file) VALUES (?1, ?2)
+                 ON CONFLICT(task_id) DO UPDATE SET select_file = excluded.select_file",
+                params![task_id, select_file],
+            )?,
+            None => conn.execute(
+                "DELETE FROM task_file_stages WHERE task_id = ?1",
+                params![task_id],
+            )?,
+        };
+        Ok(())
+    }
+
+    pub fn list_task_file_stages(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare("SELECT task_id, select_file FROM task_file_stages")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Remembers a metadata-only magnet and the aria2 options its payload
+    /// download will be added with.
+    pub fn insert_magnet_prefetch(&self, task_id: &str, options: &Value) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "INSERT OR REPLACE INTO magnet_prefetches (task_id, options, torrent_base64)
+             VALUES (?1, ?2, NULL)",
+            params![task_id, options.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Prefetches still waiting for their metadata, with their stored options.
+    pub fn list_pending_magnet_prefetches(&self) -> Result<Vec<(String, Value)>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT task_id, options FROM magnet_prefetches WHERE torrent_base64 IS NULL",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let options: String = row.get(1)?;
+                Ok((
+                    row.get(0)?,
+                    serde_json::from_str(&options).unwrap_or(Value::Null),
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    pub fn pending_magnet_prefetch_gids(&self) -> Result<HashSet<String>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT t.aria2_gid FROM magnet_prefetches p JOIN tasks t ON t.id = p.task_id
+             WHERE p.torrent_base64 IS NULL AND t.aria2_gid IS NOT NULL",
+        )?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<HashSet<String>>>()?;
+        Ok(rows)
+    }
+
+    pub fn set_magnet_prefetch_torrent(&self, task_id: &str, torrent_base64: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "UPDATE magnet_prefetches SET torrent_base64 = ?2 WHERE task_id = ?1",
+            params![task_id, torrent_base64],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_magnet_prefetch_torrent(&self, task_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let torrent = conn
+            .query_row(
+                "SELECT torrent_base64 FROM magnet_prefetches WHERE task_id = ?1",
+                params![task_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(torrent.flatten())
+    }
+
+    pub fn remove_magnet_prefetch(&self, task_id: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "DELETE FROM magnet_prefetches WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        Ok(())
+    }
+
+    /// Stores a tracker list fetch; a failed one keeps the trackers it had before.
+    pub fn record_tracker_list_fetch(
+        &self,
+        url: &str,
+        trackers: Option<&[String]>,
+        fetched_at: i64,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        match trackers {
+            Some(trackers) => conn.execute(
+                "INSERT INTO tracker_lists (url, trackers, fetched_at, last_error)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(url) DO UPDATE SET trackers = excluded.trackers,
+                   fetched_at = excluded.fetched_at, last_error = excluded.last_error",
+                params![url, trackers.join("\n"), fetched_at, error],
+            )?,
+            None => conn.execute(
+                "INSERT INTO tracker_lists (url, fetched_at, last_error) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(url) DO UPDATE SET fetched_at = excluded.fetched_at,
+                   last_error = excluded.last_error",
+                params![url, fetched_at, error],
+            )?,
+        };
+        Ok(())
+    }
+
+    pub fn list_tracker_lists(&self) -> Result<Vec<(TrackerListStatus, Vec<String>)>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT url, trackers, fetched_at, last_error FROM tracker_lists ORDER BY url",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let trackers: String = row.get(1)?;
+                let trackers = trackers
+                    .lines()
+                    .filter(|v| !v.is_empty())
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                Ok((
+                    TrackerListStatus {
+                        url: row.get(0)?,
+                        tracker_count: trackers.len(),
+                        fetched_at: row.get(2)?,
+                        error: row.get(3)?,
+                    },
+                    trackers,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Drops lists that are no longer subscribed.
+    pub fn retain_tracker_lists(&self, urls: &[String]) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare("SELECT url FROM tracker_lists")?;
+        let stored = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for url in stored.iter().filter(|url| !urls.contains(url)) {
+            conn.execute("DELETE FROM tracker_lists WHERE url = ?1", params![url])?;
+        }
+        Ok(())
+    }
+
+    pub fn set_tracker_health(&self, health: &[TrackerHealth]) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        for entry in health {
+            conn.execute(
+                "INSERT OR REPLACE INTO tracker_health (uri, ok, checked_at, error)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    entry.uri,
+                    entry.status == "ok",
+                    entry.checked_at.unwrap_or_default(),
+                    entry.error
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_tracker_health(&self, uri: &str) -> Result<Option<TrackerHealth>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let health = conn
+            .query_row(
+                "SELECT ok, checked_at, error FROM tracker_health WHERE uri = ?1",
+                params![uri],
+                |row| {
+                    Ok(TrackerHealth {
+                        uri: uri.to_string(),
+                        status: if row.get::<_, bool>(0)? {
+                            "ok"
+                        } else {
+                            "unreachable"
+                        }
+                        .to_string(),
+                        checked_at: row.get(1)?,
+                        error: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(health)
+    }
+
+    /// A tracker added to (`excluded = false`) or removed from one task.
+    pub fn set_task_tracker(&self, task_id: &str, uri: &str, excluded: bool) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "INSERT OR REPLACE INTO task_trackers (task_id, uri, excluded) VALUES (?1, ?2, ?3)",
+            params![task_id, uri, excluded],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_task_trackers(&self, task_id: &str) -> Result<Vec<(String, bool)>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn
+            .prepare("SELECT uri, excluded FROM task_trackers WHERE task_id = ?1 ORDER BY rowid")?;
+        let rows = stmt
+            .query_map(params![task_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Adds what a gid uploaded since the last sync to the task's total and
+    /// returns that amount; a new gid starts counting from zero again.
+    pub fn record_task_upload(
+        &self,
+        task_id: &str,
+        gid: &str,
+        upload_length: i64,
+        seeding: bool,
+        now_ts: i64,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let previous = conn
+            .query_row(
+                "SELECT gid, gid_uploaded, seeding_since FROM task_seeding WHERE task_id = ?1",
+                params![task_id],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, Option<i64>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let delta = match &previous {
+            Some((Some(last_gid), last_uploaded, _)) if last_gid == gid => {
+                (upload_length - last_uploaded).max(0)
+            }
+            _ => upload_length.max(0),
+        };
+        let unchanged = previous
+            .as_ref()
+            .is_some_and(|(last_gid, last_uploaded, since)| {
+                last_gid.as_deref() == Some(gid)
+                    && *last_uploaded == upload_length
+                    && (!seeding || since.is_some())
+            });
+        if unchanged {
+            return Ok(0);
+        }
+        conn.execute(
+            r#"
+            INSERT INTO task_seeding (task_id, uploaded_length, gid, gid_uploaded, seeding_since, last_upload_at)
+            VALUES (?1, ?2, ?3, ?4, CASE WHEN ?5 THEN ?6 END, CASE WHEN ?2 > 0 OR ?5 THEN ?6 END)
+            ON CONFLICT(task_id) DO UPDATE SET
+              uploaded_length = uploaded_length + ?2,
+              gid = ?3,
+              gid_uploaded = ?4,
+              seeding_since = COALESCE(seeding_since, CASE WHEN ?5 THEN ?6 END),
+              last_upload_at = CASE
+                WHEN ?2 > 0 OR (?5 AND seeding_since IS NULL) THEN ?6
+                ELSE last_upload_at
+              END
+            "#,
+            params![task_id, delta, gid, upload_length, seeding, now_ts],
+        )?;
+        Ok(delta)
+    }
+
+    /// Adds transferred bytes to the task's minute, hour and day buckets (UTC).
+    fn record_transfer(
+        &self,
+        task: &Task,
+        downloaded: i64,
+        uploaded: i64,
+        now_ts: i64,
+    ) -> Result<()> {
+        if downloaded <= 0 && uploaded <= 0 {
+            return Ok(());
+        }
+        let category = task.category.as_deref().unwrap_or_default().trim();
+        let domain = source_domain(&task.source).unwrap_or_default();
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        for (resolution, seconds) in HISTORY_RESOLUTIONS {
+            conn.execute(
+                r#"
+                INSERT INTO transfer_history (resolution, bucket_start, task_id, category, domain, downloaded, uploaded)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ON CONFLICT(resolution, bucket_start, task_id) DO UPDATE SET
+                  category = excluded.category,
+                  domain = excluded.domain,
+                  downloaded = downloaded + excluded.downloaded,
+                  uploaded = uploaded + excluded.uploaded
+                "#,
+                params![
+                    resolution,
+                    now_ts - now_ts.rem_euclid(seconds),
+                    task.id,
+                    category,
+                    domain,
+                    downloaded,
+                    uploaded
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sums transfer history into buckets of `resolution` (`minute`, `hour`,
+    /// `day`, or `month` built from the day buckets), optionally one row per
+    /// task, category or domain.
+    pub fn bandwidth_history(
+        &self,
+        resolution: &str,
+        group_by: Option<&str>,
+        from: i64,
+        to: i64,
+        filter: (Option<&str>, Option<&str>, Option<&str>),
+    ) -> Result<Vec<BandwidthSample>> {
+        let (stored, bucket) = match resolution {
+            "minute" | "hour" | "day" => (resolution, "bucket_start"),
+            "month" => (
+                "day",
+                "CAST(strftime('%s', bucket_start, 'unixepoch', 'start of month') AS INTEGER)",
+            ),
+            other => return Err(anyhow!("invalid resolution={other}")),
+        };
+        let key = match group_by {
+            None => "NULL",
+            Some("task") => "task_id",
+            Some("category") => "NULLIF(category, '')",
+            Some("domain") => "NULLIF(domain, '')",
+            Some(other) => return Err(anyhow!("invalid group_by={other}")),
+        };
+        let (task_id, category, domain) = filter;
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT {bucket} AS bucket, {key} AS key, SUM(downloaded), SUM(uploaded)
+            FROM transfer_history
+            WHERE resolution = ?1 AND bucket_start >= ?2 AND bucket_start < ?3
+              AND (?4 IS NULL OR task_id = ?4)
+              AND (?5 IS NULL OR category = ?5)
+              AND (?6 IS NULL OR domain = ?6)
+            GROUP BY bucket, key
+            ORDER BY bucket, key
+            "#
+        ))?;
+        let rows = stmt
+            .query_map(
+                params![stored, from, to, task_id, category, domain],
+                |row| {
+                    Ok(BandwidthSample {
+                        bucket_start: row.get(0)?,
+                        key: row.get(1)?,
+                        downloaded: row.get(2)?,
+                        uploaded: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    pub fn prune_transfer_history(&self, resolution: &str, before_ts: i64) -> Result<usize> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let deleted = conn.execute(
+            "DELETE FROM transfer_history WHERE resolution = ?1 AND bucket_start < ?2",
+            params![resolution, before_ts],
+        )?;
+        Ok(deleted)
+    }
+
+    pub fn get_task_seeding(&self, task_id: &str) -> Result<Option<TaskSeeding>> {
+        Ok(self
+            .list_task_seeding()?
+            .into_iter()
+            .find(|seeding| seeding.task_id == task_id))
+    }
+
+    pub fn list_task_seeding(&self) -> Result<Vec<TaskSeeding>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT s.task_id, s.uploaded_length, t.total_length, s.seeding_since,
+                   s.last_upload_at, s.held, s.stopped_reason
+            FROM task_seeding s JOIN tasks t ON t.id = s.task_id
+            ORDER BY t.created_at
+            "#,
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let uploaded_length: i64 = row.get(1)?;
+                let total_length: i64 = row.get(2)?;
+                Ok(TaskSeeding {
+                    task_id: row.get(0)?,
+                    uploaded_length,
+                    ratio: if total_length > 0 {
+                        uploaded_length as f64 / total_length as f64
+                    } else {
+                        0.0
+                    },
+                    seeding_since: row.get(3)?,
+                    last_upload_at: row.get(4)?,
+                    held: row.get(5)?,
+                    stopped_reason: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    pub fn set_task_seed_held(&self, task_id: &str, held: bool) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "UPDATE task_seeding SET held = ?2 WHERE task_id = ?1",
+            params![task_id, held],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_task_seed_stopped(&self, task_id: &str, reason: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            r#"
+            INSERT INTO task_seeding (task_id, stopped_reason) VALUES (?1, ?2)
+            ON CONFLICT(task_id) DO UPDATE SET stopped_reason = ?2, held = 0
+            "#,
+            params![task_id, reason],
+        )?;
+        Ok(())
+    }
+
+    pub fn upsert_pending_submission(&self, pending: &PendingSubmission) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            r#"
+            INSERT INTO pending_submissions (
+              task_id, kind, payload, options, on_dependency_failure, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(task_id) DO UPDATE SET
+              kind=excluded.kind,
+              payload=excluded.payload,
+              options=excluded.options,
+              on_dependency_failure=excluded.on_dependency_failure
+            "#,
+            params![
+                pending.task_id,
+                pending.kind,
+                pending.payload,
+                pending.options,
+                pending.on_dependency_failure,
+                pending.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_pending_submissions(&self) -> Result<Vec<PendingSubmission>> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT task_id, kind, payload, options, on_dependency_failure, created_at
+            FROM pending_submissions
+            ORDER BY created_at ASC, rowid ASC
+            "#,
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(PendingSubmission {
+                    task_id: row.get(0)?,
+                    kind: row.get(1)?,
+                    payload: row.get(2)?,
+                    options: row.get(3)?,
+                    on_dependency_failure: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    pub fn has_pending_submission(&self, task_id: &str) -> Result<bool> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let found = conn
+            .query_row(
+                "SELECT 1 FROM pending_submissions WHERE task_id = ?1",
+                params![task_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    pub fn has_queue_held_tasks(&self) -> Result<bool> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        let found = conn
+            .query_row(
+                "SELECT 1 FROM tasks WHERE queue_held = 1 LIMIT 1",
+                [],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    pub fn remove_pending_submission(&self, task_id: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("db mutex poisoned");
+        conn.execute(
+            "DELETE FROM pending_submissions WHERE task_id = ?1",
+            params![task_id],
+        )?;
+        Ok(())
     }
 
     pub fn run_integrity_check(&self) -> Result<String> {
@@ -850,6 +1698,9 @@ fn validate_runtime_settings_with_conn(conn: &Connection, must_exist: &[&str]) -
     parse_optional_u32_setting(conn, "retry_max_attempts")?;
     parse_optional_u32_setting(conn, "retry_backoff_secs")?;
     parse_optional_u32_setting(conn, "metadata_timeout_secs")?;
+    parse_optional_u32_setting(conn, "tracker_refresh_hours")?;
+    parse_optional_u32_setting(conn, "max_active_seeds")?;
+    parse_optional_u32_setting(conn, "transfer_history_days")?;
     parse_optional_u32_setting(conn, "auto_clear_completed_days")?;
 
     for key in [
@@ -858,6 +1709,7 @@ fn validate_runtime_settings_with_conn(conn: &Connection, must_exist: &[&str]) -
         "media_merge_enabled",
         "clipboard_watch_enabled",
         "completion_hook_on_error",
+        "pause_seeding_in_speed_plan",
         "auto_delete_control_files",
         "first_run_done",
         "start_minimized",
@@ -889,6 +1741,45 @@ fn validate_runtime_settings_with_conn(conn: &Connection, must_exist: &[&str]) -
         }
     }
 
+    let engine_mode = get_optional_setting_from_conn(conn, "engine_mode")?;
+    match engine_mode.as_deref().map(str::trim) {
+        None | Some("managed" | "demo" | "native") => {}
+        Some("external") => {
+            let url = get_optional_setting_from_conn(conn, "aria2_rpc_url")?.unwrap_or_default();
+            let url = url.trim();
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(anyhow!(
+                    "invalid setting aria2_rpc_url={url}, external engine_mode requires an http(s) RPC URL"
+                ));
+            }
+        }
+        Some(other) => {
+            return Err(anyhow!(
+                "invalid setting engine_mode={other}, expected managed|external|demo|native"
+            ));
+        }
+    }
+
+    if let Some(policy) = get_optional_setting_from_conn(conn, "collision_policy")? {
+        match policy.trim() {
+            "rename" | "overwrite" | "skip" | "resume" | "ask" => {}
+            _ => {
+                return Err(anyhow!(
+                    "invalid setting collision_policy={policy}, expected rename|overwrite|skip|resume|ask"
+                ));
+            }
+        }
+    }
+    if let Some(policy) = get_optional_setting_from_conn(conn, "duplicate_policy")? {
+        match policy.trim() {
+            "warn" | "skip" | "redownload" | "open" => {}
+            _ => {
+                return Err(anyhow!(
+                    "invalid setting duplicate_policy={policy}, expected warn|skip|redownload|open"
+                ));
+            }
+        }
+    }
     if let Some(action) = get_optional_setting_from_conn(conn, "post_complete_action")? {
         match action.trim() {
             "none" | "open_dir" | "open_file" => {}
@@ -916,8 +1807,139 @@ fn validate_runtime_settings_with_conn(conn: &Connection, must_exist: &[&str]) -
         }
     }
 
-    parse_optional_json_setting::<Vec<DownloadDirRule>>(conn, "download_dir_rules")?;
-    parse_optional_json_setting::<Vec<CategoryRule>>(conn, "category_rules")?;
+    let profiles = parse_optional_json_setting::<Vec<EngineProfile>>(conn, "engine_profiles")?
+        .unwrap_or_default();
+    let mut engine_ids = HashSet::from([DEFAULT_ENGINE_ID.to_string()]);
+    for (index, profile) in profiles.iter().enumerate() {
+        let id = profile.id.trim();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "invalid engine_profiles[{index}].id={id}, expected letters, digits, - or _"
+            ));
+        }
+        if !engine_ids.insert(id.to_string()) {
+            return Err(anyhow!(
+                "invalid engine_profiles[{index}].id={id}, ids must be unique and not `{DEFAULT_ENGINE_ID}`"
+            ));
+        }
+        let url = profile.rpc_url.trim();
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(anyhow!(
+                "invalid engine_profiles[{index}].rpc_url={url}, expected an http(s) RPC URL"
+            ));
+        }
+    }
+    let tracker_lists =
+        parse_optional_json_setting::<Vec<String>>(conn, "tracker_lists")?.unwrap_or_default();
+    for (index, url) in tracker_lists.iter().enumerate() {
+        let scheme = reqwest::Url::parse(url.trim())
+            .map(|u| u.scheme().to_string())
+            .unwrap_or_default();
+        if !matches!(scheme.as_str(), "http" | "https" | "file") {
+            return Err(anyhow!(
+                "invalid tracker_lists[{index}]={url}, expected an http(s) or file URL"
+            ));
+        }
+    }
+    let seeding_rules =
+        parse_optional_json_setting::<Vec<SeedingRule>>(conn, "seeding_rules")?.unwrap_or_default();
+    let mut seeding_categories = HashSet::new();
+    for (index, rule) in seeding_rules.iter().enumerate() {
+        let category = rule.category.as_deref().map(str::trim).unwrap_or_default();
+        if !seeding_categories.insert(category.to_string()) {
+            return Err(anyhow!(
+                "invalid seeding_rules[{index}].category={category}, each category needs one rule"
+            ));
+        }
+        if rule
+            .ratio
+            .is_some_and(|ratio| ratio.is_nan() || ratio <= 0.0)
+        {
+            return Err(anyhow!(
+                "invalid seeding_rules[{index}].ratio, expected a positive number"
+            ));
+        }
+        if rule.ratio.is_none()
+            && rule.seed_hours.unwrap_or(0) == 0
+            && rule.idle_minutes.unwrap_or(0) == 0
+        {
+            return Err(anyhow!(
+                "invalid seeding_rules[{index}], expected ratio, seed_hours or idle_minutes"
+            ));
+        }
+    }
+    let queues =
+        parse_optional_json_setting::<Vec<TaskQueue>>(conn, "task_queues")?.unwrap_or_default();
+    let mut queue_names = HashSet::new();
+    for (index, queue) in queues.iter().enumerate() {
+        let name = queue.name.trim();
+        if name.is_empty() || !queue_names.insert(name.to_string()) {
+            return Err(anyhow!(
+                "invalid task_queues[{index}].name={name}, names must be non-empty and unique"
+            ));
+        }
+        if queue.max_active == 0 {
+            return Err(anyhow!(
+                "invalid task_queues[{index}].max_active=0, expected at least 1"
+            ));
+        }
+    }
+    let check_rule_engine = |key: &str, index: usize, engine: Option<&String>| -> Result<()> {
+        match engine.map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            Some(engine) if !engine_ids.contains(engine) => Err(anyhow!(
+                "invalid {key}[{index}].engine={engine}, no such engine profile"
+            )),
+            _ => Ok(()),
+        }
+    };
+    if let Some(rules) =
+        parse_optional_json_setting::<Vec<DownloadDirRule>>(conn, "download_dir_rules")?
+    {
+        for (index, rule) in rules.iter().enumerate() {
+            check_rule_engine("download_dir_rules", index, rule.engine.as_ref())?;
+            validate_template(&rule.save_dir)
+                .map_err(|e| anyhow!("invalid download_dir_rules[{index}].save_dir: {e}"))?;
+            validate_rule_matchers(
+                "download_dir_rules",
+                index,
+                (&rule.matcher, &rule.pattern),
+                &rule.conditions,
+                rule.combine.as_deref(),
+            )?;
+        }
+    }
+    if let Some(rules) = parse_optional_json_setting::<Vec<CategoryRule>>(conn, "category_rules")? {
+        for (index, rule) in rules.iter().enumerate() {
+            check_rule_engine("category_rules", index, rule.engine.as_ref())?;
+            validate_rule_matchers(
+                "category_rules",
+                index,
+                (&rule.matcher, &rule.pattern),
+                &rule.conditions,
+                rule.combine.as_deref(),
+            )?;
+        }
+    }
+    if let Some(rules) = parse_optional_json_setting::<Vec<PolicyRule>>(conn, "policy_rules")? {
+        for (index, rule) in rules.iter().enumerate() {
+            validate_rule_matchers(
+                "policy_rules",
+                index,
+                (&rule.matcher, &rule.pattern),
+                &rule.conditions,
+                rule.combine.as_deref(),
+            )?;
+            if rule.split == Some(0) || rule.max_connection_per_server == Some(0) {
+                return Err(anyhow!(
+                    "invalid policy_rules[{index}], split and max_connection_per_server must be at least 1"
+                ));
+            }
+        }
+    }
     if let Some(rules) =
         parse_optional_json_setting::<Vec<StoredSpeedPlanRule>>(conn, "speed_plan")?
     {
@@ -1027,6 +2049,58 @@ where
         .map_err(|e| anyhow!("invalid setting {key}: {e}"))
 }
 
+fn validate_rule_matchers(
+    key: &str,
+    index: usize,
+    (matcher, pattern): (&str, &str),
+    conditions: &[RuleCondition],
+    combine: Option<&str>,
+) -> Result<()> {
+    if let Some(combine) = combine.map(str::trim).filter(|v| !v.is_empty())
+        && !matches!(combine.to_lowercase().as_str(), "all" | "any")
+    {
+        return Err(anyhow!(
+            "invalid {key}[{index}].combine={combine}, expected all|any"
+        ));
+    }
+    let own = std::iter::once((format!("{key}[{index}]"), matcher, pattern));
+    let extra = conditions.iter().enumerate().map(|(i, c)| {
+        (
+            format!("{key}[{index}].conditions[{i}]"),
+            c.matcher.as_str(),
+            c.pattern.as_str(),
+        )
+    });
+    for (field, matcher, pattern) in own.chain(extra) {
+        let pattern = pattern.trim();
+        match matcher.trim().to_lowercase().as_str() {
+            "ext" | "domain" | "type" | "category" | "glob" | "source" => {}
+            "regex" => {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    return Err(anyhow!("invalid {field}.pattern, bad regex: {e}"));
+                }
+            }
+            "size" => {
+                if let Some(bad) = pattern
+                    .split(',')
+                    .map(str::trim)
+                    .find(|v| !v.is_empty() && parse_size_range(v).is_none())
+                {
+                    return Err(anyhow!(
+                        "invalid {field}.pattern={bad}, expected a size range like 100M-2G"
+                    ));
+                }
+            }
+            other => {
+                return Err(anyhow!(
+                    "invalid {field}.matcher={other}, expected ext|domain|type|category|regex|glob|size|source"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 fn validate_speed_plan_days(days: &str, index: usize) -> Result<()> {
     for day in days.split(',').map(str::trim).filter(|v| !v.is_empty()) {
         let parsed = day.parse::<u8>().map_err(|_| {
@@ -1068,6 +2142,7 @@ fn row_to_task(row: &rusqlite::Row<'_>) -> rusqlite::Result<Task> {
     Ok(Task {
         id: row.get(0)?,
         aria2_gid: row.get(1)?,
+        engine_id: row.get(25)?,
         task_type: parse_task_type(task_type_raw.as_str())?,
         source: row.get(3)?,
         status: parse_task_status(status_raw.as_str())?,
@@ -1091,6 +2166,17 @@ fn row_to_task(row: &rusqlite::Row<'_>) -> rusqlite::Result<Task> {
         checksum_status: row.get(22)?,
         created_at: row.get(23)?,
         updated_at: row.get(24)?,
+        depends_on: row
+            .get::<_, Option<String>>(26)?
+            .map(|v| v.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+        start_at: row.get(27)?,
+        window: row
+            .get::<_, Option<String>>(28)?
+            .and_then(|v| serde_json::from_str(&v).ok()),
+        schedule_held: row.get::<_, Option<bool>>(29)?.unwrap_or(false),
+        queue: row.get(30)?,
+        queue_held: row.get::<_, Option<bool>>(31)?.unwrap_or(false),
     })
 }
 
@@ -1363,6 +2449,191 @@ fn apply_migration(conn: &Connection, version: i64) -> Result<()> {
                 conn.execute("ALTER TABLE tasks ADD COLUMN checksum_status TEXT", [])?;
             }
         }
+        9 => {
+            if !table_has_column(conn, "tasks", "engine_id")? {
+                conn.execute("ALTER TABLE tasks ADD COLUMN engine_id TEXT", [])?;
+            }
+            conn.execute(
+                "CREATE INDEX IF NOT EXISTS idx_tasks_engine_gid ON tasks(engine_id, aria2_gid)",
+                [],
+            )?;
+        }
+        10 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS task_dependencies (
+                  task_id TEXT NOT NULL,
+                  depends_on TEXT NOT NULL,
+                  PRIMARY KEY(task_id, depends_on)
+                );
+                CREATE INDEX IF NOT EXISTS idx_task_dependencies_depends_on
+                ON task_dependencies(depends_on);
+                CREATE TABLE IF NOT EXISTS pending_submissions (
+                  task_id TEXT PRIMARY KEY,
+                  kind TEXT NOT NULL,
+                  payload TEXT NOT NULL,
+                  options TEXT NOT NULL,
+                  on_dependency_failure TEXT NOT NULL,
+                  created_at INTEGER NOT NULL
+                );
+                "#,
+            )?;
+        }
+        11 => {
+            if !table_has_column(conn, "tasks", "start_at")? {
+                conn.execute("ALTER TABLE tasks ADD COLUMN start_at INTEGER", [])?;
+            }
+            if !table_has_column(conn, "tasks", "schedule_window")? {
+                conn.execute("ALTER TABLE tasks ADD COLUMN schedule_window TEXT", [])?;
+            }
+            if !table_has_column(conn, "tasks", "schedule_held")? {
+                conn.execute(
+                    "ALTER TABLE tasks ADD COLUMN schedule_held INTEGER DEFAULT 0",
+                    [],
+                )?;
+            }
+        }
+        12 => {
+            if !table_has_column(conn, "tasks", "queue")? {
+                conn.execute("ALTER TABLE tasks ADD COLUMN queue TEXT", [])?;
+            }
+            if !table_has_column(conn, "tasks", "queue_held")? {
+                conn.execute(
+                    "ALTER TABLE tasks ADD COLUMN queue_held INTEGER DEFAULT 0",
+                    [],
+                )?;
+            }
+            conn.execute(
+                "CREATE INDEX IF NOT EXISTS idx_tasks_queue ON tasks(queue)",
+                [],
+            )?;
+        }
+        13 => {
+            if !table_has_column(conn, "tasks", "dedupe_key")? {
+                conn.execute("ALTER TABLE tasks ADD COLUMN dedupe_key TEXT", [])?;
+            }
+            if !table_has_column(conn, "tasks", "final_url")? {
+                conn.execute("ALTER TABLE tasks ADD COLUMN final_url TEXT", [])?;
+            }
+            let sources = {
+                let mut stmt = conn.prepare("SELECT id, source FROM tasks")?;
+                stmt.query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            for (id, source) in sources {
+                conn.execute(
+                    "UPDATE tasks SET dedupe_key = ?2 WHERE id = ?1",
+                    params![id, source_dedupe_key(&source)],
+                )?;
+            }
+            conn.execute_batch(
+                r#"
+                CREATE INDEX IF NOT EXISTS idx_tasks_dedupe_key ON tasks(dedupe_key);
+                CREATE INDEX IF NOT EXISTS idx_tasks_final_url ON tasks(final_url);
+                "#,
+            )?;
+        }
+        14 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS task_mirrors (
+                  task_id TEXT NOT NULL,
+                  uri TEXT NOT NULL,
+                  location TEXT,
+                  priority INTEGER,
+                  position INTEGER NOT NULL DEFAULT 0,
+                  PRIMARY KEY(task_id, uri)
+                );
+                "#,
+            )?;
+        }
+        15 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS task_file_stages (
+                  task_id TEXT PRIMARY KEY,
+                  select_file TEXT NOT NULL
+                );
+                "#,
+            )?;
+        }
+        16 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS magnet_prefetches (
+                  task_id TEXT PRIMARY KEY,
+                  options TEXT NOT NULL,
+                  torrent_base64 TEXT
+                );
+                "#,
+            )?;
+        }
+        17 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS tracker_lists (
+                  url TEXT PRIMARY KEY,
+                  trackers TEXT NOT NULL DEFAULT '',
+                  fetched_at INTEGER,
+                  last_error TEXT
+                );
+                CREATE TABLE IF NOT EXISTS tracker_health (
+                  uri TEXT PRIMARY KEY,
+                  ok INTEGER NOT NULL,
+                  checked_at INTEGER NOT NULL,
+                  error TEXT
+                );
+                CREATE TABLE IF NOT EXISTS task_trackers (
+                  task_id TEXT NOT NULL,
+                  uri TEXT NOT NULL,
+                  excluded INTEGER NOT NULL DEFAULT 0,
+                  PRIMARY KEY(task_id, uri)
+                );
+                "#,
+            )?;
+        }
+        18 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS task_seeding (
+                  task_id TEXT PRIMARY KEY,
+                  uploaded_length INTEGER NOT NULL DEFAULT 0,
+                  gid TEXT,
+                  gid_uploaded INTEGER NOT NULL DEFAULT 0,
+                  seeding_since INTEGER,
+                  last_upload_at INTEGER,
+                  held INTEGER NOT NULL DEFAULT 0,
+                  stopped_reason TEXT
+                );
+                "#,
+            )?;
+        }
+        19 => {
+            conn.execute_batch(
+                r#"
+                CREATE TABLE IF NOT EXISTS transfer_history (
+                  resolution TEXT NOT NULL,
+                  bucket_start INTEGER NOT NULL,
+                  task_id TEXT NOT NULL,
+                  category TEXT NOT NULL DEFAULT '',
+                  domain TEXT NOT NULL DEFAULT '',
+                  downloaded INTEGER NOT NULL DEFAULT 0,
+                  uploaded INTEGER NOT NULL DEFAULT 0,
+                  PRIMARY KEY(resolution, bucket_start, task_id)
+                );
+                CREATE INDEX IF NOT EXISTS idx_transfer_history_bucket
+                  ON transfer_history(resolution, bucket_start);
+                "#,
+            )?;
+        }
+        20 if !table_has_column(conn, "task_dependencies", "satisfied")? => {
+            conn.execute(
+                "ALTER TABLE task_dependencies ADD COLUMN satisfied INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
         _ => {}
     }
     Ok(())
@@ -1415,6 +2686,15 @@ fn parse_task_status(value: &str) -> rusqlite::Result<TaskStatus> {
     }
 }
 
+/// The host a task downloads from, for per-domain transfer history.
+fn source_domain(source: &str) -> Option<String> {
+    let url = reqwest::Url::parse(source).ok()?;
+    if !matches!(url.scheme(), "http" | "https" | "ftp" | "sftp") {
+        return None;
+    }
+    url.host_str().map(|host| host.to_ascii_lowercase())
+}
+
 fn to_type_str(value: &TaskType) -> &'static str {
     match value {
         TaskType::Http => "http",
@@ -1448,6 +2728,9 @@ mod tests {
         let db = Database::new(&db_path).expect("create db");
         let settings = GlobalSettings {
             aria2_bin_path: Some("/tmp/aria2c".to_string()),
+            engine_mode: Some("external".to_string()),
+            aria2_rpc_url: Some("https://nas.local:6800/jsonrpc".to_string()),
+            aria2_rpc_secret: Some("rpc-secret".to_string()),
             download_dir: Some("/tmp/dl".to_string()),
             max_concurrent_downloads: Some(7),
             max_connection_per_server: Some(12),
@@ -1463,12 +2746,24 @@ mod tests {
                 save_dir: "/tmp/video".to_string(),
                 subdir_by_date: false,
                 subdir_by_domain: false,
+                engine: None,
+                ..Default::default()
             }],
             category_rules: vec![CategoryRule {
                 enabled: true,
                 matcher: "domain".to_string(),
                 pattern: "example.com".to_string(),
                 category: "work".to_string(),
+                engine: Some("seedbox".to_string()),
+                ..Default::default()
+            }],
+            policy_rules: vec![PolicyRule {
+                enabled: true,
+                matcher: "domain".to_string(),
+                pattern: "mirror.internal".to_string(),
+                max_connection_per_server: Some(2),
+                split: Some(2),
+                ..Default::default()
             }],
             browser_bridge_enabled: Some(true),
             browser_bridge_port: Some(16789),
@@ -1490,6 +2785,22 @@ mod tests {
                 r#"[{"name":"Video Standard","task_type":"http","options":{"max_connection_per_server":8,"split":16}}]"#
                     .to_string(),
             ),
+            engine_profiles: Some(
+                r#"[{"id":"seedbox","rpc_url":"https://seedbox.example/jsonrpc","secret":"s"}]"#
+                    .to_string(),
+            ),
+            task_queues: Some(r#"[{"name":"urgent","max_active":1,"priority":10}]"#.to_string()),
+            collision_policy: Some("skip".to_string()),
+            duplicate_policy: Some("open".to_string()),
+            tracker_lists: Some(r#"["https://lists.example/trackers.txt"]"#.to_string()),
+            tracker_refresh_hours: Some(6),
+            seeding_rules: Some(
+                r#"[{"ratio":2.0},{"category":"linux","seed_hours":48,"idle_minutes":30}]"#
+                    .to_string(),
+            ),
+            max_active_seeds: Some(3),
+            transfer_history_days: Some(730),
+            pause_seeding_in_speed_plan: Some(true),
             post_complete_action: Some("open_dir".to_string()),
             completion_webhook_url: Some("http://127.0.0.1:9000/flamingo".to_string()),
             completion_command: Some("echo {task_id}".to_string()),
@@ -1506,6 +2817,12 @@ mod tests {
         let loaded = db.load_global_settings().expect("load settings");
 
         assert_eq!(loaded.aria2_bin_path.as_deref(), Some("/tmp/aria2c"));
+        assert_eq!(loaded.engine_mode.as_deref(), Some("external"));
+        assert_eq!(
+            loaded.aria2_rpc_url.as_deref(),
+            Some("https://nas.local:6800/jsonrpc")
+        );
+        assert_eq!(loaded.aria2_rpc_secret.as_deref(), Some("rpc-secret"));
         assert_eq!(loaded.download_dir.as_deref(), Some("/tmp/dl"));
         assert_eq!(loaded.max_concurrent_downloads, Some(7));
         assert_eq!(loaded.max_connection_per_server, Some(12));
@@ -1521,6 +2838,9 @@ mod tests {
         assert_eq!(loaded.download_dir_rules[0].matcher, "ext");
         assert_eq!(loaded.category_rules.len(), 1);
         assert_eq!(loaded.category_rules[0].category, "work");
+        assert_eq!(loaded.category_rules[0].engine.as_deref(), Some("seedbox"));
+        assert_eq!(loaded.policy_rules.len(), 1);
+        assert_eq!(loaded.policy_rules[0].max_connection_per_server, Some(2));
         assert!(
             loaded
                 .task_option_presets
@@ -1528,6 +2848,27 @@ mod tests {
                 .unwrap_or_default()
                 .contains("Video Standard")
         );
+        assert_eq!(
+            loaded.task_queues.as_deref(),
+            Some(r#"[{"name":"urgent","max_active":1,"priority":10}]"#)
+        );
+        assert_eq!(loaded.collision_policy.as_deref(), Some("skip"));
+        assert_eq!(loaded.duplicate_policy.as_deref(), Some("open"));
+        assert_eq!(
+            loaded.tracker_lists.as_deref(),
+            Some(r#"["https://lists.example/trackers.txt"]"#)
+        );
+        assert_eq!(loaded.tracker_refresh_hours, Some(6));
+        assert!(
+            loaded
+                .seeding_rules
+                .as_deref()
+                .unwrap_or_default()
+                .contains("linux")
+        );
+        assert_eq!(loaded.max_active_seeds, Some(3));
+        assert_eq!(loaded.transfer_history_days, Some(730));
+        assert_eq!(loaded.pause_seeding_in_speed_plan, Some(true));
         assert_eq!(loaded.post_complete_action.as_deref(), Some("open_dir"));
         assert_eq!(loaded.auto_delete_control_files, Some(true));
         assert_eq!(loaded.auto_clear_completed_days, Some(14));
@@ -1668,6 +3009,77 @@ mod tests {
         let _ = std::fs::remove_file(db_path);
     }
 
+    #[test]
+    fn save_global_settings_requires_rpc_url_for_external_engine() {
+        let db_path = std::env::temp_dir().join(format!("tarui-db-{}.sqlite", Uuid::new_v4()));
+        let db = Database::new(&db_path).expect("create db");
+        seed_runtime_settings(&db);
+
+        let mut patch = db.load_global_settings().expect("load settings");
+        patch.engine_mode = Some("external".to_string());
+        patch.aria2_rpc_url = Some("nas.local:6800".to_string());
+        let err = db
+            .save_global_settings(&patch)
+            .expect_err("external mode without http(s) url should be rejected");
+        assert!(err.to_string().contains("aria2_rpc_url"));
+        assert_eq!(
+            db.get_setting("engine_mode").expect("get engine_mode"),
+            None
+        );
+
+        patch.engine_mode = Some("remote".to_string());
+        let err = db
+            .save_global_settings(&patch)
+            .expect_err("unknown engine mode should be rejected");
+        assert!(err.to_string().contains("engine_mode"));
+
+        patch.engine_mode = Some("external".to_string());
+        patch.aria2_rpc_url = Some("http://nas.local:6800/jsonrpc".to_string());
+        db.save_global_settings(&patch)
+            .expect("external mode with url should save");
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn pending_submission_roundtrip_and_cleanup() {
+        let db_path = std::env::temp_dir().join(format!("tarui-db-{}.sqlite", Uuid::new_v4()));
+        let db = Database::new(&db_path).expect("create db");
+        for (task_id, created_at) in [("task-b", 20), ("task-a", 10)] {
+            db.upsert_pending_submission(&PendingSubmission {
+                task_id: task_id.to_string(),
+                kind: "uri".to_string(),
+                payload: format!("https://example.com/{task_id}.bin"),
+                options: "{}".to_string(),
+                on_dependency_failure: "cancel".to_string(),
+                created_at,
+            })
+            .expect("upsert pending submission");
+        }
+        let pending = db
+            .list_pending_submissions()
+            .expect("list pending submissions");
+        assert_eq!(
+            pending
+                .iter()
+                .map(|p| p.task_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["task-a", "task-b"]
+        );
+        assert!(db.has_pending_submission("task-a").expect("has pending"));
+
+        db.remove_pending_submission("task-a")
+            .expect("remove pending submission");
+        db.remove_task("task-b").expect("remove task");
+        assert!(!db.has_pending_submission("task-a").expect("has pending"));
+        assert!(
+            db.list_pending_submissions()
+                .expect("list pending submissions")
+                .is_empty()
+        );
+        let _ = std::fs::remove_file(db_path);
+    }
+
     #[test]
     fn deleted_gid_tombstone_roundtrip() {
         let db_path = std::env::temp_dir().join(format!("tarui-db-{}.sqlite", Uuid::new_v4()));
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
     collections::HashSet,
     collections::VecDeque,
@@ -7,7 +8,10 @@ use std::{
     io::{Cursor, Read},
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
     time::Duration,
 };
 
@@ -15,41 +19,76 @@ use anyhow::{Result, anyhow};
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 use chrono::{Datelike, Local, Timelike};
 use md5::Md5;
+use regex::Regex;
 use serde_json::{Value, json};
 use sha1::Sha1;
 use sha2::{Digest, Sha256};
 use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::{sync::Mutex as AsyncMutex, time};
+use tokio::{
+    sync::{Mutex as AsyncMutex, broadcast},
+    time,
+};
 use uuid::Uuid;
 use zip::write::SimpleFileOptions;
 
 use crate::{
-    aria2_manager::Aria2Api,
+    aria2_manager::{Aria2Api, Aria2Manager, ExternalAria2Config},
     db::Database,
     error::AppError,
-    events::SharedEmitter,
-    link_parser::{merge_duplicate_candidates, parse_link_candidates},
+    events::{ServiceEvent, SharedEmitter},
+    link_parser::{
+        MAX_EXPANDED_URLS, expand_url_pattern, is_url_pattern, magnet_infohash_hex,
+        merge_duplicate_candidates, parse_link_candidates,
+    },
+    metalink::parse_metalink,
     models::{
-        AddTaskOptions, AppUpdateStrategy, Aria2UpdateApplyResult, Aria2UpdateInfo,
-        BrowserBridgeStatus, CategoryRule, Diagnostics, DownloadDirRule, GlobalSettings,
-        ImportTaskListResult, LinkParseInput, LinkParseResult, MediaMergeJob, OperationLog,
-        SaveDirSuggestion, StartupSelfCheck, StorageSummary, Task, TaskFailureReason, TaskFile,
-        TaskHealth, TaskListSnapshot, TaskStatus, TaskType,
+        AddTaskOptions, AddTaskOutcome, AppUpdateStrategy, Aria2TaskSnapshot,
+        Aria2UpdateApplyResult, Aria2UpdateInfo, BandwidthHistoryQuery, BandwidthSample,
+        BatchAddItem, BatchAddItemResult, BatchAddRequest, BatchAddResult, BrowserBridgeStatus,
+        BulkTaskActionResult, BulkTaskFailure, CategoryRule, DEFAULT_ENGINE_ID, Diagnostics,
+        DownloadDirRule, DuplicateMatch, EngineProfile, GlobalSettings, ImportTaskListResult,
+        LinkParseInput, LinkParseResult, MediaMergeJob, OperationLog, PendingSubmission,
+        PolicyRule, SaveDirSuggestion, SeedingRule, StartupSelfCheck, StorageSummary, Task,
+        TaskFailureReason, TaskFile, TaskHealth, TaskListSnapshot, TaskMirror, TaskQueue,
+        TaskQueueStatus, TaskSeeding, TaskStatus, TaskType, TaskWindow, TorrentInfo, TrackerHealth,
+        TrackerListStatus,
+    },
+    native_engine::parse_size_range,
+    path_template::{
+        TemplateVars, is_template, render_dir_template, render_name_template, uses_variable,
+        validate_template,
     },
+    torrent::{parse_select_file, parse_torrent, select_file_spec},
 };
 
 const LOW_DISK_BUFFER_BYTES: i64 = 32 * 1024 * 1024;
+const FULL_SYNC_INTERVAL_TICKS: u64 = 15;
+const MAX_TRACKER_LIST_BYTES: usize = 2 * 1024 * 1024;
+const MAX_TRACKER_PROBES: usize = 500;
+const TRACKER_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const MINUTE_HISTORY_RETENTION_SECS: i64 = 2 * 86_400;
+const HOUR_HISTORY_RETENTION_SECS: i64 = 90 * 86_400;
 
 pub struct DownloadService {
     db: Arc<Database>,
     aria2: Arc<dyn Aria2Api>,
+    engines: RwLock<HashMap<String, EngineSlot>>,
     emitter: SharedEmitter,
     logs: Mutex<Vec<OperationLog>>,
     pending_logs: Mutex<Vec<OperationLog>>,
     lifecycle_guard: AsyncMutex<()>,
     retry_state: Mutex<HashMap<String, RetryState>>,
+    schedule_state: Mutex<HashMap<String, bool>>, // task id -> last seen "window open"
     last_speed_limit: Mutex<Option<String>>,
     merge_processes: Arc<Mutex<HashMap<String, u32>>>,
+    tracker_refresh_running: AtomicBool,
+}
+
+/// An extra engine from `engine_profiles`; the primary engine is `aria2`.
+#[derive(Clone)]
+struct EngineSlot {
+    profile: EngineProfile,
+    api: Arc<dyn Aria2Api>,
 }
 
 #[derive(Debug, Clone)]
@@ -58,6 +97,43 @@ struct RetryState {
     next_retry_at: i64,
 }
 
+/// What gets handed to the engine for a new task.
+enum Submission {
+    Uri(String),
+    Torrent(String),
+    Metalink(String),
+}
+
+/// How a new task enters the engine: held back for its dependencies, or added
+/// paused for its schedule or a full queue.
+struct Admission {
+    depends_on: Vec<String>,
+    waiting: bool,
+    start_at: Option<i64>,
+    window: Option<TaskWindow>,
+    schedule_held: bool,
+    queue: Option<String>,
+    queue_priority: i32,
+    queue_held: bool,
+}
+
+impl Admission {
+    fn paused(&self) -> bool {
+        self.schedule_held || self.queue_held
+    }
+
+    fn apply(&self, task: &mut Task) {
+        if self.paused() {
+            task.status = TaskStatus::Paused;
+        }
+        task.start_at = self.start_at;
+        task.window = self.window.clone();
+        task.schedule_held = self.schedule_held;
+        task.queue = self.queue.clone();
+        task.queue_held = self.queue_held;
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 struct SpeedPlanRule {
     days: Option<String>,  // "1,2,3" (Mon=1..Sun=7)
@@ -77,6 +153,7 @@ struct FfmpegMergeRequest {
     output_format: Option<String>,
     checksum_algorithm: Option<String>,
     checksum_value: Option<String>,
+    collision_policy: Option<String>,
 }
 
 impl From<AddTaskOptions> for FfmpegMergeRequest {
@@ -91,6 +168,7 @@ impl From<AddTaskOptions> for FfmpegMergeRequest {
             output_format: options.merge_format,
             checksum_algorithm: options.checksum_algorithm,
             checksum_value: options.checksum_value,
+            collision_policy: options.collision_policy,
         }
     }
 }
@@ -100,17 +178,34 @@ impl DownloadService {
         Self {
             db,
             aria2,
+            engines: RwLock::new(HashMap::new()),
             emitter,
             logs: Mutex::new(Vec::new()),
             pending_logs: Mutex::new(Vec::new()),
             lifecycle_guard: AsyncMutex::new(()),
             retry_state: Mutex::new(HashMap::new()),
+            schedule_state: Mutex::new(HashMap::new()),
             last_speed_limit: Mutex::new(None),
             merge_processes: Arc::new(Mutex::new(HashMap::new())),
+            tracker_refresh_running: AtomicBool::new(false),
         }
     }
 
     pub async fn add_url(&self, url: &str, options: AddTaskOptions) -> Result<String> {
+        self.add_url_outcome(url, options)
+            .await
+            .and_then(outcome_task_id)
+    }
+
+    /// Like `add_url`, but reports an earlier task for the same download and
+    /// what the duplicate policy did about it.
+    pub async fn add_url_outcome(
+        &self,
+        url: &str,
+        options: AddTaskOptions,
+    ) -> Result<AddTaskOutcome> {
+        let (url, options) = expand_single_task_url(url, options)?;
+        let url = url.as_str();
         validate_url(url)?;
         let merge_enabled = self
             .db
@@ -121,32 +216,66 @@ impl DownloadService {
             let (task_id, _) = self
                 .spawn_ffmpeg_merge(url, FfmpegMergeRequest::from(options))
                 .await?;
-            return Ok(task_id);
+            return Ok(created_outcome(task_id, None));
         }
-        self.ensure_aria2_ready().await?;
-
-        let http_type = detect_http_content_type(url).await;
-        let save_dir = self.resolve_save_dir_for_new_task(
+        let mut facts = probe_rule_facts(url, &options).await;
+        let duplicate = self.find_duplicate(url, &options, &facts)?;
+        if let Some(outcome) = self.apply_duplicate_policy(url, &options, duplicate.as_ref())? {
+            return Ok(outcome);
+        }
+        let category = self
+            .resolve_requested_category(options.category.as_deref())
+            .or(self.resolve_category_for_new_task(TaskType::Http, url, &facts)?);
+        facts.category = category.clone();
+        let options = self.apply_policy_rules(&TaskType::Http, url, options, &facts)?;
+        let engine_id = self.resolve_engine_for_new_task(
+            &TaskType::Http,
+            url,
+            category.as_deref(),
+            &options,
+            &facts,
+        )?;
+        let admission = self.admit_new_task(&options)?;
+        let mut vars = self.template_vars(&TaskType::Http, url, &options, &facts);
+        let save_dir = self.resolve_save_dir_for_engine(
+            engine_id.as_deref(),
             TaskType::Http,
             url,
             &options,
-            http_type.as_deref(),
+            &facts,
+            &mut vars,
         )?;
-        let category = self
-            .resolve_requested_category(options.category.as_deref())
-            .or(self.resolve_category_for_new_task(TaskType::Http, url, http_type.as_deref())?);
+        let options = self.with_rendered_out(options, &mut vars)?;
+        let options =
+            self.check_collision(engine_id.as_deref(), url, &save_dir, options, &facts)?;
         let checksum = checksum_metadata_from_options(&options)?;
         let options = with_resolved_save_dir(options, save_dir.clone());
+        let mirrors = options
+            .mirrors
+            .iter()
+            .map(|uri| TaskMirror {
+                uri: uri.clone(),
+                location: None,
+                priority: None,
+            })
+            .collect::<Vec<_>>();
         let task_id = Uuid::new_v4().to_string();
-        let gid = self
-            .aria2
-            .add_uri(vec![url.to_string()], Some(to_aria2_options(options)))
-            .await?;
+        let submission = Submission::Uri(url.to_string());
+        let gid = if admission.waiting {
+            self.hold_submission(&task_id, submission, &options)?;
+            None
+        } else {
+            Some(
+                self.submit_to_engine(engine_id.as_deref(), submission, options, &admission)
+                    .await?,
+            )
+        };
 
         let now = now_ts();
-        self.db.upsert_task(&Task {
+        let mut task = Task {
             id: task_id.clone(),
-            aria2_gid: Some(gid),
+            aria2_gid: gid,
+            engine_id,
             task_type: TaskType::Http,
             source: url.to_string(),
             status: TaskStatus::Queued,
@@ -170,10 +299,126 @@ impl DownloadService {
             checksum_status: checksum.status,
             created_at: now,
             updated_at: now,
-        })?;
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
+        };
+        admission.apply(&mut task);
+        self.db.upsert_task(&task)?;
+        self.db
+            .set_task_dependencies(&task_id, &admission.depends_on)?;
+        if let Some(final_url) = &facts.final_url {
+            self.db.set_task_final_url(&task_id, final_url)?;
+        }
+        if !mirrors.is_empty() {
+            self.db.set_task_mirrors(&task_id, &mirrors)?;
+        }
         self.push_log("add_url", format!("task created for {url}"));
 
-        Ok(task_id)
+        Ok(created_outcome(task_id, duplicate))
+    }
+
+    /// The URLs a `[001-250]` / `{a,b}` pattern expands to, capped at
+    /// `MAX_EXPANDED_URLS`; a plain URL comes back as is.
+    pub fn preview_url_pattern(&self, url: &str) -> Result<Vec<String>> {
+        let urls = expand_url_pattern(url.trim(), MAX_EXPANDED_URLS)?;
+        for url in &urls {
+            validate_url(url)?;
+        }
+        Ok(urls)
+    }
+
+    /// Adds every item, URL and link found in `text` with the shared options;
+    /// one failing item does not stop the rest.
+    pub async fn add_batch(&self, request: BatchAddRequest) -> Result<BatchAddResult> {
+        let BatchAddRequest {
+            mut items,
+            urls,
+            text,
+            source_url,
+            options,
+        } = request;
+        items.extend(urls.into_iter().map(|source| BatchAddItem {
+            source,
+            options: None,
+        }));
+        if let Some(text) = text.filter(|v| !v.trim().is_empty()) {
+            let parsed = parse_link_candidates(LinkParseInput {
+                text,
+                source_url,
+                source_kind: None,
+            });
+            // Parsed links are real URLs; brackets in them are not patterns.
+            items.extend(parsed.candidates.into_iter().map(|c| BatchAddItem {
+                source: c.url,
+                options: Some(AddTaskOptions {
+                    url_expansion: Some("off".to_string()),
+                    ..Default::default()
+                }),
+            }));
+        }
+        if items.is_empty() {
+            return Err(AppError::InvalidInput("batch has no urls or magnets".to_string()).into());
+        }
+        let mut entries = Vec::new();
+        for item in items {
+            let source = item.source.trim().to_string();
+            let merged = match item.options.as_ref() {
+                Some(overrides) => overlay_task_options(&options, overrides),
+                None => Ok(options.clone()),
+            };
+            match merged.and_then(|options| expand_batch_source(&source, options)) {
+                Ok(expanded) => entries.extend(expanded.into_iter().map(|(s, o)| (s, Ok(o)))),
+                Err(e) => entries.push((source, Err(e))),
+            }
+        }
+        let mut result = BatchAddResult::default();
+        for (source, options) in entries {
+            let outcome = match options {
+                Ok(options) if source.starts_with("magnet:?") => {
+                    self.add_magnet_outcome(&source, options).await
+                }
+                Ok(options) => self.add_url_outcome(&source, options).await,
+                Err(e) => Err(e),
+            };
+            result.items.push(match outcome {
+                Ok(outcome) => {
+                    if outcome.action == "created" {
+                        result.created += 1;
+                    }
+                    BatchAddItemResult {
+                        source,
+                        task_id: Some(outcome.task_id),
+                        action: Some(outcome.action),
+                        duplicate: outcome.duplicate,
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    result.failed += 1;
+                    BatchAddItemResult {
+                        source,
+                        task_id: None,
+                        action: None,
+                        duplicate: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            });
+        }
+        self.push_log(
+            "add_batch",
+            format!(
+                "created {} of {} task(s), {} failed",
+                result.created,
+                result.items.len(),
+                result.failed
+            ),
+        );
+        Ok(result)
     }
 
     pub async fn add_via_bridge(
@@ -187,16 +432,17 @@ impl DownloadService {
         let clean_url = url.trim();
         validate_url(clean_url)?;
         if clean_url.starts_with("magnet:?") {
-            let task_id = self
-                .add_magnet(
+            let outcome = self
+                .add_magnet_outcome(
                     clean_url,
                     AddTaskOptions {
                         save_dir,
+                        origin: Some("browser".to_string()),
                         ..AddTaskOptions::default()
                     },
                 )
                 .await?;
-            return Ok(json!({ "ok": true, "mode": "aria2", "task_id": task_id }));
+            return Ok(bridge_outcome_json(outcome));
         }
         if !clean_url.starts_with("http://") && !clean_url.starts_with("https://") {
             return Err(anyhow!("unsupported url scheme for bridge add"));
@@ -208,6 +454,8 @@ impl DownloadService {
             referer: normalized_referer,
             user_agent,
             headers: normalized_headers,
+            origin: Some("browser".to_string()),
+            url_expansion: Some("off".to_string()),
             ..AddTaskOptions::default()
         };
 
@@ -228,8 +476,18 @@ impl DownloadService {
             }));
         }
 
-        let task_id = self.add_url(clean_url, bridge_options).await?;
-        Ok(json!({ "ok": true, "mode": "aria2", "task_id": task_id }))
+        let outcome = match self.add_url_outcome(clean_url, bridge_options).await {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<AppError>(),
+                    Some(AppError::AlreadyDownloaded(_))
+                ) =>
+            {
+                return Ok(json!({ "ok": true, "mode": "skipped", "detail": err.to_string() }));
+            }
+            result => result?,
+        };
+        Ok(bridge_outcome_json(outcome))
     }
 
     async fn spawn_ffmpeg_merge(
@@ -247,6 +505,7 @@ impl DownloadService {
             output_format,
             checksum_algorithm,
             checksum_value,
+            collision_policy,
         } = request;
         let ffmpeg_bin = self
             .db
@@ -264,7 +523,29 @@ impl DownloadService {
         fs::create_dir_all(&target_dir)?;
         let output_name =
             stream_output_filename(url, output_name.as_deref(), output_format.as_deref());
-        let output_path = Path::new(&target_dir).join(output_name);
+        let output_path = Path::new(&target_dir).join(&output_name);
+        let output_path = if output_path.exists() {
+            // A merge cannot pick up where an earlier one stopped, and there is
+            // nothing to compare an existing output against before it runs.
+            match self.collision_policy(collision_policy.as_deref())? {
+                "overwrite" => output_path,
+                "skip" => {
+                    return Err(AppError::AlreadyDownloaded(
+                        output_path.to_string_lossy().to_string(),
+                    )
+                    .into());
+                }
+                "ask" => {
+                    return Err(
+                        AppError::FileExists(output_path.to_string_lossy().to_string()).into(),
+                    );
+                }
+                _ => Path::new(&target_dir)
+                    .join(unique_filename(Path::new(&target_dir), &output_name)),
+            }
+        } else {
+            output_path
+        };
         let cleaned_headers = headers
             .into_iter()
             .map(|h| h.trim().to_string())
@@ -336,15 +617,16 @@ impl DownloadService {
         let task = Task {
             id: task_id.clone(),
             aria2_gid: None,
+            engine_id: None,
             task_type: TaskType::Http,
             source: url.to_string(),
             status: TaskStatus::Active,
             name: output_path
                 .file_name()
                 .map(|v| v.to_string_lossy().to_string()),
-            category: self
-                .resolve_requested_category(category.as_deref())
-                .or(self.resolve_category_for_new_task(TaskType::Http, url, None)?),
+            category: self.resolve_requested_category(category.as_deref()).or(
+                self.resolve_category_for_new_task(TaskType::Http, url, &RuleFacts::default())?
+            ),
             save_dir: target_dir.clone(),
             total_length: duration_ms,
             completed_length: 0,
@@ -363,6 +645,12 @@ impl DownloadService {
             checksum_status: checksum.status,
             created_at: now,
             updated_at: now,
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
         };
         self.db.upsert_task(&task)?;
         let initial_files = vec![TaskFile {
@@ -578,31 +866,77 @@ impl DownloadService {
     }
 
     pub async fn add_magnet(&self, magnet: &str, options: AddTaskOptions) -> Result<String> {
+        self.add_magnet_outcome(magnet, options)
+            .await
+            .and_then(outcome_task_id)
+    }
+
+    pub async fn add_magnet_outcome(
+        &self,
+        magnet: &str,
+        options: AddTaskOptions,
+    ) -> Result<AddTaskOutcome> {
         if !magnet.starts_with("magnet:?") {
             return Err(AppError::InvalidInput("invalid magnet link".to_string()).into());
         }
-        self.ensure_aria2_ready().await?;
-
-        let save_dir =
-            self.resolve_save_dir_for_new_task(TaskType::Magnet, magnet, &options, None)?;
+        let mut facts = RuleFacts::for_options(&options);
+        let duplicate = self.find_duplicate(magnet, &options, &facts)?;
+        if let Some(outcome) = self.apply_duplicate_policy(magnet, &options, duplicate.as_ref())? {
+            return Ok(outcome);
+        }
         let category = self
             .resolve_requested_category(options.category.as_deref())
-            .or(self.resolve_category_for_new_task(TaskType::Magnet, magnet, None)?);
+            .or(self.resolve_category_for_new_task(TaskType::Magnet, magnet, &facts)?);
+        facts.category = category.clone();
+        let options = self.apply_policy_rules(&TaskType::Magnet, magnet, options, &facts)?;
+        let engine_id = self.resolve_engine_for_new_task(
+            &TaskType::Magnet,
+            magnet,
+            category.as_deref(),
+            &options,
+            &facts,
+        )?;
+        let admission = self.admit_new_task(&options)?;
+        let mut vars = self.template_vars(&TaskType::Magnet, magnet, &options, &facts);
+        let save_dir = self.resolve_save_dir_for_engine(
+            engine_id.as_deref(),
+            TaskType::Magnet,
+            magnet,
+            &options,
+            &facts,
+            &mut vars,
+        )?;
+        let options = self.with_rendered_out(options, &mut vars)?;
         let checksum = checksum_metadata_from_options(&options)?;
         let options = with_resolved_save_dir(options, save_dir.clone());
+        // The payload download is added later from the fetched torrent, with
+        // the same options minus the metadata-only switch.
+        let prefetch_options = (options.metadata_only == Some(true)).then(|| {
+            to_aria2_options(AddTaskOptions {
+                metadata_only: None,
+                ..options.clone()
+            })
+        });
         let task_id = Uuid::new_v4().to_string();
-        let gid = self
-            .aria2
-            .add_uri(vec![magnet.to_string()], Some(to_aria2_options(options)))
-            .await?;
+        let submission = Submission::Uri(magnet.to_string());
+        let gid = if admission.waiting {
+            self.hold_submission(&task_id, submission, &options)?;
+            None
+        } else {
+            Some(
+                self.submit_to_engine(engine_id.as_deref(), submission, options, &admission)
+                    .await?,
+            )
+        };
 
         let now = now_ts();
-        self.db.upsert_task(&Task {
+        let mut task = Task {
             id: task_id.clone(),
-            aria2_gid: Some(gid),
+            aria2_gid: gid,
+            engine_id,
             task_type: TaskType::Magnet,
             source: magnet.to_string(),
-            status: TaskStatus::Metadata,
+            status: TaskStatus::Queued,
             name: None,
             category,
             save_dir,
@@ -611,13 +945,10 @@ impl DownloadService {
             download_speed: 0,
             upload_speed: 0,
             connections: 0,
-            health: Some(TaskHealth::MetadataPending.as_str().to_string()),
+            health: Some(TaskHealth::Normal.as_str().to_string()),
             error_code: None,
             error_message: None,
-            remediation: Some(
-                "Wait for metadata, add trackers, or retry the magnet link if it times out."
-                    .to_string(),
-            ),
+            remediation: None,
             retry_count: 0,
             last_retry_at: None,
             checksum_algorithm: checksum.algorithm,
@@ -626,10 +957,27 @@ impl DownloadService {
             checksum_status: checksum.status,
             created_at: now,
             updated_at: now,
-        })?;
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
+        };
+        if !admission.waiting && !admission.paused() {
+            mark_awaiting_metadata(&mut task);
+        }
+        admission.apply(&mut task);
+        self.db.upsert_task(&task)?;
+        self.db
+            .set_task_dependencies(&task_id, &admission.depends_on)?;
+        if let Some(prefetch_options) = prefetch_options {
+            self.db
+                .insert_magnet_prefetch(&task_id, &prefetch_options)?;
+        }
         self.push_log("add_magnet", "magnet task created".to_string());
 
-        Ok(task_id)
+        Ok(created_outcome(task_id, duplicate))
     }
 
     pub async fn add_torrent_from_file(
@@ -648,39 +996,96 @@ impl DownloadService {
         .await
     }
 
+    pub async fn inspect_torrent_from_file(&self, torrent_path: &str) -> Result<TorrentInfo> {
+        let bytes = tokio::fs::read(torrent_path)
+            .await
+            .map_err(|e| anyhow!("read torrent file failed: {e}"))?;
+        parse_torrent(&bytes)
+            .map_err(|e| AppError::InvalidInput(format!("invalid torrent: {e}")).into())
+    }
+
+    pub fn inspect_torrent_base64(&self, torrent_base64: &str) -> Result<TorrentInfo> {
+        let bytes = STANDARD
+            .decode(torrent_base64.trim().as_bytes())
+            .map_err(|e| AppError::InvalidInput(format!("invalid torrent base64: {e}")))?;
+        parse_torrent(&bytes)
+            .map_err(|e| AppError::InvalidInput(format!("invalid torrent: {e}")).into())
+    }
+
     pub async fn add_torrent_base64(
         &self,
         torrent_base64: String,
-        options: AddTaskOptions,
+        mut options: AddTaskOptions,
         source_label: Option<String>,
     ) -> Result<String> {
-        self.ensure_aria2_ready().await?;
         let source = source_label
             .clone()
             .unwrap_or_else(|| "torrent:base64".to_string());
-        let save_dir =
-            self.resolve_save_dir_for_new_task(TaskType::Torrent, &source, &options, None)?;
+        let selecting = options
+            .select_file
+            .as_deref()
+            .is_some_and(|v| !v.trim().is_empty())
+            || !options.file_priorities.is_empty();
+        let info = match self.inspect_torrent_base64(&torrent_base64) {
+            Ok(info) => Some(info),
+            Err(e) if selecting => return Err(e),
+            Err(_) => None,
+        };
+        let mut later_select_file = None;
+        if let Some(info) = info.as_ref().filter(|_| selecting) {
+            let (initial, later) = torrent_file_plan(info, &options)?;
+            options.select_file = initial;
+            later_select_file = later;
+        }
+        let mut facts = RuleFacts::for_options(&options);
         let category = self
             .resolve_requested_category(options.category.as_deref())
-            .or(self.resolve_category_for_new_task(TaskType::Torrent, &source, None)?);
+            .or(self.resolve_category_for_new_task(TaskType::Torrent, &source, &facts)?);
+        facts.category = category.clone();
+        let options = self.apply_policy_rules(&TaskType::Torrent, &source, options, &facts)?;
+        let engine_id = self.resolve_engine_for_new_task(
+            &TaskType::Torrent,
+            &source,
+            category.as_deref(),
+            &options,
+            &facts,
+        )?;
+        let admission = self.admit_new_task(&options)?;
+        let mut vars = self.template_vars(&TaskType::Torrent, &source, &options, &facts);
+        let save_dir = self.resolve_save_dir_for_engine(
+            engine_id.as_deref(),
+            TaskType::Torrent,
+            &source,
+            &options,
+            &facts,
+            &mut vars,
+        )?;
+        let options = self.with_rendered_out(options, &mut vars)?;
         let checksum = checksum_metadata_from_options(&options)?;
         let options = with_resolved_save_dir(options, save_dir.clone());
 
         let task_id = Uuid::new_v4().to_string();
-        let gid = self
-            .aria2
-            .add_torrent(torrent_base64, vec![], Some(to_aria2_options(options)))
-            .await?;
+        let submission = Submission::Torrent(torrent_base64);
+        let gid = if admission.waiting {
+            self.hold_submission(&task_id, submission, &options)?;
+            None
+        } else {
+            Some(
+                self.submit_to_engine(engine_id.as_deref(), submission, options, &admission)
+                    .await?,
+            )
+        };
 
         let now = now_ts();
 
-        self.db.upsert_task(&Task {
+        let mut task = Task {
             id: task_id.clone(),
-            aria2_gid: Some(gid),
+            aria2_gid: gid,
+            engine_id,
             task_type: TaskType::Torrent,
             source,
             status: TaskStatus::Queued,
-            name: None,
+            name: info.map(|info| info.name),
             category,
             save_dir,
             total_length: 0,
@@ -700,44 +1105,319 @@ impl DownloadService {
             checksum_status: checksum.status,
             created_at: now,
             updated_at: now,
-        })?;
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
+        };
+        admission.apply(&mut task);
+        self.db.upsert_task(&task)?;
+        self.db
+            .set_task_dependencies(&task_id, &admission.depends_on)?;
+        if let Some(select_file) = later_select_file.as_deref() {
+            self.db.set_task_file_stage(&task_id, Some(select_file))?;
+        }
         self.push_log("add_torrent", "torrent task created".to_string());
 
         Ok(task_id)
     }
 
+    pub async fn add_metalink_from_file(
+        &self,
+        metalink_path: &str,
+        options: AddTaskOptions,
+    ) -> Result<Vec<String>> {
+        let bytes = tokio::fs::read(metalink_path)
+            .await
+            .map_err(|e| anyhow!("read metalink file failed: {e}"))?;
+        self.add_metalink_base64(
+            STANDARD.encode(bytes),
+            options,
+            Some(metalink_path.to_string()),
+        )
+        .await
+    }
+
+    /// Adds one task per file of the metalink, each with the file's mirrors and
+    /// its strongest hash; aria2 checks the piece hashes itself.
+    pub async fn add_metalink_base64(
+        &self,
+        metalink_base64: String,
+        options: AddTaskOptions,
+        source_label: Option<String>,
+    ) -> Result<Vec<String>> {
+        let xml = STANDARD
+            .decode(metalink_base64.trim().as_bytes())
+            .map_err(|e| AppError::InvalidInput(format!("invalid metalink base64: {e}")))?;
+        let files = parse_metalink(&String::from_utf8_lossy(&xml))
+            .map_err(|e| AppError::InvalidInput(format!("invalid metalink: {e}")))?;
+        let source = source_label.unwrap_or_else(|| "metalink:base64".to_string());
+        let mut facts = RuleFacts::for_options(&options);
+        facts.content_length = files.iter().filter_map(|f| f.size).reduce(|a, b| a + b);
+        let category = self
+            .resolve_requested_category(options.category.as_deref())
+            .or(self.resolve_category_for_new_task(TaskType::Metalink, &source, &facts)?);
+        facts.category = category.clone();
+        let options = self.apply_policy_rules(&TaskType::Metalink, &source, options, &facts)?;
+        let engine_id = self.resolve_engine_for_new_task(
+            &TaskType::Metalink,
+            &source,
+            category.as_deref(),
+            &options,
+            &facts,
+        )?;
+        let mut vars = self.template_vars(&TaskType::Metalink, &source, &options, &facts);
+        let save_dir = self.resolve_save_dir_for_engine(
+            engine_id.as_deref(),
+            TaskType::Metalink,
+            &source,
+            &options,
+            &facts,
+            &mut vars,
+        )?;
+        let options = with_resolved_save_dir(options, save_dir.clone());
+
+        let multi_file = files.len() > 1;
+        let mut task_ids = Vec::with_capacity(files.len());
+        for (index, file) in files.into_iter().enumerate() {
+            let mut file_options = options.clone();
+            if multi_file {
+                file_options.select_file = Some((index + 1).to_string());
+            }
+            // A checksum passed in only applies to a single-file metalink.
+            let checksum = match file.best_hash() {
+                Some((algorithm, expected))
+                    if multi_file || file_options.checksum_algorithm.is_none() =>
+                {
+                    checksum_metadata_from_raw(Some(algorithm), Some(expected))?
+                }
+                None if multi_file => checksum_metadata_from_raw(None, None)?,
+                _ => checksum_metadata_from_options(&file_options)?,
+            };
+            let admission = self.admit_new_task(&file_options)?;
+            let task_id = Uuid::new_v4().to_string();
+            let submission = Submission::Metalink(metalink_base64.clone());
+            let gid = if admission.waiting {
+                self.hold_submission(&task_id, submission, &file_options)?;
+                None
+            } else {
+                Some(
+                    self.submit_to_engine(
+                        engine_id.as_deref(),
+                        submission,
+                        file_options,
+                        &admission,
+                    )
+                    .await?,
+                )
+            };
+
+            let now = now_ts();
+            let mut task = Task {
+                id: task_id.clone(),
+                aria2_gid: gid,
+                engine_id: engine_id.clone(),
+                task_type: TaskType::Metalink,
+                source: source.clone(),
+                status: TaskStatus::Queued,
+                name: Some(file.name.clone()),
+                category: category.clone(),
+                save_dir: save_dir.clone(),
+                total_length: file.size.unwrap_or(0),
+                completed_length: 0,
+                download_speed: 0,
+                upload_speed: 0,
+                connections: 0,
+                health: Some(TaskHealth::Normal.as_str().to_string()),
+                error_code: None,
+                error_message: None,
+                remediation: None,
+                retry_count: 0,
+                last_retry_at: None,
+                checksum_algorithm: checksum.algorithm,
+                checksum_expected: checksum.expected,
+                checksum_actual: None,
+                checksum_status: checksum.status,
+                created_at: now,
+                updated_at: now,
+                depends_on: Vec::new(),
+                start_at: None,
+                window: None,
+                schedule_held: false,
+                queue: None,
+                queue_held: false,
+            };
+            admission.apply(&mut task);
+            self.db.upsert_task(&task)?;
+            self.db
+                .set_task_dependencies(&task_id, &admission.depends_on)?;
+            self.db.set_task_mirrors(&task_id, &file.mirrors)?;
+            task_ids.push(task_id);
+        }
+        self.push_log(
+            "add_metalink",
+            format!("{} metalink task(s) created from {source}", task_ids.len()),
+        );
+        Ok(task_ids)
+    }
+
+    pub fn list_task_mirrors(&self, task_id: &str) -> Result<Vec<TaskMirror>> {
+        self.db.list_task_mirrors(task_id)
+    }
+
+    /// Works out how a new task enters the engine from its options.
+    fn admit_new_task(&self, options: &AddTaskOptions) -> Result<Admission> {
+        let (depends_on, waiting) = self.check_task_dependencies(options)?;
+        let (start_at, window) = task_schedule_from_options(options)?;
+        let queue = self.resolve_task_queue(options.queue.as_deref())?;
+        let schedule_held = !waiting && !schedule_allows(start_at, window.as_ref(), &Local::now());
+        let queue_held = !waiting
+            && !schedule_held
+            && queue
+                .as_ref()
+                .map(|queue| self.queue_is_full(queue))
+                .transpose()?
+                .unwrap_or(false);
+        Ok(Admission {
+            depends_on,
+            waiting,
+            start_at,
+            window,
+            schedule_held,
+            queue_priority: queue.as_ref().map_or(0, |queue| queue.priority),
+            queue: queue.map(|queue| queue.name),
+            queue_held,
+        })
+    }
+
+    /// Validates `depends_on` and reports whether the new task has to wait for it.
+    fn check_task_dependencies(&self, options: &AddTaskOptions) -> Result<(Vec<String>, bool)> {
+        dependency_failure_policy(options.on_dependency_failure.as_deref())?;
+        let mut depends_on: Vec<String> = Vec::new();
+        let mut waiting = false;
+        for task_id in options.depends_on.iter().map(|v| v.trim()) {
+            if task_id.is_empty() || depends_on.iter().any(|v| v == task_id) {
+                continue;
+            }
+            let prerequisite = self
+                .db
+                .get_task(task_id)?
+                .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
+            waiting |= prerequisite.status != TaskStatus::Completed;
+            depends_on.push(task_id.to_string());
+        }
+        Ok((depends_on, waiting))
+    }
+
+    fn hold_submission(
+        &self,
+        task_id: &str,
+        submission: Submission,
+        options: &AddTaskOptions,
+    ) -> Result<()> {
+        let (kind, payload) = match submission {
+            Submission::Uri(uri) => ("uri", uri),
+            Submission::Torrent(torrent) => ("torrent", torrent),
+            Submission::Metalink(metalink) => ("metalink", metalink),
+        };
+        self.db.upsert_pending_submission(&PendingSubmission {
+            task_id: task_id.to_string(),
+            kind: kind.to_string(),
+            payload,
+            options: serde_json::to_string(options)?,
+            on_dependency_failure: dependency_failure_policy(
+                options.on_dependency_failure.as_deref(),
+            )?
+            .to_string(),
+            created_at: now_ts(),
+        })
+    }
+
+    /// Adds the download, paused when its schedule or queue holds it back, and moves
+    /// tasks of a priority queue to the front of the engine's waiting list.
+    async fn submit_to_engine(
+        &self,
+        engine_id: Option<&str>,
+        submission: Submission,
+        options: AddTaskOptions,
+        admission: &Admission,
+    ) -> Result<String> {
+        let engine = self.ready_engine(engine_id).await?;
+        let mirrors = options.mirrors.clone();
+        let mut aria2_options = to_aria2_options(options);
+        if admission.paused() {
+            aria2_options["pause"] = json!("true");
+        }
+        let gid = match submission {
+            Submission::Uri(uri) => {
+                let uris = std::iter::once(uri).chain(mirrors).collect();
+                engine.add_uri(uris, Some(aria2_options)).await?
+            }
+            Submission::Torrent(torrent) => {
+                engine
+                    .add_torrent(torrent, vec![], Some(aria2_options))
+                    .await?
+            }
+            Submission::Metalink(metalink) => engine
+                .add_metalink(metalink, Some(aria2_options))
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("engine added no download for the metalink"))?,
+        };
+        if admission.queue_priority > 0 && !admission.paused() {
+            let _ = engine.change_position(&gid, 0, "POS_SET").await;
+        }
+        Ok(gid)
+    }
+
     pub fn suggest_save_dir(&self, task_type: TaskType, source: Option<&str>) -> Result<String> {
-        self.suggest_save_dir_detail(task_type, source)
+        self.suggest_save_dir_detail(task_type, source, None)
             .map(|v| v.save_dir)
     }
 
+    /// Previews where a task would go, including rendered templates, without
+    /// using up a `{counter}` value.
     pub fn suggest_save_dir_detail(
         &self,
         task_type: TaskType,
         source: Option<&str>,
+        out: Option<&str>,
     ) -> Result<SaveDirSuggestion> {
-        let (save_dir, matched_rule) = self.resolve_save_dir_for_new_task_detail(
-            task_type,
-            source.unwrap_or_default(),
-            &AddTaskOptions::default(),
-            None,
-        )?;
+        let source = source.unwrap_or_default();
+        let options = AddTaskOptions {
+            out: out.map(ToString::to_string),
+            ..AddTaskOptions::default()
+        };
+        let mut facts = RuleFacts::default();
+        facts.category = self.resolve_category_for_new_task(task_type.clone(), source, &facts)?;
+        let mut vars = self.template_vars(&task_type, source, &options, &facts);
+        vars.counter = Some(self.peek_template_counter()?);
+        let (save_dir, matched_rule) = self
+            .resolve_save_dir_for_new_task_detail(task_type, source, &options, &facts, &mut vars)?;
+        let out = self.with_rendered_out(options, &mut vars)?.out;
         Ok(SaveDirSuggestion {
             save_dir,
             matched_rule,
+            out,
         })
     }
 
     pub async fn pause_task(&self, task_id: &str) -> Result<()> {
-        self.ensure_aria2_ready().await?;
         let task = self
             .db
             .get_task(task_id)?
             .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
+        if task.aria2_gid.is_none() && self.db.has_pending_submission(task_id)? {
+            return self.set_held_task_status(task, TaskStatus::Paused);
+        }
         let gid = task
             .aria2_gid
             .ok_or_else(|| AppError::InvalidInput("task has no aria2 gid".to_string()))?;
-        self.aria2.pause(&gid).await?;
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+        engine.pause(&gid).await?;
         self.push_log("pause_task", format!("paused task {task_id}"));
         Ok(())
     }
@@ -745,12 +1425,14 @@ impl DownloadService {
     pub async fn pause_all(&self) -> Result<()> {
         self.ensure_aria2_ready().await?;
         self.aria2.pause_all().await?;
+        for (_, engine) in self.attached_engines().await {
+            let _ = engine.pause_all().await;
+        }
         self.push_log("pause_all", "paused all tasks".to_string());
         Ok(())
     }
 
     pub async fn move_task_position(&self, task_id: &str, action: &str) -> Result<()> {
-        self.ensure_aria2_ready().await?;
         let task = self
             .db
             .get_task(task_id)?
@@ -775,7 +1457,11 @@ impl DownloadService {
                 .into());
             }
         };
-        let new_pos = self.aria2.change_position(&gid, pos, how).await?;
+        let new_pos = self
+            .ready_engine(task.engine_id.as_deref())
+            .await?
+            .change_position(&gid, pos, how)
+            .await?;
         self.push_log(
             "move_task_position",
             format!("task={task_id}, action={action}, new_pos={new_pos}"),
@@ -784,21 +1470,51 @@ impl DownloadService {
     }
 
     pub async fn resume_task(&self, task_id: &str) -> Result<()> {
-        self.ensure_aria2_ready().await?;
         let task = self
             .db
             .get_task(task_id)?
             .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
+        if task.aria2_gid.is_none() && self.db.has_pending_submission(task_id)? {
+            return self.set_held_task_status(task, TaskStatus::Queued);
+        }
         let gid = task
             .aria2_gid
+            .clone()
             .ok_or_else(|| AppError::InvalidInput("task has no aria2 gid".to_string()))?;
-        self.aria2.unpause(&gid).await?;
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+        engine.unpause(&gid).await?;
+        self.clear_resume_holds(task)?;
         self.push_log("resume_task", format!("resumed task {task_id}"));
         Ok(())
     }
 
+    /// A manual resume overrides the schedule until its window next closes,
+    /// and jumps the queue.
+    fn clear_resume_holds(&self, mut task: Task) -> Result<()> {
+        if task.schedule_held || task.queue_held {
+            task.schedule_held = false;
+            task.queue_held = false;
+            task.updated_at = now_ts();
+            self.db.upsert_task(&task)?;
+        }
+        Ok(())
+    }
+
+    /// Pauses or resumes a task that is still waiting on its dependencies.
+    fn set_held_task_status(&self, mut task: Task, status: TaskStatus) -> Result<()> {
+        let action = if status == TaskStatus::Paused {
+            "pause_task"
+        } else {
+            "resume_task"
+        };
+        task.status = status;
+        task.updated_at = now_ts();
+        self.db.upsert_task(&task)?;
+        self.push_log(action, format!("{action} on held task {}", task.id));
+        Ok(())
+    }
+
     pub async fn stop_seeding(&self, task_id: &str) -> Result<()> {
-        self.ensure_aria2_ready().await?;
         let task = self
             .db
             .get_task(task_id)?
@@ -808,23 +1524,315 @@ impl DownloadService {
                 AppError::InvalidInput("stop seeding only supports bt tasks".to_string()).into(),
             );
         }
+        self.finish_seeding(task, "manual").await
+    }
+
+    /// Removes a seeding torrent from aria2 and keeps the task as completed,
+    /// along with why it stopped.
+    async fn finish_seeding(&self, mut task: Task, reason: &str) -> Result<()> {
         let gid = task
             .aria2_gid
+            .clone()
             .ok_or_else(|| AppError::InvalidInput("task has no aria2 gid".to_string()))?;
-        self.aria2.remove(&gid, true).await?;
-        self.push_log("stop_seeding", format!("stopped seeding task {task_id}"));
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+        engine.remove(&gid, true).await?;
+        // Otherwise the next sync would see the gid as removed.
+        let _ = engine.remove_download_result(&gid).await;
+        self.db.set_task_seed_stopped(&task.id, reason)?;
+        if task.total_length > 0 && task.completed_length >= task.total_length {
+            task.status = TaskStatus::Completed;
+            task.download_speed = 0;
+            task.upload_speed = 0;
+            task.connections = 0;
+            task.updated_at = now_ts();
+            self.db.upsert_task(&task)?;
+            let _ = self.emitter.emit_task_update(std::slice::from_ref(&task));
+        }
+        self.push_log(
+            "stop_seeding",
+            format!("stopped seeding task {} ({reason})", task.id),
+        );
         Ok(())
     }
 
-    pub async fn resume_all(&self) -> Result<()> {
-        self.ensure_aria2_ready().await?;
-        self.aria2.unpause_all().await?;
-        self.push_log("resume_all", "resumed all tasks".to_string());
-        Ok(())
+    pub fn list_task_seeding(&self) -> Result<Vec<TaskSeeding>> {
+        self.db.list_task_seeding()
     }
 
-    pub async fn remove_task(&self, task_id: &str, delete_files: bool) -> Result<()> {
-        let task = self
+    /// Downloaded and uploaded bytes over time; without `from` it covers the
+    /// last hour of minutes, two days of hours, 30 days or twelve months.
+    pub fn get_bandwidth_history(
+        &self,
+        query: BandwidthHistoryQuery,
+    ) -> Result<Vec<BandwidthSample>> {
+        let resolution = query.resolution.as_deref().unwrap_or("day").trim();
+        let span = match resolution {
+            "minute" => 3600,
+            "hour" => 2 * 86_400,
+            "day" => 30 * 86_400,
+            "month" => 365 * 86_400,
+            other => {
+                return Err(AppError::InvalidInput(format!(
+                    "invalid resolution={other}, expected minute, hour, day or month"
+                ))
+                .into());
+            }
+        };
+        let group_by = query
+            .group_by
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+        if let Some(other) = group_by.filter(|v| !matches!(*v, "task" | "category" | "domain")) {
+            return Err(AppError::InvalidInput(format!(
+                "invalid group_by={other}, expected task, category or domain"
+            ))
+            .into());
+        }
+        let to = query.to.unwrap_or_else(|| now_ts() + 1);
+        let from = query.from.unwrap_or(to - span);
+        let filter = |v: &Option<String>| {
+            v.as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(ToString::to_string)
+        };
+        let (task_id, category, domain) = (
+            filter(&query.task_id),
+            filter(&query.category),
+            filter(&query.domain).map(|v| v.to_ascii_lowercase()),
+        );
+        self.db.bandwidth_history(
+            resolution,
+            group_by,
+            from,
+            to,
+            (task_id.as_deref(), category.as_deref(), domain.as_deref()),
+        )
+    }
+
+    fn prune_transfer_history(&self) -> Result<()> {
+        let now = now_ts();
+        self.db
+            .prune_transfer_history("minute", now - MINUTE_HISTORY_RETENTION_SECS)?;
+        self.db
+            .prune_transfer_history("hour", now - HOUR_HISTORY_RETENTION_SECS)?;
+        let days = self
+            .get_global_settings()?
+            .transfer_history_days
+            .unwrap_or(400);
+        if days > 0 {
+            self.db
+                .prune_transfer_history("day", now - i64::from(days) * 86_400)?;
+        }
+        Ok(())
+    }
+
+    pub async fn resume_all(&self) -> Result<()> {
+        self.ensure_aria2_ready().await?;
+        self.aria2.unpause_all().await?;
+        for (_, engine) in self.attached_engines().await {
+            let _ = engine.unpause_all().await;
+        }
+        self.push_log("resume_all", "resumed all tasks".to_string());
+        Ok(())
+    }
+
+    pub async fn pause_tasks(&self, task_ids: &[String]) -> Result<BulkTaskActionResult> {
+        let (groups, held, mut result) = self.resolve_bulk_gids(task_ids)?;
+        self.set_held_tasks_status(held, TaskStatus::Paused, &mut result);
+        for (engine_id, targets) in groups {
+            let gids = targets
+                .iter()
+                .map(|(_, gid)| gid.clone())
+                .collect::<Vec<_>>();
+            let outcomes = match self.ready_engine(engine_id.as_deref()).await {
+                Ok(engine) => engine.pause_many(&gids).await,
+                Err(e) => Err(e),
+            };
+            collect_bulk_outcomes(&targets, outcomes, &mut result);
+        }
+        self.push_log(
+            "pause_tasks",
+            format!(
+                "paused {} task(s), {} failed",
+                result.succeeded.len(),
+                result.failed.len()
+            ),
+        );
+        Ok(result)
+    }
+
+    pub async fn resume_tasks(&self, task_ids: &[String]) -> Result<BulkTaskActionResult> {
+        let (groups, held, mut result) = self.resolve_bulk_gids(task_ids)?;
+        self.set_held_tasks_status(held, TaskStatus::Queued, &mut result);
+        for (engine_id, targets) in groups {
+            let gids = targets
+                .iter()
+                .map(|(_, gid)| gid.clone())
+                .collect::<Vec<_>>();
+            let outcomes = match self.ready_engine(engine_id.as_deref()).await {
+                Ok(engine) => engine.unpause_many(&gids).await,
+                Err(e) => Err(e),
+            };
+            let resumed_from = result.succeeded.len();
+            collect_bulk_outcomes(&targets, outcomes, &mut result);
+            for task_id in &result.succeeded[resumed_from..] {
+                if let Some(task) = self.db.get_task(task_id)? {
+                    self.clear_resume_holds(task)?;
+                }
+            }
+        }
+        self.push_log(
+            "resume_tasks",
+            format!(
+                "resumed {} task(s), {} failed",
+                result.succeeded.len(),
+                result.failed.len()
+            ),
+        );
+        Ok(result)
+    }
+
+    pub async fn remove_tasks(
+        &self,
+        task_ids: &[String],
+        delete_files: bool,
+    ) -> Result<BulkTaskActionResult> {
+        let mut result = BulkTaskActionResult::default();
+        let mut removable = Vec::with_capacity(task_ids.len());
+        let mut gids_by_engine: Vec<(Option<String>, Vec<String>)> = Vec::new();
+        let now = now_ts();
+        for task_id in task_ids {
+            let task = match self.db.get_task(task_id)? {
+                Some(task) => task,
+                None => {
+                    result.failed.push(BulkTaskFailure {
+                        task_id: task_id.clone(),
+                        error: AppError::TaskNotFound(task_id.clone()).to_string(),
+                    });
+                    continue;
+                }
+            };
+            if delete_files && let Err(e) = self.delete_task_files_safely(&task) {
+                result.failed.push(BulkTaskFailure {
+                    task_id: task_id.clone(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+            match task.aria2_gid.as_ref() {
+                Some(gid) => {
+                    // Same ordering as remove_task: mark first so reconcile never revives it.
+                    let _ = self.db.mark_deleted_gid(gid, now);
+                    let engine_id = normalize_engine_id(task.engine_id.as_deref());
+                    match gids_by_engine.iter_mut().find(|(id, _)| *id == engine_id) {
+                        Some((_, gids)) => gids.push(gid.clone()),
+                        None => gids_by_engine.push((engine_id, vec![gid.clone()])),
+                    }
+                }
+                None if matches!(task.status, TaskStatus::Active | TaskStatus::Queued) => {
+                    let _ = self.cancel_merge_process(task_id);
+                }
+                None => {}
+            }
+            removable.push(task.id);
+        }
+
+        for (engine_id, gids) in gids_by_engine {
+            let Ok(engine) = self.engine_for(engine_id.as_deref()) else {
+                continue;
+            };
+            let _ = time::timeout(Duration::from_secs(10), engine.remove_many(&gids, true)).await;
+            let _ = time::timeout(
+                Duration::from_secs(10),
+                engine.remove_download_result_many(&gids),
+            )
+            .await;
+            let _ = time::timeout(Duration::from_millis(1200), engine.save_session()).await;
+        }
+
+        for task_id in removable {
+            match self.db.remove_task(&task_id) {
+                Ok(()) => result.succeeded.push(task_id),
+                Err(e) => result.failed.push(BulkTaskFailure {
+                    task_id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        self.push_log(
+            "remove_tasks",
+            format!(
+                "removed {} task(s), {} failed, delete_files={delete_files}",
+                result.succeeded.len(),
+                result.failed.len()
+            ),
+        );
+        Ok(result)
+    }
+
+    /// Resolves task ids to gids, grouped by the engine that owns them; tasks
+    /// still held on their dependencies come back separately.
+    fn resolve_bulk_gids(
+        &self,
+        task_ids: &[String],
+    ) -> Result<(BulkGidGroups, Vec<Task>, BulkTaskActionResult)> {
+        let mut groups: BulkGidGroups = Vec::new();
+        let mut held = Vec::new();
+        let mut result = BulkTaskActionResult::default();
+        for task_id in task_ids {
+            match self.db.get_task(task_id)? {
+                Some(task)
+                    if task.aria2_gid.is_none() && self.db.has_pending_submission(task_id)? =>
+                {
+                    held.push(task);
+                }
+                Some(Task {
+                    aria2_gid: Some(gid),
+                    engine_id,
+                    ..
+                }) => {
+                    let engine_id = normalize_engine_id(engine_id.as_deref());
+                    let target = (task_id.clone(), gid);
+                    match groups.iter_mut().find(|(id, _)| *id == engine_id) {
+                        Some((_, targets)) => targets.push(target),
+                        None => groups.push((engine_id, vec![target])),
+                    }
+                }
+                Some(_) => result.failed.push(BulkTaskFailure {
+                    task_id: task_id.clone(),
+                    error: "task has no aria2 gid".to_string(),
+                }),
+                None => result.failed.push(BulkTaskFailure {
+                    task_id: task_id.clone(),
+                    error: AppError::TaskNotFound(task_id.clone()).to_string(),
+                }),
+            }
+        }
+        Ok((groups, held, result))
+    }
+
+    fn set_held_tasks_status(
+        &self,
+        held: Vec<Task>,
+        status: TaskStatus,
+        result: &mut BulkTaskActionResult,
+    ) {
+        for task in held {
+            let task_id = task.id.clone();
+            match self.set_held_task_status(task, status.clone()) {
+                Ok(()) => result.succeeded.push(task_id),
+                Err(e) => result.failed.push(BulkTaskFailure {
+                    task_id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+    }
+
+    pub async fn remove_task(&self, task_id: &str, delete_files: bool) -> Result<()> {
+        let task = self
             .db
             .get_task(task_id)?
             .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
@@ -838,15 +1846,18 @@ impl DownloadService {
             // recreate this user-deleted task after restart.
             let _ = self.db.mark_deleted_gid(gid, now_ts());
             // Best effort: remove running/waiting task, then purge stopped result.
-            let _ = time::timeout(Duration::from_millis(1200), self.aria2.remove(gid, true)).await;
-            let _ = time::timeout(
-                Duration::from_millis(1200),
-                self.aria2.remove_download_result(gid),
-            )
-            .await;
-            // Persist aria2 session immediately so a quick app restart doesn't reload
-            // stale tasks from the previous save-session interval.
-            let _ = time::timeout(Duration::from_millis(1200), self.aria2.save_session()).await;
+            // A task whose engine profile was deleted has nothing left to remove.
+            if let Ok(engine) = self.engine_for(task.engine_id.as_deref()) {
+                let _ = time::timeout(Duration::from_millis(1200), engine.remove(gid, true)).await;
+                let _ = time::timeout(
+                    Duration::from_millis(1200),
+                    engine.remove_download_result(gid),
+                )
+                .await;
+                // Persist aria2 session immediately so a quick app restart doesn't reload
+                // stale tasks from the previous save-session interval.
+                let _ = time::timeout(Duration::from_millis(1200), engine.save_session()).await;
+            }
         } else if matches!(task.status, TaskStatus::Active | TaskStatus::Queued) {
             let _ = self.cancel_merge_process(task_id);
         }
@@ -1123,6 +2134,7 @@ impl DownloadService {
 
     fn verify_task_checksum(&self, task: &mut Task) -> Result<bool> {
         if task.status != TaskStatus::Completed
+            || !is_local_task(task)
             || task.checksum_status.as_deref() != Some("pending")
             || task.checksum_algorithm.is_none()
             || task.checksum_expected.is_none()
@@ -1310,6 +2322,40 @@ impl DownloadService {
         Ok(())
     }
 
+    pub async fn set_task_schedule(
+        &self,
+        task_id: &str,
+        start_at: Option<i64>,
+        window: Option<TaskWindow>,
+    ) -> Result<()> {
+        if let Some(window) = window.as_ref() {
+            validate_task_window(window)?;
+        }
+        let mut task = self
+            .db
+            .get_task(task_id)?
+            .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
+        task.start_at = start_at;
+        task.window = window;
+        task.updated_at = now_ts();
+        self.db.upsert_task(&task)?;
+        self.schedule_state
+            .lock()
+            .expect("schedule_state mutex poisoned")
+            .remove(task_id);
+        self.push_log(
+            "set_task_schedule",
+            format!(
+                "task {task_id} schedule set to start_at={}, window={}",
+                start_at.map_or_else(|| "<none>".to_string(), |v| v.to_string()),
+                task.window
+                    .as_ref()
+                    .map_or_else(|| "<none>".to_string(), describe_task_window)
+            ),
+        );
+        self.enforce_task_schedules().await
+    }
+
     pub async fn get_task_detail(&self, task_id: &str) -> Result<(Task, Vec<TaskFile>)> {
         let mut task = self
             .db
@@ -1317,7 +2363,8 @@ impl DownloadService {
             .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
 
         if let Some(gid) = task.aria2_gid.clone()
-            && let Ok(status_value) = self.aria2.tell_status(&gid).await
+            && let Ok(engine) = self.engine_for(task.engine_id.as_deref())
+            && let Ok(status_value) = engine.tell_status(&gid).await
         {
             let files = extract_task_files(task_id, &status_value);
             if !files.is_empty() {
@@ -1345,8 +2392,8 @@ impl DownloadService {
         let gid = task
             .aria2_gid
             .ok_or_else(|| AppError::InvalidInput("task has no aria2 gid".to_string()))?;
-        self.ensure_aria2_ready().await?;
-        let status = self.aria2.tell_status(&gid).await?;
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+        let status = engine.tell_status(&gid).await?;
 
         let trackers = status
             .get("bittorrent")
@@ -1364,7 +2411,7 @@ impl DownloadService {
             .unwrap_or_default();
 
         let peers = if task.task_type == TaskType::Torrent || task.task_type == TaskType::Magnet {
-            self.aria2.get_peers(&gid).await.unwrap_or_default()
+            engine.get_peers(&gid).await.unwrap_or_default()
         } else {
             Vec::new()
         };
@@ -1375,13 +2422,42 @@ impl DownloadService {
             .or_else(|| status.get("numSeeders").and_then(Value::as_i64))
             .unwrap_or_default();
 
+        let trackers_health = trackers
+            .iter()
+            .map(|uri| {
+                Ok(self
+                    .db
+                    .get_tracker_health(uri)?
+                    .unwrap_or_else(|| TrackerHealth {
+                        uri: uri.clone(),
+                        status: "unknown".to_string(),
+                        checked_at: None,
+                        error: None,
+                    }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let trackers_reachable = trackers_health.iter().filter(|h| h.status == "ok").count();
+        let overrides = self.db.list_task_trackers(task_id)?;
+        let override_list = |excluded: bool| {
+            overrides
+                .iter()
+                .filter(|(_, e)| *e == excluded)
+                .map(|(uri, _)| uri.clone())
+                .collect::<Vec<_>>()
+        };
+
         Ok(json!({
             "raw": status,
             "summary": {
                 "peers_count": peers.len(),
                 "seeders_count": num_seeders,
                 "trackers_count": trackers.len(),
-                "trackers": trackers
+                "trackers": trackers,
+                "trackers_reachable": trackers_reachable,
+                "trackers_health": trackers_health,
+                "trackers_added": override_list(false),
+                "trackers_removed": override_list(true),
+                "seeding": self.db.get_task_seeding(task_id)?
             },
             "peers": peers
         }))
@@ -1392,7 +2468,6 @@ impl DownloadService {
         task_id: &str,
         selected_indexes: &[usize],
     ) -> Result<()> {
-        self.ensure_aria2_ready().await?;
         let task = self
             .db
             .get_task(task_id)?
@@ -1400,20 +2475,185 @@ impl DownloadService {
         let gid = task
             .aria2_gid
             .ok_or_else(|| AppError::InvalidInput("task has no aria2 gid".to_string()))?;
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
 
-        let select_file = selected_indexes
-            .iter()
-            .map(|i| (i + 1).to_string())
-            .collect::<Vec<_>>()
-            .join(",");
-        self.aria2
+        let select_file = select_file_spec(selected_indexes);
+        engine
             .change_option(&gid, json!({ "select-file": select_file }))
             .await?;
+        // An explicit selection replaces whatever was staged at add time.
+        self.db.set_task_file_stage(task_id, None)?;
+        Ok(())
+    }
+
+    /// A finished metadata-only download is not the task finishing: it stays in
+    /// `Metadata` until `finish_magnet_prefetches` swaps in the payload download.
+    fn without_finished_prefetches<'a>(
+        &self,
+        snapshots: &'a [Aria2TaskSnapshot],
+    ) -> Cow<'a, [Aria2TaskSnapshot]> {
+        let prefetching = self.db.pending_magnet_prefetch_gids().unwrap_or_default();
+        if prefetching.is_empty() {
+            return Cow::Borrowed(snapshots);
+        }
+        Cow::Owned(
+            snapshots
+                .iter()
+                .filter(|s| !(s.status == "complete" && prefetching.contains(&s.gid)))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Hands metadata-only magnets over to the torrent aria2 saved for them:
+    /// the payload download is added paused with its file list filled in, so
+    /// files can be picked with `set_task_file_selection` before it is resumed.
+    async fn finish_magnet_prefetches(&self) -> Result<()> {
+        for (task_id, options) in self.db.list_pending_magnet_prefetches()? {
+            let Some(task) = self.db.get_task(&task_id)? else {
+                self.db.remove_magnet_prefetch(&task_id)?;
+                continue;
+            };
+            let Some(gid) = task.aria2_gid.clone() else {
+                continue;
+            };
+            let Ok(engine) = self.ready_engine(task.engine_id.as_deref()).await else {
+                continue;
+            };
+            match engine.tell_snapshot(&gid).await {
+                Ok(Some(snapshot)) if snapshot.status == "complete" => {}
+                _ => continue,
+            }
+            if let Err(e) = self
+                .add_prefetched_torrent(task.clone(), &gid, engine, options)
+                .await
+            {
+                let mut task = task;
+                task.status = TaskStatus::Error;
+                task.health = Some(TaskHealth::UnknownError.as_str().to_string());
+                task.error_message = Some(format!("metadata-only magnet: {e}"));
+                task.updated_at = now_ts();
+                self.db.upsert_task(&task)?;
+                self.db.remove_magnet_prefetch(&task_id)?;
+                let _ = self.emitter.emit_task_finished(&task);
+                let _ = self.emitter.emit_task_update(&[task]);
+            }
+        }
+        Ok(())
+    }
+
+    async fn add_prefetched_torrent(
+        &self,
+        mut task: Task,
+        metadata_gid: &str,
+        engine: Arc<dyn Aria2Api>,
+        mut options: Value,
+    ) -> Result<()> {
+        let infohash = magnet_infohash_hex(&task.source)
+            .ok_or_else(|| anyhow!("magnet has no btih infohash"))?;
+        let dir = match options.get("dir").and_then(Value::as_str) {
+            Some(dir) => dir.to_string(),
+            None => match self.engine_slot(task.engine_id.as_deref())? {
+                Some(slot) => slot.profile.download_dir.unwrap_or_default(),
+                None => self.configured_download_dir()?,
+            },
+        };
+        let path = Path::new(&dir).join(format!("{infohash}.torrent"));
+        let bytes = tokio::fs::read(&path)
+            .await
+            .map_err(|e| anyhow!("read saved torrent {} failed: {e}", path.display()))?;
+        let info = parse_torrent(&bytes).map_err(|e| anyhow!("invalid saved torrent: {e}"))?;
+        let torrent = STANDARD.encode(&bytes);
+
+        options["pause"] = json!("true");
+        let gid = engine
+            .add_torrent(torrent.clone(), vec![], Some(options))
+            .await?;
+        let now = now_ts();
+        let _ = engine.remove_download_result(metadata_gid).await;
+        let _ = self.db.mark_deleted_gid(metadata_gid, now);
+
+        let single = info.files.len() == 1 && info.files[0].path == info.name;
+        let files = info
+            .files
+            .iter()
+            .map(|file| TaskFile {
+                task_id: task.id.clone(),
+                path: if single {
+                    format!("{dir}/{}", info.name)
+                } else {
+                    format!("{dir}/{}/{}", info.name, file.path)
+                },
+                length: file.length,
+                completed_length: 0,
+                selected: true,
+            })
+            .collect::<Vec<_>>();
+        task.aria2_gid = Some(gid);
+        task.status = TaskStatus::Paused;
+        task.name = Some(info.name.clone());
+        task.total_length = info.total_length;
+        task.health = Some(TaskHealth::Normal.as_str().to_string());
+        task.remediation = None;
+        task.updated_at = now;
+        self.db.upsert_task(&task)?;
+        self.db.replace_task_files(&task.id, &files)?;
+        self.db.set_magnet_prefetch_torrent(&task.id, &torrent)?;
+        self.push_log(
+            "magnet_prefetch",
+            format!(
+                "task {}: metadata ready, {} files waiting for a selection",
+                task.id,
+                files.len()
+            ),
+        );
+        let _ = self.emitter.emit_task_update(&[task]);
+        Ok(())
+    }
+
+    /// The torrent a metadata-only magnet fetched, once it is in.
+    pub fn get_task_torrent(&self, task_id: &str) -> Result<Option<String>> {
+        self.db.get_magnet_prefetch_torrent(task_id)
+    }
+
+    /// Widens a torrent's selection to its normal-priority files once the
+    /// high-priority files it started with are all done.
+    async fn advance_file_stages(&self) -> Result<()> {
+        for (task_id, select_file) in self.db.list_task_file_stages()? {
+            let Some(task) = self.db.get_task(&task_id)? else {
+                self.db.set_task_file_stage(&task_id, None)?;
+                continue;
+            };
+            let Some(gid) = task.aria2_gid.clone() else {
+                continue;
+            };
+            let files = self.db.list_task_files(&task_id)?;
+            let staged = files.iter().filter(|f| f.selected).collect::<Vec<_>>();
+            if staged.is_empty() || staged.iter().any(|f| f.completed_length < f.length) {
+                continue;
+            }
+            let Ok(engine) = self.ready_engine(task.engine_id.as_deref()).await else {
+                continue;
+            };
+            match engine
+                .change_option(&gid, json!({ "select-file": select_file }))
+                .await
+            {
+                Ok(_) => self.push_log(
+                    "task_file_stage",
+                    format!("task {task_id}: high-priority files done, selecting {select_file}"),
+                ),
+                Err(e) => self.push_log(
+                    "task_file_stage",
+                    format!("task {task_id}: selecting the remaining files failed: {e}"),
+                ),
+            }
+            self.db.set_task_file_stage(&task_id, None)?;
+        }
         Ok(())
     }
 
     pub async fn set_task_runtime_options(&self, task_id: &str, options: Value) -> Result<()> {
-        self.ensure_aria2_ready().await?;
         let task = self
             .db
             .get_task(task_id)?
@@ -1421,6 +2661,7 @@ impl DownloadService {
         let gid = task
             .aria2_gid
             .ok_or_else(|| AppError::InvalidInput("task has no aria2 gid".to_string()))?;
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
 
         let allowed = [
             "max-download-limit",
@@ -1452,9 +2693,7 @@ impl DownloadService {
             return Err(anyhow!("no valid runtime options provided"));
         }
 
-        self.aria2
-            .change_option(&gid, Value::Object(sanitized))
-            .await?;
+        engine.change_option(&gid, Value::Object(sanitized)).await?;
         self.push_log(
             "set_task_runtime_options",
             format!("updated task {task_id} runtime options"),
@@ -1509,8 +2748,12 @@ impl DownloadService {
         }
 
         self.db.save_global_settings(&settings)?;
+        self.apply_engine_mode().await?;
+        self.sync_engine_profiles().await?;
 
-        if let Some(path) = manual_path.as_deref() {
+        if let Some(path) = manual_path.as_deref()
+            && self.runs_managed_aria2()
+        {
             let managed_path = self.aria2_bin_path();
             if !managed_path.is_empty() && managed_path != path {
                 if let Err(e) = self.apply_manual_aria2_binary(path).await {
@@ -1532,6 +2775,40 @@ impl DownloadService {
         Ok(())
     }
 
+    /// Re-reads the engine settings and, if they changed, detaches from the
+    /// current aria2 so the next request starts or attaches the new one.
+    async fn apply_engine_mode(&self) -> Result<()> {
+        let settings = self.db.load_global_settings()?;
+        let wanted = settings.engine_mode.as_deref().map(str::trim);
+        let in_process = self.aria2.in_process_mode();
+        let wants_in_process = matches!(wanted, Some("demo" | "native"));
+        if in_process.is_some() || wants_in_process {
+            if in_process != wanted {
+                self.push_log(
+                    "engine_mode",
+                    format!(
+                        "switch to {} engine takes effect after restart",
+                        wanted.unwrap_or("managed")
+                    ),
+                );
+            }
+            return Ok(());
+        }
+        let next = ExternalAria2Config::from_settings(&settings);
+        if next == self.aria2.external_config() {
+            return Ok(());
+        }
+        let _guard = self.lifecycle_guard.lock().await;
+        let _ = self.aria2.stop().await;
+        let message = match &next {
+            Some(external) => format!("switched to external aria2 at {}", external.rpc_url),
+            None => "switched to managed aria2".to_string(),
+        };
+        self.aria2.set_external(next);
+        self.push_log("engine_mode", message);
+        Ok(())
+    }
+
     async fn apply_manual_aria2_binary(&self, path: &str) -> Result<()> {
         let p = Path::new(path);
         let bytes = fs::read(p).map_err(|e| anyhow!("read aria2 binary failed: {e}"))?;
@@ -1558,6 +2835,28 @@ impl DownloadService {
         Ok(())
     }
 
+    pub async fn patch_global_settings(&self, patch: Value) -> Result<GlobalSettings> {
+        let Some(patch) = patch.as_object() else {
+            return Err(
+                AppError::InvalidInput("settings patch must be a JSON object".to_string()).into(),
+            );
+        };
+        let mut merged = serde_json::to_value(self.get_global_settings()?)?;
+        let fields = merged
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("global settings did not serialize to an object"))?;
+        for (key, value) in patch {
+            if !fields.contains_key(key) {
+                return Err(AppError::InvalidInput(format!("unknown setting: {key}")).into());
+            }
+            fields.insert(key.clone(), value.clone());
+        }
+        let settings: GlobalSettings = serde_json::from_value(merged)
+            .map_err(|e| AppError::InvalidInput(format!("invalid settings patch: {e}")))?;
+        self.set_global_settings(settings).await?;
+        self.get_global_settings()
+    }
+
     pub fn get_global_settings(&self) -> Result<GlobalSettings> {
         self.db.load_global_settings()
     }
@@ -1647,6 +2946,9 @@ impl DownloadService {
 
         let defaults = GlobalSettings {
             aria2_bin_path: current.aria2_bin_path,
+            engine_mode: current.engine_mode,
+            aria2_rpc_url: current.aria2_rpc_url,
+            aria2_rpc_secret: current.aria2_rpc_secret,
             download_dir: Some(default_download_dir),
             max_concurrent_downloads: Some(5),
             max_connection_per_server: Some(8),
@@ -1657,6 +2959,7 @@ impl DownloadService {
             github_token: Some(String::new()),
             download_dir_rules: Vec::new(),
             category_rules: Vec::new(),
+            policy_rules: Vec::new(),
             browser_bridge_enabled: Some(true),
             browser_bridge_port: Some(16789),
             browser_bridge_token: current.browser_bridge_token,
@@ -1678,6 +2981,16 @@ impl DownloadService {
             metadata_timeout_secs: Some(180),
             speed_plan: Some("[]".to_string()),
             task_option_presets: Some("[]".to_string()),
+            engine_profiles: current.engine_profiles,
+            task_queues: current.task_queues,
+            collision_policy: Some("rename".to_string()),
+            duplicate_policy: Some("warn".to_string()),
+            tracker_lists: current.tracker_lists,
+            tracker_refresh_hours: Some(24),
+            seeding_rules: Some("[]".to_string()),
+            max_active_seeds: Some(0),
+            transfer_history_days: Some(400),
+            pause_seeding_in_speed_plan: Some(false),
             post_complete_action: Some("none".to_string()),
             completion_webhook_url: Some(String::new()),
             completion_command: Some(String::new()),
@@ -1787,6 +3100,7 @@ impl DownloadService {
                     .map(ToString::to_string)
             });
             return Ok(Diagnostics {
+                engine_mode: self.engine_mode_label().to_string(),
                 rpc_endpoint: ep.endpoint,
                 rpc_port: Some(ep.port),
                 rpc_secret_set: !ep.secret.is_empty(),
@@ -1802,10 +3116,15 @@ impl DownloadService {
             });
         }
 
+        let external = self.aria2.external_config();
         Ok(Diagnostics {
-            rpc_endpoint: String::new(),
+            engine_mode: self.engine_mode_label().to_string(),
+            rpc_endpoint: external
+                .as_ref()
+                .map(|e| e.rpc_url.clone())
+                .unwrap_or_default(),
             rpc_port: None,
-            rpc_secret_set: false,
+            rpc_secret_set: external.is_some_and(|e| !e.secret.is_empty()),
             aria2_running: false,
             aria2_bin_path: self.aria2_bin_path(),
             aria2_bin_exists: self.aria2_bin_exists(),
@@ -1818,6 +3137,16 @@ impl DownloadService {
         })
     }
 
+    fn engine_mode_label(&self) -> &'static str {
+        if let Some(mode) = self.aria2.in_process_mode() {
+            mode
+        } else if self.aria2.is_external() {
+            "external"
+        } else {
+            "managed"
+        }
+    }
+
     pub async fn startup_check_aria2(&self) -> Result<String> {
         let _guard = self.lifecycle_guard.lock().await;
         let mut attempts = Vec::new();
@@ -1915,6 +3244,17 @@ impl DownloadService {
 
     pub async fn check_aria2_update(&self) -> Result<Aria2UpdateInfo> {
         let current_version = self.current_aria2_version().await.ok();
+        if let Some(reason) = self.unmanaged_aria2_reason() {
+            return Ok(Aria2UpdateInfo {
+                current_version,
+                latest_version: None,
+                update_available: false,
+                selected_asset_name: None,
+                selected_asset_url: None,
+                latest_url: None,
+                check_error: Some(reason.to_string()),
+            });
+        }
         let latest = fetch_latest_aria2_release(
             self.configured_github_cdn().as_deref(),
             self.configured_github_token().as_deref(),
@@ -1950,6 +3290,9 @@ impl DownloadService {
     }
 
     pub async fn update_aria2_now(&self) -> Result<Aria2UpdateApplyResult> {
+        if let Some(reason) = self.unmanaged_aria2_reason() {
+            return Err(AppError::InvalidInput(reason.to_string()).into());
+        }
         let current = self.current_aria2_version().await.ok();
         let github_cdn = self.configured_github_cdn();
         let github_token = self.configured_github_token();
@@ -2043,20 +3386,52 @@ impl DownloadService {
 
     async fn reconcile_with_aria2_inner(&self) -> Result<usize> {
         self.ensure_aria2_ready().await?;
-        let snapshots = self.aria2.tell_all().await?;
         let now = now_ts();
         let _ = self.db.prune_deleted_gids_before(now - 30 * 24 * 3600);
         let _ = self.purge_recovered_error_placeholders(now);
 
-        let _ = self.db.update_from_snapshots(&snapshots, now)?;
+        let mut created = self.reconcile_engine(None, self.aria2.clone(), now).await?;
+        for (engine_id, engine) in self.extra_engines() {
+            let reconciled = match engine.ensure_started().await {
+                Ok(_) => self.reconcile_engine(Some(&engine_id), engine, now).await,
+                Err(e) => Err(e),
+            };
+            match reconciled {
+                Ok(count) => created += count,
+                Err(e) => self.push_log(
+                    "reconcile_with_aria2",
+                    format!("engine {engine_id} skipped: {e}"),
+                ),
+            }
+        }
+        Ok(created)
+    }
+
+    async fn reconcile_engine(
+        &self,
+        engine_id: Option<&str>,
+        engine: Arc<dyn Aria2Api>,
+        now: i64,
+    ) -> Result<usize> {
+        let snapshots = engine.tell_all().await?;
+        let _ = self.db.update_from_snapshots(
+            engine_id,
+            &self.without_finished_prefetches(&snapshots),
+            now,
+        )?;
 
         let mut created = 0usize;
         let mut skipped_deleted = 0usize;
         let mut skipped_terminal_orphans = 0usize;
         let mut skipped_empty_orphans = 0usize;
-        let default_dir = self.configured_download_dir()?;
+        let default_dir = match self.engine_slot(engine_id)? {
+            Some(slot) => slot.profile.download_dir.unwrap_or_default(),
+            None => self.configured_download_dir()?,
+        };
+        let mut force_remove_gids = Vec::new();
+        let mut purge_result_gids = Vec::new();
         for snapshot in snapshots {
-            if let Some(existing) = self.db.get_task_by_gid(&snapshot.gid)? {
+            if let Some(existing) = self.db.get_task_by_gid(engine_id, &snapshot.gid)? {
                 if is_terminal_aria2_status(&snapshot.status)
                     && is_recovered_source(&existing.source)
                     && existing.total_length == 0
@@ -2064,29 +3439,26 @@ impl DownloadService {
                 {
                     let _ = self.db.mark_deleted_gid(&snapshot.gid, now);
                     let _ = self.db.remove_task(&existing.id);
-                    let _ = self.aria2.remove_download_result(&snapshot.gid).await;
+                    purge_result_gids.push(snapshot.gid.clone());
                     skipped_terminal_orphans += 1;
                 }
                 continue;
             }
             if self.db.is_gid_deleted(&snapshot.gid)? {
                 skipped_deleted += 1;
-                let _ = self.aria2.remove(&snapshot.gid, true).await;
-                let _ = self.aria2.remove_download_result(&snapshot.gid).await;
+                force_remove_gids.push(snapshot.gid.clone());
                 continue;
             }
             if is_terminal_aria2_status(&snapshot.status) {
                 skipped_terminal_orphans += 1;
                 let _ = self.db.mark_deleted_gid(&snapshot.gid, now);
-                let _ = self.aria2.remove(&snapshot.gid, true).await;
-                let _ = self.aria2.remove_download_result(&snapshot.gid).await;
+                force_remove_gids.push(snapshot.gid.clone());
                 continue;
             }
             if snapshot.total_length == 0 && snapshot.completed_length == 0 {
                 skipped_empty_orphans += 1;
                 let _ = self.db.mark_deleted_gid(&snapshot.gid, now);
-                let _ = self.aria2.remove(&snapshot.gid, true).await;
-                let _ = self.aria2.remove_download_result(&snapshot.gid).await;
+                force_remove_gids.push(snapshot.gid.clone());
                 continue;
             }
             let task_id = Uuid::new_v4().to_string();
@@ -2098,6 +3470,7 @@ impl DownloadService {
             self.db.upsert_task(&Task {
                 id: task_id.clone(),
                 aria2_gid: Some(snapshot.gid.clone()),
+                engine_id: engine_id.map(ToString::to_string),
                 task_type: TaskType::Http,
                 source: format!("aria2:recovered:{}", snapshot.gid),
                 status: status.clone(),
@@ -2132,6 +3505,12 @@ impl DownloadService {
                 checksum_status: None,
                 created_at: now,
                 updated_at: now,
+                depends_on: Vec::new(),
+                start_at: None,
+                window: None,
+                schedule_held: false,
+                queue: None,
+                queue_held: false,
             })?;
             if !snapshot.files.is_empty() {
                 let files = snapshot
@@ -2150,10 +3529,18 @@ impl DownloadService {
             created += 1;
         }
 
+        // Batched so thousands of stale gids cost a few round trips, not thousands.
+        let _ = engine.remove_many(&force_remove_gids, true).await;
+        purge_result_gids.extend(force_remove_gids);
+        let _ = engine.remove_download_result_many(&purge_result_gids).await;
+
+        let engine_label = engine_id
+            .map(|id| format!("engine {id} "))
+            .unwrap_or_default();
         self.push_log(
             "reconcile_with_aria2",
             format!(
-                "reconciled, recovered {created} orphan task(s), skipped {skipped_deleted} deleted gid(s), skipped {skipped_terminal_orphans} terminal orphan(s), skipped {skipped_empty_orphans} empty orphan(s)"
+                "{engine_label}reconciled, recovered {created} orphan task(s), skipped {skipped_deleted} deleted gid(s), skipped {skipped_terminal_orphans} terminal orphan(s), skipped {skipped_empty_orphans} empty orphan(s)"
             ),
         );
         Ok(created)
@@ -2222,9 +3609,47 @@ impl DownloadService {
         Ok(r)
     }
 
-    pub fn list_operation_logs(&self, limit: usize) -> Result<Vec<OperationLog>> {
-        self.flush_pending_logs()?;
-        self.db.list_operation_logs(limit)
+    /// Persists the aria2 session (if aria2 is running) and flushes pending
+    /// operation logs. Stopping aria2 itself is left to the owner of the manager.
+    pub async fn prepare_shutdown(&self, reason: &str) -> Result<()> {
+        if self.aria2.endpoint().await.is_some() {
+            match time::timeout(Duration::from_secs(5), self.aria2.save_session()).await {
+                Ok(Ok(r)) => self.push_log("save_session", format!("saveSession -> {r}")),
+                Ok(Err(e)) => self.push_log("shutdown", format!("saveSession failed: {e}")),
+                Err(_) => self.push_log("shutdown", "saveSession timed out".to_string()),
+            }
+        }
+        self.push_log("shutdown", format!("backend shutting down ({reason})"));
+        self.flush_pending_logs()
+    }
+
+    /// Saves and stops every engine the service owns besides the managed aria2:
+    /// the in-process primary engine (native/demo) and each extra engine profile.
+    pub async fn shutdown_engines(&self) {
+        let mut engines = self.extra_engines();
+        if self.aria2.in_process_mode().is_some() {
+            engines.push(("default".to_string(), self.aria2.clone()));
+        }
+        for (id, engine) in engines {
+            if engine.endpoint().await.is_none() {
+                continue;
+            }
+            match time::timeout(Duration::from_secs(5), engine.save_session()).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    self.push_log("shutdown", format!("engine {id} saveSession failed: {e}"))
+                }
+                Err(_) => self.push_log("shutdown", format!("engine {id} saveSession timed out")),
+            }
+            if let Err(e) = engine.stop().await {
+                self.push_log("shutdown", format!("engine {id} stop failed: {e}"));
+            }
+        }
+    }
+
+    pub fn list_operation_logs(&self, limit: usize) -> Result<Vec<OperationLog>> {
+        self.flush_pending_logs()?;
+        self.db.list_operation_logs(limit)
     }
 
     pub fn clear_operation_logs(&self) -> Result<()> {
@@ -2411,8 +3836,9 @@ impl DownloadService {
         {
             aria2_options.insert("max-overall-download-limit".to_string(), json!(v));
         }
-        if let Some(v) = settings.bt_tracker.filter(|v| !v.trim().is_empty()) {
-            aria2_options.insert("bt-tracker".to_string(), json!(v));
+        let trackers = self.effective_trackers()?;
+        if !trackers.is_empty() {
+            aria2_options.insert("bt-tracker".to_string(), json!(trackers.join(",")));
         }
 
         if aria2_options.is_empty() {
@@ -2431,6 +3857,287 @@ impl DownloadService {
         Ok(())
     }
 
+    fn subscribed_tracker_lists(&self) -> Result<Vec<String>> {
+        let raw = self.db.get_setting("tracker_lists")?.unwrap_or_default();
+        if raw.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let urls: Vec<String> = serde_json::from_str(&raw)
+            .map_err(|e| AppError::InvalidInput(format!("invalid tracker_lists: {e}")))?;
+        Ok(urls
+            .into_iter()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect())
+    }
+
+    /// `bt_tracker` plus every subscribed list, validated and deduplicated.
+    pub fn effective_trackers(&self) -> Result<Vec<String>> {
+        let mut text = self.db.get_setting("bt_tracker")?.unwrap_or_default();
+        for (_, trackers) in self.db.list_tracker_lists()? {
+            text.push('\n');
+            text.push_str(&trackers.join("\n"));
+        }
+        Ok(parse_tracker_text(&text))
+    }
+
+    pub fn list_tracker_lists(&self) -> Result<Vec<TrackerListStatus>> {
+        let stored = self.db.list_tracker_lists()?;
+        Ok(self
+            .subscribed_tracker_lists()?
+            .into_iter()
+            .map(|url| {
+                stored
+                    .iter()
+                    .find(|(status, _)| status.url == url)
+                    .map(|(status, _)| status.clone())
+                    .unwrap_or(TrackerListStatus {
+                        url,
+                        ..Default::default()
+                    })
+            })
+            .collect())
+    }
+
+    /// Fetches every subscribed tracker list, hands the merged trackers to
+    /// aria2 as its global `bt-tracker` and probes them for health.
+    pub async fn refresh_tracker_lists(&self) -> Result<Vec<TrackerListStatus>> {
+        let urls = self.subscribed_tracker_lists()?;
+        self.db.retain_tracker_lists(&urls)?;
+        let now = now_ts();
+        for url in &urls {
+            match fetch_tracker_list(url).await {
+                Ok(text) => {
+                    let trackers = parse_tracker_text(&text);
+                    self.db
+                        .record_tracker_list_fetch(url, Some(&trackers), now, None)?;
+                }
+                Err(e) => {
+                    self.db
+                        .record_tracker_list_fetch(url, None, now, Some(&e.to_string()))?;
+                }
+            }
+        }
+
+        let trackers = self.effective_trackers()?;
+        if self.aria2.endpoint().await.is_some() {
+            self.aria2
+                .change_global_option(json!({ "bt-tracker": trackers.join(",") }))
+                .await?;
+        }
+        let probed = &trackers[..trackers.len().min(MAX_TRACKER_PROBES)];
+        let health = probe_trackers(probed).await;
+        let reachable = health.iter().filter(|h| h.status == "ok").count();
+        self.db.set_tracker_health(
+            &health
+                .into_iter()
+                .filter(|h| h.status != "unknown")
+                .collect::<Vec<_>>(),
+        )?;
+        self.push_log(
+            "tracker_lists",
+            format!(
+                "refreshed {} lists: {} trackers, {reachable} of {} probed reachable",
+                urls.len(),
+                trackers.len(),
+                probed.len()
+            ),
+        );
+        self.list_tracker_lists()
+    }
+
+    async fn refresh_tracker_lists_if_due(&self) -> Result<()> {
+        let hours = self
+            .get_global_settings()?
+            .tracker_refresh_hours
+            .unwrap_or(24);
+        let urls = self.subscribed_tracker_lists()?;
+        if hours == 0 || urls.is_empty() {
+            return Ok(());
+        }
+        let stale_before = now_ts() - i64::from(hours) * 3600;
+        let due = self
+            .list_tracker_lists()?
+            .iter()
+            .any(|list| list.fetched_at.is_none_or(|at| at < stale_before));
+        if due {
+            self.refresh_tracker_lists().await?;
+        }
+        Ok(())
+    }
+
+    /// Adds trackers to one torrent or magnet task on top of the global ones.
+    pub async fn add_task_trackers(&self, task_id: &str, trackers: &[String]) -> Result<()> {
+        self.edit_task_trackers(task_id, trackers, false).await
+    }
+
+    /// Stops one task from announcing to these trackers, wherever they came from.
+    pub async fn remove_task_trackers(&self, task_id: &str, trackers: &[String]) -> Result<()> {
+        self.edit_task_trackers(task_id, trackers, true).await
+    }
+
+    async fn edit_task_trackers(
+        &self,
+        task_id: &str,
+        trackers: &[String],
+        excluded: bool,
+    ) -> Result<()> {
+        let task = self
+            .db
+            .get_task(task_id)?
+            .ok_or_else(|| AppError::TaskNotFound(task_id.to_string()))?;
+        if !matches!(task.task_type, TaskType::Torrent | TaskType::Magnet) {
+            return Err(AppError::InvalidInput(
+                "trackers only apply to torrent and magnet tasks".to_string(),
+            )
+            .into());
+        }
+        let trackers = trackers
+            .iter()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>();
+        if trackers.is_empty() {
+            return Err(AppError::InvalidInput("no trackers given".to_string()).into());
+        }
+        if let Some(bad) = trackers.iter().find(|t| !valid_tracker(t)) {
+            return Err(AppError::InvalidInput(format!("invalid tracker: {bad}")).into());
+        }
+        for tracker in trackers {
+            self.db.set_task_tracker(task_id, tracker, excluded)?;
+        }
+        if let Some(gid) = task.aria2_gid.as_deref() {
+            let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+            engine
+                .change_option(gid, Value::Object(self.task_tracker_options(task_id)?))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// `bt-tracker` and `bt-exclude-tracker` for a task with tracker overrides;
+    /// empty when it has none, so the global list applies.
+    fn task_tracker_options(&self, task_id: &str) -> Result<serde_json::Map<String, Value>> {
+        let overrides = self.db.list_task_trackers(task_id)?;
+        let mut options = serde_json::Map::new();
+        if overrides.is_empty() {
+            return Ok(options);
+        }
+        let excluded = overrides
+            .iter()
+            .filter(|(_, excluded)| *excluded)
+            .map(|(uri, _)| uri.clone())
+            .collect::<Vec<_>>();
+        let mut trackers = self.effective_trackers()?;
+        for (uri, _) in overrides.iter().filter(|(_, excluded)| !*excluded) {
+            if !trackers.contains(uri) {
+                trackers.push(uri.clone());
+            }
+        }
+        trackers.retain(|uri| !excluded.contains(uri));
+        options.insert("bt-tracker".to_string(), json!(trackers.join(",")));
+        options.insert("bt-exclude-tracker".to_string(), json!(excluded.join(",")));
+        Ok(options)
+    }
+
+    fn configured_seeding_rules(&self) -> Vec<SeedingRule> {
+        self.db
+            .get_setting("seeding_rules")
+            .ok()
+            .flatten()
+            .and_then(|v| serde_json::from_str::<Vec<SeedingRule>>(&v).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stops torrents that met their seeding goal and pauses seeds beyond
+    /// `max_active_seeds` or inside a speed plan window, resuming them later.
+    async fn enforce_seeding_policy(&self) -> Result<()> {
+        let settings = self.get_global_settings()?;
+        let rules = self.configured_seeding_rules();
+        let max_seeds = settings.max_active_seeds.unwrap_or(0) as usize;
+        let in_speed_plan = settings.pause_seeding_in_speed_plan.unwrap_or(false)
+            && serde_json::from_str::<Vec<SpeedPlanRule>>(
+                settings.speed_plan.as_deref().unwrap_or_default(),
+            )
+            .is_ok_and(|plan| select_speed_limit(&plan).is_some());
+        let seeding = self
+            .db
+            .list_task_seeding()?
+            .into_iter()
+            .filter(|s| s.seeding_since.is_some() && s.stopped_reason.is_none())
+            .collect::<Vec<_>>();
+        if seeding.is_empty() {
+            return Ok(());
+        }
+
+        let now = now_ts();
+        let mut active = Vec::new();
+        let mut held = Vec::new();
+        for state in seeding {
+            let Some(task) = self.db.get_task(&state.task_id)? else {
+                continue;
+            };
+            let seeding_now = task.status == TaskStatus::Active
+                && task.total_length > 0
+                && task.completed_length >= task.total_length;
+            let held_now = state.held && task.status == TaskStatus::Paused;
+            if !seeding_now && !held_now {
+                if state.held {
+                    // Resumed or removed outside the seeding limits.
+                    self.db.set_task_seed_held(&task.id, false)?;
+                }
+                continue;
+            }
+            if let Some(reason) = seeding_goal_reached(&rules, &task, &state, now) {
+                if let Err(e) = self.finish_seeding(task, reason).await {
+                    self.push_log("seeding_policy", format!("stop seeding failed: {e}"));
+                }
+                continue;
+            }
+            if seeding_now {
+                active.push((task, state));
+            } else {
+                held.push((task, state));
+            }
+        }
+
+        let allowed = if in_speed_plan {
+            0
+        } else if max_seeds == 0 {
+            usize::MAX
+        } else {
+            max_seeds
+        };
+        // The seeds furthest from their goal keep their slots.
+        active.sort_by(|a, b| a.1.ratio.total_cmp(&b.1.ratio));
+        held.sort_by(|a, b| a.1.ratio.total_cmp(&b.1.ratio));
+        for (task, _) in active.iter().skip(allowed) {
+            let Some(gid) = task.aria2_gid.as_deref() else {
+                continue;
+            };
+            let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+            if engine.pause(gid).await.is_ok() {
+                self.db.set_task_seed_held(&task.id, true)?;
+                self.push_log("seeding_policy", format!("paused seeding task {}", task.id));
+            }
+        }
+        let free = allowed.saturating_sub(active.len());
+        for (task, _) in held.into_iter().take(free) {
+            let Some(gid) = task.aria2_gid.as_deref() else {
+                continue;
+            };
+            let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+            if engine.unpause(gid).await.is_ok() {
+                self.db.set_task_seed_held(&task.id, false)?;
+                self.push_log(
+                    "seeding_policy",
+                    format!("resumed seeding task {}", task.id),
+                );
+            }
+        }
+        Ok(())
+    }
+
     async fn apply_speed_plan_if_needed(&self) -> Result<()> {
         let settings = self.get_global_settings()?;
         let plan_json = settings.speed_plan.unwrap_or_default();
@@ -2576,6 +4283,10 @@ impl DownloadService {
                     }
                 }
                 TaskStatus::Active | TaskStatus::Queued => {
+                    // Remote engines download to their own disks.
+                    if !is_local_task(&task) {
+                        continue;
+                    }
                     if let Some(failure) = task_disk_space_failure(&task) {
                         if let Some(gid) = task.aria2_gid.as_deref() {
                             let _ = self.aria2.pause(gid).await;
@@ -2648,2638 +4359,6293 @@ impl DownloadService {
         Ok(())
     }
 
-    async fn retry_task_with_fallback(
-        &self,
-        task: &Task,
-        attempt: usize,
-        mirrors: &[String],
-    ) -> Result<String> {
-        self.ensure_aria2_ready().await?;
-        match task.task_type {
-            TaskType::Http => {
-                let source = apply_fallback_source(&task.source, attempt, mirrors);
-                self.aria2
-                    .add_uri(
-                        vec![source],
-                        Some(to_aria2_options(AddTaskOptions {
-                            save_dir: Some(task.save_dir.clone()),
-                            out: task.name.clone(),
-                            ..AddTaskOptions::default()
-                        })),
-                    )
-                    .await
+    /// Pauses scheduled tasks when their window closes and resumes the ones it paused
+    /// when it opens again. Acts on changes only, so manual pause/resume sticks in between.
+    async fn enforce_task_schedules(&self) -> Result<()> {
+        let now = Local::now();
+        let tasks = self.db.list_tasks(None, 2000, 0)?;
+        for mut task in tasks {
+            // A held task whose schedule was cleared still needs resuming once.
+            if task.start_at.is_none() && task.window.is_none() && !task.schedule_held {
+                continue;
             }
-            TaskType::Magnet => {
-                self.aria2
-                    .add_uri(
-                        vec![task.source.clone()],
-                        Some(to_aria2_options(AddTaskOptions {
-                            save_dir: Some(task.save_dir.clone()),
-                            ..AddTaskOptions::default()
-                        })),
-                    )
-                    .await
+            if !matches!(
+                task.status,
+                TaskStatus::Queued | TaskStatus::Active | TaskStatus::Paused | TaskStatus::Metadata
+            ) {
+                continue;
             }
-            _ => Err(anyhow!("auto retry currently supports http/magnet tasks")),
+            let Some(gid) = task.aria2_gid.clone() else {
+                continue;
+            };
+            let allowed = schedule_allows(task.start_at, task.window.as_ref(), &now);
+            let previous = self
+                .schedule_state
+                .lock()
+                .expect("schedule_state mutex poisoned")
+                .get(&task.id)
+                .copied();
+            if previous == Some(allowed) {
+                continue;
+            }
+            // Failures leave the state unrecorded so the next pass tries again.
+            let Ok(engine) = self.ready_engine(task.engine_id.as_deref()).await else {
+                continue;
+            };
+            if allowed && task.schedule_held {
+                if task.status == TaskStatus::Paused {
+                    if engine.unpause(&gid).await.is_err() {
+                        continue;
+                    }
+                    task.status = TaskStatus::Queued;
+                    self.push_log(
+                        "task_schedule",
+                        format!("task {} resumed, its window opened", task.id),
+                    );
+                }
+                task.schedule_held = false;
+                task.updated_at = now_ts();
+                self.db.upsert_task(&task)?;
+            } else if !allowed && !task.schedule_held && task.status != TaskStatus::Paused {
+                if engine.pause(&gid).await.is_err() {
+                    continue;
+                }
+                task.status = TaskStatus::Paused;
+                task.schedule_held = true;
+                task.updated_at = now_ts();
+                self.db.upsert_task(&task)?;
+                self.push_log(
+                    "task_schedule",
+                    format!("task {} paused outside its window", task.id),
+                );
+            }
+            self.schedule_state
+                .lock()
+                .expect("schedule_state mutex poisoned")
+                .insert(task.id.clone(), allowed);
         }
+        Ok(())
     }
 
-    pub fn start_sync_loop(self: Arc<Self>) {
-        tokio::spawn(async move {
-            let mut interval = time::interval(Duration::from_millis(1000));
-            let mut tick: u64 = 0;
-            loop {
-                interval.tick().await;
-                tick = tick.wrapping_add(1);
-                let _ = self.flush_pending_logs();
-                if tick.is_multiple_of(30) {
-                    let _ = self.apply_speed_plan_if_needed().await;
-                }
-                if tick.is_multiple_of(5) {
-                    let _ = self.process_retry_and_metadata_policies().await;
+    /// Submits held tasks whose prerequisites completed and applies each task's
+    /// `on_dependency_failure` policy when a prerequisite failed for good.
+    async fn process_held_submissions(&self) -> Result<()> {
+        let held = self.db.list_pending_submissions()?;
+        if held.is_empty() {
+            return Ok(());
+        }
+        let retry_max_attempts = self.get_global_settings()?.retry_max_attempts.unwrap_or(2);
+        for pending in held {
+            let Some(mut task) = self.db.get_task(&pending.task_id)? else {
+                self.db.remove_pending_submission(&pending.task_id)?;
+                continue;
+            };
+            if task.status != TaskStatus::Queued {
+                continue;
+            }
+            let mut waiting = false;
+            let mut failed = None;
+            let satisfied = self.db.satisfied_dependencies(&task.id)?;
+            for prerequisite_id in &task.depends_on {
+                match self.db.get_task(prerequisite_id)? {
+                    Some(prerequisite) if prerequisite.status == TaskStatus::Completed => {}
+                    None if satisfied.contains(prerequisite_id) => {}
+                    Some(prerequisite)
+                        if !self.prerequisite_failed(&prerequisite, retry_max_attempts) =>
+                    {
+                        waiting = true;
+                    }
+                    _ => {
+                        failed.get_or_insert_with(|| prerequisite_id.clone());
+                    }
                 }
-                let snapshots = match self.aria2.tell_all().await {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-
-                let now = now_ts();
-                let changed_tasks = match self.db.update_from_snapshots(&snapshots, now) {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-
-                for task in &changed_tasks {
-                    let Some(gid) = task.aria2_gid.as_ref() else {
-                        continue;
-                    };
-                    let Some(snapshot) = snapshots.iter().find(|s| &s.gid == gid) else {
-                        continue;
-                    };
-                    if snapshot.files.is_empty() {
+            }
+            if let Some(prerequisite_id) = failed {
+                match pending.on_dependency_failure.as_str() {
+                    "wait" => continue,
+                    "run" => {}
+                    _ => {
+                        task.status = TaskStatus::Error;
+                        apply_task_failure(
+                            &mut task,
+                            TaskFailureReason {
+                                health: TaskHealth::UnknownError,
+                                code: "DEPENDENCY_FAILED".to_string(),
+                                message: format!("prerequisite task {prerequisite_id} failed"),
+                                remediation:
+                                    "Retry or replace the prerequisite task, then add this task again."
+                                        .to_string(),
+                            },
+                        );
+                        task.updated_at = now_ts();
+                        self.db.upsert_task(&task)?;
+                        self.db.remove_pending_submission(&task.id)?;
+                        self.push_log(
+                            "dependency_failed",
+                            format!(
+                                "task {} cancelled, prerequisite {prerequisite_id} failed",
+                                task.id
+                            ),
+                        );
                         continue;
                     }
-                    let files = snapshot
-                        .files
-                        .iter()
-                        .map(|f| TaskFile {
-                            task_id: task.id.clone(),
-                            path: f.path.clone(),
-                            length: f.length,
-                            completed_length: f.completed_length,
-                            selected: f.selected,
-                        })
-                        .collect::<Vec<_>>();
-                    let _ = self.db.replace_task_files(&task.id, &files);
                 }
+            }
+            if waiting {
+                continue;
+            }
 
-                let _ = self.apply_completion_rules(&changed_tasks, tick);
-
-                if self.emitter.emit_task_update(&changed_tasks).is_err() {
+            let options: AddTaskOptions = serde_json::from_str(&pending.options)?;
+            let submission = match pending.kind.as_str() {
+                "torrent" => Submission::Torrent(pending.payload),
+                "metalink" => Submission::Metalink(pending.payload),
+                _ => Submission::Uri(pending.payload),
+            };
+            let admission = match self.admit_released_task(&task) {
+                Ok(admission) => admission,
+                Err(err) => {
+                    self.push_log(
+                        "dependency_release",
+                        format!("task {} submit failed: {err}", task.id),
+                    );
                     continue;
                 }
+            };
+            match self
+                .submit_to_engine(task.engine_id.as_deref(), submission, options, &admission)
+                .await
+            {
+                Ok(gid) => {
+                    task.aria2_gid = Some(gid);
+                    if task.task_type == TaskType::Magnet && !admission.paused() {
+                        mark_awaiting_metadata(&mut task);
+                    }
+                    admission.apply(&mut task);
+                    task.updated_at = now_ts();
+                    self.db.upsert_task(&task)?;
+                    self.db.remove_pending_submission(&task.id)?;
+                    self.push_log(
+                        "dependency_release",
+                        format!("task {} submitted after its dependencies", task.id),
+                    );
+                }
+                Err(err) => self.push_log(
+                    "dependency_release",
+                    format!("task {} submit failed: {err}", task.id),
+                ),
             }
-        });
+        }
+        Ok(())
     }
 
-    pub fn start_log_flush_loop(self: Arc<Self>) {
-        tokio::spawn(async move {
-            let mut interval = time::interval(Duration::from_secs(2));
-            loop {
-                interval.tick().await;
-                let _ = self.flush_pending_logs();
-            }
-        });
+    /// Admission for a task whose prerequisites just finished.
+    fn admit_released_task(&self, task: &Task) -> Result<Admission> {
+        let schedule_held = !schedule_allows(task.start_at, task.window.as_ref(), &Local::now());
+        let queue = task
+            .queue
+            .as_deref()
+            .and_then(|name| self.configured_task_queue(name));
+        let queue_held = !schedule_held
+            && queue
+                .as_ref()
+                .map(|queue| self.queue_is_full(queue))
+                .transpose()?
+                .unwrap_or(false);
+        Ok(Admission {
+            depends_on: task.depends_on.clone(),
+            waiting: false,
+            start_at: task.start_at,
+            window: task.window.clone(),
+            schedule_held,
+            queue: task.queue.clone(),
+            queue_priority: queue.as_ref().map_or(0, |queue| queue.priority),
+            queue_held,
+        })
     }
-}
 
-fn validate_url(url: &str) -> Result<()> {
-    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("invalid url: {e}"))?;
-    let scheme = parsed.scheme();
-    if !matches!(scheme, "http" | "https" | "ftp") {
-        return Err(AppError::InvalidInput(format!("unsupported scheme: {scheme}")).into());
-    }
-    Ok(())
-}
+    fn configured_task_queues(&self) -> Vec<TaskQueue> {
+        let mut queues = self
+            .db
+            .get_setting("task_queues")
+            .ok()
+            .flatten()
+            .and_then(|v| serde_json::from_str::<Vec<TaskQueue>>(&v).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut queue| {
+                queue.name = queue.name.trim().to_string();
+                queue
+            })
+            .collect::<Vec<_>>();
+        queues.sort_by_key(|queue| std::cmp::Reverse(queue.priority));
+        queues
+    }
 
-fn is_stream_manifest_url(url: &str) -> bool {
-    let lower = url.to_lowercase();
-    lower.contains(".m3u8") || lower.contains(".mpd")
-}
+    fn configured_task_queue(&self, name: &str) -> Option<TaskQueue> {
+        self.configured_task_queues()
+            .into_iter()
+            .find(|queue| queue.name == name)
+    }
 
-fn normalize_bridge_referer(referer: Option<String>) -> Result<Option<String>> {
-    let Some(v) = referer
-        .map(|v| v.trim().to_string())
-        .filter(|v| !v.is_empty())
-    else {
-        return Ok(None);
-    };
-    let parsed = reqwest::Url::parse(&v).map_err(|e| anyhow!("invalid referer: {e}"))?;
-    let scheme = parsed.scheme();
-    if !matches!(scheme, "http" | "https") {
-        return Err(anyhow!("unsupported referer scheme: {scheme}"));
+    fn resolve_task_queue(&self, requested: Option<&str>) -> Result<Option<TaskQueue>> {
+        let Some(name) = requested.map(str::trim).filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+        self.configured_task_queue(name)
+            .map(Some)
+            .ok_or_else(|| AppError::InvalidInput(format!("unknown task queue: {name}")).into())
     }
-    Ok(Some(v))
-}
 
-fn normalize_bridge_headers(headers: Vec<String>) -> Result<Vec<String>> {
-    let allowed = [
-        "accept",
-        "accept-language",
-        "cookie",
-        "origin",
-        "referer",
-        "user-agent",
-    ];
-    let mut out = Vec::new();
-    for line in headers {
-        let clean = line.trim();
-        if clean.is_empty() {
-            continue;
+    fn queue_is_full(&self, queue: &TaskQueue) -> Result<bool> {
+        let tasks = self.db.list_tasks(None, 5000, 0)?;
+        Ok(queue_running_count(&tasks, &queue.name) >= queue.max_active as usize)
+    }
+
+    /// Running and held task counts per queue, highest priority first.
+    pub fn list_task_queues(&self) -> Result<Vec<TaskQueueStatus>> {
+        let tasks = self.db.list_tasks(None, 5000, 0)?;
+        Ok(self
+            .configured_task_queues()
+            .into_iter()
+            .map(|queue| TaskQueueStatus {
+                running: queue_running_count(&tasks, &queue.name) as u32,
+                held: tasks
+                    .iter()
+                    .filter(|task| task.queue_held && task.queue.as_deref() == Some(&queue.name))
+                    .count() as u32,
+                name: queue.name,
+                max_active: queue.max_active,
+                priority: queue.priority,
+            })
+            .collect())
+    }
+
+    /// Resumes queue-held tasks, oldest first, while their queue has free slots,
+    /// serving higher priority queues first. Tasks whose queue was deleted are let go.
+    async fn enforce_task_queues(&self) -> Result<()> {
+        // Runs every tick; only held tasks need the full task scan.
+        if !self.db.has_queue_held_tasks()? {
+            return Ok(());
         }
-        let Some((name, value)) = clean.split_once(':') else {
-            return Err(anyhow!("invalid header format: {clean}"));
-        };
-        let key = name.trim().to_ascii_lowercase();
-        if !allowed.contains(&key.as_str()) {
-            continue;
+        let mut tasks = self.db.list_tasks(None, 5000, 0)?;
+        tasks.sort_by_key(|task| task.created_at);
+        for task in tasks.iter_mut() {
+            // Resumed outside Flamingo's queues (resume all, another aria2 client):
+            // it runs now, so it counts against the queue like any other.
+            if task.queue_held && task.status != TaskStatus::Paused {
+                task.queue_held = false;
+                task.updated_at = now_ts();
+                self.db.upsert_task(task)?;
+            }
         }
-        let val = value.trim();
-        if val.is_empty() {
-            continue;
+        let queues = self.configured_task_queues();
+        let now = Local::now();
+        for task in tasks.iter().filter(|task| {
+            task.queue_held
+                && !queues
+                    .iter()
+                    .any(|queue| task.queue.as_deref() == Some(&queue.name))
+        }) {
+            self.release_queued_task(task.clone(), 0).await;
+        }
+        for queue in &queues {
+            let mut free = (queue.max_active as usize)
+                .saturating_sub(queue_running_count(&tasks, &queue.name));
+            for task in tasks
+                .iter()
+                .filter(|task| task.queue_held && task.queue.as_deref() == Some(&queue.name))
+            {
+                if free == 0 {
+                    break;
+                }
+                if !schedule_allows(task.start_at, task.window.as_ref(), &now) {
+                    continue;
+                }
+                if self.release_queued_task(task.clone(), queue.priority).await {
+                    free -= 1;
+                }
+            }
         }
-        out.push(format!("{}: {}", name.trim(), val));
+        Ok(())
     }
-    Ok(out)
-}
 
-fn stream_output_filename(
-    url: &str,
-    preferred_name: Option<&str>,
-    preferred_format: Option<&str>,
-) -> String {
-    let ext = preferred_format
-        .map(|v| v.trim().trim_start_matches('.').to_ascii_lowercase())
-        .filter(|v| matches!(v.as_str(), "mp4" | "mkv" | "mov" | "webm"))
-        .unwrap_or_else(|| "mp4".to_string());
-    if let Some(name) = preferred_name
-        .map(str::trim)
-        .filter(|v| !v.is_empty())
-        .map(sanitize_output_basename)
-        .filter(|v| !v.is_empty())
-    {
-        if name.to_ascii_lowercase().ends_with(&format!(".{ext}")) {
-            return name;
+    async fn release_queued_task(&self, mut task: Task, priority: i32) -> bool {
+        let Some(gid) = task.aria2_gid.clone() else {
+            return false;
+        };
+        let Ok(engine) = self.ready_engine(task.engine_id.as_deref()).await else {
+            return false;
+        };
+        if engine.unpause(&gid).await.is_err() {
+            return false;
         }
-        return format!("{name}.{ext}");
+        if priority > 0 {
+            let _ = engine.change_position(&gid, 0, "POS_SET").await;
+        }
+        task.queue_held = false;
+        task.status = TaskStatus::Queued;
+        task.updated_at = now_ts();
+        if self.db.upsert_task(&task).is_err() {
+            return false;
+        }
+        self.push_log(
+            "task_queue",
+            format!(
+                "task {} started from queue {}",
+                task.id,
+                task.queue.as_deref().unwrap_or("<none>")
+            ),
+        );
+        true
     }
-    let candidate = reqwest::Url::parse(url)
-        .ok()
-        .and_then(|u| {
-            Path::new(u.path())
-                .file_stem()
-                .map(|v| v.to_string_lossy().to_string())
-        })
-        .filter(|v| !v.trim().is_empty())
-        .unwrap_or_else(|| format!("stream-{}", now_ts()));
-    format!("{}.{ext}", sanitize_output_basename(&candidate))
-}
 
-fn sanitize_output_basename(input: &str) -> String {
-    let mut out = input
-        .chars()
-        .map(|c| match c {
-            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-            _ => c,
-        })
-        .collect::<String>()
-        .trim()
-        .trim_matches('.')
-        .to_string();
-    if out.is_empty() {
-        out = format!("stream-{}", now_ts());
+    /// A prerequisite has failed once it is removed, or errored with no auto retry left.
+    fn prerequisite_failed(&self, task: &Task, retry_max_attempts: u32) -> bool {
+        match task.status {
+            TaskStatus::Removed => true,
+            TaskStatus::Error => {
+                if !should_auto_retry(task) || retry_max_attempts == 0 {
+                    return true;
+                }
+                self.retry_state
+                    .lock()
+                    .expect("retry_state mutex poisoned")
+                    .get(&task.id)
+                    .is_some_and(|state| state.attempts >= retry_max_attempts)
+            }
+            _ => false,
+        }
     }
-    out
-}
 
-fn ffprobe_binary_from_ffmpeg(ffmpeg_bin: &str) -> String {
-    let as_path = Path::new(ffmpeg_bin);
-    let file = as_path
-        .file_name()
-        .and_then(|v| v.to_str())
-        .unwrap_or_default()
-        .to_ascii_lowercase();
-    let probe_name = if file.ends_with(".exe") {
-        "ffprobe.exe"
-    } else {
-        "ffprobe"
-    };
-    if let Some(parent) = as_path.parent()
-        && !parent.as_os_str().is_empty()
-    {
-        return parent.join(probe_name).to_string_lossy().to_string();
-    }
-    if file.contains("ffmpeg") {
-        return ffmpeg_bin
-            .replace("ffmpeg.exe", "ffprobe.exe")
-            .replace("ffmpeg", "ffprobe");
+    async fn retry_task_with_fallback(
+        &self,
+        task: &Task,
+        attempt: usize,
+        mirrors: &[String],
+    ) -> Result<String> {
+        let engine = self.ready_engine(task.engine_id.as_deref()).await?;
+        let save_dir = Some(task.save_dir.clone()).filter(|dir| !dir.is_empty());
+        match task.task_type {
+            TaskType::Http => {
+                let source = apply_fallback_source(&task.source, attempt, mirrors);
+                engine
+                    .add_uri(
+                        vec![source],
+                        Some(to_aria2_options(AddTaskOptions {
+                            save_dir,
+                            out: task.name.clone(),
+                            ..AddTaskOptions::default()
+                        })),
+                    )
+                    .await
+            }
+            TaskType::Magnet => {
+                let mut options = to_aria2_options(AddTaskOptions {
+                    save_dir,
+                    ..AddTaskOptions::default()
+                });
+                // A metadata timeout is worth retrying with the freshest trackers.
+                let mut trackers = self.task_tracker_options(&task.id)?;
+                if trackers.is_empty() {
+                    let global = self.effective_trackers()?;
+                    if !global.is_empty() {
+                        trackers.insert("bt-tracker".to_string(), json!(global.join(",")));
+                    }
+                }
+                if let Value::Object(options) = &mut options {
+                    options.extend(trackers);
+                }
+                engine
+                    .add_uri(vec![task.source.clone()], Some(options))
+                    .await
+            }
+            _ => Err(anyhow!("auto retry currently supports http/magnet tasks")),
+        }
     }
-    probe_name.to_string()
-}
 
-fn probe_media_duration_ms(
-    ffprobe_bin: &str,
-    url: &str,
-    user_agent: Option<&str>,
-    referer: Option<&str>,
-    headers: &[String],
-) -> Option<i64> {
-    let mut cmd = Command::new(ffprobe_bin);
-    cmd.arg("-v")
-        .arg("error")
-        .arg("-show_entries")
-        .arg("format=duration")
-        .arg("-of")
-        .arg("default=noprint_wrappers=1:nokey=1");
-    if let Some(v) = user_agent.map(str::trim).filter(|v| !v.is_empty()) {
-        cmd.arg("-user_agent").arg(v);
-    }
-    if let Some(v) = referer.map(str::trim).filter(|v| !v.is_empty()) {
-        cmd.arg("-referer").arg(v);
+    pub fn start_sync_loop(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_millis(1000));
+            let mut tick: u64 = 0;
+            // Keyed by engine, then task id.
+            let mut last_status: HashMap<String, HashMap<String, TaskStatus>> = HashMap::new();
+            let mut notifications = self.aria2.subscribe_notifications();
+            let mut push_connected = false;
+            loop {
+                let mut notified_gids = Vec::new();
+                let mut force_full_sync = false;
+                let mut notifications_closed = false;
+                match notifications.as_mut() {
+                    Some(rx) => tokio::select! {
+                        _ = interval.tick() => {}
+                        received = rx.recv() => match received {
+                            Ok(notification) => {
+                                notified_gids.push(notification.gid);
+                                while let Ok(more) = rx.try_recv() {
+                                    notified_gids.push(more.gid);
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(_)) => force_full_sync = true,
+                            Err(broadcast::error::RecvError::Closed) => notifications_closed = true,
+                        },
+                    },
+                    None => {
+                        interval.tick().await;
+                    }
+                }
+                if notifications_closed {
+                    notifications = None;
+                    continue;
+                }
+
+                let connected = self.aria2.notifications_connected();
+                if connected != push_connected {
+                    push_connected = connected;
+                    self.push_log(
+                        "aria2_notifications",
+                        if connected {
+                            "websocket connected, polling active tasks only".to_string()
+                        } else {
+                            "websocket disconnected, polling all tasks".to_string()
+                        },
+                    );
+                }
+
+                let (snapshots, full_sync) = if notified_gids.is_empty() {
+                    tick = tick.wrapping_add(1);
+                    let _ = self.flush_pending_logs();
+                    if tick.is_multiple_of(30) {
+                        let _ = self.apply_speed_plan_if_needed().await;
+                    }
+                    if tick.is_multiple_of(3600) {
+                        let _ = self.prune_transfer_history();
+                    }
+                    // Fetching and probing can take a while; keep it off the sync tick
+                    // and skip the tick while an earlier refresh is still running.
+                    if tick.is_multiple_of(60)
+                        && !self.tracker_refresh_running.swap(true, Ordering::SeqCst)
+                    {
+                        let service = self.clone();
+                        tokio::spawn(async move {
+                            let _ = service.refresh_tracker_lists_if_due().await;
+                            service
+                                .tracker_refresh_running
+                                .store(false, Ordering::SeqCst);
+                        });
+                    }
+                    let _ = self.enforce_task_queues().await;
+                    if tick.is_multiple_of(5) {
+                        let _ = self.process_retry_and_metadata_policies().await;
+                        let _ = self.process_held_submissions().await;
+                        let _ = self.enforce_task_schedules().await;
+                        let _ = self.advance_file_stages().await;
+                        let _ = self.finish_magnet_prefetches().await;
+                        let _ = self.enforce_seeding_policy().await;
+                    }
+                    // With push notifications only active tasks need progress polling;
+                    // a periodic full sync still catches anything a notification missed.
+                    let full_sync = force_full_sync
+                        || !connected
+                        || tick.is_multiple_of(FULL_SYNC_INTERVAL_TICKS);
+                    self.sync_extra_engines(tick, force_full_sync, &mut last_status)
+                        .await;
+                    let polled = if full_sync {
+                        self.aria2.tell_all().await
+                    } else {
+                        self.aria2.tell_active().await
+                    };
+                    match polled {
+                        Ok(v) => (v, full_sync),
+                        Err(_) => continue,
+                    }
+                } else {
+                    notified_gids.sort();
+                    notified_gids.dedup();
+                    let mut fetched = Vec::with_capacity(notified_gids.len());
+                    for gid in &notified_gids {
+                        if let Ok(Some(snapshot)) = self.aria2.tell_snapshot(gid).await {
+                            fetched.push(snapshot);
+                        }
+                    }
+                    (fetched, false)
+                };
+
+                self.apply_sync_snapshots(
+                    None,
+                    &snapshots,
+                    tick,
+                    last_status
+                        .entry(DEFAULT_ENGINE_ID.to_string())
+                        .or_default(),
+                    full_sync,
+                );
+            }
+        });
     }
-    if !headers.is_empty() {
-        let mut merged = headers.join("\r\n");
-        merged.push_str("\r\n");
-        cmd.arg("-headers").arg(merged);
+
+    /// Extra engines are polled rather than pushed: active tasks every tick and
+    /// everything on full-sync ticks, which is also when unreachable ones are retried.
+    async fn sync_extra_engines(
+        &self,
+        tick: u64,
+        force_full_sync: bool,
+        last_status: &mut HashMap<String, HashMap<String, TaskStatus>>,
+    ) {
+        let full_sync = force_full_sync || tick.is_multiple_of(FULL_SYNC_INTERVAL_TICKS);
+        for (engine_id, engine) in self.extra_engines() {
+            if engine.endpoint().await.is_none()
+                && (!full_sync || engine.ensure_started().await.is_err())
+            {
+                continue;
+            }
+            let polled = if full_sync {
+                engine.tell_all().await
+            } else {
+                engine.tell_active().await
+            };
+            if let Ok(snapshots) = polled {
+                self.apply_sync_snapshots(
+                    Some(&engine_id),
+                    &snapshots,
+                    tick,
+                    last_status.entry(engine_id.clone()).or_default(),
+                    full_sync,
+                );
+            }
+        }
     }
-    #[cfg(target_os = "windows")]
-    {
-        use std::os::windows::process::CommandExt;
-        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
-        cmd.creation_flags(CREATE_NO_WINDOW);
-    }
-    let out = cmd.arg(url).output().ok()?;
-    if !out.status.success() {
-        return None;
+
+    fn apply_sync_snapshots(
+        &self,
+        engine_id: Option<&str>,
+        snapshots: &[Aria2TaskSnapshot],
+        tick: u64,
+        last_status: &mut HashMap<String, TaskStatus>,
+        full_sync: bool,
+    ) {
+        let now = now_ts();
+        let snapshots = &*self.without_finished_prefetches(snapshots);
+        // Tasks not seen by an earlier sync (e.g. one that finished within a
+        // single sync interval) are compared against their stored status instead.
+        let stored_status = snapshots
+            .iter()
+            .filter(|s| matches!(s.status.as_str(), "complete" | "error"))
+            .filter_map(|s| self.db.get_task_by_gid(engine_id, &s.gid).ok().flatten())
+            .filter(|task| !last_status.contains_key(&task.id))
+            .map(|task| (task.id, task.status))
+            .collect::<HashMap<_, _>>();
+        let changed_tasks = match self.db.update_from_snapshots(engine_id, snapshots, now) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        for task in &changed_tasks {
+            let Some(gid) = task.aria2_gid.as_ref() else {
+                continue;
+            };
+            let Some(snapshot) = snapshots.iter().find(|s| &s.gid == gid) else {
+                continue;
+            };
+            if snapshot.files.is_empty() {
+                continue;
+            }
+            let files = snapshot
+                .files
+                .iter()
+                .map(|f| TaskFile {
+                    task_id: task.id.clone(),
+                    path: f.path.clone(),
+                    length: f.length,
+                    completed_length: f.completed_length,
+                    selected: f.selected,
+                })
+                .collect::<Vec<_>>();
+            let _ = self.db.replace_task_files(&task.id, &files);
+        }
+
+        if full_sync {
+            let live = changed_tasks
+                .iter()
+                .map(|t| t.id.as_str())
+                .collect::<HashSet<_>>();
+            last_status.retain(|id, _| live.contains(id.as_str()));
+        }
+        for task in &changed_tasks {
+            let finished = matches!(task.status, TaskStatus::Completed | TaskStatus::Error);
+            let previous = last_status
+                .insert(task.id.clone(), task.status.clone())
+                .or_else(|| stored_status.get(&task.id).cloned());
+            if finished && previous.is_some_and(|previous| previous != task.status) {
+                let _ = self.emitter.emit_task_finished(task);
+            }
+        }
+
+        let _ = self.apply_completion_rules(&changed_tasks, tick);
+        let _ = self.emitter.emit_task_update(&changed_tasks);
     }
-    let text = String::from_utf8(out.stdout).ok()?;
-    let secs = text.trim().parse::<f64>().ok()?;
-    if !secs.is_finite() || secs <= 0.0 {
-        return None;
+
+    pub fn start_log_flush_loop(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(2));
+            loop {
+                interval.tick().await;
+                let _ = self.flush_pending_logs();
+            }
+        });
     }
-    Some((secs * 1000.0).round() as i64)
 }
 
-fn normalize_ffmpeg_failure(exit_code: Option<i32>, detail: &str) -> TaskFailureReason {
-    let fallback_code = format!("FFMPEG_EXIT_{}", exit_code.unwrap_or(-1));
-    let fallback_msg = if detail.trim().is_empty() {
-        "ffmpeg merge failed with unknown error".to_string()
-    } else {
-        detail.to_string()
+type BulkGidGroups = Vec<(Option<String>, Vec<(String, String)>)>;
+
+/// Maps per-gid outcomes back to task ids; an outer error fails the whole group.
+fn collect_bulk_outcomes(
+    targets: &[(String, String)],
+    outcomes: Result<Vec<Result<String>>>,
+    result: &mut BulkTaskActionResult,
+) {
+    let outcomes = match outcomes {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            result
+                .failed
+                .extend(targets.iter().map(|(task_id, _)| BulkTaskFailure {
+                    task_id: task_id.clone(),
+                    error: e.to_string(),
+                }));
+            return;
+        }
     };
-    let lower = detail.to_ascii_lowercase();
-    if lower.contains("403 forbidden") || lower.contains("http error 403") {
-        return TaskFailureReason {
-            health: TaskHealth::AuthRequired,
-            code: "FFMPEG_HTTP_403".to_string(),
-            message: "request forbidden (403). try adding referer/cookie or refresh source URL"
-                .to_string(),
-            remediation: "Refresh the browser capture, add referer/cookie headers, then retry."
-                .to_string(),
-        };
-    }
-    if lower.contains("401 unauthorized") || lower.contains("http error 401") {
-        return TaskFailureReason {
-            health: TaskHealth::AuthRequired,
-            code: "FFMPEG_HTTP_401".to_string(),
-            message: "request unauthorized (401). authentication token/cookie may be expired"
-                .to_string(),
-            remediation: "Refresh the source page and capture a new URL with valid cookies."
-                .to_string(),
-        };
+    let mut outcomes = outcomes.into_iter();
+    for (task_id, _) in targets {
+        match outcomes.next() {
+            Some(Ok(_)) => result.succeeded.push(task_id.clone()),
+            Some(Err(e)) => result.failed.push(BulkTaskFailure {
+                task_id: task_id.clone(),
+                error: e.to_string(),
+            }),
+            None => result.failed.push(BulkTaskFailure {
+                task_id: task_id.clone(),
+                error: "aria2 returned no result".to_string(),
+            }),
+        }
     }
-    if lower.contains("protocol not found")
-        || lower.contains("protocol whitelist")
-        || lower.contains("unsafe file name")
-    {
-        return TaskFailureReason {
-            health: TaskHealth::MergeFailed,
-            code: "FFMPEG_PROTOCOL_BLOCKED".to_string(),
-            message: "source protocol blocked by ffmpeg. try a direct media URL or update ffmpeg"
-                .to_string(),
-            remediation: "Use a direct media URL, change ffmpeg build, or disable the merge path."
-                .to_string(),
-        };
+}
+
+fn validate_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("invalid url: {e}"))?;
+    let scheme = parsed.scheme();
+    if !matches!(scheme, "http" | "https" | "ftp") {
+        return Err(AppError::InvalidInput(format!("unsupported scheme: {scheme}")).into());
     }
-    if lower.contains("cors")
-        || lower.contains("access-control-allow-origin")
-        || lower.contains("origin not allowed")
+    Ok(())
+}
+
+/// Resolves `select_file` and `file_priorities` against a torrent's files into
+/// the `select-file` to start with and, when only some selected files are high
+/// priority, the one to widen to once those are done.
+fn torrent_file_plan(
+    info: &TorrentInfo,
+    options: &AddTaskOptions,
+) -> Result<(Option<String>, Option<String>)> {
+    let count = info.files.len();
+    let mut selected = match options
+        .select_file
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
     {
-        return TaskFailureReason {
-            health: TaskHealth::AuthRequired,
-            code: "FFMPEG_CORS_RESTRICTED".to_string(),
-            message: "source blocked by CORS/origin policy. add referer headers or use browser bridge capture".to_string(),
-            remediation: "Send the media from the browser extension so referer headers are preserved."
-                .to_string(),
-        };
+        Some(spec) => {
+            parse_select_file(spec, count).map_err(|e| AppError::InvalidInput(e.to_string()))?
+        }
+        None => (0..count).collect(),
+    };
+    let mut high = Vec::new();
+    for file in &options.file_priorities {
+        if file.index >= count {
+            return Err(AppError::InvalidInput(format!(
+                "file priority index {} is out of range: the torrent has {count} files",
+                file.index
+            ))
+            .into());
+        }
+        match file.priority.trim() {
+            "skip" => selected.retain(|index| *index != file.index),
+            "high" => high.push(file.index),
+            "normal" => {}
+            other => {
+                return Err(AppError::InvalidInput(format!(
+                    "invalid file priority={other}, expected skip|normal|high"
+                ))
+                .into());
+            }
+        }
     }
-    if lower.contains("too many redirects")
-        || lower.contains("redirect")
-        || lower.contains("moved permanently")
-    {
-        return TaskFailureReason {
-            health: TaskHealth::UrlExpired,
-            code: "FFMPEG_REDIRECT_ERROR".to_string(),
-            message: "redirect chain failed. source URL may be temporary or requires fresh capture"
-                .to_string(),
-            remediation: "Refresh the source page and capture a fresh media URL.".to_string(),
-        };
+    if selected.is_empty() {
+        return Err(AppError::InvalidInput("no torrent files are selected".to_string()).into());
     }
-    TaskFailureReason {
-        health: TaskHealth::MergeFailed,
-        code: fallback_code,
-        message: fallback_msg,
-        remediation: "Open task details, inspect ffmpeg arguments, or export a debug bundle."
-            .to_string(),
+    high.retain(|index| selected.contains(index));
+    high.sort_unstable();
+    high.dedup();
+    let full = (selected.len() < count).then(|| select_file_spec(&selected));
+    if high.is_empty() || high.len() == selected.len() {
+        return Ok((full, None));
     }
+    Ok((
+        Some(select_file_spec(&high)),
+        Some(select_file_spec(&selected)),
+    ))
 }
 
-fn apply_task_failure(task: &mut Task, failure: TaskFailureReason) {
-    task.health = Some(failure.health.as_str().to_string());
-    task.error_code = Some(failure.code);
-    task.error_message = Some(failure.message);
-    task.remediation = Some(failure.remediation);
-}
-
-#[derive(Debug, Clone)]
-struct ChecksumMetadata {
-    algorithm: Option<String>,
-    expected: Option<String>,
-    status: Option<String>,
+/// Announce URLs aria2 can use: udp, http(s) or wss with a host.
+fn valid_tracker(uri: &str) -> bool {
+    reqwest::Url::parse(uri).is_ok_and(|url| {
+        matches!(url.scheme(), "udp" | "http" | "https" | "wss")
+            && url.host_str().is_some_and(|host| !host.is_empty())
+    })
 }
 
-fn checksum_metadata_from_options(options: &AddTaskOptions) -> Result<ChecksumMetadata> {
-    checksum_metadata_from_raw(
-        options.checksum_algorithm.as_deref(),
-        options.checksum_value.as_deref(),
-    )
+/// Tracker list files put one tracker per line, usually with blank lines
+/// between them; commas are accepted too, as in aria2's `bt-tracker`.
+fn parse_tracker_text(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| valid_tracker(v) && seen.insert(v.to_string()))
+        .map(ToString::to_string)
+        .collect()
 }
 
-fn checksum_metadata_from_raw(
-    algorithm: Option<&str>,
-    expected: Option<&str>,
-) -> Result<ChecksumMetadata> {
-    let algorithm = normalize_checksum_algorithm(algorithm)?;
-    let expected = normalize_checksum_expected(expected);
-    if algorithm.is_some() ^ expected.is_some() {
-        return Err(AppError::InvalidInput(
-            "checksum algorithm and value must be provided together".to_string(),
-        )
-        .into());
-    }
-    let status = if algorithm.is_some() {
-        Some("pending".to_string())
+async fn fetch_tracker_list(url: &str) -> Result<String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("invalid url: {e}"))?;
+    let too_large = || anyhow!("tracker list is larger than 2 MiB");
+    let bytes = if parsed.scheme() == "file" {
+        let path = parsed
+            .to_file_path()
+            .map_err(|_| anyhow!("invalid file url: {url}"))?;
+        if tokio::fs::metadata(&path).await?.len() > MAX_TRACKER_LIST_BYTES as u64 {
+            return Err(too_large());
+        }
+        tokio::fs::read(path).await?
     } else {
-        None
-    };
-    Ok(ChecksumMetadata {
-        algorithm,
-        expected,
-        status,
-    })
-}
-
-fn normalize_checksum_algorithm(value: Option<&str>) -> Result<Option<String>> {
-    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
-        return Ok(None);
+        let mut response = reqwest::Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()?
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_TRACKER_LIST_BYTES as u64)
+        {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > MAX_TRACKER_LIST_BYTES {
+                return Err(too_large());
+            }
+        }
+        bytes
     };
-    let normalized = value.to_ascii_lowercase().replace('-', "");
-    match normalized.as_str() {
-        "sha256" => Ok(Some("sha256".to_string())),
-        "sha1" => Ok(Some("sha1".to_string())),
-        "md5" => Ok(Some("md5".to_string())),
-        _ => Err(AppError::InvalidInput(format!("unsupported checksum algorithm: {value}")).into()),
+    if bytes.len() > MAX_TRACKER_LIST_BYTES {
+        return Err(too_large());
     }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
 }
 
-fn normalize_checksum_expected(value: Option<&str>) -> Option<String> {
-    value
-        .map(|v| {
-            v.chars()
-                .filter(|ch| !ch.is_whitespace())
-                .collect::<String>()
-                .to_ascii_lowercase()
-        })
-        .filter(|v| !v.is_empty())
+async fn probe_trackers(uris: &[String]) -> Vec<TrackerHealth> {
+    let mut health = Vec::with_capacity(uris.len());
+    for chunk in uris.chunks(32) {
+        health.extend(
+            futures_util::future::join_all(chunk.iter().map(|uri| probe_tracker(uri))).await,
+        );
+    }
+    health
 }
 
-fn compute_file_checksum(path: &Path, algorithm: &str) -> Result<String> {
-    let mut file = File::open(path).map_err(|e| anyhow!("open file for checksum failed: {e}"))?;
-    let mut buffer = [0_u8; 64 * 1024];
-    match algorithm {
-        "sha256" => {
-            let mut hasher = Sha256::new();
-            loop {
-                let read = file.read(&mut buffer)?;
-                if read == 0 {
-                    break;
-                }
-                hasher.update(&buffer[..read]);
-            }
-            Ok(hex::encode(hasher.finalize()))
+async fn probe_tracker(uri: &str) -> TrackerHealth {
+    let result = match reqwest::Url::parse(uri) {
+        Ok(url) if url.scheme() == "udp" => {
+            time::timeout(TRACKER_PROBE_TIMEOUT, probe_udp_tracker(&url)).await
         }
-        "sha1" => {
-            let mut hasher = Sha1::new();
-            loop {
-                let read = file.read(&mut buffer)?;
-                if read == 0 {
-                    break;
-                }
-                hasher.update(&buffer[..read]);
-            }
-            Ok(hex::encode(hasher.finalize()))
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            time::timeout(TRACKER_PROBE_TIMEOUT, probe_http_tracker(&url)).await
         }
-        "md5" => {
-            let mut hasher = Md5::new();
-            loop {
-                let read = file.read(&mut buffer)?;
-                if read == 0 {
-                    break;
-                }
-                hasher.update(&buffer[..read]);
-            }
-            Ok(hex::encode(hasher.finalize()))
+        _ => {
+            return TrackerHealth {
+                uri: uri.to_string(),
+                status: "unknown".to_string(),
+                checked_at: None,
+                error: None,
+            };
         }
-        _ => Err(
-            AppError::InvalidInput(format!("unsupported checksum algorithm: {algorithm}")).into(),
-        ),
+    };
+    let error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some("timed out".to_string()),
+    };
+    TrackerHealth {
+        uri: uri.to_string(),
+        status: if error.is_none() { "ok" } else { "unreachable" }.to_string(),
+        checked_at: Some(now_ts()),
+        error,
     }
 }
 
-fn checksum_failure(message: &str) -> TaskFailureReason {
-    TaskFailureReason {
-        health: TaskHealth::UnknownError,
-        code: "CHECKSUM_VERIFY_FAILED".to_string(),
-        message: message.to_string(),
-        remediation:
-            "Check whether the file still exists and retry verification by refreshing task details."
-                .to_string(),
+/// Sends a BEP 15 connect request and waits for the matching reply.
+async fn probe_udp_tracker(url: &reqwest::Url) -> Result<()> {
+    let host = url
+        .host_str()
+        .map(|h| h.trim_start_matches('[').trim_end_matches(']'))
+        .ok_or_else(|| anyhow!("tracker has no host"))?;
+    let port = url.port().ok_or_else(|| anyhow!("tracker has no port"))?;
+    let addr = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("tracker host did not resolve"))?;
+    let socket = tokio::net::UdpSocket::bind(if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await?;
+    socket.connect(addr).await?;
+    let transaction = Uuid::new_v4().as_bytes()[..4].to_vec();
+    let mut request = 0x0417_2710_1980_u64.to_be_bytes().to_vec();
+    request.extend(0_u32.to_be_bytes());
+    request.extend(&transaction);
+    socket.send(&request).await?;
+    let mut reply = [0_u8; 16];
+    let read = socket.recv(&mut reply).await?;
+    if read < 16 || reply[..4] != [0, 0, 0, 0] || reply[4..8] != transaction[..] {
+        return Err(anyhow!("unexpected tracker reply"));
     }
+    Ok(())
 }
 
-async fn enrich_candidates_with_content_type(
-    client: &reqwest::Client,
-    candidates: &mut [crate::models::LinkCandidate],
-) {
-    for candidate in candidates
-        .iter_mut()
-        .filter(|candidate| candidate.kind == "http" || candidate.kind == "torrent")
-        .take(20)
-    {
-        let Ok(response) = client.head(&candidate.url).send().await else {
-            continue;
-        };
-        if !response.status().is_success() {
-            continue;
-        }
-        let Some(content_type) = response
-            .headers()
-            .get(reqwest::header::CONTENT_TYPE)
-            .and_then(|v| v.to_str().ok())
-            .map(|v| v.split(';').next().unwrap_or(v).trim().to_ascii_lowercase())
-            .filter(|v| !v.is_empty())
-        else {
-            continue;
-        };
-        if is_download_content_type(&content_type) {
-            candidate.score += 15;
-        }
-        candidate.final_url = Some(response.url().to_string()).filter(|v| v != &candidate.url);
-        candidate.content_length = response
-            .content_length()
-            .and_then(|v| i64::try_from(v).ok());
-        if candidate.content_length.unwrap_or_default() > 0 {
-            candidate.score += 10;
-        }
-        candidate.content_type = Some(content_type);
-    }
-}
-
-fn is_download_content_type(content_type: &str) -> bool {
-    content_type.starts_with("application/")
-        || content_type.starts_with("audio/")
-        || content_type.starts_with("video/")
-        || matches!(
-            content_type,
-            "application/x-bittorrent"
-                | "application/octet-stream"
-                | "application/zip"
-                | "application/x-7z-compressed"
-                | "application/x-rar-compressed"
-                | "application/pdf"
-        )
+/// Any HTTP answer counts: trackers reply to a bare announce with a bencoded failure.
+async fn probe_http_tracker(url: &reqwest::Url) -> Result<()> {
+    reqwest::Client::builder()
+        .timeout(TRACKER_PROBE_TIMEOUT)
+        .build()?
+        .get(url.clone())
+        .send()
+        .await?;
+    Ok(())
 }
 
-fn should_auto_retry(task: &Task) -> bool {
-    if task.error_code.as_deref() == Some("CHECKSUM_MISMATCH") {
-        return false;
+fn url_expansion_mode(value: Option<&str>) -> Result<&'static str> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None | Some("tasks") => Ok("tasks"),
+        Some("mirrors") => Ok("mirrors"),
+        Some("off") => Ok("off"),
+        Some(other) => Err(AppError::InvalidInput(format!(
+            "invalid url_expansion={other}, expected tasks|mirrors|off"
+        ))
+        .into()),
     }
-    let health = task.health.as_deref().unwrap_or_default();
-    !matches!(
-        health,
-        "auth_required" | "url_expired" | "disk_full" | "merge_failed"
-    )
 }
 
-fn task_disk_space_failure(task: &Task) -> Option<TaskFailureReason> {
-    let total = task.total_length;
-    if total <= 0 {
-        return None;
+/// Resolves a URL pattern for a single task: `mirrors` turns the expanded URLs
+/// into one task's source and mirrors, `tasks` only allows a pattern that
+/// expands to one URL.
+fn expand_single_task_url(
+    url: &str,
+    mut options: AddTaskOptions,
+) -> Result<(String, AddTaskOptions)> {
+    let mut url = url.trim().to_string();
+    let mode = url_expansion_mode(options.url_expansion.as_deref())?;
+    if mode != "off" && is_url_pattern(&url) {
+        let mut urls = expand_url_pattern(&url, MAX_EXPANDED_URLS)?;
+        if urls.len() > 1 && mode == "tasks" {
+            return Err(AppError::InvalidInput(format!(
+                "url pattern expands to {} urls; add it as a batch or with url_expansion=mirrors",
+                urls.len()
+            ))
+            .into());
+        }
+        let rest = urls.split_off(1);
+        url = urls.remove(0);
+        options.mirrors.splice(0..0, rest);
     }
-    let remaining = total.saturating_sub(task.completed_length).max(0);
-    if remaining <= 0 {
-        return None;
+    for mirror in &options.mirrors {
+        validate_url(mirror)?;
     }
-    let available = fs2::available_space(Path::new(&task.save_dir)).ok()? as i64;
-    if has_enough_disk_space(available, remaining) {
-        return None;
+    Ok((url, options))
+}
+
+/// One batch entry per URL of a `tasks` pattern; other sources pass through.
+fn expand_batch_source(
+    source: &str,
+    options: AddTaskOptions,
+) -> Result<Vec<(String, AddTaskOptions)>> {
+    if !is_url_pattern(source) || url_expansion_mode(options.url_expansion.as_deref())? != "tasks" {
+        return Ok(vec![(source.to_string(), options)]);
     }
-    Some(TaskFailureReason {
-        health: TaskHealth::DiskFull,
-        code: "INSUFFICIENT_DISK_SPACE".to_string(),
-        message: format!(
-            "save directory has {} bytes available, but task needs about {} bytes",
-            available, remaining
-        ),
-        remediation: "Free disk space or choose another save directory before resuming."
-            .to_string(),
-    })
+    Ok(expand_url_pattern(source, MAX_EXPANDED_URLS)?
+        .into_iter()
+        .map(|url| (url, options.clone()))
+        .collect())
 }
 
-fn has_enough_disk_space(available_bytes: i64, remaining_bytes: i64) -> bool {
-    available_bytes >= remaining_bytes.saturating_add(LOW_DISK_BUFFER_BYTES)
+fn is_stream_manifest_url(url: &str) -> bool {
+    let lower = url.to_lowercase();
+    lower.contains(".m3u8") || lower.contains(".mpd")
 }
 
-fn is_dir_writable(path: &Path) -> bool {
-    if !path.exists() || !path.is_dir() {
-        return false;
+fn normalize_bridge_referer(referer: Option<String>) -> Result<Option<String>> {
+    let Some(v) = referer
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+    else {
+        return Ok(None);
+    };
+    let parsed = reqwest::Url::parse(&v).map_err(|e| anyhow!("invalid referer: {e}"))?;
+    let scheme = parsed.scheme();
+    if !matches!(scheme, "http" | "https") {
+        return Err(anyhow!("unsupported referer scheme: {scheme}"));
     }
-    let probe = path.join(format!(".flamingo-write-check-{}", Uuid::new_v4()));
-    match fs::write(&probe, b"ok") {
-        Ok(_) => {
-            let _ = fs::remove_file(probe);
-            true
+    Ok(Some(v))
+}
+
+fn normalize_bridge_headers(headers: Vec<String>) -> Result<Vec<String>> {
+    let allowed = [
+        "accept",
+        "accept-language",
+        "cookie",
+        "origin",
+        "referer",
+        "user-agent",
+    ];
+    let mut out = Vec::new();
+    for line in headers {
+        let clean = line.trim();
+        if clean.is_empty() {
+            continue;
         }
-        Err(_) => false,
+        let Some((name, value)) = clean.split_once(':') else {
+            return Err(anyhow!("invalid header format: {clean}"));
+        };
+        let key = name.trim().to_ascii_lowercase();
+        if !allowed.contains(&key.as_str()) {
+            continue;
+        }
+        let val = value.trim();
+        if val.is_empty() {
+            continue;
+        }
+        out.push(format!("{}: {}", name.trim(), val));
     }
+    Ok(out)
 }
 
-fn is_executable(path: &Path) -> bool {
-    if !path.exists() || !path.is_file() {
-        return false;
-    }
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        return fs::metadata(path)
-            .map(|m| m.permissions().mode() & 0o111 != 0)
-            .unwrap_or(false);
-    }
-    #[cfg(windows)]
+fn stream_output_filename(
+    url: &str,
+    preferred_name: Option<&str>,
+    preferred_format: Option<&str>,
+) -> String {
+    let ext = preferred_format
+        .map(|v| v.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|v| matches!(v.as_str(), "mp4" | "mkv" | "mov" | "webm"))
+        .unwrap_or_else(|| "mp4".to_string());
+    if let Some(name) = preferred_name
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(sanitize_output_basename)
+        .filter(|v| !v.is_empty())
     {
-        return path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| matches!(e.to_ascii_lowercase().as_str(), "exe" | "bat" | "cmd"))
-            .unwrap_or(false);
+        if name.to_ascii_lowercase().ends_with(&format!(".{ext}")) {
+            return name;
+        }
+        return format!("{name}.{ext}");
     }
-    #[allow(unreachable_code)]
-    false
+    let candidate = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            Path::new(u.path())
+                .file_stem()
+                .map(|v| v.to_string_lossy().to_string())
+        })
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| format!("stream-{}", now_ts()));
+    format!("{}.{ext}", sanitize_output_basename(&candidate))
 }
 
-fn resolve_binary_candidate(input: &str, include_common_dirs: bool) -> Option<PathBuf> {
-    let trimmed = input.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-    let as_path = PathBuf::from(trimmed);
-    if as_path.is_absolute() || trimmed.contains(std::path::MAIN_SEPARATOR) {
-        return as_path.exists().then_some(as_path);
+fn sanitize_output_basename(input: &str) -> String {
+    let mut out = input
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            _ => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_matches('.')
+        .to_string();
+    if out.is_empty() {
+        out = format!("stream-{}", now_ts());
     }
+    out
+}
 
-    if let Some(path_var) = std::env::var_os("PATH") {
-        for dir in std::env::split_paths(&path_var) {
-            let candidate = dir.join(trimmed);
-            if candidate.exists() {
-                return Some(candidate);
-            }
-            #[cfg(windows)]
-            {
-                let candidate_exe = dir.join(format!("{trimmed}.exe"));
-                if candidate_exe.exists() {
-                    return Some(candidate_exe);
-                }
-            }
-        }
+fn ffprobe_binary_from_ffmpeg(ffmpeg_bin: &str) -> String {
+    let as_path = Path::new(ffmpeg_bin);
+    let file = as_path
+        .file_name()
+        .and_then(|v| v.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let probe_name = if file.ends_with(".exe") {
+        "ffprobe.exe"
+    } else {
+        "ffprobe"
+    };
+    if let Some(parent) = as_path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        return parent.join(probe_name).to_string_lossy().to_string();
+    }
+    if file.contains("ffmpeg") {
+        return ffmpeg_bin
+            .replace("ffmpeg.exe", "ffprobe.exe")
+            .replace("ffmpeg", "ffprobe");
     }
+    probe_name.to_string()
+}
 
-    if include_common_dirs {
-        #[cfg(target_os = "macos")]
-        {
-            for p in [
-                "/opt/homebrew/bin/ffmpeg",
-                "/usr/local/bin/ffmpeg",
-                "/usr/bin/ffmpeg",
-            ] {
-                let candidate = PathBuf::from(p);
-                if candidate.exists() {
-                    return Some(candidate);
-                }
-            }
-        }
-        #[cfg(target_os = "linux")]
-        {
-            for p in ["/usr/bin/ffmpeg", "/usr/local/bin/ffmpeg"] {
-                let candidate = PathBuf::from(p);
-                if candidate.exists() {
-                    return Some(candidate);
-                }
-            }
-        }
+fn probe_media_duration_ms(
+    ffprobe_bin: &str,
+    url: &str,
+    user_agent: Option<&str>,
+    referer: Option<&str>,
+    headers: &[String],
+) -> Option<i64> {
+    let mut cmd = Command::new(ffprobe_bin);
+    cmd.arg("-v")
+        .arg("error")
+        .arg("-show_entries")
+        .arg("format=duration")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1:nokey=1");
+    if let Some(v) = user_agent.map(str::trim).filter(|v| !v.is_empty()) {
+        cmd.arg("-user_agent").arg(v);
     }
-
-    None
-}
-
-fn to_aria2_options(options: AddTaskOptions) -> Value {
-    let mut m = serde_json::Map::new();
-    if let Some(dir) = options.save_dir {
-        m.insert("dir".to_string(), json!(dir));
+    if let Some(v) = referer.map(str::trim).filter(|v| !v.is_empty()) {
+        cmd.arg("-referer").arg(v);
     }
-    if let Some(out) = options.out {
-        let filename = Path::new(&out)
-            .file_name()
-            .map(|v| v.to_string_lossy().to_string())
-            .unwrap_or(out);
-        m.insert("out".to_string(), json!(filename));
+    if !headers.is_empty() {
+        let mut merged = headers.join("\r\n");
+        merged.push_str("\r\n");
+        cmd.arg("-headers").arg(merged);
     }
-    if let Some(v) = options.max_connection_per_server {
-        m.insert(
-            "max-connection-per-server".to_string(),
-            json!(v.to_string()),
-        );
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
     }
-    if let Some(v) = options.split {
-        m.insert("split".to_string(), json!(v.to_string()));
+    let out = cmd.arg(url).output().ok()?;
+    if !out.status.success() {
+        return None;
     }
-    if let Some(v) = options.max_download_limit {
-        let limit = v.trim();
-        if !limit.is_empty() {
-            m.insert("max-download-limit".to_string(), json!(limit));
-        }
+    let text = String::from_utf8(out.stdout).ok()?;
+    let secs = text.trim().parse::<f64>().ok()?;
+    if !secs.is_finite() || secs <= 0.0 {
+        return None;
     }
-    if let Some(v) = options.max_upload_limit {
-        let limit = v.trim();
-        if !limit.is_empty() {
-            m.insert("max-upload-limit".to_string(), json!(limit));
-        }
+    Some((secs * 1000.0).round() as i64)
+}
+
+fn normalize_ffmpeg_failure(exit_code: Option<i32>, detail: &str) -> TaskFailureReason {
+    let fallback_code = format!("FFMPEG_EXIT_{}", exit_code.unwrap_or(-1));
+    let fallback_msg = if detail.trim().is_empty() {
+        "ffmpeg merge failed with unknown error".to_string()
+    } else {
+        detail.to_string()
+    };
+    let lower = detail.to_ascii_lowercase();
+    if lower.contains("403 forbidden") || lower.contains("http error 403") {
+        return TaskFailureReason {
+            health: TaskHealth::AuthRequired,
+            code: "FFMPEG_HTTP_403".to_string(),
+            message: "request forbidden (403). try adding referer/cookie or refresh source URL"
+                .to_string(),
+            remediation: "Refresh the browser capture, add referer/cookie headers, then retry."
+                .to_string(),
+        };
     }
-    if let Some(v) = options.seed_ratio
-        && v > 0.0
-    {
-        m.insert("seed-ratio".to_string(), json!(v.to_string()));
+    if lower.contains("401 unauthorized") || lower.contains("http error 401") {
+        return TaskFailureReason {
+            health: TaskHealth::AuthRequired,
+            code: "FFMPEG_HTTP_401".to_string(),
+            message: "request unauthorized (401). authentication token/cookie may be expired"
+                .to_string(),
+            remediation: "Refresh the source page and capture a new URL with valid cookies."
+                .to_string(),
+        };
     }
-    if let Some(v) = options.seed_time
-        && v > 0
+    if lower.contains("protocol not found")
+        || lower.contains("protocol whitelist")
+        || lower.contains("unsafe file name")
     {
-        m.insert("seed-time".to_string(), json!(v.to_string()));
+        return TaskFailureReason {
+            health: TaskHealth::MergeFailed,
+            code: "FFMPEG_PROTOCOL_BLOCKED".to_string(),
+            message: "source protocol blocked by ffmpeg. try a direct media URL or update ffmpeg"
+                .to_string(),
+            remediation: "Use a direct media URL, change ffmpeg build, or disable the merge path."
+                .to_string(),
+        };
     }
-    if let Some(v) = options.user_agent {
-        let ua = v.trim();
-        if !ua.is_empty() {
-            m.insert("user-agent".to_string(), json!(ua));
-        }
+    if lower.contains("cors")
+        || lower.contains("access-control-allow-origin")
+        || lower.contains("origin not allowed")
+    {
+        return TaskFailureReason {
+            health: TaskHealth::AuthRequired,
+            code: "FFMPEG_CORS_RESTRICTED".to_string(),
+            message: "source blocked by CORS/origin policy. add referer headers or use browser bridge capture".to_string(),
+            remediation: "Send the media from the browser extension so referer headers are preserved."
+                .to_string(),
+        };
     }
-    if let Some(v) = options.referer {
-        let referer = v.trim();
-        if !referer.is_empty() {
-            m.insert("referer".to_string(), json!(referer));
-        }
+    if lower.contains("too many redirects")
+        || lower.contains("redirect")
+        || lower.contains("moved permanently")
+    {
+        return TaskFailureReason {
+            health: TaskHealth::UrlExpired,
+            code: "FFMPEG_REDIRECT_ERROR".to_string(),
+            message: "redirect chain failed. source URL may be temporary or requires fresh capture"
+                .to_string(),
+            remediation: "Refresh the source page and capture a fresh media URL.".to_string(),
+        };
     }
-    if !options.headers.is_empty() {
-        let headers = options
-            .headers
-            .into_iter()
-            .map(|h| h.trim().to_string())
-            .filter(|h| !h.is_empty())
-            .collect::<Vec<_>>();
-        if !headers.is_empty() {
-            m.insert("header".to_string(), json!(headers));
-        }
+    TaskFailureReason {
+        health: TaskHealth::MergeFailed,
+        code: fallback_code,
+        message: fallback_msg,
+        remediation: "Open task details, inspect ffmpeg arguments, or export a debug bundle."
+            .to_string(),
     }
-    Value::Object(m)
-}
-
-fn now_ts() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or_default()
 }
 
-fn select_speed_limit(rules: &[SpeedPlanRule]) -> Option<String> {
-    let now = Local::now();
-    let weekday = now.weekday().number_from_monday();
-    let current_minute = now.hour() as i32 * 60 + now.minute() as i32;
-    for rule in rules {
-        let limit = rule.limit.trim();
-        if limit.is_empty() {
-            continue;
-        }
-        if let Some(days) = &rule.days {
-            let allowed = days
-                .split(',')
-                .filter_map(|d| d.trim().parse::<u32>().ok())
-                .any(|d| d == weekday);
-            if !allowed {
-                continue;
-            }
-        }
-        let start = rule
-            .start
-            .as_deref()
-            .and_then(parse_hhmm_minutes)
-            .unwrap_or(0);
-        let end = rule
-            .end
-            .as_deref()
-            .and_then(parse_hhmm_minutes)
-            .unwrap_or(24 * 60);
-        let in_range = if start <= end {
-            current_minute >= start && current_minute < end
-        } else {
-            current_minute >= start || current_minute < end
-        };
-        if in_range {
-            return Some(limit.to_string());
-        }
-    }
-    None
+fn apply_task_failure(task: &mut Task, failure: TaskFailureReason) {
+    task.health = Some(failure.health.as_str().to_string());
+    task.error_code = Some(failure.code);
+    task.error_message = Some(failure.message);
+    task.remediation = Some(failure.remediation);
 }
 
-fn parse_hhmm_minutes(v: &str) -> Option<i32> {
-    let (h, m) = v.split_once(':')?;
-    let hh = h.parse::<i32>().ok()?;
-    let mm = m.parse::<i32>().ok()?;
-    if !(0..=23).contains(&hh) || !(0..=59).contains(&mm) {
-        return None;
-    }
-    Some(hh * 60 + mm)
+#[derive(Debug, Clone)]
+struct ChecksumMetadata {
+    algorithm: Option<String>,
+    expected: Option<String>,
+    status: Option<String>,
 }
 
-fn compute_next_retry_at(now_ts: i64, backoff_secs: i64, attempts: u32) -> i64 {
-    now_ts + backoff_secs * (attempts as i64 + 1)
+fn checksum_metadata_from_options(options: &AddTaskOptions) -> Result<ChecksumMetadata> {
+    checksum_metadata_from_raw(
+        options.checksum_algorithm.as_deref(),
+        options.checksum_value.as_deref(),
+    )
 }
 
-fn apply_fallback_source(source: &str, attempt: usize, mirrors: &[String]) -> String {
-    if mirrors.is_empty() || attempt == 0 {
-        return source.to_string();
+fn checksum_metadata_from_raw(
+    algorithm: Option<&str>,
+    expected: Option<&str>,
+) -> Result<ChecksumMetadata> {
+    let algorithm = normalize_checksum_algorithm(algorithm)?;
+    let expected = normalize_checksum_expected(expected);
+    if algorithm.is_some() ^ expected.is_some() {
+        return Err(AppError::InvalidInput(
+            "checksum algorithm and value must be provided together".to_string(),
+        )
+        .into());
     }
-    let idx = (attempt - 1) % mirrors.len();
-    let prefix = mirrors[idx].trim_end_matches('/');
-    if source.starts_with("http://") || source.starts_with("https://") {
-        format!("{prefix}/{}", source.trim_start_matches('/'))
+    let status = if algorithm.is_some() {
+        Some("pending".to_string())
     } else {
-        source.to_string()
-    }
+        None
+    };
+    Ok(ChecksumMetadata {
+        algorithm,
+        expected,
+        status,
+    })
 }
 
-fn absolute_path(base: &Path, value: &str) -> PathBuf {
-    let p = Path::new(value);
-    if p.is_absolute() {
-        p.to_path_buf()
-    } else {
-        base.join(p)
+fn normalize_checksum_algorithm(value: Option<&str>) -> Result<Option<String>> {
+    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+        return Ok(None);
+    };
+    let normalized = value.to_ascii_lowercase().replace('-', "");
+    match normalized.as_str() {
+        "sha256" => Ok(Some("sha256".to_string())),
+        "sha1" => Ok(Some("sha1".to_string())),
+        "md5" => Ok(Some("md5".to_string())),
+        _ => Err(AppError::InvalidInput(format!("unsupported checksum algorithm: {value}")).into()),
     }
 }
 
-fn normalize_lexical_path(path: &Path) -> PathBuf {
-    use std::path::Component;
-    let mut out = PathBuf::new();
-    for comp in path.components() {
-        match comp {
-            Component::CurDir => {}
-            Component::ParentDir => {
-                let _ = out.pop();
+fn normalize_checksum_expected(value: Option<&str>) -> Option<String> {
+    value
+        .map(|v| {
+            v.chars()
+                .filter(|ch| !ch.is_whitespace())
+                .collect::<String>()
+                .to_ascii_lowercase()
+        })
+        .filter(|v| !v.is_empty())
+}
+
+fn compute_file_checksum(path: &Path, algorithm: &str) -> Result<String> {
+    let mut file = File::open(path).map_err(|e| anyhow!("open file for checksum failed: {e}"))?;
+    let mut buffer = [0_u8; 64 * 1024];
+    match algorithm {
+        "sha256" => {
+            let mut hasher = Sha256::new();
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
             }
-            other => out.push(other.as_os_str()),
+            Ok(hex::encode(hasher.finalize()))
+        }
+        "sha1" => {
+            let mut hasher = Sha1::new();
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+            Ok(hex::encode(hasher.finalize()))
+        }
+        "md5" => {
+            let mut hasher = Md5::new();
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+            Ok(hex::encode(hasher.finalize()))
         }
+        _ => Err(
+            AppError::InvalidInput(format!("unsupported checksum algorithm: {algorithm}")).into(),
+        ),
     }
-    out
 }
 
-fn is_subpath(path: &Path, root: &Path) -> bool {
-    path.starts_with(root)
+fn checksum_failure(message: &str) -> TaskFailureReason {
+    TaskFailureReason {
+        health: TaskHealth::UnknownError,
+        code: "CHECKSUM_VERIFY_FAILED".to_string(),
+        message: message.to_string(),
+        remediation:
+            "Check whether the file still exists and retry verification by refreshing task details."
+                .to_string(),
+    }
 }
 
-fn cleanup_empty_dirs_upwards(from: &Path, root: &Path) {
-    let mut cursor = from.to_path_buf();
-    while let Ok(canonical) = cursor.canonicalize() {
-        if canonical == root || !is_subpath(&canonical, root) {
-            break;
+async fn enrich_candidates_with_content_type(
+    client: &reqwest::Client,
+    candidates: &mut [crate::models::LinkCandidate],
+) {
+    for candidate in candidates
+        .iter_mut()
+        .filter(|candidate| candidate.kind == "http" || candidate.kind == "torrent")
+        .take(20)
+    {
+        let Ok(response) = client.head(&candidate.url).send().await else {
+            continue;
+        };
+        if !response.status().is_success() {
+            continue;
         }
-        let is_empty = fs::read_dir(&canonical)
-            .ok()
-            .map(|mut it| it.next().is_none())
-            .unwrap_or(false);
-        if !is_empty {
-            break;
+        let Some(content_type) = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or(v).trim().to_ascii_lowercase())
+            .filter(|v| !v.is_empty())
+        else {
+            continue;
+        };
+        if is_download_content_type(&content_type) {
+            candidate.score += 15;
         }
-        if fs::remove_dir(&canonical).is_err() {
-            break;
+        candidate.final_url = Some(response.url().to_string()).filter(|v| v != &candidate.url);
+        candidate.content_length = response
+            .content_length()
+            .and_then(|v| i64::try_from(v).ok());
+        if candidate.content_length.unwrap_or_default() > 0 {
+            candidate.score += 10;
         }
-        let Some(parent) = canonical.parent() else {
-            break;
-        };
-        cursor = parent.to_path_buf();
+        candidate.content_type = Some(content_type);
     }
 }
 
-fn open_path_in_os(path: &Path) -> Result<()> {
-    #[cfg(target_os = "macos")]
-    {
-        Command::new("open")
-            .arg(path)
-            .spawn()
-            .map_err(|e| anyhow!("open failed: {e}"))?;
-        return Ok(());
+fn is_download_content_type(content_type: &str) -> bool {
+    content_type.starts_with("application/")
+        || content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        || matches!(
+            content_type,
+            "application/x-bittorrent"
+                | "application/octet-stream"
+                | "application/zip"
+                | "application/x-7z-compressed"
+                | "application/x-rar-compressed"
+                | "application/pdf"
+        )
+}
+
+fn should_auto_retry(task: &Task) -> bool {
+    if matches!(
+        task.error_code.as_deref(),
+        Some("CHECKSUM_MISMATCH" | "DEPENDENCY_FAILED")
+    ) {
+        return false;
     }
-    #[cfg(target_os = "linux")]
+    let health = task.health.as_deref().unwrap_or_default();
+    !matches!(
+        health,
+        "auth_required" | "url_expired" | "disk_full" | "merge_failed"
+    )
+}
+
+fn task_disk_space_failure(task: &Task) -> Option<TaskFailureReason> {
+    let total = task.total_length;
+    if total <= 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(task.completed_length).max(0);
+    if remaining <= 0 {
+        return None;
+    }
+    let available = fs2::available_space(Path::new(&task.save_dir)).ok()? as i64;
+    if has_enough_disk_space(available, remaining) {
+        return None;
+    }
+    Some(TaskFailureReason {
+        health: TaskHealth::DiskFull,
+        code: "INSUFFICIENT_DISK_SPACE".to_string(),
+        message: format!(
+            "save directory has {} bytes available, but task needs about {} bytes",
+            available, remaining
+        ),
+        remediation: "Free disk space or choose another save directory before resuming."
+            .to_string(),
+    })
+}
+
+fn has_enough_disk_space(available_bytes: i64, remaining_bytes: i64) -> bool {
+    available_bytes >= remaining_bytes.saturating_add(LOW_DISK_BUFFER_BYTES)
+}
+
+fn is_dir_writable(path: &Path) -> bool {
+    if !path.exists() || !path.is_dir() {
+        return false;
+    }
+    let probe = path.join(format!(".flamingo-write-check-{}", Uuid::new_v4()));
+    match fs::write(&probe, b"ok") {
+        Ok(_) => {
+            let _ = fs::remove_file(probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    if !path.exists() || !path.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
     {
-        Command::new("xdg-open")
-            .arg(path)
-            .spawn()
-            .map_err(|e| anyhow!("xdg-open failed: {e}"))?;
-        return Ok(());
+        use std::os::unix::fs::PermissionsExt;
+        return fs::metadata(path)
+            .map(|m| m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false);
     }
-    #[cfg(target_os = "windows")]
+    #[cfg(windows)]
     {
-        Command::new("explorer")
-            .arg(path)
-            .spawn()
-            .map_err(|e| anyhow!("explorer failed: {e}"))?;
-        return Ok(());
+        return path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| matches!(e.to_ascii_lowercase().as_str(), "exe" | "bat" | "cmd"))
+            .unwrap_or(false);
     }
     #[allow(unreachable_code)]
-    Err(anyhow!("open path is not supported on this platform"))
+    false
 }
 
-impl DownloadService {
-    async fn ensure_aria2_ready(&self) -> Result<()> {
-        if !self.aria2_bin_exists() {
-            return Err(anyhow!(
-                "aria2 is unavailable. Please set a valid aria2 binary path in Settings and restart aria2."
-            ));
-        }
-        let mut last_err = None;
-        for attempt in 1..=2 {
-            match self.aria2.ensure_started().await {
-                Ok(_) => return Ok(()),
-                Err(e) => {
-                    self.push_log(
-                        "ensure_aria2_ready",
-                        format!("attempt {attempt} failed: {e}"),
-                    );
-                    last_err = Some(e.to_string());
-                    let _ = self.aria2.stop().await;
-                    time::sleep(Duration::from_millis(300)).await;
-                }
-            }
-        }
-        let err = last_err.unwrap_or_else(|| "unknown startup error".to_string());
-        let stderr = self.aria2.stderr_tail().unwrap_or_default();
-        if stderr.is_empty() {
-            Err(anyhow!(
-                "aria2 is unavailable. Please check aria2 path in Settings and restart aria2. details: {err}"
-            ))
-        } else {
-            Err(anyhow!(
-                "aria2 is unavailable. Please check aria2 path in Settings and restart aria2. details: {err}. stderr: {stderr}"
-            ))
-        }
-    }
-
-    fn configured_download_dir(&self) -> Result<String> {
-        self.db.get_setting("download_dir")?.ok_or_else(|| {
-            AppError::InvalidInput("missing required setting: download_dir".to_string()).into()
-        })
-    }
-
-    fn configured_download_dir_rules(&self) -> Vec<DownloadDirRule> {
-        self.db
-            .load_global_settings()
-            .map(|s| s.download_dir_rules)
-            .unwrap_or_default()
+fn resolve_binary_candidate(input: &str, include_common_dirs: bool) -> Option<PathBuf> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return None;
     }
-
-    fn resolve_save_dir_for_new_task(
-        &self,
-        task_type: TaskType,
-        source: &str,
-        options: &AddTaskOptions,
-        http_content_type: Option<&str>,
-    ) -> Result<String> {
-        self.resolve_save_dir_for_new_task_detail(task_type, source, options, http_content_type)
-            .map(|v| v.0)
+    let as_path = PathBuf::from(trimmed);
+    if as_path.is_absolute() || trimmed.contains(std::path::MAIN_SEPARATOR) {
+        return as_path.exists().then_some(as_path);
     }
 
-    fn resolve_category_for_new_task(
-        &self,
-        task_type: TaskType,
-        source: &str,
-        http_content_type: Option<&str>,
-    ) -> Result<Option<String>> {
-        let settings = self.db.load_global_settings()?;
-        for rule in settings.category_rules {
-            if !rule.enabled {
-                continue;
+    if let Some(path_var) = std::env::var_os("PATH") {
+        for dir in std::env::split_paths(&path_var) {
+            let candidate = dir.join(trimmed);
+            if candidate.exists() {
+                return Some(candidate);
             }
-            if category_rule_matches(&rule, &task_type, source, http_content_type) {
-                let category = rule.category.trim();
-                if !category.is_empty() {
-                    return Ok(Some(category.to_string()));
+            #[cfg(windows)]
+            {
+                let candidate_exe = dir.join(format!("{trimmed}.exe"));
+                if candidate_exe.exists() {
+                    return Some(candidate_exe);
                 }
             }
         }
-        Ok(None)
-    }
-
-    fn resolve_requested_category(&self, category: Option<&str>) -> Option<String> {
-        category
-            .map(str::trim)
-            .filter(|value| !value.is_empty())
-            .map(ToString::to_string)
     }
 
-    fn resolve_save_dir_for_new_task_detail(
-        &self,
-        task_type: TaskType,
-        source: &str,
-        options: &AddTaskOptions,
-        http_content_type: Option<&str>,
-    ) -> Result<(String, Option<DownloadDirRule>)> {
-        if let Some(v) = options
-            .save_dir
-            .as_ref()
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
+    if include_common_dirs {
+        #[cfg(target_os = "macos")]
         {
-            return Ok((v.to_string(), None));
-        }
-        let default_dir = self.configured_download_dir()?;
-        for rule in self.configured_download_dir_rules() {
-            if !rule.enabled {
-                continue;
-            }
-            let candidate = rule.save_dir.trim();
-            if candidate.is_empty() {
-                continue;
+            for p in [
+                "/opt/homebrew/bin/ffmpeg",
+                "/usr/local/bin/ffmpeg",
+                "/usr/bin/ffmpeg",
+            ] {
+                let candidate = PathBuf::from(p);
+                if candidate.exists() {
+                    return Some(candidate);
+                }
             }
-            if rule_matches(&rule, &task_type, source, http_content_type) {
-                let resolved = apply_rule_subdir(candidate, &rule, source);
-                return Ok((resolved, Some(rule)));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            for p in ["/usr/bin/ffmpeg", "/usr/local/bin/ffmpeg"] {
+                let candidate = PathBuf::from(p);
+                if candidate.exists() {
+                    return Some(candidate);
+                }
             }
         }
-        Ok((default_dir, None))
-    }
-
-    fn aria2_bin_path(&self) -> String {
-        self.db
-            .get_setting("aria2_bin_path")
-            .ok()
-            .flatten()
-            .unwrap_or_default()
     }
 
-    fn aria2_path_source(&self, path: &Path) -> String {
-        let manual = self
-            .db
-            .get_setting("manual_aria2_bin_path")
-            .ok()
-            .flatten()
-            .unwrap_or_default();
-        if !manual.trim().is_empty() && Path::new(manual.trim()) == path {
-            return "manual".to_string();
-        }
+    None
+}
 
-        if let Some(resource_dir) = std::env::var_os("FLAMINGO_RESOURCE_DIR").map(PathBuf::from) {
-            let bundled_root = resource_dir.join("aria2").join("bin");
-            if path.starts_with(&bundled_root) {
-                return "bundled".to_string();
-            }
+fn to_aria2_options(options: AddTaskOptions) -> Value {
+    let mut m = serde_json::Map::new();
+    if let Some(dir) = options.save_dir {
+        m.insert("dir".to_string(), json!(dir));
+    }
+    if let Some(select_file) = options.select_file {
+        m.insert("select-file".to_string(), json!(select_file));
+    }
+    if options.metadata_only == Some(true) {
+        m.insert("bt-metadata-only".to_string(), json!("true"));
+        m.insert("bt-save-metadata".to_string(), json!("true"));
+    }
+    if let Some(out) = options.out {
+        let filename = Path::new(&out)
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or(out);
+        m.insert("out".to_string(), json!(filename));
+    }
+    match options.collision_policy.as_deref() {
+        Some("overwrite") => {
+            m.insert("allow-overwrite".to_string(), json!("true"));
+            m.insert("auto-file-renaming".to_string(), json!("false"));
         }
-
-        let path_text = path.to_string_lossy();
-        if path_text.contains("/aria2/bin/") || path_text.contains("\\aria2\\bin\\") {
-            return "bundled".to_string();
+        Some("resume") => {
+            m.insert("continue".to_string(), json!("true"));
+            m.insert("auto-file-renaming".to_string(), json!("false"));
         }
-
-        "system".to_string()
+        _ => {}
     }
-
-    fn aria2_bin_exists(&self) -> bool {
-        let path = self.aria2_bin_path();
-        !path.is_empty() && Path::new(&path).exists()
+    if let Some(v) = options.max_connection_per_server {
+        m.insert(
+            "max-connection-per-server".to_string(),
+            json!(v.to_string()),
+        );
     }
-
-    fn configured_ffmpeg_bin_path(&self) -> String {
-        self.db
-            .get_setting("ffmpeg_bin_path")
-            .ok()
-            .flatten()
-            .map(|v| v.trim().to_string())
-            .filter(|v| !v.is_empty())
-            .unwrap_or_else(|| "ffmpeg".to_string())
+    if let Some(v) = options.split {
+        m.insert("split".to_string(), json!(v.to_string()));
     }
-
-    fn effective_ffmpeg_bin_path(&self) -> String {
-        let configured = self.configured_ffmpeg_bin_path();
-        if let Some(found) = resolve_binary_candidate(&configured, true) {
-            return found.to_string_lossy().to_string();
+    if let Some(v) = options.max_download_limit {
+        let limit = v.trim();
+        if !limit.is_empty() {
+            m.insert("max-download-limit".to_string(), json!(limit));
         }
-        configured
     }
-
-    fn ffmpeg_bin_exists(&self) -> bool {
-        let configured = self.configured_ffmpeg_bin_path();
-        resolve_binary_candidate(&configured, true).is_some()
+    if let Some(v) = options.max_upload_limit {
+        let limit = v.trim();
+        if !limit.is_empty() {
+            m.insert("max-upload-limit".to_string(), json!(limit));
+        }
     }
-
-    fn delete_task_files_safely(&self, task: &Task) -> Result<()> {
-        let configured_root = self.configured_download_dir()?;
-        let root_raw = absolute_path(&std::env::current_dir()?, &configured_root);
-        if !root_raw.exists() {
-            self.push_log(
-                "delete_task_files",
-                format!("skip cleanup for task {}: download root missing", task.id),
-            );
-            return Ok(());
+    if let Some(v) = options.seed_ratio
+        && v > 0.0
+    {
+        m.insert("seed-ratio".to_string(), json!(v.to_string()));
+    }
+    if let Some(v) = options.seed_time
+        && v > 0
+    {
+        m.insert("seed-time".to_string(), json!(v.to_string()));
+    }
+    if let Some(v) = options.user_agent {
+        let ua = v.trim();
+        if !ua.is_empty() {
+            m.insert("user-agent".to_string(), json!(ua));
         }
-        let root = root_raw.canonicalize()?;
-        let task_save_dir = absolute_path(&std::env::current_dir()?, &task.save_dir);
-        let task_save_dir_canonical = task_save_dir
-            .canonicalize()
-            .map_err(|e| anyhow!("resolve task save_dir failed ({}): {e}", task.save_dir))?;
-        if !is_subpath(&task_save_dir_canonical, &root) {
-            return Err(anyhow!(
-                "refused to delete files outside download root: {}",
-                task_save_dir_canonical.display()
-            ));
+    }
+    if let Some(v) = options.referer {
+        let referer = v.trim();
+        if !referer.is_empty() {
+            m.insert("referer".to_string(), json!(referer));
         }
-
-        let files = self.db.list_task_files(&task.id)?;
-        let mut candidate_set: HashSet<PathBuf> = HashSet::new();
-        for f in &files {
-            if f.path.is_empty() {
-                continue;
-            }
-            candidate_set.insert(absolute_path(Path::new(&task.save_dir), &f.path));
-        }
-
-        if candidate_set.is_empty()
-            && let Some(name) = &task.name
-        {
-            candidate_set.insert(Path::new(&task.save_dir).join(name));
+    }
+    if !options.headers.is_empty() {
+        let headers = options
+            .headers
+            .into_iter()
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .collect::<Vec<_>>();
+        if !headers.is_empty() {
+            m.insert("header".to_string(), json!(headers));
         }
+    }
+    Value::Object(m)
+}
 
-        let mut candidates = candidate_set.into_iter().collect::<Vec<_>>();
-        candidates.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
-
-        let mut blocked = Vec::new();
-        let mut failed = Vec::new();
-        let mut removed = 0usize;
+fn now_ts() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
 
-        for candidate in &candidates {
-            let normalized = normalize_lexical_path(candidate);
-            if !is_subpath(&normalized, &root) {
-                blocked.push(normalized.display().to_string());
-                continue;
-            }
-            if !candidate.exists() {
-                continue;
-            }
-            let canonical = match candidate.canonicalize() {
-                Ok(v) => v,
-                Err(e) => {
-                    failed.push(format!("resolve {} failed: {e}", candidate.display()));
-                    continue;
-                }
-            };
-            if !is_subpath(&canonical, &root) {
-                blocked.push(canonical.display().to_string());
-                continue;
-            }
-            let remove_result = if canonical.is_dir() {
-                fs::remove_dir_all(&canonical)
-            } else {
-                fs::remove_file(&canonical)
-            };
-            if let Err(e) = remove_result {
-                failed.push(format!("remove {} failed: {e}", canonical.display()));
-            } else {
-                removed += 1;
-            }
-        }
+/// The goal a seeding task has met, if any: its category's rule, else the
+/// rule without a category.
+fn seeding_goal_reached(
+    rules: &[SeedingRule],
+    task: &Task,
+    state: &TaskSeeding,
+    now: i64,
+) -> Option<&'static str> {
+    let category = task.category.as_deref().map(str::trim);
+    fn rule_category(rule: &SeedingRule) -> Option<&str> {
+        rule.category
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+    }
+    let rule = rules
+        .iter()
+        .find(|rule| category.is_some() && rule_category(rule) == category)
+        .or_else(|| rules.iter().find(|rule| rule_category(rule).is_none()))?;
+    if rule.ratio.is_some_and(|ratio| state.ratio >= ratio) {
+        return Some("ratio");
+    }
+    if let (Some(hours), Some(since)) = (rule.seed_hours.filter(|h| *h > 0), state.seeding_since)
+        && now - since >= i64::from(hours) * 3600
+    {
+        return Some("time");
+    }
+    if let (Some(minutes), Some(last)) = (
+        rule.idle_minutes.filter(|m| *m > 0),
+        state.last_upload_at.or(state.seeding_since),
+    ) && now - last >= i64::from(minutes) * 60
+    {
+        return Some("idle");
+    }
+    None
+}
 
-        if !blocked.is_empty() {
-            return Err(anyhow!(
-                "refused to delete {} path(s) outside download root: {}",
-                blocked.len(),
-                blocked.join(" | ")
-            ));
+fn select_speed_limit(rules: &[SpeedPlanRule]) -> Option<String> {
+    let now = Local::now();
+    let weekday = now.weekday().number_from_monday();
+    let current_minute = now.hour() as i32 * 60 + now.minute() as i32;
+    for rule in rules {
+        let limit = rule.limit.trim();
+        if limit.is_empty() {
+            continue;
         }
-        if !failed.is_empty() {
-            return Err(anyhow!(
-                "failed to delete {} path(s): {}",
-                failed.len(),
-                failed.join(" | ")
-            ));
+        let start = rule
+            .start
+            .as_deref()
+            .and_then(parse_hhmm_minutes)
+            .unwrap_or(0);
+        let end = rule
+            .end
+            .as_deref()
+            .and_then(parse_hhmm_minutes)
+            .unwrap_or(24 * 60);
+        if window_contains(rule.days.as_deref(), start, end, weekday, current_minute) {
+            return Some(limit.to_string());
         }
+    }
+    None
+}
 
-        for candidate in &candidates {
-            if let Some(parent) = candidate.parent() {
-                cleanup_empty_dirs_upwards(parent, &root);
-            }
+/// Whether `weekday`/`minute` fall in a window; `start > end` wraps past midnight.
+fn window_contains(days: Option<&str>, start: i32, end: i32, weekday: u32, minute: i32) -> bool {
+    if let Some(days) = days {
+        let allowed = days
+            .split(',')
+            .filter_map(|d| d.trim().parse::<u32>().ok())
+            .any(|d| d == weekday);
+        if !allowed {
+            return false;
         }
-        self.push_log(
-            "delete_task_files",
-            format!("cleanup finished for task {}: removed {}", task.id, removed),
-        );
-        Ok(())
     }
+    if start <= end {
+        minute >= start && minute < end
+    } else {
+        minute >= start || minute < end
+    }
+}
 
-    pub fn append_operation_log(&self, action: &str, message: impl Into<String>) {
-        self.push_log(action, message.into());
-        let _ = self.flush_pending_logs();
+fn schedule_allows(
+    start_at: Option<i64>,
+    window: Option<&TaskWindow>,
+    now: &chrono::DateTime<Local>,
+) -> bool {
+    if start_at.is_some_and(|start_at| now.timestamp() < start_at) {
+        return false;
     }
+    let Some(window) = window else {
+        return true;
+    };
+    let (Some(start), Some(end)) = (
+        parse_hhmm_minutes(&window.start),
+        parse_hhmm_minutes(&window.end),
+    ) else {
+        return true;
+    };
+    window_contains(
+        window.days.as_deref().filter(|v| !v.trim().is_empty()),
+        start,
+        end,
+        now.weekday().number_from_monday(),
+        now.hour() as i32 * 60 + now.minute() as i32,
+    )
+}
 
-    fn push_log(&self, action: &str, message: String) {
-        let entry = OperationLog {
-            ts: now_ts(),
-            action: action.to_string(),
-            message,
-        };
+fn task_schedule_from_options(
+    options: &AddTaskOptions,
+) -> Result<(Option<i64>, Option<TaskWindow>)> {
+    if let Some(window) = options.window.as_ref() {
+        validate_task_window(window)?;
+    }
+    Ok((options.start_at, options.window.clone()))
+}
 
-        let mut guard = self.logs.lock().expect("operation logs mutex poisoned");
-        guard.push(entry.clone());
-        if guard.len() > 300 {
-            let drain = guard.len() - 300;
-            guard.drain(0..drain);
+fn validate_task_window(window: &TaskWindow) -> Result<()> {
+    for (field, value) in [("start", &window.start), ("end", &window.end)] {
+        if parse_hhmm_minutes(value.trim()).is_none() {
+            return Err(AppError::InvalidInput(format!(
+                "invalid window.{field}={value}, expected HH:MM"
+            ))
+            .into());
         }
-        drop(guard);
-
-        let mut pending = self
-            .pending_logs
-            .lock()
-            .expect("pending logs mutex poisoned");
-        pending.push(entry);
     }
-
-    fn flush_pending_logs(&self) -> Result<()> {
-        let drained = {
-            let mut pending = self
-                .pending_logs
-                .lock()
-                .expect("pending logs mutex poisoned");
-            if pending.is_empty() {
-                Vec::new()
-            } else {
-                pending.drain(..).collect::<Vec<_>>()
+    if let Some(days) = window.days.as_deref() {
+        for day in days.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            if !matches!(day.parse::<u32>(), Ok(1..=7)) {
+                return Err(AppError::InvalidInput(format!(
+                    "invalid window.days={days}, expected 1-7 (Mon=1)"
+                ))
+                .into());
             }
-        };
-        if drained.is_empty() {
-            return Ok(());
         }
-        self.db.append_operation_logs(&drained)
     }
+    Ok(())
 }
 
-fn with_resolved_save_dir(mut options: AddTaskOptions, save_dir: String) -> AddTaskOptions {
-    options.save_dir = Some(save_dir);
-    options
+fn describe_task_window(window: &TaskWindow) -> String {
+    match window.days.as_deref().filter(|v| !v.trim().is_empty()) {
+        Some(days) => format!("{}-{} on {days}", window.start, window.end),
+        None => format!("{}-{}", window.start, window.end),
+    }
 }
 
-fn redact_operation_logs(logs: &[OperationLog]) -> Vec<OperationLog> {
-    logs.iter()
-        .map(|log| OperationLog {
-            ts: log.ts,
-            action: log.action.clone(),
-            message: redact_sensitive_text(&log.message),
-        })
-        .collect()
+fn parse_hhmm_minutes(v: &str) -> Option<i32> {
+    let (h, m) = v.split_once(':')?;
+    let hh = h.parse::<i32>().ok()?;
+    let mm = m.parse::<i32>().ok()?;
+    if !(0..=23).contains(&hh) || !(0..=59).contains(&mm) {
+        return None;
+    }
+    Some(hh * 60 + mm)
 }
 
-fn redact_tasks(tasks: &[Task]) -> Vec<Task> {
-    tasks
-        .iter()
-        .map(|task| Task {
-            id: task.id.clone(),
-            aria2_gid: task.aria2_gid.clone(),
-            task_type: task.task_type.clone(),
-            source: redact_sensitive_text(&task.source),
-            status: task.status.clone(),
-            name: task.name.as_deref().map(redact_sensitive_text),
-            category: task.category.clone(),
-            save_dir: redact_sensitive_text(&task.save_dir),
-            total_length: task.total_length,
-            completed_length: task.completed_length,
-            download_speed: task.download_speed,
-            upload_speed: task.upload_speed,
-            connections: task.connections,
-            health: task.health.clone(),
-            error_code: task.error_code.clone(),
-            error_message: task.error_message.as_deref().map(redact_sensitive_text),
-            remediation: task.remediation.as_deref().map(redact_sensitive_text),
-            retry_count: task.retry_count,
-            last_retry_at: task.last_retry_at,
-            checksum_algorithm: task.checksum_algorithm.clone(),
-            checksum_expected: task.checksum_expected.as_deref().map(redact_sensitive_text),
-            checksum_actual: task.checksum_actual.clone(),
-            checksum_status: task.checksum_status.clone(),
-            created_at: task.created_at,
-            updated_at: task.updated_at,
-        })
-        .collect()
+fn compute_next_retry_at(now_ts: i64, backoff_secs: i64, attempts: u32) -> i64 {
+    now_ts + backoff_secs * (attempts as i64 + 1)
 }
 
-fn redact_task_files(files: &[TaskFile]) -> Vec<TaskFile> {
-    files
-        .iter()
-        .map(|f| TaskFile {
-            task_id: f.task_id.clone(),
-            path: redact_sensitive_text(&f.path),
-            length: f.length,
-            completed_length: f.completed_length,
-            selected: f.selected,
-        })
-        .collect()
+fn apply_fallback_source(source: &str, attempt: usize, mirrors: &[String]) -> String {
+    if mirrors.is_empty() || attempt == 0 {
+        return source.to_string();
+    }
+    let idx = (attempt - 1) % mirrors.len();
+    let prefix = mirrors[idx].trim_end_matches('/');
+    if source.starts_with("http://") || source.starts_with("https://") {
+        format!("{prefix}/{}", source.trim_start_matches('/'))
+    } else {
+        source.to_string()
+    }
 }
 
-fn redact_media_merge_jobs(items: &[MediaMergeJob]) -> Vec<MediaMergeJob> {
-    items
-        .iter()
-        .map(|job| MediaMergeJob {
-            task_id: job.task_id.clone(),
-            input_url: redact_sensitive_text(&job.input_url),
-            output_path: redact_sensitive_text(&job.output_path),
-            ffmpeg_bin: redact_sensitive_text(&job.ffmpeg_bin),
-            ffmpeg_args: redact_sensitive_text(&job.ffmpeg_args),
-            status: job.status.clone(),
-            error_message: job.error_message.as_deref().map(redact_sensitive_text),
-            created_at: job.created_at,
-            updated_at: job.updated_at,
-        })
-        .collect()
-}
-
-fn redact_sensitive_text(input: &str) -> String {
-    let mut value = input.to_string();
-    let markers = [
-        "token:",
-        "token=",
-        "rpc-secret",
-        "github_token",
-        "authorization:",
-        "Authorization:",
-        "browser_bridge_token",
-    ];
-    for marker in markers {
-        value = redact_marker_value(&value, marker);
+fn absolute_path(base: &Path, value: &str) -> PathBuf {
+    let p = Path::new(value);
+    if p.is_absolute() {
+        p.to_path_buf()
+    } else {
+        base.join(p)
     }
-    value
 }
 
-fn redact_marker_value(input: &str, marker: &str) -> String {
-    let mut output = String::with_capacity(input.len());
-    let mut remaining = input;
-    while let Some(pos) = remaining.find(marker) {
-        let (head, tail) = remaining.split_at(pos + marker.len());
-        output.push_str(head);
-        let token_len = tail
-            .chars()
-            .take_while(|c| !c.is_whitespace() && *c != '&' && *c != '|' && *c != ',' && *c != ';')
-            .count();
-        if token_len == 0 {
-            remaining = tail;
-            continue;
+fn normalize_lexical_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+    let mut out = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                let _ = out.pop();
+            }
+            other => out.push(other.as_os_str()),
         }
-        output.push_str("***");
-        remaining = &tail[token_len..];
     }
-    output.push_str(remaining);
-    output
+    out
 }
 
-fn rule_matches(
-    rule: &DownloadDirRule,
-    task_type: &TaskType,
-    source: &str,
-    http_content_type: Option<&str>,
-) -> bool {
-    let matcher = rule.matcher.trim().to_lowercase();
-    let patterns = rule
-        .pattern
-        .split(',')
-        .map(|v| v.trim().to_lowercase())
-        .filter(|v| !v.is_empty())
-        .collect::<Vec<_>>();
-    if patterns.is_empty() {
-        return false;
-    }
+fn is_subpath(path: &Path, root: &Path) -> bool {
+    path.starts_with(root)
+}
 
-    match matcher.as_str() {
-        "type" => {
-            let t = task_type_str(task_type);
-            if patterns.iter().any(|p| p == t) {
-                return true;
-            }
-            if !matches!(task_type, TaskType::Http) {
-                return false;
-            }
-            let inferred = infer_http_type_candidates(source, http_content_type);
-            patterns.iter().any(|p| inferred.iter().any(|v| v == p))
+fn cleanup_empty_dirs_upwards(from: &Path, root: &Path) {
+    let mut cursor = from.to_path_buf();
+    while let Ok(canonical) = cursor.canonicalize() {
+        if canonical == root || !is_subpath(&canonical, root) {
+            break;
         }
-        "domain" => {
-            let host = reqwest::Url::parse(source)
-                .ok()
-                .and_then(|u| u.host_str().map(|v| v.to_lowercase()));
-            let Some(host) = host else {
-                return false;
-            };
-            patterns
-                .iter()
-                .any(|p| host == *p || host.ends_with(&format!(".{p}")))
+        let is_empty = fs::read_dir(&canonical)
+            .ok()
+            .map(|mut it| it.next().is_none())
+            .unwrap_or(false);
+        if !is_empty {
+            break;
         }
-        "ext" => {
-            let ext = reqwest::Url::parse(source).ok().and_then(|u| {
-                Path::new(u.path())
-                    .extension()
-                    .map(|v| v.to_string_lossy().to_lowercase())
-            });
-            let Some(ext) = ext else {
-                return false;
-            };
-            patterns.iter().any(|p| {
-                let p = p.strip_prefix('.').unwrap_or(p.as_str());
-                p == ext
-            })
+        if fs::remove_dir(&canonical).is_err() {
+            break;
         }
-        _ => false,
+        let Some(parent) = canonical.parent() else {
+            break;
+        };
+        cursor = parent.to_path_buf();
     }
 }
 
-fn category_rule_matches(
-    rule: &CategoryRule,
-    task_type: &TaskType,
-    source: &str,
-    http_content_type: Option<&str>,
-) -> bool {
-    let as_dir_rule = DownloadDirRule {
-        enabled: rule.enabled,
-        matcher: rule.matcher.clone(),
-        pattern: rule.pattern.clone(),
-        save_dir: String::new(),
-        subdir_by_date: false,
-        subdir_by_domain: false,
-    };
-    rule_matches(&as_dir_rule, task_type, source, http_content_type)
+fn open_path_in_os(path: &Path) -> Result<()> {
+    #[cfg(target_os = "macos")]
+    {
+        Command::new("open")
+            .arg(path)
+            .spawn()
+            .map_err(|e| anyhow!("open failed: {e}"))?;
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Command::new("xdg-open")
+            .arg(path)
+            .spawn()
+            .map_err(|e| anyhow!("xdg-open failed: {e}"))?;
+        return Ok(());
+    }
+    #[cfg(target_os = "windows")]
+    {
+        Command::new("explorer")
+            .arg(path)
+            .spawn()
+            .map_err(|e| anyhow!("explorer failed: {e}"))?;
+        return Ok(());
+    }
+    #[allow(unreachable_code)]
+    Err(anyhow!("open path is not supported on this platform"))
 }
 
-fn infer_http_type_candidates(source: &str, http_content_type: Option<&str>) -> Vec<String> {
-    let mut out = Vec::<String>::new();
-    if let Some(ct) = http_content_type {
-        let ct = ct.trim().to_lowercase();
-        if !ct.is_empty() {
-            out.push(ct.clone());
-            if let Some(group) = ct.split('/').next()
-                && !group.is_empty()
-            {
-                out.push(group.to_string());
+impl DownloadService {
+    /// Registers an extra engine under `profile.id`, replacing any previous one.
+    pub fn register_engine(&self, profile: EngineProfile, api: Arc<dyn Aria2Api>) {
+        self.engines
+            .write()
+            .expect("engines lock poisoned")
+            .insert(profile.id.clone(), EngineSlot { profile, api });
+    }
+
+    /// Brings the extra engines in line with the `engine_profiles` setting.
+    /// Engines whose endpoint changed are detached and attached again lazily.
+    pub async fn sync_engine_profiles(&self) -> Result<()> {
+        let profiles = self.configured_engine_profiles();
+        let mut retired = Vec::new();
+        {
+            let mut engines = self.engines.write().expect("engines lock poisoned");
+            engines.retain(|id, slot| {
+                let keep = profiles.iter().any(|p| {
+                    p.id == *id
+                        && p.rpc_url == slot.profile.rpc_url
+                        && p.secret == slot.profile.secret
+                });
+                if !keep {
+                    retired.push(slot.api.clone());
+                }
+                keep
+            });
+            for profile in profiles {
+                match engines.get_mut(&profile.id) {
+                    Some(slot) => slot.profile = profile,
+                    None => {
+                        let api = Arc::new(Aria2Manager::attached(ExternalAria2Config {
+                            rpc_url: profile.rpc_url.clone(),
+                            secret: profile.secret.clone(),
+                        }));
+                        engines.insert(profile.id.clone(), EngineSlot { profile, api });
+                    }
+                }
             }
         }
+        for api in retired {
+            let _ = api.stop().await;
+        }
+        Ok(())
     }
-    if let Some(ext) = reqwest::Url::parse(source).ok().and_then(|u| {
-        Path::new(u.path())
-            .extension()
-            .map(|v| v.to_string_lossy().to_lowercase())
-    }) {
-        out.push(ext_to_type_group(&ext).to_string());
+
+    fn configured_engine_profiles(&self) -> Vec<EngineProfile> {
+        self.db
+            .get_setting("engine_profiles")
+            .ok()
+            .flatten()
+            .and_then(|v| serde_json::from_str::<Vec<EngineProfile>>(&v).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut profile| {
+                profile.id = profile.id.trim().to_string();
+                profile.rpc_url = profile.rpc_url.trim().to_string();
+                profile
+            })
+            .collect()
     }
-    out.sort();
-    out.dedup();
-    out
-}
 
-fn ext_to_type_group(ext: &str) -> &'static str {
-    match ext {
-        "mp4" | "mkv" | "avi" | "mov" | "wmv" | "flv" | "webm" => "video",
-        "mp3" | "flac" | "wav" | "aac" | "ogg" | "m4a" => "audio",
-        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "svg" => "image",
-        "zip" | "rar" | "7z" | "tar" | "gz" | "bz2" | "xz" => "archive",
-        "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" => "document",
-        "txt" | "md" | "csv" | "json" | "xml" | "html" | "htm" => "text",
-        _ => "binary",
+    fn engine_slot(&self, engine_id: Option<&str>) -> Result<Option<EngineSlot>> {
+        let Some(id) = normalize_engine_id(engine_id) else {
+            return Ok(None);
+        };
+        self.engines
+            .read()
+            .expect("engines lock poisoned")
+            .get(&id)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| AppError::InvalidInput(format!("unknown engine profile: {id}")).into())
+    }
+
+    fn engine_for(&self, engine_id: Option<&str>) -> Result<Arc<dyn Aria2Api>> {
+        Ok(self
+            .engine_slot(engine_id)?
+            .map(|slot| slot.api)
+            .unwrap_or_else(|| self.aria2.clone()))
+    }
+
+    /// The engine owning `engine_id` tasks, started or attached on demand.
+    async fn ready_engine(&self, engine_id: Option<&str>) -> Result<Arc<dyn Aria2Api>> {
+        let Some(slot) = self.engine_slot(engine_id)? else {
+            self.ensure_aria2_ready().await?;
+            return Ok(self.aria2.clone());
+        };
+        slot.api
+            .ensure_started()
+            .await
+            .map_err(|e| anyhow!("engine {} is unavailable: {e}", slot.profile.id))?;
+        Ok(slot.api)
     }
-}
 
-fn is_terminal_aria2_status(status: &str) -> bool {
-    matches!(status, "complete" | "error" | "removed")
-}
+    fn extra_engines(&self) -> Vec<(String, Arc<dyn Aria2Api>)> {
+        let mut engines = self
+            .engines
+            .read()
+            .expect("engines lock poisoned")
+            .iter()
+            .map(|(id, slot)| (id.clone(), slot.api.clone()))
+            .collect::<Vec<_>>();
+        engines.sort_by(|a, b| a.0.cmp(&b.0));
+        engines
+    }
 
-fn is_recovered_source(source: &str) -> bool {
-    source.starts_with("aria2:recovered:")
-}
+    async fn attached_engines(&self) -> Vec<(String, Arc<dyn Aria2Api>)> {
+        let mut attached = Vec::new();
+        for (id, engine) in self.extra_engines() {
+            if engine.endpoint().await.is_some() {
+                attached.push((id, engine));
+            }
+        }
+        attached
+    }
 
-async fn detect_http_content_type(url: &str) -> Option<String> {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build()
-        .ok()?;
-    let response = client.head(url).send().await.ok()?;
-    response
-        .headers()
-        .get(reqwest::header::CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
-}
+    /// Fills options the task leaves unset from matching `policy_rules`; the first
+    /// matching rule that sets an option wins, and headers are added unless the
+    /// task already sends a header of that name.
+    fn apply_policy_rules(
+        &self,
+        task_type: &TaskType,
+        source: &str,
+        mut options: AddTaskOptions,
+        facts: &RuleFacts,
+    ) -> Result<AddTaskOptions> {
+        let settings = self.db.load_global_settings()?;
+        for rule in settings
+            .policy_rules
+            .iter()
+            .filter(|rule| rule.enabled && policy_rule_matches(rule, task_type, source, facts))
+        {
+            let limit = rule
+                .max_download_limit
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty());
+            if options.max_download_limit.is_none()
+                && let Some(limit) = limit
+            {
+                options.max_download_limit = Some(limit.to_string());
+            }
+            options.split = options.split.or(rule.split);
+            options.max_connection_per_server = options
+                .max_connection_per_server
+                .or(rule.max_connection_per_server);
+            let user_agent = rule
+                .user_agent
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty());
+            if options.user_agent.is_none()
+                && let Some(user_agent) = user_agent
+            {
+                options.user_agent = Some(user_agent.to_string());
+            }
+            for header in &rule.headers {
+                let name = header_name(header);
+                if !name.is_empty() && !options.headers.iter().any(|h| header_name(h) == name) {
+                    options.headers.push(header.trim().to_string());
+                }
+            }
+        }
+        Ok(options)
+    }
 
-fn task_type_str(task_type: &TaskType) -> &'static str {
-    match task_type {
-        TaskType::Http => "http",
-        TaskType::Torrent => "torrent",
-        TaskType::Magnet => "magnet",
-        TaskType::Metalink => "metalink",
+    /// An explicit `options.engine` wins; otherwise a category rule for the
+    /// task's category, then the first matching download-dir rule, may route it.
+    fn resolve_engine_for_new_task(
+        &self,
+        task_type: &TaskType,
+        source: &str,
+        category: Option<&str>,
+        options: &AddTaskOptions,
+        facts: &RuleFacts,
+    ) -> Result<Option<String>> {
+        let has_engine = |engine: &Option<String>| {
+            engine
+                .as_deref()
+                .is_some_and(|engine| !engine.trim().is_empty())
+        };
+        let routed = if has_engine(&options.engine) {
+            options.engine.clone()
+        } else {
+            let settings = self.db.load_global_settings()?;
+            category
+                .and_then(|category| {
+                    settings
+                        .category_rules
+                        .iter()
+                        .find(|rule| {
+                            rule.enabled
+                                && rule.category.trim() == category
+                                && has_engine(&rule.engine)
+                        })
+                        .and_then(|rule| rule.engine.clone())
+                })
+                .or_else(|| {
+                    settings
+                        .download_dir_rules
+                        .iter()
+                        .find(|rule| {
+                            rule.enabled
+                                && has_engine(&rule.engine)
+                                && rule_matches(rule, task_type, source, facts)
+                        })
+                        .and_then(|rule| rule.engine.clone())
+                })
+        };
+        let engine_id = normalize_engine_id(routed.as_deref());
+        self.engine_slot(engine_id.as_deref())?;
+        Ok(engine_id)
     }
-}
 
-fn apply_rule_subdir(base: &str, rule: &DownloadDirRule, source: &str) -> String {
-    let mut path = PathBuf::from(base);
-    if rule.subdir_by_domain
-        && let Some(host) = reqwest::Url::parse(source)
-            .ok()
-            .and_then(|u| u.host_str().map(ToString::to_string))
-            .map(|v| v.trim().to_lowercase())
-            .filter(|v| !v.is_empty())
-    {
-        path = path.join(host);
-    }
-    if rule.subdir_by_date {
-        let date = Local::now().format("%Y-%m-%d").to_string();
-        path = path.join(date);
+    /// Local download-dir rules mean nothing on another host, so remote engines
+    /// use the requested dir, the profile's dir, or their own default.
+    fn resolve_save_dir_for_engine(
+        &self,
+        engine_id: Option<&str>,
+        task_type: TaskType,
+        source: &str,
+        options: &AddTaskOptions,
+        facts: &RuleFacts,
+        vars: &mut TemplateVars,
+    ) -> Result<String> {
+        let Some(slot) = self.engine_slot(engine_id)? else {
+            return self.resolve_save_dir_for_new_task(task_type, source, options, facts, vars);
+        };
+        Ok(options
+            .save_dir
+            .as_deref()
+            .map(str::trim)
+            .filter(|dir| !dir.is_empty())
+            .or(slot.profile.download_dir.as_deref().map(str::trim))
+            .unwrap_or_default()
+            .to_string())
     }
-    path.to_string_lossy().to_string()
-}
 
-#[derive(Debug, Clone)]
-struct ReleaseInfo {
-    latest_version: Option<String>,
-    latest_url: Option<String>,
-    selected_asset_name: Option<String>,
-    selected_asset_url: Option<String>,
-}
+    fn runs_managed_aria2(&self) -> bool {
+        self.unmanaged_aria2_reason().is_none()
+    }
 
-#[derive(Debug, Clone)]
-struct ReleaseAsset {
-    name: String,
-    url: String,
-}
+    fn unmanaged_aria2_reason(&self) -> Option<&'static str> {
+        if let Some(mode) = self.aria2.in_process_mode() {
+            Some(match mode {
+                "demo" => "aria2 is simulated in demo mode; there is nothing to update",
+                _ => "the native HTTP engine is built in; there is no aria2 to update",
+            })
+        } else if self.aria2.is_external() {
+            Some("aria2 is external; update it where it runs")
+        } else {
+            None
+        }
+    }
 
-async fn fetch_latest_aria2_release(
-    github_cdn: Option<&str>,
-    github_token: Option<&str>,
-) -> Result<ReleaseInfo> {
-    let repos = ["aria2/aria2", "abcfy2/aria2-static-build"];
-    let mut last_error: Option<anyhow::Error> = None;
-    let mut saw_non_error_repo = false;
-    for repo in repos {
-        match fetch_release_from_repo(repo, github_cdn, github_token).await {
-            Ok(info) if info.selected_asset_url.is_some() => return Ok(info),
-            Ok(_) => {
-                saw_non_error_repo = true;
-                continue;
+    async fn ensure_aria2_ready(&self) -> Result<()> {
+        let external = self.aria2.is_external();
+        if self.runs_managed_aria2() && !self.aria2_bin_exists() {
+            return Err(anyhow!(
+                "aria2 is unavailable. Please set a valid aria2 binary path in Settings and restart aria2."
+            ));
+        }
+        let mut last_err = None;
+        for attempt in 1..=2 {
+            match self.aria2.ensure_started().await {
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    self.push_log(
+                        "ensure_aria2_ready",
+                        format!("attempt {attempt} failed: {e}"),
+                    );
+                    last_err = Some(e.to_string());
+                    let _ = self.aria2.stop().await;
+                    time::sleep(Duration::from_millis(300)).await;
+                }
             }
-            Err(e) => last_error = Some(e),
+        }
+        let err = last_err.unwrap_or_else(|| "unknown startup error".to_string());
+        if external {
+            return Err(anyhow!(
+                "external aria2 is unavailable. Please check the RPC URL and secret in Settings. details: {err}"
+            ));
+        }
+        let stderr = self.aria2.stderr_tail().unwrap_or_default();
+        if stderr.is_empty() {
+            Err(anyhow!(
+                "aria2 is unavailable. Please check aria2 path in Settings and restart aria2. details: {err}"
+            ))
+        } else {
+            Err(anyhow!(
+                "aria2 is unavailable. Please check aria2 path in Settings and restart aria2. details: {err}. stderr: {stderr}"
+            ))
         }
     }
-    let platform = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
-    if saw_non_error_repo {
-        Err(anyhow!(
-            "no compatible binary asset found for current platform ({platform})"
-        ))
-    } else {
-        Err(last_error.unwrap_or_else(|| {
-            anyhow!("no compatible binary asset found for current platform ({platform})")
-        }))
+
+    fn configured_download_dir(&self) -> Result<String> {
+        self.db.get_setting("download_dir")?.ok_or_else(|| {
+            AppError::InvalidInput("missing required setting: download_dir".to_string()).into()
+        })
     }
-}
 
-async fn fetch_release_from_repo(
-    repo: &str,
-    github_cdn: Option<&str>,
-    github_token: Option<&str>,
-) -> Result<ReleaseInfo> {
-    let client = reqwest::Client::new();
-    // GitHub API should stay direct. Many download CDNs return HTML for API endpoints.
-    let latest_url = format!("https://api.github.com/repos/{repo}/releases/latest");
-    let latest_payload = fetch_json(&client, &latest_url, github_token).await?;
+    fn configured_download_dir_rules(&self) -> Vec<DownloadDirRule> {
+        self.db
+            .load_global_settings()
+            .map(|s| s.download_dir_rules)
+            .unwrap_or_default()
+    }
 
-    if let Some(msg) = latest_payload.get("message").and_then(Value::as_str) {
-        if msg.to_ascii_lowercase().contains("rate limit") {
-            return Err(anyhow!("repo {repo} api error: {msg}"));
-        }
-        return Err(anyhow!("repo {repo} api error: {msg}"));
+    fn resolve_save_dir_for_new_task(
+        &self,
+        task_type: TaskType,
+        source: &str,
+        options: &AddTaskOptions,
+        facts: &RuleFacts,
+        vars: &mut TemplateVars,
+    ) -> Result<String> {
+        self.resolve_save_dir_for_new_task_detail(task_type, source, options, facts, vars)
+            .map(|v| v.0)
     }
 
-    let latest_release = parse_release_info(&latest_payload, github_cdn, repo);
-    if let Some(info) = latest_release.as_ref()
-        && info.selected_asset_url.is_some()
-    {
-        return Ok(info.clone());
+    fn collision_policy(&self, requested: Option<&str>) -> Result<&'static str> {
+        let configured = self.db.get_setting("collision_policy")?;
+        collision_policy(
+            requested
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .or(configured.as_deref()),
+        )
     }
 
-    // If latest release has no compatible asset, fallback to release history.
-    let releases_url = format!("https://api.github.com/repos/{repo}/releases?per_page=30");
-    let releases_payload = fetch_json(&client, &releases_url, github_token).await?;
+    fn duplicate_policy(&self, requested: Option<&str>) -> Result<&'static str> {
+        let configured = self.db.get_setting("duplicate_policy")?;
+        duplicate_policy(
+            requested
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .or(configured.as_deref()),
+        )
+    }
 
-    if let Some(msg) = releases_payload.get("message").and_then(Value::as_str) {
-        if msg.to_ascii_lowercase().contains("rate limit") {
-            return Err(anyhow!("repo {repo} api error: {msg}"));
+    /// Looks for an earlier task by normalized URL or infohash, final URL, and
+    /// file name plus size. `redownload` does not look.
+    fn find_duplicate(
+        &self,
+        source: &str,
+        options: &AddTaskOptions,
+        facts: &RuleFacts,
+    ) -> Result<Option<DuplicateMatch>> {
+        if self.duplicate_policy(options.duplicate_policy.as_deref())? == "redownload" {
+            return Ok(None);
         }
-        return Err(anyhow!("repo {repo} api error: {msg}"));
+        let name = (!source.starts_with("magnet:?"))
+            .then(|| target_filename(source, options.out.as_deref()))
+            .flatten();
+        let name_size = name.as_deref().zip(facts.content_length);
+        self.db
+            .find_duplicate_task(source, facts.final_url.as_deref(), name_size)
     }
 
-    if let Some(items) = releases_payload.as_array() {
-        for item in items {
-            let is_draft = item.get("draft").and_then(Value::as_bool).unwrap_or(false);
-            if is_draft {
-                continue;
+    /// `skip` and `open` hand the earlier task back instead of adding another;
+    /// `warn` only logs it.
+    fn apply_duplicate_policy(
+        &self,
+        source: &str,
+        options: &AddTaskOptions,
+        duplicate: Option<&DuplicateMatch>,
+    ) -> Result<Option<AddTaskOutcome>> {
+        let Some(duplicate) = duplicate else {
+            return Ok(None);
+        };
+        let policy = self.duplicate_policy(options.duplicate_policy.as_deref())?;
+        let action = match policy {
+            "skip" => "skipped",
+            "open" => "existing",
+            _ => {
+                self.push_log(
+                    "duplicate",
+                    format!(
+                        "{source} matches task {} by {}, adding anyway",
+                        duplicate.task_id, duplicate.matched_by
+                    ),
+                );
+                return Ok(None);
             }
-            if let Some(info) = parse_release_info(item, github_cdn, repo)
-                && info.selected_asset_url.is_some()
-            {
-                return Ok(info);
+        };
+        self.push_log(
+            "duplicate",
+            format!(
+                "{source} matches task {} by {}, {action}",
+                duplicate.task_id, duplicate.matched_by
+            ),
+        );
+        Ok(Some(AddTaskOutcome {
+            task_id: duplicate.task_id.clone(),
+            action: action.to_string(),
+            duplicate: Some(duplicate.clone()),
+        }))
+    }
+
+    /// Applies the collision policy when the task's file already exists in a
+    /// local save dir. Remote engines write to their own disks, so aria2 decides
+    /// there.
+    fn check_collision(
+        &self,
+        engine_id: Option<&str>,
+        url: &str,
+        save_dir: &str,
+        mut options: AddTaskOptions,
+        facts: &RuleFacts,
+    ) -> Result<AddTaskOptions> {
+        let policy = self.collision_policy(options.collision_policy.as_deref())?;
+        options.collision_policy = Some(policy.to_string());
+        if self.engine_slot(engine_id)?.is_some() {
+            return Ok(options);
+        }
+        let Some(name) = target_filename(url, options.out.as_deref()) else {
+            return Ok(options);
+        };
+        let dir = Path::new(save_dir);
+        let path = dir.join(&name);
+        if !path.exists() {
+            return Ok(options);
+        }
+        let path_text = path.to_string_lossy().to_string();
+        match policy {
+            "overwrite" | "resume" => Ok(options),
+            "ask" => Err(AppError::FileExists(path_text).into()),
+            "skip" if is_identical_file(&path, facts.content_length, &options) => {
+                self.push_log(
+                    "collision",
+                    format!("skipped {url}: {path_text} is identical"),
+                );
+                Err(AppError::AlreadyDownloaded(path_text).into())
+            }
+            _ => {
+                let renamed = unique_filename(dir, &name);
+                self.push_log(
+                    "collision",
+                    format!("{path_text} exists, saving as {renamed}"),
+                );
+                options.out = Some(renamed);
+                Ok(options)
             }
         }
     }
 
-    Ok(latest_release.unwrap_or(ReleaseInfo {
-        latest_version: None,
-        latest_url: None,
-        selected_asset_name: None,
-        selected_asset_url: None,
-    }))
-}
+    fn template_vars(
+        &self,
+        task_type: &TaskType,
+        source: &str,
+        options: &AddTaskOptions,
+        facts: &RuleFacts,
+    ) -> TemplateVars {
+        let mut vars = TemplateVars::from_source(source);
+        vars.category = facts.category.clone();
+        vars.referer_host = options
+            .referer
+            .as_deref()
+            .and_then(|v| reqwest::Url::parse(v.trim()).ok())
+            .and_then(|u| u.host_str().map(|v| v.to_lowercase()));
+        if *task_type == TaskType::Torrent && vars.torrent_name.is_none() {
+            vars.torrent_name = source
+                .strip_suffix(".torrent")
+                .and_then(|v| Path::new(v).file_name())
+                .map(|v| v.to_string_lossy().to_string());
+        }
+        vars
+    }
 
-async fn fetch_json(
-    client: &reqwest::Client,
-    url: &str,
-    github_token: Option<&str>,
-) -> Result<Value> {
-    let mut req = client.get(url).header("User-Agent", "flamingo-downloader");
-    if let Some(token) = github_token {
-        req = req.header("Authorization", format!("Bearer {token}"));
+    /// `{counter}` takes the next value of the persistent `template_counter`, once
+    /// per task; a preview sets `vars.counter` beforehand so nothing is used up.
+    fn fill_template_counter(&self, template: &str, vars: &mut TemplateVars) -> Result<()> {
+        if vars.counter.is_some() || !uses_variable(template, "counter") {
+            return Ok(());
+        }
+        let next = self.peek_template_counter()?;
+        self.db.set_setting("template_counter", &next.to_string())?;
+        vars.counter = Some(next);
+        Ok(())
     }
-    let resp = req.send().await?;
-    let status = resp.status();
-    let body = resp.text().await?;
-    if !status.is_success() {
-        let snippet = body.chars().take(220).collect::<String>();
-        return Err(anyhow!(
-            "request failed ({status}) for {url}: {}",
-            snippet.trim()
-        ));
+
+    fn peek_template_counter(&self) -> Result<u64> {
+        Ok(self
+            .db
+            .get_setting("template_counter")?
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0)
+            + 1)
     }
-    serde_json::from_str::<Value>(&body).map_err(|e| {
-        let snippet = body.chars().take(220).collect::<String>();
-        anyhow!(
-            "invalid json response from {url}: {e}; body starts with: {}",
-            snippet.trim()
-        )
-    })
-}
 
-async fn download_asset_archive(
-    asset_url: &str,
-    asset_name: &str,
-    github_cdn: Option<&str>,
-    github_token: Option<&str>,
-) -> Result<Vec<u8>> {
-    let mut candidates = Vec::new();
-    if let Some(cdn) = github_cdn {
-        let wrapped = apply_github_cdn(asset_url, Some(cdn));
-        if wrapped != asset_url {
-            candidates.push(wrapped);
-        }
+    fn with_rendered_out(
+        &self,
+        mut options: AddTaskOptions,
+        vars: &mut TemplateVars,
+    ) -> Result<AddTaskOptions> {
+        let Some(template) = options.out.clone().filter(|v| is_template(v)) else {
+            return Ok(options);
+        };
+        validate_template(&template)
+            .map_err(|e| AppError::InvalidInput(format!("invalid out: {e}")))?;
+        self.fill_template_counter(&template, vars)?;
+        options.out = render_name_template(&template, vars, &Local::now())?;
+        Ok(options)
     }
-    candidates.push(asset_url.to_string());
 
-    let client = reqwest::Client::new();
-    let mut last_err: Option<anyhow::Error> = None;
-    for url in candidates {
-        match download_asset_bytes_once(&client, &url, github_token).await {
-            Ok(bytes) => {
-                // Quick sanity check: if decoder says bad header, caller may retry direct URL.
-                if extract_aria2_binary(asset_name, &bytes).is_ok() {
-                    return Ok(bytes);
+    fn resolve_category_for_new_task(
+        &self,
+        task_type: TaskType,
+        source: &str,
+        facts: &RuleFacts,
+    ) -> Result<Option<String>> {
+        let settings = self.db.load_global_settings()?;
+        for rule in settings.category_rules {
+            if !rule.enabled {
+                continue;
+            }
+            if category_rule_matches(&rule, &task_type, source, facts) {
+                let category = rule.category.trim();
+                if !category.is_empty() {
+                    return Ok(Some(category.to_string()));
                 }
-                last_err = Some(anyhow!(
-                    "downloaded content from {url} is not a valid `{asset_name}` archive/binary"
-                ));
             }
-            Err(e) => last_err = Some(e),
         }
+        Ok(None)
     }
-    Err(last_err.unwrap_or_else(|| anyhow!("failed to download asset archive")))
-}
 
-async fn download_asset_bytes_once(
-    client: &reqwest::Client,
-    url: &str,
-    github_token: Option<&str>,
-) -> Result<Vec<u8>> {
-    let mut req = client.get(url).header("User-Agent", "flamingo-downloader");
-    if let Some(token) = github_token {
-        req = req.header("Authorization", format!("Bearer {token}"));
+    fn resolve_requested_category(&self, category: Option<&str>) -> Option<String> {
+        category
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(ToString::to_string)
     }
-    let resp = req.send().await?;
-    let status = resp.status();
-    let body = resp.bytes().await?.to_vec();
-    if !status.is_success() {
-        let snippet = String::from_utf8_lossy(&body)
-            .chars()
-            .take(200)
-            .collect::<String>();
-        return Err(anyhow!(
-            "asset download failed ({status}) for {url}: {}",
-            snippet.trim()
-        ));
+
+    fn resolve_save_dir_for_new_task_detail(
+        &self,
+        task_type: TaskType,
+        source: &str,
+        options: &AddTaskOptions,
+        facts: &RuleFacts,
+        vars: &mut TemplateVars,
+    ) -> Result<(String, Option<DownloadDirRule>)> {
+        if let Some(v) = options
+            .save_dir
+            .as_ref()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+        {
+            return Ok((v.to_string(), None));
+        }
+        let default_dir = self.configured_download_dir()?;
+        for rule in self.configured_download_dir_rules() {
+            if !rule.enabled {
+                continue;
+            }
+            let candidate = rule.save_dir.trim();
+            if candidate.is_empty() {
+                continue;
+            }
+            if rule_matches(&rule, &task_type, source, facts) {
+                let candidate = if is_template(candidate) {
+                    self.fill_template_counter(candidate, vars)?;
+                    render_dir_template(candidate, vars, &Local::now())?
+                } else {
+                    candidate.to_string()
+                };
+                let resolved = apply_rule_subdir(&candidate, &rule, source);
+                return Ok((resolved, Some(rule)));
+            }
+        }
+        Ok((default_dir, None))
     }
-    if body.is_empty() {
-        return Err(anyhow!("asset download returned empty body for {url}"));
+
+    fn aria2_bin_path(&self) -> String {
+        self.db
+            .get_setting("aria2_bin_path")
+            .ok()
+            .flatten()
+            .unwrap_or_default()
     }
-    Ok(body)
-}
 
-fn parse_release_info(
-    payload: &Value,
-    github_cdn: Option<&str>,
-    repo: &str,
-) -> Option<ReleaseInfo> {
-    let tag = payload
-        .get("tag_name")
-        .and_then(Value::as_str)
-        .map(|s| s.trim_start_matches('v').to_string());
-    let url = payload
-        .get("html_url")
-        .and_then(Value::as_str)
-        .map(ToString::to_string);
-    let assets = payload
-        .get("assets")
-        .and_then(Value::as_array)?
-        .iter()
-        .filter_map(|item| {
-            let name = item.get("name").and_then(Value::as_str)?.to_string();
-            let raw_url = item
-                .get("browser_download_url")
-                .and_then(Value::as_str)?
-                .to_string();
-            let url = if github_cdn.is_some() {
-                raw_url.clone()
-            } else {
-                raw_url
-            };
-            Some(ReleaseAsset { name, url })
-        })
-        .collect::<Vec<_>>();
+    fn aria2_path_source(&self, path: &Path) -> String {
+        let manual = self
+            .db
+            .get_setting("manual_aria2_bin_path")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        if !manual.trim().is_empty() && Path::new(manual.trim()) == path {
+            return "manual".to_string();
+        }
 
-    let selected = select_release_asset(&assets, repo);
-    Some(ReleaseInfo {
-        latest_version: tag,
-        latest_url: url,
-        selected_asset_name: selected.as_ref().map(|a| a.name.clone()),
-        selected_asset_url: selected.map(|a| a.url),
-    })
-}
+        if let Some(resource_dir) = std::env::var_os("FLAMINGO_RESOURCE_DIR").map(PathBuf::from) {
+            let bundled_root = resource_dir.join("aria2").join("bin");
+            if path.starts_with(&bundled_root) {
+                return "bundled".to_string();
+            }
+        }
 
-fn apply_github_cdn(url: &str, github_cdn: Option<&str>) -> String {
-    let Some(prefix) = github_cdn.map(str::trim).filter(|v| !v.is_empty()) else {
-        return url.to_string();
-    };
-    if prefix.contains("{url}") {
-        return prefix.replace("{url}", url);
+        let path_text = path.to_string_lossy();
+        if path_text.contains("/aria2/bin/") || path_text.contains("\\aria2\\bin\\") {
+            return "bundled".to_string();
+        }
+
+        "system".to_string()
     }
-    format!("{prefix}{url}")
-}
 
-fn parse_version_parts(input: &str) -> Vec<u64> {
-    input
-        .split('.')
-        .map(|part| {
-            part.chars()
-                .take_while(|c| c.is_ascii_digit())
-                .collect::<String>()
-                .parse::<u64>()
-                .unwrap_or(0)
-        })
-        .collect::<Vec<_>>()
-}
+    fn aria2_bin_exists(&self) -> bool {
+        let path = self.aria2_bin_path();
+        !path.is_empty() && Path::new(&path).exists()
+    }
 
-fn is_version_newer(latest: &str, current: &str) -> bool {
-    let a = parse_version_parts(latest);
-    let b = parse_version_parts(current);
-    let max_len = a.len().max(b.len());
-    for idx in 0..max_len {
-        let av = *a.get(idx).unwrap_or(&0);
-        let bv = *b.get(idx).unwrap_or(&0);
-        if av > bv {
-            return true;
-        }
-        if av < bv {
-            return false;
+    fn configured_ffmpeg_bin_path(&self) -> String {
+        self.db
+            .get_setting("ffmpeg_bin_path")
+            .ok()
+            .flatten()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "ffmpeg".to_string())
+    }
+
+    fn effective_ffmpeg_bin_path(&self) -> String {
+        let configured = self.configured_ffmpeg_bin_path();
+        if let Some(found) = resolve_binary_candidate(&configured, true) {
+            return found.to_string_lossy().to_string();
         }
+        configured
     }
-    false
-}
 
-fn select_release_asset(assets: &[ReleaseAsset], repo: &str) -> Option<ReleaseAsset> {
-    let os_keys: Vec<&str> = if cfg!(target_os = "windows") {
-        vec!["windows", "win", "mingw"]
-    } else if cfg!(target_os = "macos") {
-        vec![
-            "macos", "darwin", "osx", "apple", "mac", "mac12", "mac13", "mac14",
-        ]
-    } else {
-        vec!["linux", "gnu", "musl"]
-    };
-    let arch_keys: Vec<&str> = if cfg!(target_arch = "x86_64") {
-        vec!["x86_64", "amd64", "x64", "64bit"]
-    } else if cfg!(target_arch = "aarch64") {
-        vec!["aarch64", "arm64", "armv8", "64bit"]
-    } else {
-        vec![std::env::consts::ARCH]
-    };
+    fn ffmpeg_bin_exists(&self) -> bool {
+        let configured = self.configured_ffmpeg_bin_path();
+        resolve_binary_candidate(&configured, true).is_some()
+    }
 
-    let preferred_ext: &[&str] = if cfg!(target_os = "windows") {
-        &[".zip", ".exe"]
-    } else {
-        &[".tar.xz", ".tar.gz", ".tgz", ".zip"]
-    };
+    fn delete_task_files_safely(&self, task: &Task) -> Result<()> {
+        if let Some(engine_id) = normalize_engine_id(task.engine_id.as_deref()) {
+            self.push_log(
+                "delete_task_files",
+                format!(
+                    "skip cleanup for task {}: files live on engine {engine_id}",
+                    task.id
+                ),
+            );
+            return Ok(());
+        }
+        let configured_root = self.configured_download_dir()?;
+        let root_raw = absolute_path(&std::env::current_dir()?, &configured_root);
+        if !root_raw.exists() {
+            self.push_log(
+                "delete_task_files",
+                format!("skip cleanup for task {}: download root missing", task.id),
+            );
+            return Ok(());
+        }
+        let root = root_raw.canonicalize()?;
+        let task_save_dir = absolute_path(&std::env::current_dir()?, &task.save_dir);
+        let task_save_dir_canonical = task_save_dir
+            .canonicalize()
+            .map_err(|e| anyhow!("resolve task save_dir failed ({}): {e}", task.save_dir))?;
+        if !is_subpath(&task_save_dir_canonical, &root) {
+            return Err(anyhow!(
+                "refused to delete files outside download root: {}",
+                task_save_dir_canonical.display()
+            ));
+        }
 
-    let mut scored = assets
-        .iter()
-        .filter_map(|asset| {
-            let name = asset.name.to_lowercase();
-            if !is_supported_asset_name(&name) {
-                return None;
-            }
-            // Filter out likely source archives like aria2-1.37.0.tar.xz.
-            if is_source_archive_name(&name) {
-                return None;
+        let files = self.db.list_task_files(&task.id)?;
+        let mut candidate_set: HashSet<PathBuf> = HashSet::new();
+        for f in &files {
+            if f.path.is_empty() {
+                continue;
             }
-            let os_score = os_keys.iter().any(|k| name.contains(k));
-            let arch_score = arch_keys.iter().any(|k| name.contains(k));
-            let universal = name.contains("universal") || name.contains("all");
+            candidate_set.insert(absolute_path(Path::new(&task.save_dir), &f.path));
+        }
 
-            // Must match current OS and either architecture or universal package.
-            if !os_score || (!arch_score && !universal) {
-                return None;
-            }
-            let mut score = 10;
-            if os_score {
-                score += 8;
+        if candidate_set.is_empty()
+            && let Some(name) = &task.name
+        {
+            candidate_set.insert(Path::new(&task.save_dir).join(name));
+        }
+
+        let mut candidates = candidate_set.into_iter().collect::<Vec<_>>();
+        candidates.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+        let mut blocked = Vec::new();
+        let mut failed = Vec::new();
+        let mut removed = 0usize;
+
+        for candidate in &candidates {
+            let normalized = normalize_lexical_path(candidate);
+            if !is_subpath(&normalized, &root) {
+                blocked.push(normalized.display().to_string());
+                continue;
             }
-            if arch_score {
-                score += 5;
+            if !candidate.exists() {
+                continue;
             }
-            if name.contains("static") {
-                score += 2;
+            let canonical = match candidate.canonicalize() {
+                Ok(v) => v,
+                Err(e) => {
+                    failed.push(format!("resolve {} failed: {e}", candidate.display()));
+                    continue;
+                }
+            };
+            if !is_subpath(&canonical, &root) {
+                blocked.push(canonical.display().to_string());
+                continue;
             }
-            if universal {
-                score += 1;
+            let remove_result = if canonical.is_dir() {
+                fs::remove_dir_all(&canonical)
+            } else {
+                fs::remove_file(&canonical)
+            };
+            if let Err(e) = remove_result {
+                failed.push(format!("remove {} failed: {e}", canonical.display()));
+            } else {
+                removed += 1;
             }
-            if let Some(pos) = preferred_ext.iter().position(|ext| name.ends_with(ext)) {
-                score += (preferred_ext.len().saturating_sub(pos)) as i32;
+        }
+
+        if !blocked.is_empty() {
+            return Err(anyhow!(
+                "refused to delete {} path(s) outside download root: {}",
+                blocked.len(),
+                blocked.join(" | ")
+            ));
+        }
+        if !failed.is_empty() {
+            return Err(anyhow!(
+                "failed to delete {} path(s): {}",
+                failed.len(),
+                failed.join(" | ")
+            ));
+        }
+
+        for candidate in &candidates {
+            if let Some(parent) = candidate.parent() {
+                cleanup_empty_dirs_upwards(parent, &root);
             }
-            Some((score, asset.clone()))
-        })
-        .collect::<Vec<_>>();
+        }
+        self.push_log(
+            "delete_task_files",
+            format!("cleanup finished for task {}: removed {}", task.id, removed),
+        );
+        Ok(())
+    }
 
-    scored.sort_by(|a, b| b.0.cmp(&a.0));
-    if let Some((_, asset)) = scored.first() {
-        return Some(asset.clone());
+    pub fn append_operation_log(&self, action: &str, message: impl Into<String>) {
+        self.push_log(action, message.into());
+        let _ = self.flush_pending_logs();
     }
 
-    if repo == "aria2/aria2" {
-        let fallback = assets
-            .iter()
-            .filter_map(|asset| {
-                let name = asset.name.to_lowercase();
-                if !is_supported_asset_name(&name) || is_source_archive_name(&name) {
-                    return None;
-                }
-                let os_score = os_keys.iter().any(|k| name.contains(k));
-                if !os_score {
-                    return None;
-                }
-                let arch_score = arch_keys.iter().any(|k| name.contains(k));
-                let universal = name.contains("universal") || name.contains("all");
-                if arch_score || universal {
-                    return None;
-                }
-                let mut score: i32 = 20;
-                if let Some(pos) = preferred_ext.iter().position(|ext| name.ends_with(ext)) {
-                    score += (preferred_ext.len().saturating_sub(pos)) as i32;
-                }
-                Some((score, asset.clone()))
-            })
-            .max_by_key(|(score, _)| *score);
-        if let Some((_, asset)) = fallback {
-            return Some(asset);
+    fn push_log(&self, action: &str, message: String) {
+        let entry = OperationLog {
+            ts: now_ts(),
+            action: action.to_string(),
+            message,
+        };
+
+        let mut guard = self.logs.lock().expect("operation logs mutex poisoned");
+        guard.push(entry.clone());
+        if guard.len() > 300 {
+            let drain = guard.len() - 300;
+            guard.drain(0..drain);
         }
+        drop(guard);
+
+        let _ = self.emitter.emit_operation_log(&entry);
+        let mut pending = self
+            .pending_logs
+            .lock()
+            .expect("pending logs mutex poisoned");
+        pending.push(entry);
     }
 
-    None
+    pub fn subscribe_events(&self) -> Option<broadcast::Receiver<ServiceEvent>> {
+        self.emitter.subscribe()
+    }
+
+    pub fn flush_pending_logs(&self) -> Result<()> {
+        let drained = {
+            let mut pending = self
+                .pending_logs
+                .lock()
+                .expect("pending logs mutex poisoned");
+            if pending.is_empty() {
+                Vec::new()
+            } else {
+                pending.drain(..).collect::<Vec<_>>()
+            }
+        };
+        if drained.is_empty() {
+            return Ok(());
+        }
+        self.db.append_operation_logs(&drained)
+    }
 }
 
-fn is_supported_asset_name(name: &str) -> bool {
-    name.ends_with(".zip")
-        || name.ends_with(".tar.gz")
-        || name.ends_with(".tgz")
-        || name.ends_with(".tar.xz")
-        || name.ends_with(".tar.bz2")
-        || name.ends_with(".tbz2")
-        || name.ends_with(".exe")
-        || name.ends_with("aria2c")
+fn queue_running_count(tasks: &[Task], queue: &str) -> usize {
+    tasks
+        .iter()
+        .filter(|task| task.queue.as_deref() == Some(queue) && occupies_queue_slot(task))
+        .count()
 }
 
-fn is_source_archive_name(name: &str) -> bool {
-    // Source packages are usually aria2-<version>.tar.* without platform markers.
-    if !name.starts_with("aria2-")
-        || !(name.ends_with(".tar.gz")
-            || name.ends_with(".tgz")
-            || name.ends_with(".tar.xz")
-            || name.ends_with(".tar.bz2")
-            || name.ends_with(".tbz2"))
+/// Whether a task takes one of its queue's slots: submitted and not paused.
+fn occupies_queue_slot(task: &Task) -> bool {
+    task.aria2_gid.is_some()
+        && matches!(
+            task.status,
+            TaskStatus::Queued | TaskStatus::Active | TaskStatus::Metadata
+        )
+}
+
+/// How a held task reacts to a failed prerequisite; `cancel` unless set.
+fn dependency_failure_policy(value: Option<&str>) -> Result<&'static str> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None | Some("cancel") => Ok("cancel"),
+        Some("wait") => Ok("wait"),
+        Some("run") => Ok("run"),
+        Some(other) => Err(AppError::InvalidInput(format!(
+            "invalid on_dependency_failure={other}, expected cancel|wait|run"
+        ))
+        .into()),
+    }
+}
+
+fn collision_policy(value: Option<&str>) -> Result<&'static str> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None | Some("rename") => Ok("rename"),
+        Some("overwrite") => Ok("overwrite"),
+        Some("skip") => Ok("skip"),
+        Some("resume") => Ok("resume"),
+        Some("ask") => Ok("ask"),
+        Some(other) => Err(AppError::InvalidInput(format!(
+            "invalid collision_policy={other}, expected rename|overwrite|skip|resume|ask"
+        ))
+        .into()),
+    }
+}
+
+fn duplicate_policy(value: Option<&str>) -> Result<&'static str> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None | Some("warn") => Ok("warn"),
+        Some("skip") => Ok("skip"),
+        Some("redownload") => Ok("redownload"),
+        Some("open") => Ok("open"),
+        Some(other) => Err(AppError::InvalidInput(format!(
+            "invalid duplicate_policy={other}, expected warn|skip|redownload|open"
+        ))
+        .into()),
+    }
+}
+
+/// `mode` is `aria2` for a new task, else `skipped` or `existing` with the
+/// earlier task in `duplicate_of`.
+fn bridge_outcome_json(outcome: AddTaskOutcome) -> Value {
+    let mode = match outcome.action.as_str() {
+        "created" => "aria2",
+        other => other,
+    };
+    json!({
+        "ok": true,
+        "mode": mode,
+        "task_id": outcome.task_id,
+        "duplicate_of": outcome.duplicate,
+    })
+}
+
+/// Per-item options win over the batch's shared ones field by field; unset
+/// fields and empty lists fall through.
+fn overlay_task_options(
+    base: &AddTaskOptions,
+    overrides: &AddTaskOptions,
+) -> Result<AddTaskOptions> {
+    let mut merged = serde_json::to_value(base)?;
+    if let (Some(merged), Value::Object(overrides)) =
+        (merged.as_object_mut(), serde_json::to_value(overrides)?)
     {
-        return false;
+        for (key, value) in overrides {
+            if !value.is_null() && !value.as_array().is_some_and(Vec::is_empty) {
+                merged.insert(key, value);
+            }
+        }
     }
+    Ok(serde_json::from_value(merged)?)
+}
 
-    let platform_markers = [
-        "windows", "win-", "mingw", "linux", "android", "darwin", "osx", "mac", "apple", "x86_64",
-        "amd64", "x64", "aarch64", "arm64", "armv8", "32bit", "64bit",
-    ];
-    !platform_markers.iter().any(|k| name.contains(k))
+fn created_outcome(task_id: String, duplicate: Option<DuplicateMatch>) -> AddTaskOutcome {
+    AddTaskOutcome {
+        task_id,
+        action: "created".to_string(),
+        duplicate,
+    }
 }
 
-fn extract_aria2_binary(asset_name: &str, archive: &[u8]) -> Result<Vec<u8>> {
-    let lower = asset_name.to_lowercase();
-    if lower.ends_with(".zip") {
-        return extract_from_zip(archive);
+/// Callers that only want a task id get the earlier task for `open` and an
+/// error for `skip`.
+fn outcome_task_id(outcome: AddTaskOutcome) -> Result<String> {
+    if outcome.action == "skipped" {
+        return Err(AppError::DuplicateTask(outcome.task_id).into());
     }
-    if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
-        return extract_from_tar_gz(archive);
+    Ok(outcome.task_id)
+}
+
+/// The file name aria2 will save a URL under: `out`, else the decoded last path
+/// segment.
+fn target_filename(url: &str, out: Option<&str>) -> Option<String> {
+    if let Some(out) = out.map(str::trim).filter(|v| !v.is_empty()) {
+        return Path::new(out)
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string());
     }
-    if lower.ends_with(".tar.xz") {
-        return extract_from_tar_xz(archive);
+    let url = reqwest::Url::parse(url).ok()?;
+    let segment = url.path_segments()?.next_back()?;
+    let name = percent_decode(segment);
+    let name = name.trim();
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
     }
-    if lower.ends_with(".tar.bz2") || lower.ends_with(".tbz2") {
-        return extract_from_tar_bz2(archive);
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Same checksum when the task carries one, otherwise the same size as the
+/// server reports.
+fn is_identical_file(path: &Path, content_length: Option<i64>, options: &AddTaskOptions) -> bool {
+    if let Ok(checksum) = checksum_metadata_from_options(options)
+        && let (Some(algorithm), Some(expected)) = (checksum.algorithm, checksum.expected)
+    {
+        return compute_file_checksum(path, &algorithm)
+            .is_ok_and(|actual| actual.eq_ignore_ascii_case(&expected));
+    }
+    let Some(length) = content_length.filter(|v| *v > 0) else {
+        return false;
+    };
+    fs::metadata(path).is_ok_and(|meta| meta.len() == length as u64)
+}
+
+/// `name (1).ext`, `name (2).ext`, ... whichever is free in `dir`, keeping
+/// `.tar.gz`-style double extensions together.
+fn unique_filename(dir: &Path, name: &str) -> String {
+    let (stem, ext) = match name.rfind('.').filter(|i| *i > 0) {
+        Some(i) => {
+            let stem = &name[..i];
+            match stem.strip_suffix(".tar") {
+                Some(base) if !base.is_empty() => (base, &name[base.len()..]),
+                _ => (stem, &name[i..]),
+            }
+        }
+        None => (name, ""),
+    };
+    (1..)
+        .map(|n| format!("{stem} ({n}){ext}"))
+        .find(|candidate| {
+            !dir.join(candidate).exists() && !dir.join(format!("{candidate}.aria2")).exists()
+        })
+        .unwrap_or_else(|| name.to_string())
+}
+
+fn mark_awaiting_metadata(task: &mut Task) {
+    task.status = TaskStatus::Metadata;
+    task.health = Some(TaskHealth::MetadataPending.as_str().to_string());
+    task.remediation = Some(
+        "Wait for metadata, add trackers, or retry the magnet link if it times out.".to_string(),
+    );
+}
+
+fn with_resolved_save_dir(mut options: AddTaskOptions, save_dir: String) -> AddTaskOptions {
+    // An empty dir lets a remote engine fall back to its own --dir.
+    options.save_dir = Some(save_dir).filter(|dir| !dir.is_empty());
+    options
+}
+
+/// `None` for the primary engine, so tasks created before engine profiles
+/// existed and tasks routed to `local` are stored the same way.
+fn normalize_engine_id(engine_id: Option<&str>) -> Option<String> {
+    engine_id
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && *id != DEFAULT_ENGINE_ID)
+        .map(ToString::to_string)
+}
+
+fn is_local_task(task: &Task) -> bool {
+    normalize_engine_id(task.engine_id.as_deref()).is_none()
+}
+
+fn redact_operation_logs(logs: &[OperationLog]) -> Vec<OperationLog> {
+    logs.iter()
+        .map(|log| OperationLog {
+            ts: log.ts,
+            action: log.action.clone(),
+            message: redact_sensitive_text(&log.message),
+        })
+        .collect()
+}
+
+fn redact_tasks(tasks: &[Task]) -> Vec<Task> {
+    tasks
+        .iter()
+        .map(|task| Task {
+            id: task.id.clone(),
+            aria2_gid: task.aria2_gid.clone(),
+            engine_id: task.engine_id.clone(),
+            task_type: task.task_type.clone(),
+            source: redact_sensitive_text(&task.source),
+            status: task.status.clone(),
+            name: task.name.as_deref().map(redact_sensitive_text),
+            category: task.category.clone(),
+            save_dir: redact_sensitive_text(&task.save_dir),
+            total_length: task.total_length,
+            completed_length: task.completed_length,
+            download_speed: task.download_speed,
+            upload_speed: task.upload_speed,
+            connections: task.connections,
+            health: task.health.clone(),
+            error_code: task.error_code.clone(),
+            error_message: task.error_message.as_deref().map(redact_sensitive_text),
+            remediation: task.remediation.as_deref().map(redact_sensitive_text),
+            retry_count: task.retry_count,
+            last_retry_at: task.last_retry_at,
+            checksum_algorithm: task.checksum_algorithm.clone(),
+            checksum_expected: task.checksum_expected.as_deref().map(redact_sensitive_text),
+            checksum_actual: task.checksum_actual.clone(),
+            checksum_status: task.checksum_status.clone(),
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            depends_on: task.depends_on.clone(),
+            start_at: task.start_at,
+            window: task.window.clone(),
+            schedule_held: task.schedule_held,
+            queue: task.queue.clone(),
+            queue_held: task.queue_held,
+        })
+        .collect()
+}
+
+fn redact_task_files(files: &[TaskFile]) -> Vec<TaskFile> {
+    files
+        .iter()
+        .map(|f| TaskFile {
+            task_id: f.task_id.clone(),
+            path: redact_sensitive_text(&f.path),
+            length: f.length,
+            completed_length: f.completed_length,
+            selected: f.selected,
+        })
+        .collect()
+}
+
+fn redact_media_merge_jobs(items: &[MediaMergeJob]) -> Vec<MediaMergeJob> {
+    items
+        .iter()
+        .map(|job| MediaMergeJob {
+            task_id: job.task_id.clone(),
+            input_url: redact_sensitive_text(&job.input_url),
+            output_path: redact_sensitive_text(&job.output_path),
+            ffmpeg_bin: redact_sensitive_text(&job.ffmpeg_bin),
+            ffmpeg_args: redact_sensitive_text(&job.ffmpeg_args),
+            status: job.status.clone(),
+            error_message: job.error_message.as_deref().map(redact_sensitive_text),
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        })
+        .collect()
+}
+
+fn redact_sensitive_text(input: &str) -> String {
+    let mut value = input.to_string();
+    let markers = [
+        "token:",
+        "token=",
+        "rpc-secret",
+        "github_token",
+        "authorization:",
+        "Authorization:",
+        "browser_bridge_token",
+    ];
+    for marker in markers {
+        value = redact_marker_value(&value, marker);
+    }
+    value
+}
+
+fn redact_marker_value(input: &str, marker: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut remaining = input;
+    while let Some(pos) = remaining.find(marker) {
+        let (head, tail) = remaining.split_at(pos + marker.len());
+        output.push_str(head);
+        let token_len = tail
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '&' && *c != '|' && *c != ',' && *c != ';')
+            .count();
+        if token_len == 0 {
+            remaining = tail;
+            continue;
+        }
+        output.push_str("***");
+        remaining = &tail[token_len..];
+    }
+    output.push_str(remaining);
+    output
+}
+
+fn rule_matches(
+    rule: &DownloadDirRule,
+    task_type: &TaskType,
+    source: &str,
+    facts: &RuleFacts,
+) -> bool {
+    // A rule made only of `conditions` may leave its own pattern empty.
+    let own = (!rule.pattern.trim().is_empty() || rule.conditions.is_empty())
+        .then_some((rule.matcher.as_str(), rule.pattern.as_str()));
+    let mut results = own
+        .into_iter()
+        .chain(
+            rule.conditions
+                .iter()
+                .map(|c| (c.matcher.as_str(), c.pattern.as_str())),
+        )
+        .map(|(matcher, pattern)| condition_matches(matcher, pattern, task_type, source, facts));
+    if rule
+        .combine
+        .as_deref()
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("any"))
+    {
+        results.any(|matched| matched)
+    } else {
+        results.all(|matched| matched)
+    }
+}
+
+fn condition_matches(
+    matcher: &str,
+    pattern: &str,
+    task_type: &TaskType,
+    source: &str,
+    facts: &RuleFacts,
+) -> bool {
+    let matcher = matcher.trim().to_lowercase();
+    if matcher == "regex" {
+        // Not split on commas or lowercased: both mean something in a regex.
+        return Regex::new(pattern.trim()).is_ok_and(|re| re.is_match(source));
+    }
+    let patterns = pattern
+        .split(',')
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+    if patterns.is_empty() {
+        return false;
+    }
+
+    match matcher.as_str() {
+        "type" => {
+            let t = task_type_str(task_type);
+            if patterns.iter().any(|p| p == t) {
+                return true;
+            }
+            if !matches!(task_type, TaskType::Http) {
+                return false;
+            }
+            let inferred = infer_http_type_candidates(source, facts.content_type.as_deref());
+            patterns.iter().any(|p| inferred.iter().any(|v| v == p))
+        }
+        "domain" => {
+            let host = reqwest::Url::parse(source)
+                .ok()
+                .and_then(|u| u.host_str().map(|v| v.to_lowercase()));
+            let Some(host) = host else {
+                return false;
+            };
+            patterns
+                .iter()
+                .any(|p| host == *p || host.ends_with(&format!(".{p}")))
+        }
+        "ext" => {
+            let ext = reqwest::Url::parse(source).ok().and_then(|u| {
+                Path::new(u.path())
+                    .extension()
+                    .map(|v| v.to_string_lossy().to_lowercase())
+            });
+            let Some(ext) = ext else {
+                return false;
+            };
+            patterns.iter().any(|p| {
+                let p = p.strip_prefix('.').unwrap_or(p.as_str());
+                p == ext
+            })
+        }
+        "glob" => {
+            let Ok(url) = reqwest::Url::parse(source) else {
+                return false;
+            };
+            let path = url.path().to_lowercase();
+            patterns.iter().any(|p| glob_matches(p, &path))
+        }
+        "size" => {
+            let Some(length) = facts.content_length.filter(|v| *v > 0) else {
+                return false;
+            };
+            patterns.iter().any(|p| {
+                parse_size_range(p).is_some_and(|(min, max)| {
+                    min.is_none_or(|min| length as u64 >= min)
+                        && max.is_none_or(|max| length as u64 <= max)
+                })
+            })
+        }
+        "source" => facts
+            .origin
+            .as_deref()
+            .is_some_and(|origin| patterns.iter().any(|p| origin.eq_ignore_ascii_case(p))),
+        "category" => facts.category.as_deref().is_some_and(|category| {
+            patterns
+                .iter()
+                .any(|p| category.trim().eq_ignore_ascii_case(p))
+        }),
+        _ => false,
+    }
+}
+
+/// Globs on a URL path: `*` matches any run of characters, `?` one character.
+/// A pattern with a `/` matches the tail of the path from a segment boundary;
+/// one without matches the filename, and its wildcards stay within it.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let (any, one) = if pattern.contains('/') {
+        (".*", ".")
+    } else {
+        ("[^/]*", "[^/]")
+    };
+    let mut re = String::from("(?:^|/)");
+    for c in pattern.trim_start_matches('/').chars() {
+        match c {
+            '*' => re.push_str(any),
+            '?' => re.push_str(one),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).is_ok_and(|re| re.is_match(path))
+}
+
+fn category_rule_matches(
+    rule: &CategoryRule,
+    task_type: &TaskType,
+    source: &str,
+    facts: &RuleFacts,
+) -> bool {
+    let as_dir_rule = DownloadDirRule {
+        enabled: rule.enabled,
+        matcher: rule.matcher.clone(),
+        pattern: rule.pattern.clone(),
+        conditions: rule.conditions.clone(),
+        combine: rule.combine.clone(),
+        ..Default::default()
+    };
+    rule_matches(&as_dir_rule, task_type, source, facts)
+}
+
+fn policy_rule_matches(
+    rule: &PolicyRule,
+    task_type: &TaskType,
+    source: &str,
+    facts: &RuleFacts,
+) -> bool {
+    let as_dir_rule = DownloadDirRule {
+        enabled: rule.enabled,
+        matcher: rule.matcher.clone(),
+        pattern: rule.pattern.clone(),
+        conditions: rule.conditions.clone(),
+        combine: rule.combine.clone(),
+        ..Default::default()
+    };
+    rule_matches(&as_dir_rule, task_type, source, facts)
+}
+
+fn header_name(header: &str) -> String {
+    header
+        .split_once(':')
+        .map_or(header, |(name, _)| name)
+        .trim()
+        .to_lowercase()
+}
+
+fn infer_http_type_candidates(source: &str, http_content_type: Option<&str>) -> Vec<String> {
+    let mut out = Vec::<String>::new();
+    if let Some(ct) = http_content_type {
+        let ct = ct.trim().to_lowercase();
+        if !ct.is_empty() {
+            out.push(ct.clone());
+            if let Some(group) = ct.split('/').next()
+                && !group.is_empty()
+            {
+                out.push(group.to_string());
+            }
+        }
+    }
+    if let Some(ext) = reqwest::Url::parse(source).ok().and_then(|u| {
+        Path::new(u.path())
+            .extension()
+            .map(|v| v.to_string_lossy().to_lowercase())
+    }) {
+        out.push(ext_to_type_group(&ext).to_string());
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+fn ext_to_type_group(ext: &str) -> &'static str {
+    match ext {
+        "mp4" | "mkv" | "avi" | "mov" | "wmv" | "flv" | "webm" => "video",
+        "mp3" | "flac" | "wav" | "aac" | "ogg" | "m4a" => "audio",
+        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "svg" => "image",
+        "zip" | "rar" | "7z" | "tar" | "gz" | "bz2" | "xz" => "archive",
+        "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" => "document",
+        "txt" | "md" | "csv" | "json" | "xml" | "html" | "htm" => "text",
+        _ => "binary",
+    }
+}
+
+fn is_terminal_aria2_status(status: &str) -> bool {
+    matches!(status, "complete" | "error" | "removed")
+}
+
+fn is_recovered_source(source: &str) -> bool {
+    source.starts_with("aria2:recovered:")
+}
+
+/// What rules can match on besides the task type and source URL.
+#[derive(Debug, Clone, Default)]
+struct RuleFacts {
+    content_type: Option<String>,
+    content_length: Option<i64>,
+    origin: Option<String>,
+    category: Option<String>,
+    final_url: Option<String>,
+}
+
+impl RuleFacts {
+    fn for_options(options: &AddTaskOptions) -> Self {
+        Self {
+            origin: options.origin.clone(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Fills content type and length from a quick HEAD request.
+async fn probe_rule_facts(url: &str, options: &AddTaskOptions) -> RuleFacts {
+    let mut facts = RuleFacts::for_options(options);
+    let Ok(client) = reqwest::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()
+    else {
+        return facts;
+    };
+    let Ok(response) = client.head(url).send().await else {
+        return facts;
+    };
+    facts.final_url = Some(response.url().to_string()).filter(|v| v != url);
+    let headers = response.headers();
+    facts.content_type = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase());
+    facts.content_length = headers
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<i64>().ok());
+    facts
+}
+
+fn task_type_str(task_type: &TaskType) -> &'static str {
+    match task_type {
+        TaskType::Http => "http",
+        TaskType::Torrent => "torrent",
+        TaskType::Magnet => "magnet",
+        TaskType::Metalink => "metalink",
+    }
+}
+
+fn apply_rule_subdir(base: &str, rule: &DownloadDirRule, source: &str) -> String {
+    let mut path = PathBuf::from(base);
+    if rule.subdir_by_domain
+        && let Some(host) = reqwest::Url::parse(source)
+            .ok()
+            .and_then(|u| u.host_str().map(ToString::to_string))
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty())
+    {
+        path = path.join(host);
+    }
+    if rule.subdir_by_date {
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        path = path.join(date);
+    }
+    path.to_string_lossy().to_string()
+}
+
+#[derive(Debug, Clone)]
+struct ReleaseInfo {
+    latest_version: Option<String>,
+    latest_url: Option<String>,
+    selected_asset_name: Option<String>,
+    selected_asset_url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ReleaseAsset {
+    name: String,
+    url: String,
+}
+
+async fn fetch_latest_aria2_release(
+    github_cdn: Option<&str>,
+    github_token: Option<&str>,
+) -> Result<ReleaseInfo> {
+    let repos = ["aria2/aria2", "abcfy2/aria2-static-build"];
+    let mut last_error: Option<anyhow::Error> = None;
+    let mut saw_non_error_repo = false;
+    for repo in repos {
+        match fetch_release_from_repo(repo, github_cdn, github_token).await {
+            Ok(info) if info.selected_asset_url.is_some() => return Ok(info),
+            Ok(_) => {
+                saw_non_error_repo = true;
+                continue;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let platform = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+    if saw_non_error_repo {
+        Err(anyhow!(
+            "no compatible binary asset found for current platform ({platform})"
+        ))
+    } else {
+        Err(last_error.unwrap_or_else(|| {
+            anyhow!("no compatible binary asset found for current platform ({platform})")
+        }))
+    }
+}
+
+async fn fetch_release_from_repo(
+    repo: &str,
+    github_cdn: Option<&str>,
+    github_token: Option<&str>,
+) -> Result<ReleaseInfo> {
+    let client = reqwest::Client::new();
+    // GitHub API should stay direct. Many download CDNs return HTML for API endpoints.
+    let latest_url = format!("https://api.github.com/repos/{repo}/releases/latest");
+    let latest_payload = fetch_json(&client, &latest_url, github_token).await?;
+
+    if let Some(msg) = latest_payload.get("message").and_then(Value::as_str) {
+        if msg.to_ascii_lowercase().contains("rate limit") {
+            return Err(anyhow!("repo {repo} api error: {msg}"));
+        }
+        return Err(anyhow!("repo {repo} api error: {msg}"));
+    }
+
+    let latest_release = parse_release_info(&latest_payload, github_cdn, repo);
+    if let Some(info) = latest_release.as_ref()
+        && info.selected_asset_url.is_some()
+    {
+        return Ok(info.clone());
+    }
+
+    // If latest release has no compatible asset, fallback to release history.
+    let releases_url = format!("https://api.github.com/repos/{repo}/releases?per_page=30");
+    let releases_payload = fetch_json(&client, &releases_url, github_token).await?;
+
+    if let Some(msg) = releases_payload.get("message").and_then(Value::as_str) {
+        if msg.to_ascii_lowercase().contains("rate limit") {
+            return Err(anyhow!("repo {repo} api error: {msg}"));
+        }
+        return Err(anyhow!("repo {repo} api error: {msg}"));
+    }
+
+    if let Some(items) = releases_payload.as_array() {
+        for item in items {
+            let is_draft = item.get("draft").and_then(Value::as_bool).unwrap_or(false);
+            if is_draft {
+                continue;
+            }
+            if let Some(info) = parse_release_info(item, github_cdn, repo)
+                && info.selected_asset_url.is_some()
+            {
+                return Ok(info);
+            }
+        }
+    }
+
+    Ok(latest_release.unwrap_or(ReleaseInfo {
+        latest_version: None,
+        latest_url: None,
+        selected_asset_name: None,
+        selected_asset_url: None,
+    }))
+}
+
+async fn fetch_json(
+    client: &reqwest::Client,
+    url: &str,
+    github_token: Option<&str>,
+) -> Result<Value> {
+    let mut req = client.get(url).header("User-Agent", "flamingo-downloader");
+    if let Some(token) = github_token {
+        req = req.header("Authorization", format!("Bearer {token}"));
+    }
+    let resp = req.send().await?;
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        let snippet = body.chars().take(220).collect::<String>();
+        return Err(anyhow!(
+            "request failed ({status}) for {url}: {}",
+            snippet.trim()
+        ));
+    }
+    serde_json::from_str::<Value>(&body).map_err(|e| {
+        let snippet = body.chars().take(220).collect::<String>();
+        anyhow!(
+            "invalid json response from {url}: {e}; body starts with: {}",
+            snippet.trim()
+        )
+    })
+}
+
+async fn download_asset_archive(
+    asset_url: &str,
+    asset_name: &str,
+    github_cdn: Option<&str>,
+    github_token: Option<&str>,
+) -> Result<Vec<u8>> {
+    let mut candidates = Vec::new();
+    if let Some(cdn) = github_cdn {
+        let wrapped = apply_github_cdn(asset_url, Some(cdn));
+        if wrapped != asset_url {
+            candidates.push(wrapped);
+        }
+    }
+    candidates.push(asset_url.to_string());
+
+    let client = reqwest::Client::new();
+    let mut last_err: Option<anyhow::Error> = None;
+    for url in candidates {
+        match download_asset_bytes_once(&client, &url, github_token).await {
+            Ok(bytes) => {
+                // Quick sanity check: if decoder says bad header, caller may retry direct URL.
+                if extract_aria2_binary(asset_name, &bytes).is_ok() {
+                    return Ok(bytes);
+                }
+                last_err = Some(anyhow!(
+                    "downloaded content from {url} is not a valid `{asset_name}` archive/binary"
+                ));
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| anyhow!("failed to download asset archive")))
+}
+
+async fn download_asset_bytes_once(
+    client: &reqwest::Client,
+    url: &str,
+    github_token: Option<&str>,
+) -> Result<Vec<u8>> {
+    let mut req = client.get(url).header("User-Agent", "flamingo-downloader");
+    if let Some(token) = github_token {
+        req = req.header("Authorization", format!("Bearer {token}"));
+    }
+    let resp = req.send().await?;
+    let status = resp.status();
+    let body = resp.bytes().await?.to_vec();
+    if !status.is_success() {
+        let snippet = String::from_utf8_lossy(&body)
+            .chars()
+            .take(200)
+            .collect::<String>();
+        return Err(anyhow!(
+            "asset download failed ({status}) for {url}: {}",
+            snippet.trim()
+        ));
+    }
+    if body.is_empty() {
+        return Err(anyhow!("asset download returned empty body for {url}"));
+    }
+    Ok(body)
+}
+
+fn parse_release_info(
+    payload: &Value,
+    github_cdn: Option<&str>,
+    repo: &str,
+) -> Option<ReleaseInfo> {
+    let tag = payload
+        .get("tag_name")
+        .and_then(Value::as_str)
+        .map(|s| s.trim_start_matches('v').to_string());
+    let url = payload
+        .get("html_url")
+        .and_then(Value::as_str)
+        .map(ToString::to_string);
+    let assets = payload
+        .get("assets")
+        .and_then(Value::as_array)?
+        .iter()
+        .filter_map(|item| {
+            let name = item.get("name").and_then(Value::as_str)?.to_string();
+            let raw_url = item
+                .get("browser_download_url")
+                .and_then(Value::as_str)?
+                .to_string();
+            let url = if github_cdn.is_some() {
+                raw_url.clone()
+            } else {
+                raw_url
+            };
+            Some(ReleaseAsset { name, url })
+        })
+        .collect::<Vec<_>>();
+
+    let selected = select_release_asset(&assets, repo);
+    Some(ReleaseInfo {
+        latest_version: tag,
+        latest_url: url,
+        selected_asset_name: selected.as_ref().map(|a| a.name.clone()),
+        selected_asset_url: selected.map(|a| a.url),
+    })
+}
+
+fn apply_github_cdn(url: &str, github_cdn: Option<&str>) -> String {
+    let Some(prefix) = github_cdn.map(str::trim).filter(|v| !v.is_empty()) else {
+        return url.to_string();
+    };
+    if prefix.contains("{url}") {
+        return prefix.replace("{url}", url);
+    }
+    format!("{prefix}{url}")
+}
+
+fn parse_version_parts(input: &str) -> Vec<u64> {
+    input
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse::<u64>()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>()
+}
+
+fn is_version_newer(latest: &str, current: &str) -> bool {
+    let a = parse_version_parts(latest);
+    let b = parse_version_parts(current);
+    let max_len = a.len().max(b.len());
+    for idx in 0..max_len {
+        let av = *a.get(idx).unwrap_or(&0);
+        let bv = *b.get(idx).unwrap_or(&0);
+        if av > bv {
+            return true;
+        }
+        if av < bv {
+            return false;
+        }
+    }
+    false
+}
+
+fn select_release_asset(assets: &[ReleaseAsset], repo: &str) -> Option<ReleaseAsset> {
+    let os_keys: Vec<&str> = if cfg!(target_os = "windows") {
+        vec!["windows", "win", "mingw"]
+    } else if cfg!(target_os = "macos") {
+        vec![
+            "macos", "darwin", "osx", "apple", "mac", "mac12", "mac13", "mac14",
+        ]
+    } else {
+        vec!["linux", "gnu", "musl"]
+    };
+    let arch_keys: Vec<&str> = if cfg!(target_arch = "x86_64") {
+        vec!["x86_64", "amd64", "x64", "64bit"]
+    } else if cfg!(target_arch = "aarch64") {
+        vec!["aarch64", "arm64", "armv8", "64bit"]
+    } else {
+        vec![std::env::consts::ARCH]
+    };
+
+    let preferred_ext: &[&str] = if cfg!(target_os = "windows") {
+        &[".zip", ".exe"]
+    } else {
+        &[".tar.xz", ".tar.gz", ".tgz", ".zip"]
+    };
+
+    let mut scored = assets
+        .iter()
+        .filter_map(|asset| {
+            let name = asset.name.to_lowercase();
+            if !is_supported_asset_name(&name) {
+                return None;
+            }
+            // Filter out likely source archives like aria2-1.37.0.tar.xz.
+            if is_source_archive_name(&name) {
+                return None;
+            }
+            let os_score = os_keys.iter().any(|k| name.contains(k));
+            let arch_score = arch_keys.iter().any(|k| name.contains(k));
+            let universal = name.contains("universal") || name.contains("all");
+
+            // Must match current OS and either architecture or universal package.
+            if !os_score || (!arch_score && !universal) {
+                return None;
+            }
+            let mut score = 10;
+            if os_score {
+                score += 8;
+            }
+            if arch_score {
+                score += 5;
+            }
+            if name.contains("static") {
+                score += 2;
+            }
+            if universal {
+                score += 1;
+            }
+            if let Some(pos) = preferred_ext.iter().position(|ext| name.ends_with(ext)) {
+                score += (preferred_ext.len().saturating_sub(pos)) as i32;
+            }
+            Some((score, asset.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    scored.sort_by(|a, b| b.0.cmp(&a.0));
+    if let Some((_, asset)) = scored.first() {
+        return Some(asset.clone());
+    }
+
+    if repo == "aria2/aria2" {
+        let fallback = assets
+            .iter()
+            .filter_map(|asset| {
+                let name = asset.name.to_lowercase();
+                if !is_supported_asset_name(&name) || is_source_archive_name(&name) {
+                    return None;
+                }
+                let os_score = os_keys.iter().any(|k| name.contains(k));
+                if !os_score {
+                    return None;
+                }
+                let arch_score = arch_keys.iter().any(|k| name.contains(k));
+                let universal = name.contains("universal") || name.contains("all");
+                if arch_score || universal {
+                    return None;
+                }
+                let mut score: i32 = 20;
+                if let Some(pos) = preferred_ext.iter().position(|ext| name.ends_with(ext)) {
+                    score += (preferred_ext.len().saturating_sub(pos)) as i32;
+                }
+                Some((score, asset.clone()))
+            })
+            .max_by_key(|(score, _)| *score);
+        if let Some((_, asset)) = fallback {
+            return Some(asset);
+        }
+    }
+
+    None
+}
+
+fn is_supported_asset_name(name: &str) -> bool {
+    name.ends_with(".zip")
+        || name.ends_with(".tar.gz")
+        || name.ends_with(".tgz")
+        || name.ends_with(".tar.xz")
+        || name.ends_with(".tar.bz2")
+        || name.ends_with(".tbz2")
+        || name.ends_with(".exe")
+        || name.ends_with("aria2c")
+}
+
+fn is_source_archive_name(name: &str) -> bool {
+    // Source packages are usually aria2-<version>.tar.* without platform markers.
+    if !name.starts_with("aria2-")
+        || !(name.ends_with(".tar.gz")
+            || name.ends_with(".tgz")
+            || name.ends_with(".tar.xz")
+            || name.ends_with(".tar.bz2")
+            || name.ends_with(".tbz2"))
+    {
+        return false;
+    }
+
+    let platform_markers = [
+        "windows", "win-", "mingw", "linux", "android", "darwin", "osx", "mac", "apple", "x86_64",
+        "amd64", "x64", "aarch64", "arm64", "armv8", "32bit", "64bit",
+    ];
+    !platform_markers.iter().any(|k| name.contains(k))
+}
+
+fn extract_aria2_binary(asset_name: &str, archive: &[u8]) -> Result<Vec<u8>> {
+    let lower = asset_name.to_lowercase();
+    if lower.ends_with(".zip") {
+        return extract_from_zip(archive);
+    }
+    if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        return extract_from_tar_gz(archive);
+    }
+    if lower.ends_with(".tar.xz") {
+        return extract_from_tar_xz(archive);
+    }
+    if lower.ends_with(".tar.bz2") || lower.ends_with(".tbz2") {
+        return extract_from_tar_bz2(archive);
+    }
+    Ok(archive.to_vec())
+}
+
+fn extract_from_zip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let reader = Cursor::new(bytes);
+    let mut zip = zip::ZipArchive::new(reader)?;
+    for idx in 0..zip.len() {
+        let mut file = zip.by_index(idx)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_ascii_lowercase();
+        if name.ends_with("/aria2c")
+            || name.ends_with("/aria2c.exe")
+            || name == "aria2c"
+            || name == "aria2c.exe"
+        {
+            let mut out = Vec::new();
+            file.read_to_end(&mut out)?;
+            return Ok(out);
+        }
+    }
+    Err(anyhow!("aria2c binary not found in zip archive"))
+}
+
+fn extract_from_tar_gz(bytes: &[u8]) -> Result<Vec<u8>> {
+    let decoder = flate2::read::GzDecoder::new(Cursor::new(bytes));
+    extract_from_tar_reader(decoder)
+}
+
+fn extract_from_tar_xz(bytes: &[u8]) -> Result<Vec<u8>> {
+    let decoder = xz2::read::XzDecoder::new(Cursor::new(bytes));
+    extract_from_tar_reader(decoder)
+}
+
+fn extract_from_tar_bz2(bytes: &[u8]) -> Result<Vec<u8>> {
+    let decoder = bzip2::read::BzDecoder::new(Cursor::new(bytes));
+    extract_from_tar_reader(decoder)
+}
+
+fn extract_from_tar_reader<R: Read>(reader: R) -> Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().to_ascii_lowercase();
+        if path.ends_with("/aria2c")
+            || path.ends_with("/aria2c.exe")
+            || path == "aria2c"
+            || path == "aria2c.exe"
+        {
+            let mut out = Vec::new();
+            entry.read_to_end(&mut out)?;
+            return Ok(out);
+        }
+    }
+    Err(anyhow!("aria2c binary not found in tar archive"))
+}
+
+fn install_aria2_binary_with_backup(target: &Path, bytes: &[u8]) -> Result<Option<PathBuf>> {
+    let parent = target
+        .parent()
+        .ok_or_else(|| anyhow!("invalid aria2 binary path"))?;
+    fs::create_dir_all(parent)?;
+
+    if bytes.is_empty() {
+        return Err(anyhow!("downloaded aria2 binary is empty"));
+    }
+
+    let tmp_path = target.with_extension("new");
+    fs::write(&tmp_path, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perm = fs::Permissions::from_mode(0o755);
+        fs::set_permissions(&tmp_path, perm)?;
+    }
+    verify_aria2_binary(&tmp_path)?;
+
+    let mut backup = None;
+    if target.exists() {
+        let backup_path = target.with_extension("bak");
+        if backup_path.exists() {
+            let _ = fs::remove_file(&backup_path);
+        }
+        fs::rename(target, &backup_path)?;
+        backup = Some(backup_path);
+    }
+
+    if let Err(e) = fs::rename(&tmp_path, target) {
+        let _ = fs::remove_file(&tmp_path);
+        if let Some(ref b) = backup
+            && b.exists()
+        {
+            let _ = fs::rename(b, target);
+        }
+        return Err(e.into());
+    }
+    Ok(backup)
+}
+
+fn rollback_aria2_binary(target: &Path, backup: Option<PathBuf>) {
+    if target.exists() {
+        let _ = fs::remove_file(target);
+    }
+    if let Some(backup_path) = backup
+        && backup_path.exists()
+    {
+        let _ = fs::rename(backup_path, target);
+    }
+}
+
+fn verify_aria2_binary(path: &Path) -> Result<()> {
+    let mut command = std::process::Command::new(path);
+    command.arg("--version");
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(anyhow!("downloaded aria2 binary failed self-check"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use chrono::{Duration as ChronoDuration, Local, TimeZone, Timelike};
+    use serde_json::{Value, json};
+    use uuid::Uuid;
+
+    use crate::{
+        aria2_manager::{Aria2Api, Aria2Endpoint},
+        db::Database,
+        events::EventEmitter,
+        mock_aria2::{MockAria2Engine, MockScenario},
+        models::{
+            Aria2TaskSnapshot, DownloadDirRule, EngineProfile, RuleCondition, Task, TaskHealth,
+            TaskStatus, TaskType, TaskWindow,
+        },
+    };
+
+    use super::{
+        DownloadService, RuleFacts, SpeedPlanRule, absolute_path, checksum_metadata_from_raw,
+        compute_file_checksum, compute_next_retry_at, glob_matches, has_enough_disk_space,
+        is_download_content_type, is_subpath, normalize_ffmpeg_failure, parse_size_range,
+        rule_matches, schedule_allows, select_speed_limit, should_auto_retry, unique_filename,
+    };
+
+    #[test]
+    fn absolute_path_resolves_relative_to_base() {
+        let base = Path::new("/tmp/base");
+        let abs = absolute_path(base, "a/b/c");
+        assert_eq!(abs, base.join("a/b/c"));
+    }
+
+    #[test]
+    fn absolute_path_keeps_absolute_input() {
+        let base = Path::new("/tmp/base");
+        let abs = absolute_path(base, "/tmp/other/path");
+        assert_eq!(abs, Path::new("/tmp/other/path"));
+    }
+
+    #[test]
+    fn subpath_check_works() {
+        let root = Path::new("/tmp/root");
+        let child = Path::new("/tmp/root/a/b");
+        let outside = Path::new("/tmp/other");
+        assert!(is_subpath(child, root));
+        assert!(!is_subpath(outside, root));
+    }
+
+    #[test]
+    fn download_rule_matches_extension() {
+        let rule = DownloadDirRule {
+            enabled: true,
+            matcher: "ext".to_string(),
+            pattern: "zip,mp4".to_string(),
+            save_dir: "/tmp/media".to_string(),
+            subdir_by_date: false,
+            subdir_by_domain: false,
+            engine: None,
+            ..Default::default()
+        };
+        assert!(rule_matches(
+            &rule,
+            &TaskType::Http,
+            "https://example.com/archive/file.zip",
+            &RuleFacts::default()
+        ));
+        assert!(!rule_matches(
+            &rule,
+            &TaskType::Http,
+            "https://example.com/archive/file.txt",
+            &RuleFacts::default()
+        ));
+    }
+
+    #[test]
+    fn download_rule_matches_domain_and_type() {
+        let domain_rule = DownloadDirRule {
+            enabled: true,
+            matcher: "domain".to_string(),
+            pattern: "github.com,example.org".to_string(),
+            save_dir: "/tmp/code".to_string(),
+            subdir_by_date: false,
+            subdir_by_domain: false,
+            engine: None,
+            ..Default::default()
+        };
+        assert!(rule_matches(
+            &domain_rule,
+            &TaskType::Http,
+            "https://github.com/owner/repo/archive/main.zip",
+            &RuleFacts::default()
+        ));
+        assert!(rule_matches(
+            &domain_rule,
+            &TaskType::Http,
+            "https://a.github.com/file.bin",
+            &RuleFacts::default()
+        ));
+
+        let type_rule = DownloadDirRule {
+            enabled: true,
+            matcher: "type".to_string(),
+            pattern: "magnet,torrent".to_string(),
+            save_dir: "/tmp/bt".to_string(),
+            subdir_by_date: false,
+            subdir_by_domain: false,
+            engine: None,
+            ..Default::default()
+        };
+        assert!(rule_matches(
+            &type_rule,
+            &TaskType::Magnet,
+            "magnet:?xt=urn:btih:abc",
+            &RuleFacts::default()
+        ));
+        assert!(!rule_matches(
+            &type_rule,
+            &TaskType::Http,
+            "https://example.com/a.bin",
+            &RuleFacts::default()
+        ));
+    }
+
+    #[test]
+    fn download_rule_combines_glob_regex_size_and_source_conditions() {
+        let rule = DownloadDirRule {
+            enabled: true,
+            matcher: "domain".to_string(),
+            pattern: "github.com".to_string(),
+            save_dir: "~/builds".to_string(),
+            conditions: vec![RuleCondition {
+                matcher: "glob".to_string(),
+                pattern: "/releases/*/linux-*.tar.gz".to_string(),
+            }],
+            ..Default::default()
+        };
+        let facts = RuleFacts::default();
+        assert!(rule_matches(
+            &rule,
+            &TaskType::Http,
+            "https://github.com/o/r/releases/download/v1.2/linux-amd64.tar.gz",
+            &facts
+        ));
+        assert!(!rule_matches(
+            &rule,
+            &TaskType::Http,
+            "https://github.com/o/r/releases/download/v1.2/windows-amd64.zip",
+            &facts
+        ));
+        assert!(!rule_matches(
+            &rule,
+            &TaskType::Http,
+            "https://example.com/releases/v1/linux-amd64.tar.gz",
+            &facts
+        ));
+
+        let any = DownloadDirRule {
+            enabled: true,
+            matcher: "regex".to_string(),
+            pattern: r"/nightly-\d{8}\.".to_string(),
+            conditions: vec![
+                RuleCondition {
+                    matcher: "size".to_string(),
+                    pattern: "1G-".to_string(),
+                },
+                RuleCondition {
+                    matcher: "source".to_string(),
+                    pattern: "browser,clipboard".to_string(),
+                },
+            ],
+            combine: Some("any".to_string()),
+            ..Default::default()
+        };
+        let url = "https://example.com/file.bin";
+        assert!(!rule_matches(&any, &TaskType::Http, url, &facts));
+        assert!(rule_matches(
+            &any,
+            &TaskType::Http,
+            "https://example.com/nightly-20260101.iso",
+            &facts
+        ));
+        let big = RuleFacts {
+            content_length: Some(3 * 1024 * 1024 * 1024),
+            ..RuleFacts::default()
+        };
+        assert!(rule_matches(&any, &TaskType::Http, url, &big));
+        let from_browser = RuleFacts {
+            origin: Some("browser".to_string()),
+            ..RuleFacts::default()
+        };
+        assert!(rule_matches(&any, &TaskType::Http, url, &from_browser));
+        assert!(glob_matches("*.iso", "/pub/images/debian.iso"));
+        assert!(!glob_matches("pub*.iso", "/pub/images/debian.iso"));
+        assert_eq!(
+            parse_size_range("100m-2g"),
+            Some((Some(100 << 20), Some(2 << 30)))
+        );
+        assert_eq!(parse_size_range("-500K"), Some((None, Some(500 << 10))));
+        assert_eq!(parse_size_range("lots"), None);
+    }
+
+    #[test]
+    fn speed_plan_selects_non_empty_limit_rule() {
+        let rules = vec![
+            SpeedPlanRule {
+                days: None,
+                start: None,
+                end: None,
+                limit: "".to_string(),
+            },
+            SpeedPlanRule {
+                days: None,
+                start: None,
+                end: None,
+                limit: "2M".to_string(),
+            },
+        ];
+        let selected = select_speed_limit(&rules);
+        assert_eq!(selected.as_deref(), Some("2M"));
+    }
+
+    #[test]
+    fn schedule_allows_start_time_and_wrapping_window() {
+        // 2026-10-14 is a Wednesday (day 3).
+        let at = |hour, minute| {
+            Local
+                .with_ymd_and_hms(2026, 10, 14, hour, minute, 0)
+                .single()
+                .expect("local time")
+        };
+        let night = TaskWindow {
+            days: None,
+            start: "22:00".to_string(),
+            end: "06:00".to_string(),
+        };
+        assert!(schedule_allows(None, None, &at(12, 0)));
+        assert!(schedule_allows(None, Some(&night), &at(23, 30)));
+        assert!(schedule_allows(None, Some(&night), &at(5, 59)));
+        assert!(!schedule_allows(None, Some(&night), &at(6, 0)));
+
+        let weekdays = TaskWindow {
+            days: Some("1,2,3,4,5".to_string()),
+            start: "01:00".to_string(),
+            end: "07:00".to_string(),
+        };
+        assert!(schedule_allows(None, Some(&weekdays), &at(1, 0)));
+        let weekend = TaskWindow {
+            days: Some("6,7".to_string()),
+            ..weekdays
+        };
+        assert!(!schedule_allows(None, Some(&weekend), &at(1, 0)));
+
+        let noon = at(12, 0);
+        assert!(!schedule_allows(Some(noon.timestamp() + 60), None, &noon));
+        assert!(schedule_allows(Some(noon.timestamp()), None, &noon));
+    }
+
+    #[test]
+    fn retry_next_time_uses_backoff_and_attempt() {
+        let now = 1_700_000_000_i64;
+        let backoff = 15_i64;
+        assert_eq!(compute_next_retry_at(now, backoff, 1), now + 30);
+        assert_eq!(compute_next_retry_at(now, backoff, 2), now + 45);
+    }
+
+    #[test]
+    fn ffmpeg_failure_normalization_maps_actionable_reasons() {
+        let forbidden = normalize_ffmpeg_failure(Some(1), "Server returned 403 Forbidden");
+        assert_eq!(forbidden.health, TaskHealth::AuthRequired);
+        assert_eq!(forbidden.code, "FFMPEG_HTTP_403");
+        assert!(forbidden.remediation.contains("cookie"));
+
+        let redirect = normalize_ffmpeg_failure(Some(1), "Too many redirects");
+        assert_eq!(redirect.health, TaskHealth::UrlExpired);
+        assert_eq!(redirect.code, "FFMPEG_REDIRECT_ERROR");
+    }
+
+    #[test]
+    fn auto_retry_skips_failures_requiring_user_action() {
+        let now = super::now_ts();
+        let task = Task {
+            id: "task".to_string(),
+            aria2_gid: Some("gid".to_string()),
+            engine_id: None,
+            task_type: TaskType::Http,
+            source: "https://example.com/file.bin".to_string(),
+            status: TaskStatus::Error,
+            name: None,
+            category: None,
+            save_dir: "/tmp".to_string(),
+            total_length: 0,
+            completed_length: 0,
+            download_speed: 0,
+            upload_speed: 0,
+            connections: 0,
+            health: Some(TaskHealth::AuthRequired.as_str().to_string()),
+            error_code: Some("HTTP_403".to_string()),
+            error_message: Some("forbidden".to_string()),
+            remediation: Some("refresh headers".to_string()),
+            retry_count: 0,
+            last_retry_at: None,
+            checksum_algorithm: None,
+            checksum_expected: None,
+            checksum_actual: None,
+            checksum_status: None,
+            created_at: now,
+            updated_at: now,
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
+        };
+        assert!(!should_auto_retry(&task));
+
+        let retryable = Task {
+            health: Some(TaskHealth::NetworkUnstable.as_str().to_string()),
+            ..task
+        };
+        assert!(should_auto_retry(&retryable));
+    }
+
+    #[test]
+    fn checksum_metadata_requires_supported_algorithm_and_value() {
+        let metadata = checksum_metadata_from_raw(Some("SHA-256"), Some(" AB CD "))
+            .expect("valid checksum metadata");
+        assert_eq!(metadata.algorithm.as_deref(), Some("sha256"));
+        assert_eq!(metadata.expected.as_deref(), Some("abcd"));
+        assert_eq!(metadata.status.as_deref(), Some("pending"));
+
+        assert!(checksum_metadata_from_raw(Some("crc32"), Some("abcd")).is_err());
+        assert!(checksum_metadata_from_raw(Some("sha1"), None).is_err());
+        assert!(checksum_metadata_from_raw(None, Some("abcd")).is_err());
+    }
+
+    #[test]
+    fn compute_file_checksum_supports_sha_and_md5() {
+        let path = std::env::temp_dir().join(format!("flamingo-checksum-{}.bin", Uuid::new_v4()));
+        std::fs::write(&path, b"abc").expect("write fixture");
+
+        assert_eq!(
+            compute_file_checksum(&path, "sha256").expect("sha256"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            compute_file_checksum(&path, "sha1").expect("sha1"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            compute_file_checksum(&path, "md5").expect("md5"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn disk_space_guard_requires_remaining_bytes_plus_buffer() {
+        assert!(has_enough_disk_space(
+            100 + super::LOW_DISK_BUFFER_BYTES,
+            100
+        ));
+        assert!(!has_enough_disk_space(
+            99 + super::LOW_DISK_BUFFER_BYTES,
+            100
+        ));
+    }
+
+    #[test]
+    fn download_content_type_detection_prefers_downloadable_media() {
+        assert!(is_download_content_type("application/octet-stream"));
+        assert!(is_download_content_type("video/mp4"));
+        assert!(!is_download_content_type("text/html"));
+    }
+
+    #[test]
+    fn requested_category_trims_and_skips_empty_values() {
+        let mock = Arc::new(MockAria2::default());
+        let (service, _db, _mock) = build_service(mock);
+
+        assert_eq!(
+            service.resolve_requested_category(Some("  video  ")),
+            Some("video".to_string())
+        );
+        assert_eq!(service.resolve_requested_category(Some("   ")), None);
+        assert_eq!(service.resolve_requested_category(None), None);
+    }
+
+    #[derive(Default)]
+    struct NoopEmitter;
+
+    impl EventEmitter for NoopEmitter {
+        fn emit_task_update(&self, _tasks: &[crate::models::Task]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct MockAria2 {
+        calls: Mutex<Vec<String>>,
+        snapshots: Mutex<Vec<Aria2TaskSnapshot>>,
+    }
+
+    impl MockAria2 {
+        fn push_snapshot(&self, snapshot: Aria2TaskSnapshot) {
+            self.snapshots
+                .lock()
+                .expect("snapshots mutex")
+                .push(snapshot);
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().expect("calls mutex").clone()
+        }
+
+        fn call(&self, name: &str) {
+            self.calls
+                .lock()
+                .expect("calls mutex")
+                .push(name.to_string());
+        }
+    }
+
+    #[async_trait]
+    impl Aria2Api for MockAria2 {
+        async fn start(&self) -> Result<Aria2Endpoint> {
+            self.call("start");
+            Ok(Aria2Endpoint {
+                endpoint: "http://127.0.0.1:6800/jsonrpc".to_string(),
+                secret: "mock".to_string(),
+                port: 6800,
+                compat_mode: false,
+                external: false,
+            })
+        }
+
+        async fn stop(&self) -> Result<()> {
+            self.call("stop");
+            Ok(())
+        }
+
+        async fn endpoint(&self) -> Option<Aria2Endpoint> {
+            Some(Aria2Endpoint {
+                endpoint: "http://127.0.0.1:6800/jsonrpc".to_string(),
+                secret: "mock".to_string(),
+                port: 6800,
+                compat_mode: false,
+                external: false,
+            })
+        }
+
+        async fn ensure_started(&self) -> Result<Aria2Endpoint> {
+            self.start().await
+        }
+
+        async fn add_uri(&self, _uris: Vec<String>, _options: Option<Value>) -> Result<String> {
+            self.call("add_uri");
+            Ok("gid-add-uri".to_string())
+        }
+
+        async fn add_torrent(
+            &self,
+            _torrent_base64: String,
+            _uris: Vec<String>,
+            _options: Option<Value>,
+        ) -> Result<String> {
+            self.call("add_torrent");
+            Ok("gid-add-torrent".to_string())
+        }
+
+        async fn pause(&self, _gid: &str) -> Result<String> {
+            self.call("pause");
+            Ok("ok".to_string())
+        }
+
+        async fn unpause(&self, _gid: &str) -> Result<String> {
+            self.call("unpause");
+            Ok("ok".to_string())
+        }
+
+        async fn pause_all(&self) -> Result<String> {
+            self.call("pause_all");
+            Ok("ok".to_string())
+        }
+
+        async fn unpause_all(&self) -> Result<String> {
+            self.call("unpause_all");
+            Ok("ok".to_string())
+        }
+
+        async fn remove(&self, _gid: &str, _force: bool) -> Result<String> {
+            self.call("remove");
+            Ok("ok".to_string())
+        }
+
+        async fn remove_download_result(&self, _gid: &str) -> Result<String> {
+            self.call("remove_download_result");
+            Ok("ok".to_string())
+        }
+
+        async fn tell_status(&self, _gid: &str) -> Result<Value> {
+            self.call("tell_status");
+            Ok(json!({ "files": [] }))
+        }
+
+        async fn get_peers(&self, _gid: &str) -> Result<Vec<Value>> {
+            self.call("get_peers");
+            Ok(Vec::new())
+        }
+
+        async fn tell_all(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+            self.call("tell_all");
+            Ok(self.snapshots.lock().expect("snapshots mutex").clone())
+        }
+
+        async fn change_position(&self, _gid: &str, _pos: i64, _how: &str) -> Result<i64> {
+            self.call("change_position");
+            Ok(0)
+        }
+
+        async fn change_option(&self, _gid: &str, _options: Value) -> Result<String> {
+            self.call("change_option");
+            Ok("ok".to_string())
+        }
+
+        async fn change_global_option(&self, _options: Value) -> Result<String> {
+            self.call("change_global_option");
+            Ok("ok".to_string())
+        }
+
+        async fn get_global_stat(&self) -> Result<Value> {
+            self.call("get_global_stat");
+            Ok(json!({}))
+        }
+
+        async fn get_global_option(&self) -> Result<Value> {
+            self.call("get_global_option");
+            Ok(json!({}))
+        }
+
+        async fn get_version(&self) -> Result<Value> {
+            self.call("get_version");
+            Ok(json!({ "version": "mock" }))
+        }
+
+        async fn save_session(&self) -> Result<String> {
+            self.call("save_session");
+            Ok("ok".to_string())
+        }
+
+        fn stderr_tail(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn build_service(
+        mock: Arc<MockAria2>,
+    ) -> (Arc<DownloadService>, Arc<Database>, Arc<MockAria2>) {
+        let (service, db) = build_service_with(mock.clone());
+        (service, db, mock)
+    }
+
+    fn build_service_with(engine: Arc<dyn Aria2Api>) -> (Arc<DownloadService>, Arc<Database>) {
+        build_service_with_emitter(engine, Arc::new(NoopEmitter))
+    }
+
+    fn build_service_with_emitter(
+        engine: Arc<dyn Aria2Api>,
+        emitter: crate::events::SharedEmitter,
+    ) -> (Arc<DownloadService>, Arc<Database>) {
+        let db_path = std::env::temp_dir().join(format!("tarui-svc-{}.sqlite", Uuid::new_v4()));
+        let db = Arc::new(Database::new(&db_path).expect("create db"));
+        std::fs::create_dir_all("/tmp/tarui-tests").expect("create test download root");
+        db.set_setting("download_dir", "/tmp/tarui-tests")
+            .expect("set download_dir");
+        #[cfg(unix)]
+        db.set_setting("aria2_bin_path", "/bin/sh")
+            .expect("set aria2_bin_path");
+        #[cfg(windows)]
+        db.set_setting("aria2_bin_path", "C:\\Windows\\System32\\cmd.exe")
+            .expect("set aria2_bin_path");
+        let service = Arc::new(DownloadService::new(db.clone(), engine, emitter));
+        (service, db)
+    }
+
+    async fn build_engine_service(
+        engine: Arc<MockAria2Engine>,
+    ) -> (Arc<DownloadService>, Arc<Database>, Arc<MockAria2Engine>) {
+        engine.start().await.expect("start mock engine");
+        let (service, db) = build_service_with(engine.clone());
+        (service, db, engine)
+    }
+
+    /// A started mock engine where `https://example.com/{name}` finishes after one
+    /// tick, with that URL already added to a fresh service.
+    async fn quick_download_service(
+        name: &str,
+        emitter: crate::events::SharedEmitter,
+    ) -> (
+        Arc<DownloadService>,
+        Arc<Database>,
+        Arc<MockAria2Engine>,
+        String,
+    ) {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(name, MockScenario::http(1000, 1000));
+        engine.start().await.expect("start mock engine");
+        let (service, db) = build_service_with_emitter(engine.clone(), emitter);
+        let task_id = service
+            .add_url(
+                &format!("https://example.com/{name}"),
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
+        (service, db, engine, task_id)
+    }
+
+    async fn sync_once(service: &DownloadService, engine: &MockAria2Engine) {
+        let snapshots = engine.tell_all().await.expect("tell all");
+        service.apply_sync_snapshots(None, &snapshots, 1, &mut HashMap::new(), true);
+    }
+
+    #[tokio::test]
+    async fn add_pause_remove_flow_with_mock() {
+        let mock = Arc::new(MockAria2::default());
+        let (service, _db, mock) = build_service(mock);
+
+        let task_id = service
+            .add_url(
+                "https://example.com/file.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
+        service.pause_task(&task_id).await.expect("pause task");
+        service
+            .remove_task(&task_id, false)
+            .await
+            .expect("remove task");
+
+        let calls = mock.calls();
+        assert!(calls.iter().any(|c| c == "add_uri"));
+        assert!(calls.iter().any(|c| c == "pause"));
+        assert!(calls.iter().any(|c| c == "remove"));
+        assert!(calls.iter().any(|c| c == "remove_download_result"));
+    }
+
+    #[tokio::test]
+    async fn reconcile_recovers_orphan_snapshot() {
+        let mock = Arc::new(MockAria2::default());
+        mock.push_snapshot(Aria2TaskSnapshot {
+            gid: "gid-orphan".to_string(),
+            status: "active".to_string(),
+            total_length: 1024,
+            completed_length: 128,
+            download_speed: 32,
+            upload_speed: 0,
+            upload_length: 0,
+            connections: 2,
+            error_code: None,
+            error_message: None,
+            name: Some("orphan.bin".to_string()),
+            has_metadata: true,
+            files: vec![],
+        });
+
+        let (service, _db, _mock) = build_service(mock);
+        let created = service
+            .reconcile_with_aria2()
+            .await
+            .expect("reconcile with aria2");
+        assert_eq!(created, 1);
+
+        let tasks = service
+            .list_tasks(None, 20, 0)
+            .expect("list tasks after reconcile");
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].status, TaskStatus::Active);
+        assert!(tasks[0].source.contains("aria2:recovered:gid-orphan"));
+    }
+
+    #[tokio::test]
+    async fn reconcile_skips_deleted_gid_tombstone() {
+        let mock = Arc::new(MockAria2::default());
+        mock.push_snapshot(Aria2TaskSnapshot {
+            gid: "gid-deleted".to_string(),
+            status: "active".to_string(),
+            total_length: 1024,
+            completed_length: 64,
+            download_speed: 10,
+            upload_speed: 0,
+            upload_length: 0,
+            connections: 1,
+            error_code: None,
+            error_message: None,
+            name: Some("should-not-restore.bin".to_string()),
+            has_metadata: true,
+            files: vec![],
+        });
+
+        let (service, db, _mock) = build_service(mock);
+        db.mark_deleted_gid("gid-deleted", super::now_ts())
+            .expect("mark deleted gid");
+
+        let created = service
+            .reconcile_with_aria2()
+            .await
+            .expect("reconcile with aria2");
+        assert_eq!(created, 0);
+
+        let tasks = service
+            .list_tasks(None, 20, 0)
+            .expect("list tasks after reconcile");
+        assert!(tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn remove_with_delete_files_rejects_outside_download_root() {
+        let mock = Arc::new(MockAria2::default());
+        let (service, db, _mock) = build_service(mock);
+        let task_id = Uuid::new_v4().to_string();
+        let now = super::now_ts();
+        db.upsert_task(&Task {
+            id: task_id.clone(),
+            aria2_gid: Some("gid-outside".to_string()),
+            engine_id: None,
+            task_type: TaskType::Http,
+            source: "https://example.com/a.bin".to_string(),
+            status: TaskStatus::Completed,
+            name: Some("a.bin".to_string()),
+            category: None,
+            save_dir: "/tmp/tarui-tests".to_string(),
+            total_length: 10,
+            completed_length: 10,
+            download_speed: 0,
+            upload_speed: 0,
+            connections: 0,
+            health: Some(TaskHealth::Normal.as_str().to_string()),
+            error_code: None,
+            error_message: None,
+            remediation: None,
+            retry_count: 0,
+            last_retry_at: None,
+            checksum_algorithm: None,
+            checksum_expected: None,
+            checksum_actual: None,
+            checksum_status: None,
+            created_at: now,
+            updated_at: now,
+            depends_on: Vec::new(),
+            start_at: None,
+            window: None,
+            schedule_held: false,
+            queue: None,
+            queue_held: false,
+        })
+        .expect("upsert task");
+        db.replace_task_files(
+            &task_id,
+            &[crate::models::TaskFile {
+                task_id: task_id.clone(),
+                path: "/tmp/outside-danger.bin".to_string(),
+                length: 10,
+                completed_length: 10,
+                selected: true,
+            }],
+        )
+        .expect("replace files");
+
+        let err = service
+            .remove_task(&task_id, true)
+            .await
+            .expect_err("remove should reject outside path");
+        let msg = err.to_string();
+        assert!(msg.contains("outside download root"));
+        assert!(
+            db.get_task(&task_id).expect("get task").is_some(),
+            "task record should remain when file delete is rejected"
+        );
     }
-    Ok(archive.to_vec())
-}
 
-fn extract_from_zip(bytes: &[u8]) -> Result<Vec<u8>> {
-    let reader = Cursor::new(bytes);
-    let mut zip = zip::ZipArchive::new(reader)?;
-    for idx in 0..zip.len() {
-        let mut file = zip.by_index(idx)?;
-        if file.is_dir() {
-            continue;
-        }
-        let name = file.name().to_ascii_lowercase();
-        if name.ends_with("/aria2c")
-            || name.ends_with("/aria2c.exe")
-            || name == "aria2c"
-            || name == "aria2c.exe"
-        {
-            let mut out = Vec::new();
-            file.read_to_end(&mut out)?;
-            return Ok(out);
+    #[tokio::test]
+    async fn operation_logs_are_broadcast_to_event_subscribers() {
+        let (_service, db, mock) = build_service(Arc::new(MockAria2::default()));
+        let emitter = Arc::new(crate::events::BroadcastEventEmitter::new(Arc::new(
+            NoopEmitter,
+        ))) as crate::events::SharedEmitter;
+        let service = DownloadService::new(db, mock, emitter);
+        let mut events = service
+            .subscribe_events()
+            .expect("broadcast emitter subscribes");
+
+        service.append_operation_log("sse_probe", "hello");
+
+        match events.try_recv().expect("operation log event") {
+            crate::events::ServiceEvent::OperationLog { entry } => {
+                assert_eq!(entry.action, "sse_probe");
+                assert_eq!(entry.message, "hello");
+            }
+            other => panic!("unexpected event: {}", other.name()),
         }
     }
-    Err(anyhow!("aria2c binary not found in zip archive"))
-}
 
-fn extract_from_tar_gz(bytes: &[u8]) -> Result<Vec<u8>> {
-    let decoder = flate2::read::GzDecoder::new(Cursor::new(bytes));
-    extract_from_tar_reader(decoder)
-}
+    #[tokio::test]
+    async fn bulk_pause_and_remove_report_per_task_outcomes() {
+        let (service, db, mock) = build_service(Arc::new(MockAria2::default()));
+        let task_id = service
+            .add_url(
+                "https://example.com/bulk.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
+        let ids = vec![task_id.clone(), "missing-task".to_string()];
 
-fn extract_from_tar_xz(bytes: &[u8]) -> Result<Vec<u8>> {
-    let decoder = xz2::read::XzDecoder::new(Cursor::new(bytes));
-    extract_from_tar_reader(decoder)
-}
+        let paused = service.pause_tasks(&ids).await.expect("pause tasks");
+        assert_eq!(paused.succeeded, vec![task_id.clone()]);
+        assert_eq!(paused.failed.len(), 1);
+        assert_eq!(paused.failed[0].task_id, "missing-task");
 
-fn extract_from_tar_bz2(bytes: &[u8]) -> Result<Vec<u8>> {
-    let decoder = bzip2::read::BzDecoder::new(Cursor::new(bytes));
-    extract_from_tar_reader(decoder)
-}
+        let removed = service
+            .remove_tasks(&ids, false)
+            .await
+            .expect("remove tasks");
+        assert_eq!(removed.succeeded, vec![task_id.clone()]);
+        assert_eq!(removed.failed.len(), 1);
+        assert!(db.get_task(&task_id).expect("get task").is_none());
 
-fn extract_from_tar_reader<R: Read>(reader: R) -> Result<Vec<u8>> {
-    let mut archive = tar::Archive::new(reader);
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        if !entry.header().entry_type().is_file() {
-            continue;
-        }
-        let path = entry.path()?.to_string_lossy().to_ascii_lowercase();
-        if path.ends_with("/aria2c")
-            || path.ends_with("/aria2c.exe")
-            || path == "aria2c"
-            || path == "aria2c.exe"
-        {
-            let mut out = Vec::new();
-            entry.read_to_end(&mut out)?;
-            return Ok(out);
-        }
+        let calls = mock.calls();
+        assert_eq!(calls.iter().filter(|c| *c == "pause").count(), 1);
+        assert!(calls.iter().any(|c| c == "remove"));
+        assert!(calls.iter().any(|c| c == "remove_download_result"));
     }
-    Err(anyhow!("aria2c binary not found in tar archive"))
-}
 
-fn install_aria2_binary_with_backup(target: &Path, bytes: &[u8]) -> Result<Option<PathBuf>> {
-    let parent = target
-        .parent()
-        .ok_or_else(|| anyhow!("invalid aria2 binary path"))?;
-    fs::create_dir_all(parent)?;
+    #[tokio::test]
+    async fn tasks_are_routed_to_engine_profiles_by_rule_and_option() {
+        let (service, db, local) = build_service(Arc::new(MockAria2::default()));
+        let seedbox = Arc::new(MockAria2::default());
+        service.register_engine(
+            EngineProfile {
+                id: "seedbox".to_string(),
+                name: "Seedbox".to_string(),
+                rpc_url: "https://seedbox.example/jsonrpc".to_string(),
+                ..EngineProfile::default()
+            },
+            seedbox.clone(),
+        );
+        db.set_setting(
+            "download_dir_rules",
+            r#"[{"enabled":true,"matcher":"type","pattern":"magnet","save_dir":"","engine":"seedbox"}]"#,
+        )
+        .expect("set download_dir_rules");
 
-    if bytes.is_empty() {
-        return Err(anyhow!("downloaded aria2 binary is empty"));
-    }
+        let magnet_id = service
+            .add_magnet(
+                "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add magnet");
+        let magnet = db.get_task(&magnet_id).expect("get task").expect("task");
+        assert_eq!(magnet.engine_id.as_deref(), Some("seedbox"));
+        assert_eq!(magnet.save_dir, "");
 
-    let tmp_path = target.with_extension("new");
-    fs::write(&tmp_path, bytes)?;
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let perm = fs::Permissions::from_mode(0o755);
-        fs::set_permissions(&tmp_path, perm)?;
-    }
-    verify_aria2_binary(&tmp_path)?;
+        let http_id = service
+            .add_url(
+                "https://example.com/local.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
+        let http = db.get_task(&http_id).expect("get task").expect("task");
+        assert_eq!(http.engine_id, None);
 
-    let mut backup = None;
-    if target.exists() {
-        let backup_path = target.with_extension("bak");
-        if backup_path.exists() {
-            let _ = fs::remove_file(&backup_path);
-        }
-        fs::rename(target, &backup_path)?;
-        backup = Some(backup_path);
-    }
+        service.pause_task(&magnet_id).await.expect("pause magnet");
+        assert!(seedbox.calls().iter().any(|c| c == "add_uri"));
+        assert!(seedbox.calls().iter().any(|c| c == "pause"));
+        assert!(!local.calls().iter().any(|c| c == "pause"));
 
-    if let Err(e) = fs::rename(&tmp_path, target) {
-        let _ = fs::remove_file(&tmp_path);
-        if let Some(ref b) = backup
-            && b.exists()
-        {
-            let _ = fs::rename(b, target);
-        }
-        return Err(e.into());
-    }
-    Ok(backup)
-}
+        let explicit_id = service
+            .add_url(
+                "https://example.com/remote.bin",
+                crate::models::AddTaskOptions {
+                    engine: Some("seedbox".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add url to seedbox");
+        let explicit = db.get_task(&explicit_id).expect("get task").expect("task");
+        assert_eq!(explicit.engine_id.as_deref(), Some("seedbox"));
 
-fn rollback_aria2_binary(target: &Path, backup: Option<PathBuf>) {
-    if target.exists() {
-        let _ = fs::remove_file(target);
-    }
-    if let Some(backup_path) = backup
-        && backup_path.exists()
-    {
-        let _ = fs::rename(backup_path, target);
+        let err = service
+            .add_url(
+                "https://example.com/nowhere.bin",
+                crate::models::AddTaskOptions {
+                    engine: Some("missing".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect_err("unknown engine is rejected");
+        assert!(err.to_string().contains("unknown engine profile"));
     }
-}
 
-fn verify_aria2_binary(path: &Path) -> Result<()> {
-    let mut command = std::process::Command::new(path);
-    command.arg("--version");
-    #[cfg(target_os = "windows")]
-    {
-        use std::os::windows::process::CommandExt;
-        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
-        command.creation_flags(CREATE_NO_WINDOW);
-    }
-    let output = command.output()?;
-    if !output.status.success() {
-        return Err(anyhow!("downloaded aria2 binary failed self-check"));
-    }
-    Ok(())
-}
+    #[tokio::test]
+    async fn removing_a_task_of_a_deleted_engine_profile_still_drops_it() {
+        let (service, db, _engine, task_id) =
+            quick_download_service("orphan.bin", Arc::new(NoopEmitter)).await;
+        let mut task = db.get_task(&task_id).expect("get task").expect("task");
+        task.engine_id = Some("deleted-profile".to_string());
+        db.upsert_task(&task).expect("upsert task");
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        path::Path,
-        sync::{Arc, Mutex},
-    };
+        service
+            .remove_task(&task_id, false)
+            .await
+            .expect("remove task");
+        assert!(db.get_task(&task_id).expect("get task").is_none());
+    }
 
-    use anyhow::Result;
-    use async_trait::async_trait;
-    use serde_json::{Value, json};
-    use uuid::Uuid;
+    #[tokio::test]
+    async fn task_finishing_before_its_first_sync_still_emits_completion() {
+        let emitter = Arc::new(crate::events::BroadcastEventEmitter::new(Arc::new(
+            NoopEmitter,
+        )));
+        let mut events = emitter.subscribe().expect("subscribe");
+        let (service, _db, engine, task_id) = quick_download_service("quick.bin", emitter).await;
+
+        engine.advance(2);
+        let mut last_status = HashMap::new();
+        let snapshots = engine.tell_all().await.expect("tell all");
+        service.apply_sync_snapshots(None, &snapshots, 1, &mut last_status, true);
+        let mut completed = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let crate::events::ServiceEvent::TaskCompleted { task } = event {
+                completed.push(task.id);
+            }
+        }
+        assert_eq!(completed, vec![task_id.clone()]);
 
-    use crate::{
-        aria2_manager::{Aria2Api, Aria2Endpoint},
-        db::Database,
-        events::EventEmitter,
-        models::{Aria2TaskSnapshot, DownloadDirRule, Task, TaskHealth, TaskStatus, TaskType},
-    };
+        // A restart starts with no sync history; tasks already finished stay quiet.
+        let snapshots = engine.tell_all().await.expect("tell all");
+        service.apply_sync_snapshots(None, &snapshots, 2, &mut HashMap::new(), true);
+        while let Ok(event) = events.try_recv() {
+            assert!(
+                !matches!(event, crate::events::ServiceEvent::TaskCompleted { .. }),
+                "completion emitted twice"
+            );
+        }
+    }
 
-    use super::{
-        DownloadService, SpeedPlanRule, absolute_path, checksum_metadata_from_raw,
-        compute_file_checksum, compute_next_retry_at, has_enough_disk_space,
-        is_download_content_type, is_subpath, normalize_ffmpeg_failure, rule_matches,
-        select_speed_limit, should_auto_retry,
-    };
+    #[tokio::test]
+    async fn engine_sync_drives_http_task_to_completion() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script("movie.mkv", MockScenario::http(3000, 1000));
+        let (service, db, engine) = build_engine_service(engine).await;
+        let task_id = service
+            .add_url(
+                "https://example.com/movie.mkv",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
 
-    #[test]
-    fn absolute_path_resolves_relative_to_base() {
-        let base = Path::new("/tmp/base");
-        let abs = absolute_path(base, "a/b/c");
-        assert_eq!(abs, base.join("a/b/c"));
-    }
+        engine.tick();
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Active);
+        assert_eq!(task.completed_length, 1000);
+        assert_eq!(task.download_speed, 1000);
 
-    #[test]
-    fn absolute_path_keeps_absolute_input() {
-        let base = Path::new("/tmp/base");
-        let abs = absolute_path(base, "/tmp/other/path");
-        assert_eq!(abs, Path::new("/tmp/other/path"));
+        engine.advance(2);
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.total_length, 3000);
+        let files = db.list_task_files(&task_id).expect("list files");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "/tmp/tarui-tests/movie.mkv");
+        assert_eq!(files[0].completed_length, 3000);
     }
 
-    #[test]
-    fn subpath_check_works() {
-        let root = Path::new("/tmp/root");
-        let child = Path::new("/tmp/root/a/b");
-        let outside = Path::new("/tmp/other");
-        assert!(is_subpath(child, root));
-        assert!(!is_subpath(outside, root));
-    }
+    #[tokio::test]
+    async fn engine_error_is_auto_retried_on_a_fresh_gid() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(
+            "flaky.iso",
+            MockScenario::http(4000, 1000).fail_after(1000, "2", "Timeout."),
+        );
+        let (service, db, engine) = build_engine_service(engine).await;
+        let task_id = service
+            .add_url(
+                "https://example.com/flaky.iso",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
+        let first_gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .and_then(|t| t.aria2_gid)
+            .expect("gid");
+
+        engine.tick();
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Error);
+        assert_eq!(task.error_code.as_deref(), Some("2"));
 
-    #[test]
-    fn download_rule_matches_extension() {
-        let rule = DownloadDirRule {
-            enabled: true,
-            matcher: "ext".to_string(),
-            pattern: "zip,mp4".to_string(),
-            save_dir: "/tmp/media".to_string(),
-            subdir_by_date: false,
-            subdir_by_domain: false,
-        };
-        assert!(rule_matches(
-            &rule,
-            &TaskType::Http,
-            "https://example.com/archive/file.zip",
-            None
-        ));
-        assert!(!rule_matches(
-            &rule,
-            &TaskType::Http,
-            "https://example.com/archive/file.txt",
-            None
-        ));
+        service
+            .process_retry_and_metadata_policies()
+            .await
+            .expect("run retry policy");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        let retry_gid = task.aria2_gid.clone().expect("retry gid");
+        assert_ne!(retry_gid, first_gid);
+        assert_eq!(task.retry_count, 1);
+        assert_eq!(task.status, TaskStatus::Queued);
+
+        engine.advance(4);
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(
+            engine.options(&retry_gid).expect("retry options")["out"],
+            "flaky.iso"
+        );
     }
 
-    #[test]
-    fn download_rule_matches_domain_and_type() {
-        let domain_rule = DownloadDirRule {
-            enabled: true,
-            matcher: "domain".to_string(),
-            pattern: "github.com,example.org".to_string(),
-            save_dir: "/tmp/code".to_string(),
-            subdir_by_date: false,
-            subdir_by_domain: false,
-        };
-        assert!(rule_matches(
-            &domain_rule,
-            &TaskType::Http,
-            "https://github.com/owner/repo/archive/main.zip",
-            None
-        ));
-        assert!(rule_matches(
-            &domain_rule,
-            &TaskType::Http,
-            "https://a.github.com/file.bin",
-            None
-        ));
+    #[tokio::test]
+    async fn engine_magnet_waits_for_metadata_before_file_selection() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(
+            "btih:feedface",
+            MockScenario::torrent("bundle", &[("main.bin", 500), ("extras.bin", 4000)], 250)
+                .with_metadata_ticks(1),
+        );
+        let (service, db, engine) = build_engine_service(engine).await;
+        let task_id = service
+            .add_magnet(
+                "magnet:?xt=urn:btih:feedface00000000000000000000000000000000",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add magnet");
 
-        let type_rule = DownloadDirRule {
-            enabled: true,
-            matcher: "type".to_string(),
-            pattern: "magnet,torrent".to_string(),
-            save_dir: "/tmp/bt".to_string(),
-            subdir_by_date: false,
-            subdir_by_domain: false,
-        };
-        assert!(rule_matches(
-            &type_rule,
-            &TaskType::Magnet,
-            "magnet:?xt=urn:btih:abc",
-            None
-        ));
-        assert!(!rule_matches(
-            &type_rule,
-            &TaskType::Http,
-            "https://example.com/a.bin",
-            None
-        ));
-    }
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Metadata);
+        assert!(
+            service
+                .set_task_file_selection(&task_id, &[0])
+                .await
+                .is_err(),
+            "file selection needs metadata"
+        );
 
-    #[test]
-    fn speed_plan_selects_non_empty_limit_rule() {
-        let rules = vec![
-            SpeedPlanRule {
-                days: None,
-                start: None,
-                end: None,
-                limit: "".to_string(),
-            },
-            SpeedPlanRule {
-                days: None,
-                start: None,
-                end: None,
-                limit: "2M".to_string(),
-            },
-        ];
-        let selected = select_speed_limit(&rules);
-        assert_eq!(selected.as_deref(), Some("2M"));
+        engine.tick();
+        sync_once(&service, &engine).await;
+        let files = db.list_task_files(&task_id).expect("list files");
+        assert_eq!(files.len(), 2);
+        service
+            .set_task_file_selection(&task_id, &[0])
+            .await
+            .expect("select main file");
+
+        engine.advance(2);
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.total_length, 500);
     }
 
-    #[test]
-    fn retry_next_time_uses_backoff_and_attempt() {
-        let now = 1_700_000_000_i64;
-        let backoff = 15_i64;
-        assert_eq!(compute_next_retry_at(now, backoff, 1), now + 30);
-        assert_eq!(compute_next_retry_at(now, backoff, 2), now + 45);
+    #[tokio::test]
+    async fn disk_guard_pauses_task_that_cannot_fit() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script("huge.img", MockScenario::http(i64::MAX / 2, 1024));
+        let (service, db, engine) = build_engine_service(engine).await;
+        let task_id = service
+            .add_url(
+                "https://example.com/huge.img",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add url");
+        let gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .and_then(|t| t.aria2_gid)
+            .expect("gid");
+
+        engine.tick();
+        sync_once(&service, &engine).await;
+        service
+            .process_retry_and_metadata_policies()
+            .await
+            .expect("run disk guard");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert_eq!(task.health.as_deref(), Some("disk_full"));
+        assert_eq!(engine.status_of(&gid).as_deref(), Some("paused"));
     }
 
-    #[test]
-    fn ffmpeg_failure_normalization_maps_actionable_reasons() {
-        let forbidden = normalize_ffmpeg_failure(Some(1), "Server returned 403 Forbidden");
-        assert_eq!(forbidden.health, TaskHealth::AuthRequired);
-        assert_eq!(forbidden.code, "FFMPEG_HTTP_403");
-        assert!(forbidden.remediation.contains("cookie"));
+    #[tokio::test]
+    async fn dependent_task_is_held_until_prerequisite_completes() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script("part1.bin", MockScenario::http(2000, 1000));
+        engine.script("part2.bin", MockScenario::http(1000, 1000));
+        let (service, db, engine) = build_engine_service(engine).await;
+        let first = service
+            .add_url(
+                "https://example.com/part1.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add first part");
+        let second = service
+            .add_url(
+                "https://example.com/part2.bin",
+                crate::models::AddTaskOptions {
+                    depends_on: vec![first.clone()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add second part");
 
-        let redirect = normalize_ffmpeg_failure(Some(1), "Too many redirects");
-        assert_eq!(redirect.health, TaskHealth::UrlExpired);
-        assert_eq!(redirect.code, "FFMPEG_REDIRECT_ERROR");
-    }
+        let held = db.get_task(&second).expect("get task").expect("task");
+        assert_eq!(held.status, TaskStatus::Queued);
+        assert!(held.aria2_gid.is_none());
+        assert_eq!(held.depends_on, vec![first.clone()]);
+        assert_eq!(engine.calls().iter().filter(|c| *c == "add_uri").count(), 1);
 
-    #[test]
-    fn auto_retry_skips_failures_requiring_user_action() {
-        let now = super::now_ts();
-        let task = Task {
-            id: "task".to_string(),
-            aria2_gid: Some("gid".to_string()),
-            task_type: TaskType::Http,
-            source: "https://example.com/file.bin".to_string(),
-            status: TaskStatus::Error,
-            name: None,
-            category: None,
-            save_dir: "/tmp".to_string(),
-            total_length: 0,
-            completed_length: 0,
-            download_speed: 0,
-            upload_speed: 0,
-            connections: 0,
-            health: Some(TaskHealth::AuthRequired.as_str().to_string()),
-            error_code: Some("HTTP_403".to_string()),
-            error_message: Some("forbidden".to_string()),
-            remediation: Some("refresh headers".to_string()),
-            retry_count: 0,
-            last_retry_at: None,
-            checksum_algorithm: None,
-            checksum_expected: None,
-            checksum_actual: None,
-            checksum_status: None,
-            created_at: now,
-            updated_at: now,
-        };
-        assert!(!should_auto_retry(&task));
+        service.pause_task(&second).await.expect("pause held task");
+        engine.advance(2);
+        sync_once(&service, &engine).await;
+        service
+            .process_held_submissions()
+            .await
+            .expect("process held submissions");
+        let held = db.get_task(&second).expect("get task").expect("task");
+        assert_eq!(held.status, TaskStatus::Paused);
+        assert!(held.aria2_gid.is_none());
 
-        let retryable = Task {
-            health: Some(TaskHealth::NetworkUnstable.as_str().to_string()),
-            ..task
-        };
-        assert!(should_auto_retry(&retryable));
+        service
+            .resume_task(&second)
+            .await
+            .expect("resume held task");
+        service
+            .process_held_submissions()
+            .await
+            .expect("process held submissions");
+        let released = db.get_task(&second).expect("get task").expect("task");
+        assert!(released.aria2_gid.is_some());
+        assert!(!db.has_pending_submission(&second).expect("has pending"));
+        engine.advance(1);
+        sync_once(&service, &engine).await;
+        let released = db.get_task(&second).expect("get task").expect("task");
+        assert_eq!(released.status, TaskStatus::Completed);
+
+        let err = service
+            .add_url(
+                "https://example.com/part3.bin",
+                crate::models::AddTaskOptions {
+                    depends_on: vec!["missing-task".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect_err("unknown prerequisite should be rejected");
+        assert!(err.to_string().contains("missing-task"));
+        let err = service
+            .add_url(
+                "https://example.com/part3.bin",
+                crate::models::AddTaskOptions {
+                    depends_on: vec![first],
+                    on_dependency_failure: Some("ignore".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect_err("unknown policy should be rejected");
+        assert!(err.to_string().contains("on_dependency_failure"));
     }
 
-    #[test]
-    fn checksum_metadata_requires_supported_algorithm_and_value() {
-        let metadata = checksum_metadata_from_raw(Some("SHA-256"), Some(" AB CD "))
-            .expect("valid checksum metadata");
-        assert_eq!(metadata.algorithm.as_deref(), Some("sha256"));
-        assert_eq!(metadata.expected.as_deref(), Some("abcd"));
-        assert_eq!(metadata.status.as_deref(), Some("pending"));
+    #[tokio::test]
+    async fn bulk_pause_and_resume_handle_dependency_held_tasks() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script("part1.bin", MockScenario::http(2000, 1000));
+        let (service, db, _engine) = build_engine_service(engine).await;
+        let first = service
+            .add_url(
+                "https://example.com/part1.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add first part");
+        let second = service
+            .add_url(
+                "https://example.com/part2.bin",
+                crate::models::AddTaskOptions {
+                    depends_on: vec![first.clone()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add second part");
 
-        assert!(checksum_metadata_from_raw(Some("crc32"), Some("abcd")).is_err());
-        assert!(checksum_metadata_from_raw(Some("sha1"), None).is_err());
-        assert!(checksum_metadata_from_raw(None, Some("abcd")).is_err());
+        let ids = vec![first.clone(), second.clone()];
+        let result = service.pause_tasks(&ids).await.expect("bulk pause");
+        assert!(result.failed.is_empty(), "{:?}", result.failed);
+        assert_eq!(result.succeeded.len(), 2);
+        let held = db.get_task(&second).expect("get task").expect("task");
+        assert_eq!(held.status, TaskStatus::Paused);
+        assert!(held.aria2_gid.is_none());
+
+        let result = service.resume_tasks(&ids).await.expect("bulk resume");
+        assert!(result.failed.is_empty(), "{:?}", result.failed);
+        assert_eq!(result.succeeded.len(), 2);
+        let held = db.get_task(&second).expect("get task").expect("task");
+        assert_eq!(held.status, TaskStatus::Queued);
+        assert!(db.has_pending_submission(&second).expect("has pending"));
     }
 
-    #[test]
-    fn compute_file_checksum_supports_sha_and_md5() {
-        let path = std::env::temp_dir().join(format!("flamingo-checksum-{}.bin", Uuid::new_v4()));
-        std::fs::write(&path, b"abc").expect("write fixture");
+    #[tokio::test]
+    async fn removed_completed_prerequisite_still_releases_dependents() {
+        let (service, db, engine, base) =
+            quick_download_service("base.bin", Arc::new(NoopEmitter)).await;
+        let dependent = service
+            .add_url(
+                "https://example.com/dependent.bin",
+                crate::models::AddTaskOptions {
+                    depends_on: vec![base.clone()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add dependent");
+        service
+            .pause_task(&dependent)
+            .await
+            .expect("pause dependent");
 
-        assert_eq!(
-            compute_file_checksum(&path, "sha256").expect("sha256"),
-            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
-        );
-        assert_eq!(
-            compute_file_checksum(&path, "sha1").expect("sha1"),
-            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        engine.advance(1);
+        sync_once(&service, &engine).await;
+        service
+            .remove_task(&base, false)
+            .await
+            .expect("remove base");
+        service
+            .resume_task(&dependent)
+            .await
+            .expect("resume dependent");
+        service
+            .process_held_submissions()
+            .await
+            .expect("process held submissions");
+
+        let released = db.get_task(&dependent).expect("get task").expect("task");
+        assert_ne!(released.error_code.as_deref(), Some("DEPENDENCY_FAILED"));
+        assert!(released.aria2_gid.is_some());
+    }
+
+    #[tokio::test]
+    async fn failed_prerequisite_applies_dependency_failure_policy() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(
+            "base.bin",
+            MockScenario::http(2000, 1000).fail_after(1000, "3", "Resource not found"),
         );
+        let (service, db, engine) = build_engine_service(engine).await;
+        let base = service
+            .add_url(
+                "https://example.com/base.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add base");
+        let mut dependents = Vec::new();
+        for policy in ["cancel", "wait", "run"] {
+            let task_id = service
+                .add_url(
+                    &format!("https://example.com/{policy}.bin"),
+                    crate::models::AddTaskOptions {
+                        depends_on: vec![base.clone()],
+                        on_dependency_failure: Some(policy.to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect("add dependent");
+            dependents.push(task_id);
+        }
+        let chained = service
+            .add_url(
+                "https://example.com/chained.bin",
+                crate::models::AddTaskOptions {
+                    depends_on: vec![dependents[0].clone()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add chained dependent");
+
+        engine.tick();
+        sync_once(&service, &engine).await;
         assert_eq!(
-            compute_file_checksum(&path, "md5").expect("md5"),
-            "900150983cd24fb0d6963f7d28e17f72"
+            db.get_task(&base).expect("get task").expect("task").status,
+            TaskStatus::Error
         );
+        service
+            .process_held_submissions()
+            .await
+            .expect("process held submissions");
+
+        for task_id in [&dependents[0], &chained] {
+            let task = db.get_task(task_id).expect("get task").expect("task");
+            assert_eq!(task.status, TaskStatus::Error);
+            assert_eq!(task.error_code.as_deref(), Some("DEPENDENCY_FAILED"));
+            assert!(task.aria2_gid.is_none());
+            assert!(!should_auto_retry(&task));
+        }
+        let waiting = db
+            .get_task(&dependents[1])
+            .expect("get task")
+            .expect("task");
+        assert_eq!(waiting.status, TaskStatus::Queued);
+        assert!(waiting.aria2_gid.is_none());
+        let forced = db
+            .get_task(&dependents[2])
+            .expect("get task")
+            .expect("task");
+        assert!(forced.aria2_gid.is_some());
 
-        let _ = std::fs::remove_file(path);
+        service
+            .remove_task(&dependents[1], false)
+            .await
+            .expect("remove waiting task");
+        assert!(
+            db.list_pending_submissions()
+                .expect("list pending submissions")
+                .is_empty()
+        );
     }
 
-    #[test]
-    fn disk_space_guard_requires_remaining_bytes_plus_buffer() {
-        assert!(has_enough_disk_space(
-            100 + super::LOW_DISK_BUFFER_BYTES,
-            100
-        ));
-        assert!(!has_enough_disk_space(
-            99 + super::LOW_DISK_BUFFER_BYTES,
-            100
-        ));
-    }
+    #[tokio::test]
+    async fn scheduled_task_is_paused_until_start_and_outside_its_window() {
+        let engine = Arc::new(MockAria2Engine::new());
+        let (service, db, engine) = build_engine_service(engine).await;
+        let now = Local::now();
+        let task_id = service
+            .add_url(
+                "https://example.com/nightly.iso",
+                crate::models::AddTaskOptions {
+                    start_at: Some(now.timestamp() + 3600),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add scheduled task");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        let gid = task.aria2_gid.clone().expect("gid");
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert!(task.schedule_held);
+        assert_eq!(engine.status_of(&gid).as_deref(), Some("paused"));
+
+        service
+            .set_task_schedule(&task_id, Some(now.timestamp() - 60), None)
+            .await
+            .expect("move start time");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert!(!task.schedule_held);
+        assert_ne!(engine.status_of(&gid).as_deref(), Some("paused"));
+
+        // A window that opens two hours from now is closed right now.
+        let opens = now + ChronoDuration::hours(2);
+        let closes = now + ChronoDuration::hours(3);
+        let window = TaskWindow {
+            days: None,
+            start: format!("{:02}:{:02}", opens.hour(), opens.minute()),
+            end: format!("{:02}:{:02}", closes.hour(), closes.minute()),
+        };
+        service
+            .set_task_schedule(&task_id, None, Some(window.clone()))
+            .await
+            .expect("set window");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert!(task.schedule_held);
+        assert_eq!(task.window, Some(window));
+        assert_eq!(engine.status_of(&gid).as_deref(), Some("paused"));
+
+        // Resuming by hand overrides the window until it next closes.
+        service.resume_task(&task_id).await.expect("resume task");
+        service
+            .enforce_task_schedules()
+            .await
+            .expect("enforce schedules");
+        assert!(
+            !db.get_task(&task_id)
+                .expect("get task")
+                .expect("task")
+                .schedule_held
+        );
+        assert_ne!(engine.status_of(&gid).as_deref(), Some("paused"));
 
-    #[test]
-    fn download_content_type_detection_prefers_downloadable_media() {
-        assert!(is_download_content_type("application/octet-stream"));
-        assert!(is_download_content_type("video/mp4"));
-        assert!(!is_download_content_type("text/html"));
+        let err = service
+            .set_task_schedule(
+                &task_id,
+                None,
+                Some(TaskWindow {
+                    days: Some("0".to_string()),
+                    start: "01:00".to_string(),
+                    end: "07:00".to_string(),
+                }),
+            )
+            .await
+            .expect_err("invalid window should be rejected");
+        assert!(err.to_string().contains("window.days"));
     }
 
-    #[test]
-    fn requested_category_trims_and_skips_empty_values() {
-        let mock = Arc::new(MockAria2::default());
-        let (service, _db, _mock) = build_service(mock);
+    #[tokio::test]
+    async fn bulk_resume_overrides_schedule_hold() {
+        let engine = Arc::new(MockAria2Engine::new());
+        let (service, db, engine) = build_engine_service(engine).await;
+        let task_id = service
+            .add_url(
+                "https://example.com/nightly.iso",
+                crate::models::AddTaskOptions {
+                    start_at: Some(Local::now().timestamp() + 3600),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add scheduled task");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        let gid = task.aria2_gid.clone().expect("gid");
+        assert!(task.schedule_held);
 
-        assert_eq!(
-            service.resolve_requested_category(Some("  video  ")),
-            Some("video".to_string())
+        let result = service
+            .resume_tasks(std::slice::from_ref(&task_id))
+            .await
+            .expect("bulk resume");
+        assert_eq!(result.succeeded, vec![task_id.clone()]);
+        assert!(
+            !db.get_task(&task_id)
+                .expect("get task")
+                .expect("task")
+                .schedule_held
         );
-        assert_eq!(service.resolve_requested_category(Some("   ")), None);
-        assert_eq!(service.resolve_requested_category(None), None);
+        assert_ne!(engine.status_of(&gid).as_deref(), Some("paused"));
     }
 
-    #[derive(Default)]
-    struct NoopEmitter;
+    #[tokio::test]
+    async fn full_task_queue_holds_new_tasks_until_a_slot_frees() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script("bulk1.bin", MockScenario::http(2000, 1000));
+        engine.script("bulk2.bin", MockScenario::http(4000, 1000));
+        engine.script("hotfix.bin", MockScenario::http(4000, 1000));
+        let (service, db, engine) = build_engine_service(engine).await;
+        db.set_setting(
+            "task_queues",
+            r#"[{"name":"bulk","max_active":1},{"name":"urgent","max_active":1,"priority":10}]"#,
+        )
+        .expect("set task queues");
+        let in_queue = |name: &str| crate::models::AddTaskOptions {
+            queue: Some(name.to_string()),
+            ..Default::default()
+        };
+        let first = service
+            .add_url("https://example.com/bulk1.bin", in_queue("bulk"))
+            .await
+            .expect("add first bulk task");
+        let second = service
+            .add_url("https://example.com/bulk2.bin", in_queue("bulk"))
+            .await
+            .expect("add second bulk task");
+        let urgent = service
+            .add_url("https://example.com/hotfix.bin", in_queue("urgent"))
+            .await
+            .expect("add urgent task");
 
-    impl EventEmitter for NoopEmitter {
-        fn emit_task_update(&self, _tasks: &[crate::models::Task]) -> Result<()> {
-            Ok(())
-        }
-    }
+        assert!(
+            !db.get_task(&first)
+                .expect("get task")
+                .expect("task")
+                .queue_held
+        );
+        assert!(
+            !db.get_task(&urgent)
+                .expect("get task")
+                .expect("task")
+                .queue_held
+        );
+        let held = db.get_task(&second).expect("get task").expect("task");
+        assert_eq!(held.status, TaskStatus::Paused);
+        assert!(held.queue_held);
+        assert!(db.has_queue_held_tasks().expect("has queue held"));
+        assert_eq!(held.queue.as_deref(), Some("bulk"));
+        let held_gid = held.aria2_gid.clone().expect("gid");
+        assert_eq!(engine.status_of(&held_gid).as_deref(), Some("paused"));
+
+        let queues = service.list_task_queues().expect("list queues");
+        assert_eq!(queues[0].name, "urgent");
+        let bulk = queues.iter().find(|q| q.name == "bulk").expect("bulk");
+        assert_eq!((bulk.running, bulk.held), (1, 1));
+
+        engine.advance(2);
+        sync_once(&service, &engine).await;
+        service
+            .enforce_task_queues()
+            .await
+            .expect("enforce task queues");
+        let released = db.get_task(&second).expect("get task").expect("task");
+        assert!(!released.queue_held);
+        assert!(!db.has_queue_held_tasks().expect("has queue held"));
+        assert_ne!(engine.status_of(&held_gid).as_deref(), Some("paused"));
 
-    #[derive(Default)]
-    struct MockAria2 {
-        calls: Mutex<Vec<String>>,
-        snapshots: Mutex<Vec<Aria2TaskSnapshot>>,
+        let err = service
+            .add_url("https://example.com/other.bin", in_queue("nightly"))
+            .await
+            .expect_err("unknown queue should be rejected");
+        assert!(err.to_string().contains("unknown task queue"));
     }
 
-    impl MockAria2 {
-        fn push_snapshot(&self, snapshot: Aria2TaskSnapshot) {
-            self.snapshots
-                .lock()
-                .expect("snapshots mutex")
-                .push(snapshot);
-        }
+    #[tokio::test]
+    async fn source_ui_rule_applies_to_app_adds() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        db.set_setting(
+            "policy_rules",
+            r#"[{"enabled":true,"matcher":"source","pattern":"ui","split":3}]"#,
+        )
+        .expect("set policy rules");
 
-        fn calls(&self) -> Vec<String> {
-            self.calls.lock().expect("calls mutex").clone()
-        }
+        let outcome = crate::commands::add_url(
+            service.clone(),
+            "https://example.com/desktop.bin".to_string(),
+            crate::models::AddTaskOptions::default(),
+        )
+        .await
+        .expect("add from app");
+        let gid = db
+            .get_task(&outcome.task_id)
+            .expect("get task")
+            .expect("task")
+            .aria2_gid
+            .expect("gid");
+        assert_eq!(engine.options(&gid).expect("options")["split"], json!("3"));
 
-        fn call(&self, name: &str) {
-            self.calls
-                .lock()
-                .expect("calls mutex")
-                .push(name.to_string());
-        }
+        let task_id = service
+            .add_url(
+                "https://example.com/api.bin",
+                crate::models::AddTaskOptions {
+                    origin: Some("api".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add from api");
+        let gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .expect("task")
+            .aria2_gid
+            .expect("gid");
+        assert!(
+            engine
+                .options(&gid)
+                .expect("options")
+                .get("split")
+                .is_none()
+        );
     }
 
-    #[async_trait]
-    impl Aria2Api for MockAria2 {
-        async fn start(&self) -> Result<Aria2Endpoint> {
-            self.call("start");
-            Ok(Aria2Endpoint {
-                endpoint: "http://127.0.0.1:6800/jsonrpc".to_string(),
-                secret: "mock".to_string(),
-                port: 6800,
-                compat_mode: false,
-            })
-        }
+    #[tokio::test]
+    async fn policy_rules_fill_unset_options_for_matching_tasks() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        db.set_setting(
+            "policy_rules",
+            r#"[
+                {"enabled":true,"matcher":"domain","pattern":"mirror.internal",
+                 "max_connection_per_server":2,"split":2,"headers":["X-Mirror-Token: abc"]},
+                {"enabled":true,"matcher":"ext","pattern":"iso","max_download_limit":"5M",
+                 "split":16,"user_agent":"mirror-fetch/1.0"}
+            ]"#,
+        )
+        .expect("set policy rules");
 
-        async fn stop(&self) -> Result<()> {
-            self.call("stop");
-            Ok(())
-        }
+        let task_id = service
+            .add_url(
+                "https://dl.mirror.internal/os.iso",
+                crate::models::AddTaskOptions {
+                    user_agent: Some("custom/2.0".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add mirrored task");
+        let gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .expect("task")
+            .aria2_gid
+            .expect("gid");
+        let options = engine.options(&gid).expect("options");
+        assert_eq!(options["max-connection-per-server"], json!("2"));
+        assert_eq!(options["split"], json!("2"));
+        assert_eq!(options["max-download-limit"], json!("5M"));
+        assert_eq!(options["user-agent"], json!("custom/2.0"));
+        assert_eq!(options["header"], json!(["X-Mirror-Token: abc"]));
 
-        async fn endpoint(&self) -> Option<Aria2Endpoint> {
-            Some(Aria2Endpoint {
-                endpoint: "http://127.0.0.1:6800/jsonrpc".to_string(),
-                secret: "mock".to_string(),
-                port: 6800,
-                compat_mode: false,
-            })
-        }
+        let task_id = service
+            .add_url(
+                "https://example.com/notes.txt",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add unmatched task");
+        let gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .expect("task")
+            .aria2_gid
+            .expect("gid");
+        let options = engine.options(&gid).expect("options");
+        assert!(options.get("max-connection-per-server").is_none());
+        assert!(options.get("split").is_none());
+    }
 
-        async fn ensure_started(&self) -> Result<Aria2Endpoint> {
-            self.start().await
+    #[tokio::test]
+    async fn templates_render_rule_dirs_and_output_names() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        db.set_setting(
+            "download_dir_rules",
+            r#"[{"enabled":true,"matcher":"domain","pattern":"example.com","save_dir":"/archive/{domain}/{yyyy}"}]"#,
+        )
+        .expect("set download dir rules");
+        let url = "https://example.com/pub/disk.iso";
+        let dir = format!("/archive/example.com/{}", Local::now().format("%Y"));
+
+        for _ in 0..2 {
+            let preview = service
+                .suggest_save_dir_detail(TaskType::Http, Some(url), Some("{counter}-{name}.{ext}"))
+                .expect("preview");
+            assert_eq!(preview.save_dir, dir);
+            assert_eq!(preview.out.as_deref(), Some("1-disk.iso"));
+        }
+
+        for expected in ["1-disk.iso", "2-disk.iso"] {
+            let task_id = service
+                .add_url(
+                    url,
+                    crate::models::AddTaskOptions {
+                        out: Some("{counter}-{name}.{ext}".to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect("add templated task");
+            let task = db.get_task(&task_id).expect("get task").expect("task");
+            assert_eq!(task.save_dir, dir);
+            let options = engine
+                .options(&task.aria2_gid.expect("gid"))
+                .expect("options");
+            assert_eq!(options["out"], json!(expected));
         }
 
-        async fn add_uri(&self, _uris: Vec<String>, _options: Option<Value>) -> Result<String> {
-            self.call("add_uri");
-            Ok("gid-add-uri".to_string())
-        }
+        let err = service
+            .suggest_save_dir_detail(TaskType::Http, Some(url), Some("{title}.{ext}"))
+            .expect_err("unknown variable should be rejected");
+        assert!(
+            err.to_string()
+                .contains("unknown template variable {title}")
+        );
+    }
 
-        async fn add_torrent(
-            &self,
-            _torrent_base64: String,
-            _uris: Vec<String>,
-            _options: Option<Value>,
-        ) -> Result<String> {
-            self.call("add_torrent");
-            Ok("gid-add-torrent".to_string())
-        }
+    #[tokio::test]
+    async fn collision_policy_renames_skips_asks_or_overwrites_existing_files() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        let dir = std::env::temp_dir().join(format!("flamingo-collide-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        std::fs::write(dir.join("download.zip"), b"abc").expect("write existing file");
+        std::fs::write(dir.join("download (1).zip"), b"abc").expect("write first copy");
+        let url = "https://other.example/files/download.zip";
+        let add = |policy: Option<&str>, checksum: Option<&str>| {
+            let service = service.clone();
+            let options = crate::models::AddTaskOptions {
+                save_dir: Some(dir.to_string_lossy().to_string()),
+                collision_policy: policy.map(ToString::to_string),
+                checksum_algorithm: checksum.map(|_| "sha256".to_string()),
+                checksum_value: checksum.map(ToString::to_string),
+                ..Default::default()
+            };
+            async move { service.add_url(url, options).await }
+        };
+        let out_of = |task_id: &str| {
+            let gid = db
+                .get_task(task_id)
+                .expect("get task")
+                .expect("task")
+                .aria2_gid
+                .expect("gid");
+            engine.options(&gid).expect("options")
+        };
 
-        async fn pause(&self, _gid: &str) -> Result<String> {
-            self.call("pause");
-            Ok("ok".to_string())
-        }
+        let renamed = add(None, None).await.expect("add with default policy");
+        assert_eq!(out_of(&renamed)["out"], json!("download (2).zip"));
 
-        async fn unpause(&self, _gid: &str) -> Result<String> {
-            self.call("unpause");
-            Ok("ok".to_string())
-        }
+        let sha256_abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let err = add(Some("skip"), Some(sha256_abc))
+            .await
+            .expect_err("identical file should be skipped");
+        assert!(matches!(
+            err.downcast_ref::<crate::error::AppError>(),
+            Some(crate::error::AppError::AlreadyDownloaded(_))
+        ));
+        let different = add(Some("skip"), Some(&"0".repeat(64)))
+            .await
+            .expect("different file is renamed");
+        assert_eq!(out_of(&different)["out"], json!("download (2).zip"));
 
-        async fn pause_all(&self) -> Result<String> {
-            self.call("pause_all");
-            Ok("ok".to_string())
-        }
+        let err = add(Some("ask"), None)
+            .await
+            .expect_err("ask should refuse an existing file");
+        assert!(err.to_string().contains("file already exists"));
+
+        db.set_setting("collision_policy", "overwrite")
+            .expect("set collision policy");
+        let overwritten = add(None, None).await.expect("add with overwrite");
+        let options = out_of(&overwritten);
+        assert!(options.get("out").is_none());
+        assert_eq!(options["allow-overwrite"], json!("true"));
+        assert_eq!(
+            unique_filename(&dir, "logs.tar.gz"),
+            "logs (1).tar.gz".to_string()
+        );
+    }
 
-        async fn unpause_all(&self) -> Result<String> {
-            self.call("unpause_all");
-            Ok("ok".to_string())
-        }
+    #[tokio::test]
+    async fn duplicate_policy_warns_skips_or_opens_earlier_tasks() {
+        let (service, db, _engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        let with_policy = |policy: &str| crate::models::AddTaskOptions {
+            duplicate_policy: Some(policy.to_string()),
+            ..Default::default()
+        };
+        let first = service
+            .add_url("https://example.com/dup/file.bin#top", Default::default())
+            .await
+            .expect("add first task");
 
-        async fn remove(&self, _gid: &str, _force: bool) -> Result<String> {
-            self.call("remove");
-            Ok("ok".to_string())
-        }
+        let warned = service
+            .add_url_outcome("https://EXAMPLE.com/dup/file.bin", Default::default())
+            .await
+            .expect("warn adds anyway");
+        assert_eq!(warned.action, "created");
+        assert_ne!(warned.task_id, first);
+        let duplicate = warned.duplicate.expect("duplicate reported");
+        assert_eq!(duplicate.matched_by, "url");
+
+        let skipped = service
+            .add_url_outcome("https://example.com/dup/file.bin", with_policy("skip"))
+            .await
+            .expect("skip outcome");
+        assert_eq!(skipped.action, "skipped");
+        let err = service
+            .add_url("https://example.com/dup/file.bin", with_policy("skip"))
+            .await
+            .expect_err("skip has no new task id");
+        assert!(matches!(
+            err.downcast_ref::<crate::error::AppError>(),
+            Some(crate::error::AppError::DuplicateTask(_))
+        ));
+        db.set_setting("duplicate_policy", "open")
+            .expect("set duplicate policy");
+        let opened = service
+            .add_url("https://example.com/dup/file.bin", Default::default())
+            .await
+            .expect("open returns the earlier task");
+        assert_eq!(opened, warned.task_id);
+        let again = service
+            .add_url_outcome(
+                "https://example.com/dup/file.bin",
+                with_policy("redownload"),
+            )
+            .await
+            .expect("redownload adds without looking");
+        assert_eq!(again.action, "created");
+        assert!(again.duplicate.is_none());
 
-        async fn remove_download_result(&self, _gid: &str) -> Result<String> {
-            self.call("remove_download_result");
-            Ok("ok".to_string())
-        }
+        let magnet = service
+            .add_magnet("magnet:?xt=urn:btih:ABCDEF0123&dn=one", with_policy("warn"))
+            .await
+            .expect("add magnet");
+        let outcome = service
+            .add_magnet_outcome(
+                "magnet:?xt=urn:btih:abcdef0123&tr=udp://t",
+                with_policy("skip"),
+            )
+            .await
+            .expect("skip magnet");
+        assert_eq!(
+            (outcome.action.as_str(), outcome.task_id.as_str()),
+            ("skipped", magnet.as_str())
+        );
+        assert_eq!(outcome.duplicate.expect("match").matched_by, "infohash");
+
+        db.set_task_final_url(&first, "https://cdn.example.net/file.bin")
+            .expect("set final url");
+        let by_final = db
+            .find_duplicate_task(
+                "https://example.com/latest",
+                Some("https://cdn.example.net/file.bin"),
+                None,
+            )
+            .expect("find by final url")
+            .expect("final url match");
+        assert_eq!(
+            (by_final.task_id.as_str(), by_final.matched_by.as_str()),
+            (first.as_str(), "final_url")
+        );
+        let mut task = db.get_task(&first).expect("get task").expect("task");
+        task.name = Some("file.bin".to_string());
+        task.total_length = 4096;
+        db.upsert_task(&task).expect("upsert task");
+        let by_name = db
+            .find_duplicate_task(
+                "https://other.example.org/file.bin",
+                None,
+                Some(("file.bin", 4096)),
+            )
+            .expect("find by name")
+            .expect("name and size match");
+        assert_eq!(by_name.matched_by, "name_size");
+        assert!(
+            db.find_duplicate_task(
+                "https://other.example.org/file.bin",
+                None,
+                Some(("file.bin", 1))
+            )
+            .expect("find by name")
+            .is_none()
+        );
+    }
 
-        async fn tell_status(&self, _gid: &str) -> Result<Value> {
-            self.call("tell_status");
-            Ok(json!({ "files": [] }))
-        }
+    #[tokio::test]
+    async fn batch_add_reports_each_item_with_shared_and_overridden_options() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        let result = service
+            .add_batch(crate::models::BatchAddRequest {
+                items: vec![crate::models::BatchAddItem {
+                    source: "https://example.com/batch/a.bin".to_string(),
+                    options: Some(crate::models::AddTaskOptions {
+                        split: Some(2),
+                        ..Default::default()
+                    }),
+                }],
+                urls: vec![
+                    "magnet:?xt=urn:btih:0011223344".to_string(),
+                    "not a url".to_string(),
+                ],
+                text: Some(
+                    r#"<a href="/batch/b.zip">b</a> and https://example.com/batch/a.bin"#
+                        .to_string(),
+                ),
+                source_url: Some("https://example.com/page".to_string()),
+                options: crate::models::AddTaskOptions {
+                    split: Some(8),
+                    category: Some("batch".to_string()),
+                    ..Default::default()
+                },
+            })
+            .await
+            .expect("add batch");
 
-        async fn get_peers(&self, _gid: &str) -> Result<Vec<Value>> {
-            self.call("get_peers");
-            Ok(Vec::new())
-        }
+        let sources = result
+            .items
+            .iter()
+            .map(|item| item.source.as_str())
+            .collect::<Vec<_>>();
+        assert!(sources.contains(&"https://example.com/batch/b.zip"));
+        assert_eq!(result.failed, 1);
+        let failed = result
+            .items
+            .iter()
+            .find(|item| item.error.is_some())
+            .expect("failed item");
+        assert_eq!(failed.source, "not a url");
+        assert_eq!(result.created, result.items.len() - 1);
+        // The link parsed out of `text` again is added, but reported as a duplicate.
+        assert!(result.items.iter().any(|item| {
+            item.duplicate
+                .as_ref()
+                .is_some_and(|d| d.matched_by == "url")
+        }));
+
+        let gid_of = |task_id: &str| {
+            db.get_task(task_id)
+                .expect("get task")
+                .expect("task")
+                .aria2_gid
+                .expect("gid")
+        };
+        let first = result.items[0].task_id.as_deref().expect("first task");
+        assert_eq!(
+            engine.options(&gid_of(first)).expect("options")["split"],
+            json!("2")
+        );
+        let parsed = result
+            .items
+            .iter()
+            .find(|item| item.source.ends_with("b.zip"))
+            .and_then(|item| item.task_id.as_deref())
+            .expect("parsed task");
+        assert_eq!(
+            engine.options(&gid_of(parsed)).expect("options")["split"],
+            json!("8")
+        );
+        assert_eq!(
+            db.get_task(parsed)
+                .expect("get task")
+                .expect("task")
+                .category
+                .as_deref(),
+            Some("batch")
+        );
 
-        async fn tell_all(&self) -> Result<Vec<Aria2TaskSnapshot>> {
-            self.call("tell_all");
-            Ok(self.snapshots.lock().expect("snapshots mutex").clone())
-        }
+        let err = service
+            .add_batch(Default::default())
+            .await
+            .expect_err("empty batch");
+        assert!(err.to_string().contains("batch has no urls"));
+    }
 
-        async fn change_position(&self, _gid: &str, _pos: i64, _how: &str) -> Result<i64> {
-            self.call("change_position");
-            Ok(0)
-        }
+    #[tokio::test]
+    async fn url_patterns_expand_into_tasks_or_mirrors() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        assert_eq!(
+            service
+                .preview_url_pattern("https://example.com/pat/img[09-11].jpg")
+                .expect("preview"),
+            vec![
+                "https://example.com/pat/img09.jpg",
+                "https://example.com/pat/img10.jpg",
+                "https://example.com/pat/img11.jpg",
+            ]
+        );
 
-        async fn change_option(&self, _gid: &str, _options: Value) -> Result<String> {
-            self.call("change_option");
-            Ok("ok".to_string())
-        }
+        let result = service
+            .add_batch(crate::models::BatchAddRequest {
+                urls: vec!["https://example.com/pat/img[09-11].jpg".to_string()],
+                ..Default::default()
+            })
+            .await
+            .expect("add pattern batch");
+        assert_eq!((result.created, result.failed), (3, 0));
+        assert_eq!(result.items[2].source, "https://example.com/pat/img11.jpg");
+
+        let err = service
+            .add_url("https://example.com/pat/{a,b}.iso", Default::default())
+            .await
+            .expect_err("several tasks need a batch");
+        assert!(err.to_string().contains("add it as a batch"));
+
+        let task_id = service
+            .add_url(
+                "https://{eu,us}.mirror.example.com/pat/os.iso",
+                crate::models::AddTaskOptions {
+                    url_expansion: Some("mirrors".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("add mirrored task");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.source, "https://eu.mirror.example.com/pat/os.iso");
+        assert_eq!(
+            engine.uris(&task.aria2_gid.expect("gid")).expect("uris"),
+            vec![
+                "https://eu.mirror.example.com/pat/os.iso",
+                "https://us.mirror.example.com/pat/os.iso",
+            ]
+        );
 
-        async fn change_global_option(&self, _options: Value) -> Result<String> {
-            self.call("change_global_option");
-            Ok("ok".to_string())
-        }
+        let err = service
+            .preview_url_pattern("https://example.com/[0000-9999]/[1-2].bin")
+            .expect_err("over the limit");
+        assert!(err.to_string().contains("more than 1000 urls"));
+    }
 
-        async fn get_global_stat(&self) -> Result<Value> {
-            self.call("get_global_stat");
-            Ok(json!({}))
-        }
+    #[tokio::test]
+    async fn metalink_adds_one_task_per_file_with_mirrors_and_hashes() {
+        let (service, db, engine) = build_engine_service(Arc::new(MockAria2Engine::new())).await;
+        let meta4 = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <metalink xmlns="urn:ietf:params:xml:ns:metalink">
+              <file name="release.iso">
+                <size>2048</size>
+                <hash type="sha-256">ABCDEF</hash>
+                <url location="us" priority="2">https://us.example.com/release.iso</url>
+                <url location="de" priority="1">https://de.example.com/release.iso</url>
+              </file>
+              <file name="release.iso.asc">
+                <size>512</size>
+                <url>https://us.example.com/release.iso.asc</url>
+              </file>
+            </metalink>"#;
+        let task_ids = service
+            .add_metalink_base64(STANDARD.encode(meta4), Default::default(), None)
+            .await
+            .expect("add metalink");
+        assert_eq!(task_ids.len(), 2);
+
+        let iso = db.get_task(&task_ids[0]).expect("get task").expect("task");
+        assert_eq!(iso.task_type, TaskType::Metalink);
+        assert_eq!(iso.name.as_deref(), Some("release.iso"));
+        assert_eq!(iso.total_length, 2048);
+        assert_eq!(iso.checksum_algorithm.as_deref(), Some("sha256"));
+        assert_eq!(iso.checksum_expected.as_deref(), Some("abcdef"));
+        let gid = iso.aria2_gid.expect("gid");
+        assert_eq!(engine.options(&gid).expect("options")["select-file"], "1");
+        assert_eq!(
+            engine.uris(&gid).expect("uris")[0],
+            "https://de.example.com/release.iso"
+        );
+        let mirrors = service.list_task_mirrors(&task_ids[0]).expect("mirrors");
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(mirrors[0].location.as_deref(), Some("de"));
+
+        let sig = db.get_task(&task_ids[1]).expect("get task").expect("task");
+        assert_eq!(sig.checksum_algorithm, None);
+        let gid = sig.aria2_gid.expect("gid");
+        assert_eq!(engine.options(&gid).expect("options")["select-file"], "2");
+
+        let task_ids = service
+            .add_metalink_base64(
+                STANDARD.encode(meta4.replace("release", "beta")),
+                crate::models::AddTaskOptions {
+                    checksum_algorithm: Some("sha256".to_string()),
+                    checksum_value: Some("123456".to_string()),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .expect("add metalink with checksum");
+        let iso = db.get_task(&task_ids[0]).expect("get task").expect("task");
+        assert_eq!(iso.checksum_expected.as_deref(), Some("abcdef"));
+        let sig = db.get_task(&task_ids[1]).expect("get task").expect("task");
+        assert_eq!(sig.checksum_algorithm, None);
+        assert_eq!(sig.checksum_expected, None);
 
-        async fn get_global_option(&self) -> Result<Value> {
-            self.call("get_global_option");
-            Ok(json!({}))
-        }
+        let err = service
+            .add_metalink_base64(STANDARD.encode("<html/>"), Default::default(), None)
+            .await
+            .expect_err("not a metalink");
+        assert!(err.to_string().contains("invalid metalink"));
+    }
 
-        async fn get_version(&self) -> Result<Value> {
-            self.call("get_version");
-            Ok(json!({ "version": "mock" }))
-        }
+    #[tokio::test]
+    async fn torrent_file_priorities_skip_and_stage_files() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(
+            "stagedpack",
+            MockScenario::torrent(
+                "stagedpack",
+                &[
+                    ("main.bin", 100),
+                    ("extras/big.bin", 4000),
+                    ("notes.txt", 50),
+                ],
+                100,
+            ),
+        );
+        let (service, db, engine) = build_engine_service(engine).await;
+        let torrent = STANDARD.encode(
+            "d4:infod5:filesld6:lengthi100e4:pathl8:main.bineed6:lengthi4000e4:pathl6:extras7:big.bineed6:lengthi50e4:pathl9:notes.txteee4:name10:stagedpack12:piece lengthi1024e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+        );
+        let info = service.inspect_torrent_base64(&torrent).expect("inspect");
+        assert_eq!(info.files.len(), 3);
+        assert_eq!(info.files[1].path, "extras/big.bin");
+        assert_eq!(info.total_length, 4150);
+
+        let priority = |index: usize, priority: &str| crate::models::TorrentFilePriority {
+            index,
+            priority: priority.to_string(),
+        };
+        let err = service
+            .add_torrent_base64(
+                torrent.clone(),
+                crate::models::AddTaskOptions {
+                    file_priorities: vec![priority(3, "high")],
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .expect_err("index out of range");
+        assert!(err.to_string().contains("out of range"));
 
-        async fn save_session(&self) -> Result<String> {
-            self.call("save_session");
-            Ok("ok".to_string())
-        }
+        let task_id = service
+            .add_torrent_base64(
+                torrent,
+                crate::models::AddTaskOptions {
+                    file_priorities: vec![priority(1, "skip"), priority(2, "high")],
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .expect("add torrent");
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.name.as_deref(), Some("stagedpack"));
+        let gid = task.aria2_gid.expect("gid");
+        assert_eq!(engine.options(&gid).expect("options")["select-file"], "3");
 
-        fn stderr_tail(&self) -> Option<String> {
-            None
-        }
-    }
+        service.advance_file_stages().await.expect("advance");
+        assert_eq!(db.list_task_file_stages().expect("stages").len(), 1);
 
-    fn build_service(
-        mock: Arc<MockAria2>,
-    ) -> (Arc<DownloadService>, Arc<Database>, Arc<MockAria2>) {
-        let db_path = std::env::temp_dir().join(format!("tarui-svc-{}.sqlite", Uuid::new_v4()));
-        let db = Arc::new(Database::new(&db_path).expect("create db"));
-        std::fs::create_dir_all("/tmp/tarui-tests").expect("create test download root");
-        db.set_setting("download_dir", "/tmp/tarui-tests")
-            .expect("set download_dir");
-        #[cfg(unix)]
-        db.set_setting("aria2_bin_path", "/bin/sh")
-            .expect("set aria2_bin_path");
-        #[cfg(windows)]
-        db.set_setting("aria2_bin_path", "C:\\Windows\\System32\\cmd.exe")
-            .expect("set aria2_bin_path");
-        let emitter = Arc::new(NoopEmitter) as crate::events::SharedEmitter;
-        let service = Arc::new(DownloadService::new(db.clone(), mock.clone(), emitter));
-        (service, db, mock)
+        engine.tick();
+        sync_once(&service, &engine).await;
+        service.advance_file_stages().await.expect("advance");
+        assert_eq!(engine.options(&gid).expect("options")["select-file"], "1,3");
+        assert!(db.list_task_file_stages().expect("stages").is_empty());
     }
 
     #[tokio::test]
-    async fn add_pause_remove_flow_with_mock() {
-        let mock = Arc::new(MockAria2::default());
-        let (service, _db, mock) = build_service(mock);
+    async fn metadata_only_magnet_waits_paused_for_file_selection() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(
+            "prefetchpack",
+            MockScenario::torrent(
+                "prefetchpack",
+                &[("a.bin", 100), ("extras/b.bin", 900)],
+                100,
+            ),
+        );
+        let (service, db, engine) = build_engine_service(engine).await;
+        let dir = std::env::temp_dir().join(format!("flamingo-prefetch-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let infohash = "0123456789abcdef0123456789abcdef01234567";
+        std::fs::write(
+            dir.join(format!("{infohash}.torrent")),
+            "d4:infod5:filesld6:lengthi100e4:pathl5:a.bineed6:lengthi900e4:pathl6:extras5:b.bineee4:name12:prefetchpack12:piece lengthi1024e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+        )
+        .expect("write saved torrent");
 
         let task_id = service
-            .add_url(
-                "https://example.com/file.bin",
-                crate::models::AddTaskOptions::default(),
+            .add_magnet(
+                &format!("magnet:?xt=urn:btih:{infohash}"),
+                crate::models::AddTaskOptions {
+                    save_dir: Some(dir.to_string_lossy().to_string()),
+                    metadata_only: Some(true),
+                    ..Default::default()
+                },
             )
             .await
-            .expect("add url");
-        service.pause_task(&task_id).await.expect("pause task");
+            .expect("add magnet");
+        let metadata_gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .expect("task")
+            .aria2_gid
+            .expect("gid");
+        assert_eq!(
+            engine.options(&metadata_gid).expect("options")["bt-metadata-only"],
+            "true"
+        );
+
+        engine.tick();
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Metadata);
+
+        service.finish_magnet_prefetches().await.expect("finish");
+        let (task, files) = service.get_task_detail(&task_id).await.expect("detail");
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert_eq!(task.name.as_deref(), Some("prefetchpack"));
+        assert_ne!(task.aria2_gid.as_deref(), Some(metadata_gid.as_str()));
+        assert_eq!(files.len(), 2);
+        assert!(
+            service
+                .get_task_torrent(&task_id)
+                .expect("torrent")
+                .is_some()
+        );
+        let gid = task.aria2_gid.expect("gid");
+        assert!(
+            engine
+                .options(&gid)
+                .expect("options")
+                .get("bt-metadata-only")
+                .is_none()
+        );
+
         service
-            .remove_task(&task_id, false)
+            .set_task_file_selection(&task_id, &[0])
             .await
-            .expect("remove task");
-
-        let calls = mock.calls();
-        assert!(calls.iter().any(|c| c == "add_uri"));
-        assert!(calls.iter().any(|c| c == "pause"));
-        assert!(calls.iter().any(|c| c == "remove"));
-        assert!(calls.iter().any(|c| c == "remove_download_result"));
+            .expect("select first file");
+        service.resume_task(&task_id).await.expect("resume");
+        engine.advance(2);
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&task_id).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.total_length, 100);
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[tokio::test]
-    async fn reconcile_recovers_orphan_snapshot() {
-        let mock = Arc::new(MockAria2::default());
-        mock.push_snapshot(Aria2TaskSnapshot {
-            gid: "gid-orphan".to_string(),
-            status: "active".to_string(),
-            total_length: 1024,
-            completed_length: 128,
-            download_speed: 32,
-            upload_speed: 0,
-            connections: 2,
-            error_code: None,
-            error_message: None,
-            name: Some("orphan.bin".to_string()),
-            has_metadata: true,
-            files: vec![],
-        });
-
-        let (service, _db, _mock) = build_service(mock);
-        let created = service
-            .reconcile_with_aria2()
+    async fn oversized_tracker_lists_are_rejected_before_reading() {
+        let dir = std::env::temp_dir().join(format!("flamingo-trackers-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let list = dir.join("huge.txt");
+        let file = std::fs::File::create(&list).expect("create list");
+        file.set_len(super::MAX_TRACKER_LIST_BYTES as u64 + 1)
+            .expect("grow list");
+        let list_url = reqwest::Url::from_file_path(&list).expect("file url");
+
+        let err = super::fetch_tracker_list(list_url.as_str())
             .await
-            .expect("reconcile with aria2");
-        assert_eq!(created, 1);
-
-        let tasks = service
-            .list_tasks(None, 20, 0)
-            .expect("list tasks after reconcile");
-        assert_eq!(tasks.len(), 1);
-        assert_eq!(tasks[0].status, TaskStatus::Active);
-        assert!(tasks[0].source.contains("aria2:recovered:gid-orphan"));
+            .expect_err("oversized list");
+        assert!(err.to_string().contains("larger than 2 MiB"));
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[tokio::test]
-    async fn reconcile_skips_deleted_gid_tombstone() {
-        let mock = Arc::new(MockAria2::default());
-        mock.push_snapshot(Aria2TaskSnapshot {
-            gid: "gid-deleted".to_string(),
-            status: "active".to_string(),
-            total_length: 1024,
-            completed_length: 64,
-            download_speed: 10,
-            upload_speed: 0,
-            connections: 1,
-            error_code: None,
-            error_message: None,
-            name: Some("should-not-restore.bin".to_string()),
-            has_metadata: true,
-            files: vec![],
+    async fn tracker_lists_merge_into_global_and_per_task_trackers() {
+        let responder = tokio::net::UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("bind tracker");
+        let port = responder.local_addr().expect("addr").port();
+        tokio::spawn(async move {
+            let mut buf = [0_u8; 64];
+            while let Ok((read, from)) = responder.recv_from(&mut buf).await {
+                if read == 16 {
+                    let mut reply = buf[8..16].to_vec();
+                    reply.extend([7_u8; 8]);
+                    let _ = responder.send_to(&reply, from).await;
+                }
+            }
         });
 
-        let (service, db, _mock) = build_service(mock);
-        db.mark_deleted_gid("gid-deleted", super::now_ts())
-            .expect("mark deleted gid");
+        let udp = format!("udp://127.0.0.1:{port}/announce");
+        let http = "http://127.0.0.1:1/announce".to_string();
+        let dir = std::env::temp_dir().join(format!("flamingo-trackers-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let list = dir.join("trackers.txt");
+        std::fs::write(
+            &list,
+            format!("{udp}\n\n{http}\n{udp}\nnot a tracker\nftp://files.example/announce\n"),
+        )
+        .expect("write list");
+        let list_url = reqwest::Url::from_file_path(&list)
+            .expect("file url")
+            .to_string();
+        let missing_url = reqwest::Url::from_file_path(dir.join("missing.txt"))
+            .expect("file url")
+            .to_string();
 
-        let created = service
-            .reconcile_with_aria2()
+        let infohash = "89abcdef0123456789abcdef0123456789abcdef";
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script(
+            infohash,
+            MockScenario::torrent("trackerpack", &[("a.bin", 1000)], 10),
+        );
+        let (service, db, engine) = build_engine_service(engine).await;
+        db.set_setting(
+            "tracker_lists",
+            &serde_json::to_string(&[&list_url, &missing_url]).expect("json"),
+        )
+        .expect("set lists");
+
+        let lists = service.refresh_tracker_lists().await.expect("refresh");
+        assert_eq!(lists[0].tracker_count, 2);
+        assert!(lists[0].error.is_none());
+        assert!(lists[1].error.is_some());
+        assert_eq!(
+            service.effective_trackers().expect("trackers"),
+            vec![udp.clone(), http.clone()]
+        );
+        assert_eq!(
+            engine.get_global_option().await.expect("global")["bt-tracker"],
+            format!("{udp},{http}")
+        );
+        let health = |uri: &str| db.get_tracker_health(uri).expect("health").expect("probed");
+        assert_eq!(health(&udp).status, "ok");
+        assert_eq!(health(&http).status, "unreachable");
+
+        let task_id = service
+            .add_magnet(
+                &format!("magnet:?xt=urn:btih:{infohash}"),
+                crate::models::AddTaskOptions::default(),
+            )
             .await
-            .expect("reconcile with aria2");
-        assert_eq!(created, 0);
+            .expect("add magnet");
+        let gid = db
+            .get_task(&task_id)
+            .expect("get task")
+            .expect("task")
+            .aria2_gid
+            .expect("gid");
+        let extra = "udp://extra.example:6969/announce".to_string();
+        service
+            .add_task_trackers(&task_id, std::slice::from_ref(&extra))
+            .await
+            .expect("add tracker");
+        service
+            .remove_task_trackers(&task_id, std::slice::from_ref(&http))
+            .await
+            .expect("remove tracker");
+        let options = engine.options(&gid).expect("options");
+        assert_eq!(options["bt-tracker"], format!("{udp},{extra}"));
+        assert_eq!(options["bt-exclude-tracker"], http);
+        assert!(
+            service
+                .add_task_trackers(&task_id, &["ftp://files.example/announce".to_string()])
+                .await
+                .is_err()
+        );
 
-        let tasks = service
-            .list_tasks(None, 20, 0)
-            .expect("list tasks after reconcile");
-        assert!(tasks.is_empty());
+        engine.tick();
+        let status = service
+            .get_task_runtime_status(&task_id)
+            .await
+            .expect("runtime status");
+        let summary = &status["summary"];
+        let trackers = summary["trackers"].as_array().expect("trackers");
+        assert!(trackers.contains(&json!(udp)) && trackers.contains(&json!(extra)));
+        assert!(!trackers.contains(&json!(http)));
+        let health = summary["trackers_health"].as_array().expect("health");
+        let status_of = |uri: &str| {
+            health
+                .iter()
+                .find(|h| h["uri"] == uri)
+                .map(|h| h["status"].clone())
+        };
+        assert_eq!(status_of(&udp), Some(json!("ok")));
+        assert_eq!(status_of(&extra), Some(json!("unknown")));
+        assert_eq!(summary["trackers_removed"], json!([http]));
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[tokio::test]
-    async fn remove_with_delete_files_rejects_outside_download_root() {
-        let mock = Arc::new(MockAria2::default());
-        let (service, db, _mock) = build_service(mock);
-        let task_id = Uuid::new_v4().to_string();
-        let now = super::now_ts();
-        db.upsert_task(&Task {
-            id: task_id.clone(),
-            aria2_gid: Some("gid-outside".to_string()),
-            task_type: TaskType::Http,
-            source: "https://example.com/a.bin".to_string(),
-            status: TaskStatus::Completed,
-            name: Some("a.bin".to_string()),
-            category: None,
-            save_dir: "/tmp/tarui-tests".to_string(),
-            total_length: 10,
-            completed_length: 10,
-            download_speed: 0,
-            upload_speed: 0,
-            connections: 0,
-            health: Some(TaskHealth::Normal.as_str().to_string()),
-            error_code: None,
-            error_message: None,
-            remediation: None,
-            retry_count: 0,
-            last_retry_at: None,
-            checksum_algorithm: None,
-            checksum_expected: None,
-            checksum_actual: None,
-            checksum_status: None,
-            created_at: now,
-            updated_at: now,
-        })
-        .expect("upsert task");
-        db.replace_task_files(
-            &task_id,
-            &[crate::models::TaskFile {
-                task_id: task_id.clone(),
-                path: "/tmp/outside-danger.bin".to_string(),
-                length: 10,
-                completed_length: 10,
-                selected: true,
-            }],
-        )
-        .expect("replace files");
+    async fn seeding_policy_limits_seeds_and_stops_at_ratio() {
+        let engine = Arc::new(MockAria2Engine::new());
+        for (name, upload) in [("seedfast", 600), ("seedslow", 100)] {
+            engine.script(
+                name,
+                MockScenario::torrent(name, &[("a.bin", 1000)], 1000)
+                    .with_upload(upload)
+                    .seeding(),
+            );
+        }
+        let (service, db, engine) = build_engine_service(engine).await;
+        db.set_setting("seeding_rules", r#"[{"ratio":1.5}]"#)
+            .expect("set seeding rules");
+        db.set_setting("max_active_seeds", "1")
+            .expect("set max seeds");
+        let mut ids = Vec::new();
+        for (name, hash) in [("seedfast", "aa"), ("seedslow", "bb")] {
+            ids.push(
+                service
+                    .add_magnet(
+                        &format!("magnet:?xt=urn:btih:{}&dn={name}", hash.repeat(20)),
+                        crate::models::AddTaskOptions::default(),
+                    )
+                    .await
+                    .expect("add magnet"),
+            );
+        }
+        let (fast, slow) = (ids[0].clone(), ids[1].clone());
+        let seeding = |task_id: &str| {
+            db.get_task_seeding(task_id)
+                .expect("get seeding")
+                .expect("seeding row")
+        };
 
-        let err = service
-            .remove_task(&task_id, true)
+        engine.tick();
+        sync_once(&service, &engine).await;
+        assert_eq!(seeding(&fast).uploaded_length, 600);
+        assert!(seeding(&slow).seeding_since.is_some());
+
+        // Only one seed may run; the one closest to its ratio gives way.
+        service.enforce_seeding_policy().await.expect("enforce");
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&fast).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert!(seeding(&fast).held);
+
+        engine.advance(14);
+        sync_once(&service, &engine).await;
+        service.enforce_seeding_policy().await.expect("enforce");
+        sync_once(&service, &engine).await;
+        let task = db.get_task(&slow).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(seeding(&slow).stopped_reason.as_deref(), Some("ratio"));
+        assert_eq!(seeding(&slow).uploaded_length, 1500);
+        let task = db.get_task(&fast).expect("get task").expect("task");
+        assert_eq!(task.status, TaskStatus::Active);
+        assert!(!seeding(&fast).held);
+
+        // A new gid, e.g. after a restart, adds to the total instead of replacing it.
+        db.record_task_upload(&fast, "gid-after-restart", 50, true, super::now_ts())
+            .expect("record upload");
+        assert_eq!(seeding(&fast).uploaded_length, 650);
+    }
+
+    #[tokio::test]
+    async fn transfer_history_sums_bytes_per_bucket_and_domain() {
+        let engine = Arc::new(MockAria2Engine::new());
+        engine.script("A.example", MockScenario::http(3000, 1000));
+        engine.script("b.example", MockScenario::http(500, 500));
+        let (service, db, engine) = build_engine_service(engine).await;
+        let first = service
+            .add_url(
+                "https://A.example/big.iso",
+                crate::models::AddTaskOptions {
+                    category: Some("isos".to_string()),
+                    ..Default::default()
+                },
+            )
             .await
-            .expect_err("remove should reject outside path");
-        let msg = err.to_string();
-        assert!(msg.contains("outside download root"));
+            .expect("add first");
+        service
+            .add_url(
+                "https://b.example/small.bin",
+                crate::models::AddTaskOptions::default(),
+            )
+            .await
+            .expect("add second");
+        for _ in 0..3 {
+            engine.tick();
+            sync_once(&service, &engine).await;
+        }
+
+        let totals = service
+            .get_bandwidth_history(crate::models::BandwidthHistoryQuery::default())
+            .expect("day totals");
+        assert_eq!(totals.len(), 1);
+        assert_eq!((totals[0].downloaded, totals[0].uploaded), (3500, 0));
+        let by_domain = service
+            .get_bandwidth_history(crate::models::BandwidthHistoryQuery {
+                resolution: Some("minute".to_string()),
+                group_by: Some("domain".to_string()),
+                ..Default::default()
+            })
+            .expect("by domain");
+        let domain_total = |domain: &str| {
+            by_domain
+                .iter()
+                .filter(|s| s.key.as_deref() == Some(domain))
+                .map(|s| s.downloaded)
+                .sum::<i64>()
+        };
+        assert_eq!(domain_total("a.example"), 3000);
+        assert_eq!(domain_total("b.example"), 500);
+        let isos = service
+            .get_bandwidth_history(crate::models::BandwidthHistoryQuery {
+                resolution: Some("month".to_string()),
+                category: Some("isos".to_string()),
+                group_by: Some("task".to_string()),
+                ..Default::default()
+            })
+            .expect("monthly isos");
+        assert_eq!(isos.len(), 1);
+        assert_eq!(isos[0].key.as_deref(), Some(first.as_str()));
+        assert_eq!(isos[0].bucket_start % 86_400, 0);
         assert!(
-            db.get_task(&task_id).expect("get task").is_some(),
-            "task record should remain when file delete is rejected"
+            service
+                .get_bandwidth_history(crate::models::BandwidthHistoryQuery {
+                    resolution: Some("week".to_string()),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+
+        // History outlives the task, and retention drops whole resolutions.
+        service.remove_task(&first, false).await.expect("remove");
+        assert_eq!(
+            db.prune_transfer_history("minute", super::now_ts() + 60)
+                .expect("prune"),
+            2
         );
+        let totals = service
+            .get_bandwidth_history(crate::models::BandwidthHistoryQuery::default())
+            .expect("day totals");
+        assert_eq!(totals[0].downloaded, 3500);
     }
 }
 
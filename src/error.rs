@@ -6,4 +6,10 @@ pub enum AppError {
     InvalidInput(String),
     #[error("task not found: {0}")]
     TaskNotFound(String),
+    #[error("file already exists: {0}")]
+    FileExists(String),
+    #[error("identical file already downloaded, skipped: {0}")]
+    AlreadyDownloaded(String),
+    #[error("duplicate of existing task, skipped: {0}")]
+    DuplicateTask(String),
 }
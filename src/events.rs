@@ -1,11 +1,48 @@
 use std::sync::Arc;
 
 use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::broadcast;
 
-use crate::models::Task;
+use crate::models::{OperationLog, Task, TaskStatus};
+
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServiceEvent {
+    TaskUpdate { tasks: Vec<Task> },
+    TaskCompleted { task: Task },
+    TaskFailed { task: Task },
+    OperationLog { entry: OperationLog },
+}
+
+impl ServiceEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TaskUpdate { .. } => "task_update",
+            Self::TaskCompleted { .. } => "task_completed",
+            Self::TaskFailed { .. } => "task_failed",
+            Self::OperationLog { .. } => "operation_log",
+        }
+    }
+}
 
 pub trait EventEmitter: Send + Sync {
     fn emit_task_update(&self, tasks: &[Task]) -> Result<()>;
+
+    /// Called once when a task transitions into `Completed` or `Error`.
+    fn emit_task_finished(&self, _task: &Task) -> Result<()> {
+        Ok(())
+    }
+
+    fn emit_operation_log(&self, _entry: &OperationLog) -> Result<()> {
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ServiceEvent>> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -22,4 +59,65 @@ impl EventEmitter for StdoutEventEmitter {
     }
 }
 
+#[derive(Default)]
+pub struct NullEventEmitter;
+
+impl EventEmitter for NullEventEmitter {
+    fn emit_task_update(&self, _tasks: &[Task]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Fans every event out to in-process subscribers (e.g. the SSE endpoint)
+/// and forwards it to the wrapped shell emitter.
+pub struct BroadcastEventEmitter {
+    sender: broadcast::Sender<ServiceEvent>,
+    inner: SharedEmitter,
+}
+
+impl BroadcastEventEmitter {
+    pub fn new(inner: SharedEmitter) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { sender, inner }
+    }
+
+    fn publish(&self, event: ServiceEvent) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(event);
+        }
+    }
+}
+
+impl EventEmitter for BroadcastEventEmitter {
+    fn emit_task_update(&self, tasks: &[Task]) -> Result<()> {
+        if !tasks.is_empty() {
+            self.publish(ServiceEvent::TaskUpdate {
+                tasks: tasks.to_vec(),
+            });
+        }
+        self.inner.emit_task_update(tasks)
+    }
+
+    fn emit_task_finished(&self, task: &Task) -> Result<()> {
+        let task_owned = task.clone();
+        self.publish(if task.status == TaskStatus::Error {
+            ServiceEvent::TaskFailed { task: task_owned }
+        } else {
+            ServiceEvent::TaskCompleted { task: task_owned }
+        });
+        self.inner.emit_task_finished(task)
+    }
+
+    fn emit_operation_log(&self, entry: &OperationLog) -> Result<()> {
+        self.publish(ServiceEvent::OperationLog {
+            entry: entry.clone(),
+        });
+        self.inner.emit_operation_log(entry)
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<ServiceEvent>> {
+        Some(self.sender.subscribe())
+    }
+}
+
 pub type SharedEmitter = Arc<dyn EventEmitter>;
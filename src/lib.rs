@@ -1,35 +1,80 @@
 pub mod aria2_manager;
 pub mod browser_bridge;
+pub mod cli;
 pub mod commands;
 pub mod db;
 pub mod download_service;
 pub mod error;
 pub mod events;
 pub mod link_parser;
+pub mod metalink;
+pub mod mock_aria2;
 pub mod models;
+pub mod native_engine;
+pub mod path_template;
+pub mod torrent;
 
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Result;
-use aria2_manager::{Aria2Manager, Aria2RuntimeConfig};
+use aria2_manager::{Aria2Api, Aria2Manager, Aria2RuntimeConfig, ExternalAria2Config};
 use browser_bridge::{BrowserBridgeConfig, start_browser_bridge};
 use db::Database;
 use download_service::DownloadService;
-use events::SharedEmitter;
+use events::{BroadcastEventEmitter, SharedEmitter};
+use mock_aria2::MockAria2Engine;
+use native_engine::NativeHttpEngine;
 
 pub struct BackendHandles {
     pub service: Arc<DownloadService>,
     pub aria2: Arc<Aria2Manager>,
+    /// The engine the service drives: `aria2`, or the simulated one in demo mode.
+    pub engine: Arc<dyn Aria2Api>,
     pub config: Aria2RuntimeConfig,
 }
 
+impl BackendHandles {
+    pub async fn shutdown(&self, reason: &str) -> Result<()> {
+        let prepared = self.service.prepare_shutdown(reason).await;
+        self.service.shutdown_engines().await;
+        self.aria2.shutdown().await?;
+        let _ = self.service.flush_pending_logs();
+        prepared
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackendOptions {
+    pub autostart_aria2: bool,
+    pub start_browser_bridge: bool,
+}
+
+impl Default for BackendOptions {
+    fn default() -> Self {
+        Self {
+            autostart_aria2: true,
+            start_browser_bridge: true,
+        }
+    }
+}
+
 pub async fn init_backend(
     base_dir: &Path,
     db_path: &Path,
     emitter: SharedEmitter,
+) -> Result<BackendHandles> {
+    init_backend_with_options(base_dir, db_path, emitter, BackendOptions::default()).await
+}
+
+pub async fn init_backend_with_options(
+    base_dir: &Path,
+    db_path: &Path,
+    emitter: SharedEmitter,
+    options: BackendOptions,
 ) -> Result<BackendHandles> {
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent)?;
@@ -74,6 +119,11 @@ pub async fn init_backend(
     if let Some(trackers) = &aria2_cfg.bt_tracker {
         db.set_setting_if_absent("bt_tracker", trackers)?;
     }
+    db.set_setting_if_absent("engine_mode", "managed")?;
+    db.set_setting_if_absent("aria2_rpc_url", "")?;
+    db.set_setting_if_absent("aria2_rpc_secret", "")?;
+    db.set_setting_if_absent("engine_profiles", "[]")?;
+    db.set_setting_if_absent("task_queues", "[]")?;
     db.set_setting_if_absent("github_cdn", "")?;
     db.set_setting_if_absent("github_token", "")?;
     db.set_setting_if_absent("download_dir_rules", "[]")?;
@@ -94,6 +144,14 @@ pub async fn init_backend(
     db.set_setting_if_absent("metadata_timeout_secs", "180")?;
     db.set_setting_if_absent("speed_plan", "[]")?;
     db.set_setting_if_absent("task_option_presets", "[]")?;
+    db.set_setting_if_absent("collision_policy", "rename")?;
+    db.set_setting_if_absent("duplicate_policy", "warn")?;
+    db.set_setting_if_absent("tracker_lists", "[]")?;
+    db.set_setting_if_absent("tracker_refresh_hours", "24")?;
+    db.set_setting_if_absent("seeding_rules", "[]")?;
+    db.set_setting_if_absent("max_active_seeds", "0")?;
+    db.set_setting_if_absent("pause_seeding_in_speed_plan", "false")?;
+    db.set_setting_if_absent("transfer_history_days", "400")?;
     db.set_setting_if_absent("post_complete_action", "none")?;
     db.set_setting_if_absent("completion_webhook_url", "")?;
     db.set_setting_if_absent("completion_command", "")?;
@@ -132,15 +190,54 @@ pub async fn init_backend(
     };
     db.validate_runtime_settings()?;
     let aria2 = Arc::new(Aria2Manager::new(aria2_cfg.clone()));
-    let service = Arc::new(DownloadService::new(db.clone(), aria2.clone(), emitter));
+    let external = ExternalAria2Config::from_settings(&db.load_global_settings()?);
+    let engine_hint = if external.is_some() {
+        "Please verify the external aria2 RPC URL and secret in Settings."
+    } else {
+        "Please verify aria2 path in Settings."
+    };
+    aria2.set_external(external);
+    let engine_mode = db.get_setting("engine_mode")?.unwrap_or_default();
+    let engine: Arc<dyn Aria2Api> = match engine_mode.trim() {
+        "demo" => {
+            let mock = Arc::new(MockAria2Engine::demo());
+            mock.start_ticker(Duration::from_millis(1000));
+            mock
+        }
+        "native" => Arc::new(NativeHttpEngine::new(&aria2_cfg.work_dir)),
+        _ => aria2.clone(),
+    };
+    let emitter: SharedEmitter = Arc::new(BroadcastEventEmitter::new(emitter));
+    let service = Arc::new(DownloadService::new(db.clone(), engine.clone(), emitter));
     service.append_operation_log(
         "aria2_path_resolved",
         format!("aria2_bin_path={}", aria2_cfg.aria2_bin.to_string_lossy()),
     );
 
+    service.sync_engine_profiles().await?;
     service.clone().start_sync_loop();
     service.clone().start_log_flush_loop();
-    if aria2_cfg.aria2_bin.exists() {
+    if engine.in_process_mode() == Some("native") {
+        engine.start().await?;
+        let _ = service.apply_saved_runtime_global_options().await;
+        let recovered = service.reconcile_with_aria2().await.unwrap_or(0);
+        let _ = service.set_startup_notice(
+            "info",
+            &format!(
+                "Native HTTP engine ready (no aria2 needed; torrents and magnets are unavailable), recovered {recovered} task(s)"
+            ),
+        );
+    } else if engine.in_process_mode() == Some("demo") {
+        let recovered = service.reconcile_with_aria2().await.unwrap_or(0);
+        let _ = service.set_startup_notice(
+            "info",
+            &format!(
+                "Demo mode: downloads are simulated and nothing touches the network ({recovered} sample task(s))"
+            ),
+        );
+    } else if !options.autostart_aria2 {
+        // aria2 is started lazily by the first service call that needs it.
+    } else if aria2.external_config().is_some() || aria2_cfg.aria2_bin.exists() {
         let aria2_bg = aria2.clone();
         let service_bg = service.clone();
         tokio::spawn(async move {
@@ -171,9 +268,7 @@ pub async fn init_backend(
                 Err(e) => {
                     let _ = service_bg.set_startup_notice(
                         "warning",
-                        &format!(
-                            "Startup check failed: {e}. Please verify aria2 path in Settings."
-                        ),
+                        &format!("Startup check failed: {e}. {engine_hint}"),
                     );
                 }
             }
@@ -181,7 +276,7 @@ pub async fn init_backend(
     } else {
         let _ = service.set_startup_notice(
             "warning",
-            "aria2 binary not found (bundled/system PATH). Please set a valid aria2 path in Settings.",
+            "aria2 binary not found (bundled/system PATH). Please set a valid aria2 path in Settings, or set engine_mode=native to download HTTP(S) links without aria2.",
         );
     }
 
@@ -196,7 +291,7 @@ pub async fn init_backend(
     start_browser_bridge(
         service.clone(),
         BrowserBridgeConfig {
-            enabled: bridge_enabled,
+            enabled: bridge_enabled && options.start_browser_bridge,
             port: bridge_port,
             token: bridge_token,
         },
@@ -205,6 +300,7 @@ pub async fn init_backend(
     Ok(BackendHandles {
         service,
         aria2,
+        engine,
         config: aria2_cfg,
     })
 }
@@ -345,3 +441,51 @@ fn detect_default_ffmpeg_bin() -> String {
 
     "ffmpeg".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::NullEventEmitter, models::EngineProfile};
+
+    #[tokio::test]
+    async fn shutdown_stops_in_process_and_profile_engines() {
+        let base_dir = std::env::temp_dir().join(format!("tauri-backend-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&base_dir).expect("create base dir");
+        let db = Arc::new(Database::new(base_dir.join("tasks.sqlite")).expect("create db"));
+        let primary = Arc::new(MockAria2Engine::new());
+        let extra = Arc::new(MockAria2Engine::new());
+        primary.start().await.expect("start primary");
+        extra.start().await.expect("start extra");
+        let config = Aria2RuntimeConfig::with_defaults(&base_dir);
+        let service = Arc::new(DownloadService::new(
+            db,
+            primary.clone(),
+            Arc::new(NullEventEmitter),
+        ));
+        service.register_engine(
+            EngineProfile {
+                id: "nas".to_string(),
+                name: "NAS".to_string(),
+                rpc_url: "http://127.0.0.1:6800/jsonrpc".to_string(),
+                secret: String::new(),
+                download_dir: None,
+            },
+            extra.clone(),
+        );
+        let handles = BackendHandles {
+            service,
+            aria2: Arc::new(Aria2Manager::new(config.clone())),
+            engine: primary.clone(),
+            config,
+        };
+
+        handles.shutdown("test").await.expect("shutdown");
+
+        for engine in [&primary, &extra] {
+            assert!(engine.endpoint().await.is_none());
+            let calls = engine.calls();
+            assert!(calls.iter().any(|c| c == "stop"), "{calls:?}");
+        }
+        let _ = std::fs::remove_dir_all(&base_dir);
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use anyhow::{Result, anyhow};
 use regex::Regex;
 
 use crate::models::{LinkCandidate, LinkParseInput, LinkParseResult};
@@ -135,6 +136,46 @@ fn normalize_candidate_url(raw: &str, base_url: Option<&reqwest::Url>) -> Option
     base_url.and_then(|base| base.join(&value).ok().map(|u| u.to_string()))
 }
 
+/// The key two sources share when they fetch the same thing; only URLs and
+/// magnets have one.
+pub fn source_dedupe_key(source: &str) -> Option<String> {
+    let source = source.trim();
+    (source.starts_with("magnet:?")
+        || source.starts_with("http://")
+        || source.starts_with("https://"))
+    .then(|| dedupe_key(source))
+}
+
+/// The magnet's v1 infohash as 40 hex digits, which is also the name aria2
+/// saves fetched metadata under; base32 infohashes are converted.
+pub fn magnet_infohash_hex(magnet: &str) -> Option<String> {
+    let hash = extract_magnet_infohash(magnet)?;
+    if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(hash);
+    }
+    if hash.len() != 32 {
+        return None;
+    }
+    let mut bits = 0u64;
+    let mut width = 0;
+    let mut bytes = Vec::with_capacity(20);
+    for c in hash.chars() {
+        let value = match c {
+            'a'..='z' => c as u64 - 'a' as u64,
+            '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | value;
+        width += 5;
+        if width >= 8 {
+            width -= 8;
+            bytes.push((bits >> width) as u8);
+            bits &= (1 << width) - 1;
+        }
+    }
+    Some(hex::encode(bytes))
+}
+
 fn dedupe_key(url: &str) -> String {
     if url.starts_with("magnet:?") {
         return extract_magnet_infohash(url)
@@ -149,6 +190,166 @@ fn dedupe_key(url: &str) -> String {
         .unwrap_or_else(|_| url.to_string())
 }
 
+/// Upper bound on the URLs one pattern may expand to.
+pub const MAX_EXPANDED_URLS: usize = 1000;
+
+/// Whether `url` holds a `[001-250]`, `[a-z]` or `[0-100:5]` range or a
+/// `{a,b,c}` alternative. Other brackets, such as an IPv6 host, are literal.
+pub fn is_url_pattern(url: &str) -> bool {
+    pattern_pieces(url).iter().any(|piece| piece.len() != 1)
+}
+
+/// Expands every range and alternative in `url`, left to right; errors when
+/// that would make more than `limit` URLs.
+pub fn expand_url_pattern(url: &str, limit: usize) -> Result<Vec<String>> {
+    let pieces = pattern_pieces(url);
+    let count = pieces
+        .iter()
+        .try_fold(1_usize, |acc, piece| acc.checked_mul(piece.len()))
+        .filter(|count| *count <= limit)
+        .ok_or_else(|| anyhow!("url pattern expands to more than {limit} urls: {url}"))?;
+    let mut urls = Vec::with_capacity(count);
+    urls.push(String::new());
+    for piece in pieces {
+        let values = piece.values();
+        urls = urls
+            .iter()
+            .flat_map(|prefix| values.iter().map(move |v| format!("{prefix}{v}")))
+            .collect();
+    }
+    Ok(urls)
+}
+
+/// One part of a URL pattern. Ranges are kept as bounds so they can be
+/// counted without building them.
+enum PatternPiece {
+    Literal(String),
+    Numbers {
+        from: u64,
+        to: u64,
+        step: usize,
+        width: usize,
+    },
+    Letters {
+        from: char,
+        to: char,
+        step: usize,
+    },
+    Alternatives(Vec<String>),
+}
+
+impl PatternPiece {
+    /// How many values the piece stands for, saturating at `usize::MAX`.
+    fn len(&self) -> usize {
+        match self {
+            Self::Literal(_) => 1,
+            Self::Numbers { from, to, step, .. } => usize::try_from((to - from) / *step as u64)
+                .ok()
+                .and_then(|n| n.checked_add(1))
+                .unwrap_or(usize::MAX),
+            Self::Letters { from, to, step } => (*to as usize - *from as usize) / step + 1,
+            Self::Alternatives(values) => values.len(),
+        }
+    }
+
+    fn values(&self) -> Vec<String> {
+        match self {
+            Self::Literal(value) => vec![value.clone()],
+            Self::Numbers {
+                from,
+                to,
+                step,
+                width,
+            } => (*from..=*to)
+                .step_by(*step)
+                .map(|v| format!("{v:0width$}"))
+                .collect(),
+            Self::Letters { from, to, step } => {
+                (*from..=*to).step_by(*step).map(String::from).collect()
+            }
+            Self::Alternatives(values) => values.clone(),
+        }
+    }
+}
+
+fn pattern_pieces(url: &str) -> Vec<PatternPiece> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut rest = url;
+    while let Some(open) = rest.find(['[', '{']) {
+        let close = if rest[open..].starts_with('[') {
+            ']'
+        } else {
+            '}'
+        };
+        let parsed = rest[open + 1..].find(close).and_then(|end| {
+            let body = &rest[open + 1..open + 1 + end];
+            let piece = if close == ']' {
+                parse_range(body)
+            } else {
+                parse_alternatives(body)
+            };
+            piece.map(|piece| (piece, open + end + 2))
+        });
+        match parsed {
+            Some((piece, next)) => {
+                literal.push_str(&rest[..open]);
+                pieces.push(PatternPiece::Literal(std::mem::take(&mut literal)));
+                pieces.push(piece);
+                rest = &rest[next..];
+            }
+            None => {
+                literal.push_str(&rest[..=open]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    literal.push_str(rest);
+    pieces.push(PatternPiece::Literal(literal));
+    pieces
+}
+
+/// `001-250` keeps the start's zero padding; `a-z` and `A-Z` count letters;
+/// `:n` steps by n.
+fn parse_range(body: &str) -> Option<PatternPiece> {
+    let (range, step) = match body.split_once(':') {
+        Some((range, step)) => (range, step.parse::<usize>().ok().filter(|v| *v > 0)?),
+        None => (body, 1),
+    };
+    let (start, end) = range.split_once('-')?;
+    if let (Ok(from), Ok(to)) = (start.parse::<u64>(), end.parse::<u64>()) {
+        let width = if start.starts_with('0') {
+            start.len()
+        } else {
+            0
+        };
+        return (from <= to && start.chars().all(|c| c.is_ascii_digit())).then_some(
+            PatternPiece::Numbers {
+                from,
+                to,
+                step,
+                width,
+            },
+        );
+    }
+    let (mut from, mut to) = (start.chars(), end.chars());
+    match (from.next(), from.next(), to.next(), to.next()) {
+        (Some(from), None, Some(to), None)
+            if from <= to
+                && (from.is_ascii_lowercase() && to.is_ascii_lowercase()
+                    || from.is_ascii_uppercase() && to.is_ascii_uppercase()) =>
+        {
+            Some(PatternPiece::Letters { from, to, step })
+        }
+        _ => None,
+    }
+}
+
+fn parse_alternatives(body: &str) -> Option<PatternPiece> {
+    body.contains(',')
+        .then(|| PatternPiece::Alternatives(body.split(',').map(ToString::to_string).collect()))
+}
+
 fn extract_magnet_infohash(url: &str) -> Option<String> {
     url.split('&').find_map(|part| {
         part.strip_prefix("magnet:?xt=urn:btih:")
@@ -342,4 +543,62 @@ mod tests {
         let segment = score_candidate("https://cdn.example.com/seg-00001.ts", "http", false);
         assert!(manifest > segment);
     }
+
+    #[test]
+    fn expands_ranges_and_alternatives_but_not_ipv6_hosts() {
+        let urls = expand_url_pattern("https://host/img[008-011].jpg", 100).expect("expand");
+        assert_eq!(
+            urls,
+            vec![
+                "https://host/img008.jpg",
+                "https://host/img009.jpg",
+                "https://host/img010.jpg",
+                "https://host/img011.jpg",
+            ]
+        );
+        let urls = expand_url_pattern("https://{a,b}.host/[x-z]/[0-10:5]", 100).expect("expand");
+        assert_eq!(urls.len(), 2 * 3 * 3);
+        assert_eq!(urls[0], "https://a.host/x/0");
+        assert_eq!(urls[17], "https://b.host/z/10");
+
+        assert!(!is_url_pattern("http://[::1]:8080/file[1].bin"));
+        assert!(!is_url_pattern("https://host/{name}/x"));
+        assert!(is_url_pattern("http://[::1]/part[1-3].bin"));
+        assert_eq!(
+            expand_url_pattern("http://[::1]/part[1-3].bin", 10).expect("expand")[2],
+            "http://[::1]/part3.bin"
+        );
+
+        let err = expand_url_pattern("https://host/[1-100]/[1-100]", MAX_EXPANDED_URLS)
+            .expect_err("too many urls");
+        assert!(err.to_string().contains("more than 1000 urls"));
+    }
+
+    #[test]
+    fn huge_ranges_are_counted_without_being_built() {
+        let url = "http://h/[0-18446744073709551615]";
+        assert!(is_url_pattern(url));
+        let err = expand_url_pattern(url, MAX_EXPANDED_URLS).expect_err("too many urls");
+        assert!(err.to_string().contains("more than 1000 urls"));
+        let stepped =
+            expand_url_pattern("http://h/[0-18446744073709551615:9223372036854775807]", 10)
+                .expect("expand");
+        assert_eq!(stepped.len(), 3);
+        assert!(expand_url_pattern("http://h/[1-4294967296]/[1-4294967296]", usize::MAX).is_err());
+    }
+
+    #[test]
+    fn magnet_infohash_hex_accepts_hex_and_base32() {
+        let hex = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(
+            magnet_infohash_hex(&format!("magnet:?xt=urn:btih:{}", hex.to_uppercase())).as_deref(),
+            Some(hex)
+        );
+        assert_eq!(
+            magnet_infohash_hex("magnet:?xt=urn:btih:AERUKZ4JVPG66AJDIVTYTK6N54ASGRLH&dn=x")
+                .as_deref(),
+            Some(hex)
+        );
+        assert_eq!(magnet_infohash_hex("magnet:?xt=urn:btih:nothex"), None);
+    }
 }
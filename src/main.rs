@@ -1,18 +1,75 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use anyhow::Result;
-use flamingo_downloader::{events::StdoutEventEmitter, init_backend};
+use anyhow::{Result, anyhow};
+use flamingo_downloader::{
+    events::{NullEventEmitter, SharedEmitter, StdoutEventEmitter},
+    init_backend,
+};
+
+const USAGE: &str = "\
+Usage: flamingo_downloader [--daemon] [--data-dir <dir>] [--print-events]
+
+  --daemon          Keep the backend running until SIGINT/SIGTERM
+  --data-dir <dir>  Data dir holding runtime/app.db (default: $FLAMINGO_DATA_DIR or current dir)
+  --print-events    Print task_update events to stdout";
+
+struct MainArgs {
+    daemon: bool,
+    data_dir: Option<PathBuf>,
+    print_events: bool,
+}
+
+fn parse_main_args() -> Result<MainArgs> {
+    let mut parsed = MainArgs {
+        daemon: false,
+        data_dir: None,
+        print_events: false,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--daemon" => parsed.daemon = true,
+            "--print-events" => parsed.print_events = true,
+            "--data-dir" => {
+                let dir = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("missing value for --data-dir"))?;
+                parsed.data_dir = Some(PathBuf::from(dir));
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            other => return Err(anyhow!("unknown argument: {other}\n\n{USAGE}")),
+        }
+    }
+    Ok(parsed)
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cwd = std::env::current_dir()?;
-    let runtime_dir = cwd.join("runtime");
+    let args = parse_main_args()?;
+    let base_dir = match args.data_dir {
+        Some(dir) => dir,
+        None => match std::env::var_os("FLAMINGO_DATA_DIR").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::current_dir()?,
+        },
+    };
+    let runtime_dir = base_dir.join("runtime");
     std::fs::create_dir_all(&runtime_dir)?;
 
-    let emitter = Arc::new(StdoutEventEmitter) as flamingo_downloader::events::SharedEmitter;
-    let handles = init_backend(&cwd, &runtime_dir.join("app.db"), emitter).await?;
+    // Daemons run under a journal; per-second task snapshots would drown it.
+    let emitter: SharedEmitter = if args.print_events || !args.daemon {
+        Arc::new(StdoutEventEmitter)
+    } else {
+        Arc::new(NullEventEmitter)
+    };
+    let handles = init_backend(&base_dir, &runtime_dir.join("app.db"), emitter).await?;
 
-    if handles.config.aria2_bin.exists() {
+    if let Some(mode) = handles.engine.in_process_mode() {
+        println!("{mode} engine running in-process");
+    } else if handles.config.aria2_bin.exists() {
         println!("aria2 service started");
     } else {
         println!(
@@ -22,5 +79,33 @@ async fn main() -> Result<()> {
     }
 
     println!("backend core initialized");
+    if !args.daemon {
+        return Ok(());
+    }
+
+    println!("running as daemon, data dir {}", base_dir.display());
+    let reason = wait_for_shutdown_signal().await?;
+    println!("received {reason}, shutting down");
+    if let Err(e) = handles.shutdown(reason).await {
+        eprintln!("shutdown finished with error: {e:#}");
+    }
+    println!("backend stopped");
     Ok(())
 }
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> Result<&'static str> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        r = tokio::signal::ctrl_c() => r.map(|_| "SIGINT").map_err(Into::into),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("Ctrl-C")
+}
@@ -0,0 +1,169 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+use crate::models::TaskMirror;
+
+/// One `<file>` of a Metalink 3 (`.metalink`) or 4 (`.meta4`) document.
+#[derive(Debug, Clone, Default)]
+pub struct MetalinkFile {
+    pub name: String,
+    pub size: Option<i64>,
+    /// `(type, value)` for whole-file hashes, e.g. `("sha-256", "ab12..")`.
+    pub hashes: Vec<(String, String)>,
+    pub piece_hash_type: Option<String>,
+    pub piece_length: Option<i64>,
+    pub piece_count: usize,
+    pub mirrors: Vec<TaskMirror>,
+}
+
+impl MetalinkFile {
+    /// The strongest whole-file hash we can verify: sha-256, then sha-1, then md5.
+    pub fn best_hash(&self) -> Option<(&'static str, &str)> {
+        ["sha256", "sha1", "md5"].into_iter().find_map(|wanted| {
+            self.hashes
+                .iter()
+                .find(|(kind, _)| kind.to_ascii_lowercase().replace('-', "") == wanted)
+                .map(|(_, value)| (wanted, value.as_str()))
+        })
+    }
+}
+
+/// Reads the files, sizes, hashes and mirror URLs out of a Metalink document;
+/// aria2 does the actual download and piece verification.
+pub fn parse_metalink(xml: &str) -> Result<Vec<MetalinkFile>> {
+    if !xml.contains("<metalink") {
+        return Err(anyhow!("not a metalink document"));
+    }
+    let file_re = Regex::new(r#"(?s)<file\b([^>]*)>(.*?)</file>"#)?;
+    let pieces_re = Regex::new(r#"(?s)<pieces\b([^>]*)>(.*?)</pieces>"#)?;
+    let hash_re = Regex::new(r#"(?s)<hash\b([^>]*)>([^<]*)</hash>"#)?;
+    let url_re = Regex::new(r#"(?s)<url\b([^>]*)>([^<]*)</url>"#)?;
+    let size_re = Regex::new(r#"<size>\s*(\d+)\s*</size>"#)?;
+
+    let mut files = Vec::new();
+    for file in file_re.captures_iter(xml) {
+        let name = attribute(&file[1], "name")
+            .map(|v| unescape(&v))
+            .filter(|v| !v.trim().is_empty())
+            .ok_or_else(|| anyhow!("metalink file without a name"))?;
+        let body = &file[2];
+        let mut entry = MetalinkFile {
+            name,
+            size: size_re
+                .captures(body)
+                .and_then(|c| c[1].parse::<i64>().ok()),
+            ..Default::default()
+        };
+        if let Some(pieces) = pieces_re.captures(body) {
+            entry.piece_hash_type = attribute(&pieces[1], "type");
+            entry.piece_length = attribute(&pieces[1], "length").and_then(|v| v.parse().ok());
+            entry.piece_count = hash_re.captures_iter(&pieces[2]).count();
+        }
+        let whole_file = pieces_re.replace_all(body, "");
+        for hash in hash_re.captures_iter(&whole_file) {
+            let value = hash[2].trim().to_ascii_lowercase();
+            if let Some(kind) = attribute(&hash[1], "type").filter(|_| !value.is_empty()) {
+                entry.hashes.push((kind, value));
+            }
+        }
+        for url in url_re.captures_iter(body) {
+            let uri = unescape(url[2].trim());
+            if uri.is_empty() {
+                continue;
+            }
+            entry.mirrors.push(TaskMirror {
+                uri,
+                location: attribute(&url[1], "location"),
+                // Metalink 4 `priority` is 1 = best; v3 `preference` is 100 = best.
+                priority: attribute(&url[1], "priority")
+                    .and_then(|v| v.parse().ok())
+                    .or_else(|| {
+                        attribute(&url[1], "preference")
+                            .and_then(|v| v.parse::<u32>().ok())
+                            .map(|v| 101_u32.saturating_sub(v).max(1))
+                    }),
+            });
+        }
+        entry
+            .mirrors
+            .sort_by_key(|m| m.priority.unwrap_or(u32::MAX));
+        files.push(entry);
+    }
+    if files.is_empty() {
+        return Err(anyhow!("metalink has no files"));
+    }
+    Ok(files)
+}
+
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r#"\b{name}\s*=\s*(?:"([^"]*)"|'([^']*)')"#)).ok()?;
+    let caps = re.captures(attrs)?;
+    caps.get(1)
+        .or_else(|| caps.get(2))
+        .map(|v| v.as_str().trim().to_string())
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_metalink;
+
+    #[test]
+    fn parses_metalink_4_and_3_files_hashes_and_mirrors() {
+        let meta4 = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <metalink xmlns="urn:ietf:params:xml:ns:metalink">
+              <file name="distro.iso">
+                <size>4096</size>
+                <hash type="sha-1">AAAA</hash>
+                <hash type="sha-256">BBBB</hash>
+                <pieces length="1024" type="sha-1">
+                  <hash>p0</hash><hash>p1</hash><hash>p2</hash><hash>p3</hash>
+                </pieces>
+                <url location="us" priority="2">https://us.example.com/distro.iso</url>
+                <url location="de" priority="1">https://de.example.com/distro.iso?a=1&amp;b=2</url>
+              </file>
+              <file name="distro.iso.sig"><url>https://us.example.com/distro.iso.sig</url></file>
+            </metalink>"#;
+        let files = parse_metalink(meta4).expect("parse meta4");
+        assert_eq!(files.len(), 2);
+        let iso = &files[0];
+        assert_eq!(iso.name, "distro.iso");
+        assert_eq!(iso.size, Some(4096));
+        assert_eq!(iso.best_hash(), Some(("sha256", "bbbb")));
+        assert_eq!(iso.hashes.len(), 2);
+        assert_eq!((iso.piece_length, iso.piece_count), (Some(1024), 4));
+        assert_eq!(
+            iso.mirrors[0].uri,
+            "https://de.example.com/distro.iso?a=1&b=2"
+        );
+        assert_eq!(iso.mirrors[0].location.as_deref(), Some("de"));
+        assert_eq!(files[1].best_hash(), None);
+
+        let v3 = r#"<metalink version="3.0" xmlns="http://www.metalinker.org/">
+              <files><file name='old.tar.gz'>
+                <verification><hash type="md5">CCCC</hash></verification>
+                <resources>
+                  <url type="http" preference="10">http://slow.example.com/old.tar.gz</url>
+                  <url type="http" preference="100">http://fast.example.com/old.tar.gz</url>
+                </resources>
+              </file></files>
+            </metalink>"#;
+        let files = parse_metalink(v3).expect("parse v3");
+        assert_eq!(files[0].best_hash(), Some(("md5", "cccc")));
+        assert_eq!(
+            files[0].mirrors[0].uri,
+            "http://fast.example.com/old.tar.gz"
+        );
+
+        assert!(parse_metalink("<html></html>").is_err());
+        assert!(parse_metalink("<metalink></metalink>").is_err());
+    }
+}
@@ -0,0 +1,1281 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use serde_json::{Map, Value, json};
+use tokio::{sync::broadcast, time};
+
+use crate::{
+    aria2_manager::{Aria2Api, Aria2Endpoint, Aria2Notification, parse_snapshot},
+    models::Aria2TaskSnapshot,
+};
+
+const MOCK_ENDPOINT: &str = "mock://flamingo/jsonrpc";
+const DEFAULT_MAX_CONCURRENT: usize = 5;
+const DEFAULT_DIR: &str = "/tmp";
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 256;
+
+/// How a simulated download behaves, one step per [`MockAria2Engine::tick`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockScenario {
+    /// Output file name for HTTP, torrent name for BitTorrent.
+    pub name: Option<String>,
+    /// Torrent payload as `(relative path, length)`; empty means a single HTTP file.
+    pub files: Vec<(String, i64)>,
+    pub total_length: i64,
+    pub bytes_per_tick: i64,
+    pub upload_per_tick: i64,
+    pub connections: i64,
+    /// Ticks spent resolving magnet metadata before the file list appears.
+    pub metadata_ticks: u32,
+    pub failure: Option<MockFailure>,
+    pub trackers: Vec<String>,
+    /// Keeps a finished torrent active and uploading, as aria2 does while seeding.
+    pub seed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockFailure {
+    pub after_bytes: i64,
+    pub code: String,
+    pub message: String,
+}
+
+impl Default for MockScenario {
+    fn default() -> Self {
+        Self::http(1024 * 1024, 256 * 1024)
+    }
+}
+
+impl MockScenario {
+    pub fn http(total_length: i64, bytes_per_tick: i64) -> Self {
+        Self {
+            name: None,
+            files: Vec::new(),
+            total_length: total_length.max(0),
+            bytes_per_tick: bytes_per_tick.max(1),
+            upload_per_tick: 0,
+            connections: 4,
+            metadata_ticks: 0,
+            failure: None,
+            trackers: Vec::new(),
+            seed: false,
+        }
+    }
+
+    pub fn torrent(name: &str, files: &[(&str, i64)], bytes_per_tick: i64) -> Self {
+        let files = files
+            .iter()
+            .map(|(path, length)| (path.to_string(), (*length).max(0)))
+            .collect::<Vec<_>>();
+        Self {
+            name: Some(name.to_string()),
+            total_length: files.iter().map(|(_, length)| length).sum(),
+            files,
+            connections: 12,
+            ..Self::http(0, bytes_per_tick)
+        }
+    }
+
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_metadata_ticks(mut self, ticks: u32) -> Self {
+        self.metadata_ticks = ticks;
+        self
+    }
+
+    pub fn with_upload(mut self, bytes_per_tick: i64) -> Self {
+        self.upload_per_tick = bytes_per_tick.max(0);
+        self
+    }
+
+    pub fn with_trackers(mut self, trackers: &[&str]) -> Self {
+        self.trackers = trackers.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    pub fn seeding(mut self) -> Self {
+        self.seed = true;
+        self
+    }
+
+    /// Fails with an aria2 error code once `after_bytes` have been downloaded.
+    pub fn fail_after(mut self, after_bytes: i64, code: &str, message: &str) -> Self {
+        self.failure = Some(MockFailure {
+            after_bytes: after_bytes.max(0),
+            code: code.to_string(),
+            message: message.to_string(),
+        });
+        self
+    }
+
+    fn is_torrent(&self) -> bool {
+        !self.files.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockDownload {
+    gid: String,
+    status: &'static str,
+    scenario: MockScenario,
+    uris: Vec<String>,
+    options: Map<String, Value>,
+    name: String,
+    selected: Vec<bool>,
+    completed: i64,
+    uploaded: i64,
+    metadata_left: u32,
+    error: Option<MockFailure>,
+}
+
+impl MockDownload {
+    fn is_stopped(&self) -> bool {
+        matches!(self.status, "complete" | "error" | "removed")
+    }
+
+    fn has_metadata(&self) -> bool {
+        !self.scenario.is_torrent() || self.metadata_left == 0
+    }
+
+    fn file_lengths(&self) -> Vec<(String, i64)> {
+        if self.scenario.is_torrent() {
+            self.scenario.files.clone()
+        } else {
+            vec![(self.name.clone(), self.scenario.total_length)]
+        }
+    }
+
+    fn total_length(&self) -> i64 {
+        if !self.has_metadata() {
+            return 0;
+        }
+        self.file_lengths()
+            .iter()
+            .zip(&self.selected)
+            .filter(|(_, selected)| **selected)
+            .map(|((_, length), _)| length)
+            .sum()
+    }
+
+    fn dir(&self, global: &Map<String, Value>) -> String {
+        self.options
+            .get("dir")
+            .or_else(|| global.get("dir"))
+            .and_then(Value::as_str)
+            .unwrap_or(DEFAULT_DIR)
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    /// The torrent's own trackers plus `bt-tracker`, minus `bt-exclude-tracker`.
+    fn announce_list(&self) -> Vec<String> {
+        let option = |key: &str| {
+            self.options
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        let excluded = option("bt-exclude-tracker");
+        let mut trackers = self.scenario.trackers.clone();
+        for tracker in option("bt-tracker") {
+            if !trackers.contains(&tracker) {
+                trackers.push(tracker);
+            }
+        }
+        trackers.retain(|t| !excluded.contains(t));
+        trackers
+    }
+
+    fn status_json(&self, global: &Map<String, Value>) -> Value {
+        let active = self.status == "active";
+        let downloading = active && self.has_metadata();
+        let dir = self.dir(global);
+        let mut remaining = self.completed;
+        let files = if self.has_metadata() {
+            self.file_lengths()
+                .into_iter()
+                .zip(&self.selected)
+                .enumerate()
+                .map(|(index, ((path, length), selected))| {
+                    let done = if *selected {
+                        let done = remaining.min(length);
+                        remaining -= done;
+                        done
+                    } else {
+                        0
+                    };
+                    let path = if self.scenario.is_torrent() {
+                        format!("{dir}/{}/{path}", self.name)
+                    } else {
+                        format!("{dir}/{path}")
+                    };
+                    json!({
+                        "index": (index + 1).to_string(),
+                        "path": path,
+                        "length": length.to_string(),
+                        "completedLength": done.to_string(),
+                        "selected": selected.to_string(),
+                        "uris": self.uris.iter()
+                            .filter(|uri| !uri.starts_with("magnet:"))
+                            .map(|uri| json!({ "uri": uri, "status": "used" }))
+                            .collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+        let mut status = json!({
+            "gid": self.gid,
+            "status": self.status,
+            "totalLength": self.total_length().to_string(),
+            "completedLength": self.completed.to_string(),
+            "uploadLength": self.uploaded.to_string(),
+            "downloadSpeed": if downloading && self.completed < self.total_length() {
+                self.scenario.bytes_per_tick
+            } else {
+                0
+            }
+            .to_string(),
+            "uploadSpeed": if downloading { self.scenario.upload_per_tick } else { 0 }.to_string(),
+            "connections": if active { self.scenario.connections } else { 0 }.to_string(),
+            "dir": dir,
+            "files": files,
+        });
+        if self.scenario.is_torrent() {
+            status["numSeeders"] = json!(if active { "3" } else { "0" });
+            if self.has_metadata() {
+                status["bittorrent"] = json!({
+                    "info": { "name": self.name },
+                    "mode": if self.scenario.files.len() > 1 { "multi" } else { "single" },
+                    "announceList": self.announce_list()
+                        .into_iter()
+                        .map(|tracker| vec![tracker])
+                        .collect::<Vec<_>>(),
+                });
+            }
+        }
+        if let Some(error) = &self.error {
+            status["errorCode"] = json!(error.code);
+            status["errorMessage"] = json!(error.message);
+        }
+        status
+    }
+}
+
+struct MockState {
+    running: bool,
+    next_gid: u64,
+    downloads: Vec<MockDownload>,
+    scripts: Vec<(String, MockScenario)>,
+    default_scenario: MockScenario,
+    global_options: Map<String, Value>,
+    rpc_failures: HashMap<String, Vec<String>>,
+    calls: Vec<String>,
+}
+
+/// A deterministic in-memory aria2 for tests and the `engine_mode=demo` UI.
+/// Nothing moves until [`tick`](Self::tick) is called (or a ticker is started),
+/// and gids are handed out sequentially.
+pub struct MockAria2Engine {
+    state: Mutex<MockState>,
+    notifications: broadcast::Sender<Aria2Notification>,
+}
+
+impl Default for MockAria2Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockAria2Engine {
+    pub fn new() -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CHANNEL_CAPACITY);
+        let mut global_options = Map::new();
+        global_options.insert(
+            "max-concurrent-downloads".to_string(),
+            json!(DEFAULT_MAX_CONCURRENT.to_string()),
+        );
+        global_options.insert("dir".to_string(), json!(DEFAULT_DIR));
+        Self {
+            state: Mutex::new(MockState {
+                running: false,
+                next_gid: 1,
+                downloads: Vec::new(),
+                scripts: Vec::new(),
+                default_scenario: MockScenario::default(),
+                global_options,
+                rpc_failures: HashMap::new(),
+                calls: Vec::new(),
+            }),
+            notifications,
+        }
+    }
+
+    /// A running engine with a few downloads in flight for the demo UI.
+    pub fn demo() -> Self {
+        let engine = Self::new();
+        engine.set_default_scenario(MockScenario::http(48 * 1024 * 1024, 1536 * 1024));
+        {
+            let mut state = engine.lock();
+            state.running = true;
+        }
+        engine.inject(
+            "https://demo.flamingo.invalid/releases/flamingo-demo.iso",
+            MockScenario::http(700 * 1024 * 1024, 3 * 1024 * 1024),
+        );
+        engine.inject(
+            "magnet:?xt=urn:btih:c0ffee0000000000000000000000000000000001&dn=open-dataset",
+            MockScenario::torrent(
+                "open-dataset",
+                &[
+                    ("README.txt", 4 * 1024),
+                    ("data/part-001.csv", 96 * 1024 * 1024),
+                    ("data/part-002.csv", 96 * 1024 * 1024),
+                ],
+                2 * 1024 * 1024,
+            )
+            .with_metadata_ticks(8)
+            .with_upload(256 * 1024)
+            .with_trackers(&["udp://tracker.demo.invalid:6969/announce"]),
+        );
+        engine.inject(
+            "https://demo.flamingo.invalid/expired/report.pdf",
+            MockScenario::http(12 * 1024 * 1024, 512 * 1024).fail_after(
+                2 * 1024 * 1024,
+                "3",
+                "Resource not found",
+            ),
+        );
+        engine
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("mock aria2 state poisoned")
+    }
+
+    /// Records the call and fails it if the engine is down or a failure was queued.
+    fn rpc(&self, method: &str) -> Result<MutexGuard<'_, MockState>> {
+        let mut state = self.lock();
+        state.calls.push(method.to_string());
+        if let Some(queued) = state.rpc_failures.get_mut(method)
+            && !queued.is_empty()
+        {
+            let message = queued.remove(0);
+            return Err(anyhow!("{method} failed: {message}"));
+        }
+        if !state.running {
+            return Err(anyhow!("mock aria2 is not running"));
+        }
+        Ok(state)
+    }
+
+    fn notify(&self, method: &str, gid: &str) {
+        let _ = self.notifications.send(Aria2Notification {
+            method: method.to_string(),
+            gid: gid.to_string(),
+        });
+    }
+
+    /// The next add whose URI (or torrent content) contains `pattern` plays
+    /// `scenario`. Scripts are consumed in the order they were registered.
+    pub fn script(&self, pattern: &str, scenario: MockScenario) {
+        self.lock().scripts.push((pattern.to_string(), scenario));
+    }
+
+    pub fn set_default_scenario(&self, scenario: MockScenario) {
+        self.lock().default_scenario = scenario;
+    }
+
+    /// Makes the next `method` call (e.g. `"add_uri"`) return an RPC error.
+    pub fn fail_next(&self, method: &str, message: &str) {
+        self.lock()
+            .rpc_failures
+            .entry(method.to_string())
+            .or_default()
+            .push(message.to_string());
+    }
+
+    /// Adds a download behind Flamingo's back, as if it came from a saved session.
+    pub fn inject(&self, uri: &str, scenario: MockScenario) -> String {
+        let mut state = self.lock();
+        let gid = insert_download(&mut state, vec![uri.to_string()], Map::new(), scenario);
+        promote_waiting(&mut state);
+        gid
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.lock().calls.clone()
+    }
+
+    /// The options the download was added or last changed with.
+    pub fn options(&self, gid: &str) -> Option<Value> {
+        self.lock()
+            .downloads
+            .iter()
+            .find(|d| d.gid == gid)
+            .map(|d| Value::Object(d.options.clone()))
+    }
+
+    pub fn uris(&self, gid: &str) -> Option<Vec<String>> {
+        self.lock()
+            .downloads
+            .iter()
+            .find(|d| d.gid == gid)
+            .map(|d| d.uris.clone())
+    }
+
+    pub fn status_of(&self, gid: &str) -> Option<String> {
+        self.lock()
+            .downloads
+            .iter()
+            .find(|d| d.gid == gid)
+            .map(|d| d.status.to_string())
+    }
+
+    pub fn advance(&self, ticks: u32) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Moves every active download one step and fills free slots from the queue.
+    pub fn tick(&self) {
+        let mut events = Vec::new();
+        {
+            let mut state = self.lock();
+            if !state.running {
+                return;
+            }
+            for download in state.downloads.iter_mut().filter(|d| d.status == "active") {
+                if download.metadata_left > 0 {
+                    download.metadata_left -= 1;
+                    // aria2 stops a `bt-metadata-only` download once the metadata is in.
+                    if download.metadata_left == 0
+                        && download
+                            .options
+                            .get("bt-metadata-only")
+                            .and_then(Value::as_str)
+                            == Some("true")
+                    {
+                        download.status = "complete";
+                        events.push(("aria2.onDownloadComplete", download.gid.clone()));
+                    }
+                    continue;
+                }
+                let total = download.total_length();
+                if download.scenario.seed && download.completed >= total {
+                    download.uploaded += download.scenario.upload_per_tick;
+                    continue;
+                }
+                download.completed =
+                    (download.completed + download.scenario.bytes_per_tick).min(total);
+                download.uploaded += download.scenario.upload_per_tick;
+                if let Some(failure) = download.scenario.failure.clone()
+                    && download.completed >= failure.after_bytes.min(total)
+                {
+                    download.completed = download.completed.min(failure.after_bytes);
+                    download.status = "error";
+                    download.error = Some(failure);
+                    events.push(("aria2.onDownloadError", download.gid.clone()));
+                } else if download.completed >= total {
+                    if download.scenario.is_torrent() {
+                        events.push(("aria2.onBtDownloadComplete", download.gid.clone()));
+                    }
+                    if !download.scenario.seed {
+                        download.status = "complete";
+                        events.push(("aria2.onDownloadComplete", download.gid.clone()));
+                    }
+                }
+            }
+            events.extend(promote_waiting(&mut state));
+        }
+        for (method, gid) in events {
+            self.notify(method, &gid);
+        }
+    }
+
+    /// Ticks on a timer until the engine is dropped; used by the demo mode.
+    pub fn start_ticker(self: &Arc<Self>, every: Duration) {
+        let engine: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = time::interval(every);
+            loop {
+                interval.tick().await;
+                let Some(engine) = engine.upgrade() else {
+                    break;
+                };
+                engine.tick();
+            }
+        });
+    }
+
+    fn add(
+        &self,
+        method: &str,
+        uris: Vec<String>,
+        key: &str,
+        options: Option<Value>,
+    ) -> Result<String> {
+        let (gid, started) = {
+            let mut state = self.rpc(method)?;
+            let script = state
+                .scripts
+                .iter()
+                .position(|(pattern, _)| key.contains(pattern.as_str()));
+            let scenario = match script {
+                Some(index) => state.scripts.remove(index).1,
+                None => state.default_scenario.clone(),
+            };
+            let options = match options {
+                Some(Value::Object(options)) => options,
+                _ => Map::new(),
+            };
+            let gid = insert_download(&mut state, uris, options, scenario);
+            let started = promote_waiting(&mut state);
+            (gid, started)
+        };
+        for (method, gid) in started {
+            self.notify(method, &gid);
+        }
+        Ok(gid)
+    }
+
+    fn set_status(
+        &self,
+        method: &str,
+        gid: &str,
+        from: &[&str],
+        to: &'static str,
+    ) -> Result<String> {
+        let events = {
+            let mut state = self.rpc(method)?;
+            let download = find_mut(&mut state, gid)?;
+            if !from.contains(&download.status) {
+                return Err(anyhow!(
+                    "cannot {method} GID#{gid} while {}",
+                    download.status
+                ));
+            }
+            download.status = to;
+            let mut events = match to {
+                "paused" => vec![("aria2.onDownloadPause", gid.to_string())],
+                "removed" => vec![("aria2.onDownloadStop", gid.to_string())],
+                _ => Vec::new(),
+            };
+            events.extend(promote_waiting(&mut state));
+            events
+        };
+        for (method, gid) in events {
+            self.notify(method, &gid);
+        }
+        Ok(gid.to_string())
+    }
+}
+
+fn insert_download(
+    state: &mut MockState,
+    uris: Vec<String>,
+    options: Map<String, Value>,
+    mut scenario: MockScenario,
+) -> String {
+    let gid = format!("{:016x}", state.next_gid);
+    state.next_gid += 1;
+    let first = uris.first().cloned().unwrap_or_default();
+    if first.starts_with("magnet:") && !scenario.is_torrent() {
+        let total = scenario.total_length;
+        scenario.files = vec![("payload.bin".to_string(), total)];
+        scenario.metadata_ticks = scenario.metadata_ticks.max(1);
+    }
+    let name = options
+        .get("out")
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+        .or_else(|| scenario.name.clone())
+        .unwrap_or_else(|| name_from_uri(&first));
+    let file_count = if scenario.is_torrent() {
+        scenario.files.len()
+    } else {
+        1
+    };
+    let selected = match options.get("select-file").and_then(Value::as_str) {
+        Some(spec) if scenario.is_torrent() => {
+            let indexes = parse_select_file(spec).unwrap_or_default();
+            (1..=file_count).map(|i| indexes.contains(&i)).collect()
+        }
+        _ => vec![true; file_count],
+    };
+    // aria2's `pause=true` adds the download in the paused state.
+    let status = if options.get("pause").and_then(Value::as_str) == Some("true") {
+        "paused"
+    } else {
+        "waiting"
+    };
+    state.downloads.push(MockDownload {
+        gid: gid.clone(),
+        status,
+        metadata_left: scenario.metadata_ticks,
+        scenario,
+        uris,
+        options,
+        name,
+        selected,
+        completed: 0,
+        uploaded: 0,
+        error: None,
+    });
+    gid
+}
+
+fn promote_waiting(state: &mut MockState) -> Vec<(&'static str, String)> {
+    let limit = state
+        .global_options
+        .get("max-concurrent-downloads")
+        .and_then(Value::as_str)
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_CONCURRENT);
+    let mut active = state
+        .downloads
+        .iter()
+        .filter(|d| d.status == "active")
+        .count();
+    let mut started = Vec::new();
+    for download in state.downloads.iter_mut() {
+        if active >= limit {
+            break;
+        }
+        if download.status == "waiting" {
+            download.status = "active";
+            active += 1;
+            started.push(("aria2.onDownloadStart", download.gid.clone()));
+        }
+    }
+    started
+}
+
+fn find_mut<'a>(state: &'a mut MockState, gid: &str) -> Result<&'a mut MockDownload> {
+    state
+        .downloads
+        .iter_mut()
+        .find(|d| d.gid == gid)
+        .ok_or_else(|| anyhow!("GID {gid} is not found"))
+}
+
+fn name_from_uri(uri: &str) -> String {
+    if uri.starts_with("magnet:") {
+        let query = uri.split_once('?').map(|(_, q)| q).unwrap_or_default();
+        let param = |key: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(key).map(ToString::to_string))
+        };
+        return param("dn=")
+            .or_else(|| {
+                param("xt=urn:btih:").map(|hash| format!("magnet-{}", &hash[..hash.len().min(8)]))
+            })
+            .unwrap_or_else(|| "magnet".to_string());
+    }
+    reqwest::Url::parse(uri)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back().map(ToString::to_string))
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "index.html".to_string())
+}
+
+/// Parses aria2's `select-file` syntax, e.g. `1,3-5`, into 1-based indexes.
+fn parse_select_file(spec: &str) -> Result<Vec<usize>> {
+    let mut indexes = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| anyhow!("invalid select-file: {spec}"))
+        };
+        match part.split_once('-') {
+            Some((start, end)) => indexes.extend(parse(start)?..=parse(end)?),
+            None => indexes.push(parse(part)?),
+        }
+    }
+    Ok(indexes)
+}
+
+#[async_trait]
+impl Aria2Api for MockAria2Engine {
+    async fn start(&self) -> Result<Aria2Endpoint> {
+        let mut state = self.lock();
+        state.calls.push("start".to_string());
+        state.running = true;
+        Ok(mock_endpoint())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        let mut state = self.lock();
+        state.calls.push("stop".to_string());
+        state.running = false;
+        Ok(())
+    }
+
+    async fn endpoint(&self) -> Option<Aria2Endpoint> {
+        self.lock().running.then(mock_endpoint)
+    }
+
+    async fn ensure_started(&self) -> Result<Aria2Endpoint> {
+        if let Some(endpoint) = self.endpoint().await {
+            return Ok(endpoint);
+        }
+        self.start().await
+    }
+
+    async fn add_uri(&self, uris: Vec<String>, options: Option<Value>) -> Result<String> {
+        if uris.is_empty() {
+            return Err(anyhow!("add_uri requires at least one uri"));
+        }
+        let key = uris.join(" ");
+        self.add("add_uri", uris, &key, options)
+    }
+
+    async fn add_torrent(
+        &self,
+        torrent_base64: String,
+        uris: Vec<String>,
+        options: Option<Value>,
+    ) -> Result<String> {
+        let bytes = STANDARD
+            .decode(torrent_base64.as_bytes())
+            .map_err(|e| anyhow!("invalid torrent base64: {e}"))?;
+        let key = String::from_utf8_lossy(&bytes).to_string();
+        let gid = self.add("add_torrent", uris, &key, options)?;
+        let mut state = self.lock();
+        let download = find_mut(&mut state, &gid)?;
+        if !download.scenario.is_torrent() {
+            let total = download.scenario.total_length;
+            download.scenario.files = vec![("payload.bin".to_string(), total)];
+            download.selected = vec![true];
+            if download.scenario.name.is_none() && !download.options.contains_key("out") {
+                download.name = "mock-torrent".to_string();
+            }
+        }
+        Ok(gid)
+    }
+
+    /// One download per metalink file, limited to `select-file` when given.
+    async fn add_metalink(
+        &self,
+        metalink_base64: String,
+        options: Option<Value>,
+    ) -> Result<Vec<String>> {
+        let bytes = STANDARD
+            .decode(metalink_base64.as_bytes())
+            .map_err(|e| anyhow!("invalid metalink base64: {e}"))?;
+        let key = String::from_utf8_lossy(&bytes).to_string();
+        let files = crate::metalink::parse_metalink(&key)?;
+        let selected = options
+            .as_ref()
+            .and_then(|o| o.get("select-file"))
+            .and_then(Value::as_str)
+            .map(|v| {
+                v.split(',')
+                    .filter_map(|part| part.trim().parse::<usize>().ok())
+                    .collect::<Vec<_>>()
+            });
+        let mut gids = Vec::new();
+        for (index, file) in files.into_iter().enumerate() {
+            if selected
+                .as_ref()
+                .is_some_and(|selected| !selected.contains(&(index + 1)))
+            {
+                continue;
+            }
+            let uris = file.mirrors.into_iter().map(|m| m.uri).collect();
+            gids.push(self.add("add_metalink", uris, &key, options.clone())?);
+        }
+        Ok(gids)
+    }
+
+    async fn pause(&self, gid: &str) -> Result<String> {
+        self.set_status("pause", gid, &["active", "waiting"], "paused")
+    }
+
+    async fn unpause(&self, gid: &str) -> Result<String> {
+        self.set_status("unpause", gid, &["paused"], "waiting")
+    }
+
+    async fn pause_all(&self) -> Result<String> {
+        let paused = {
+            let mut state = self.rpc("pause_all")?;
+            let mut paused = Vec::new();
+            for download in state
+                .downloads
+                .iter_mut()
+                .filter(|d| matches!(d.status, "active" | "waiting"))
+            {
+                download.status = "paused";
+                paused.push(download.gid.clone());
+            }
+            paused
+        };
+        for gid in paused {
+            self.notify("aria2.onDownloadPause", &gid);
+        }
+        Ok("OK".to_string())
+    }
+
+    async fn unpause_all(&self) -> Result<String> {
+        let started = {
+            let mut state = self.rpc("unpause_all")?;
+            for download in state.downloads.iter_mut().filter(|d| d.status == "paused") {
+                download.status = "waiting";
+            }
+            promote_waiting(&mut state)
+        };
+        for (method, gid) in started {
+            self.notify(method, &gid);
+        }
+        Ok("OK".to_string())
+    }
+
+    async fn remove(&self, gid: &str, _force: bool) -> Result<String> {
+        self.set_status("remove", gid, &["active", "waiting", "paused"], "removed")
+    }
+
+    async fn remove_download_result(&self, gid: &str) -> Result<String> {
+        let mut state = self.rpc("remove_download_result")?;
+        let index = state
+            .downloads
+            .iter()
+            .position(|d| d.gid == gid)
+            .ok_or_else(|| anyhow!("GID {gid} is not found"))?;
+        if !state.downloads[index].is_stopped() {
+            return Err(anyhow!("Could not remove download result of GID#{gid}"));
+        }
+        state.downloads.remove(index);
+        Ok("OK".to_string())
+    }
+
+    async fn tell_status(&self, gid: &str) -> Result<Value> {
+        let state = self.rpc("tell_status")?;
+        let download = state
+            .downloads
+            .iter()
+            .find(|d| d.gid == gid)
+            .ok_or_else(|| anyhow!("GID {gid} is not found"))?;
+        Ok(download.status_json(&state.global_options))
+    }
+
+    async fn get_peers(&self, gid: &str) -> Result<Vec<Value>> {
+        let state = self.rpc("get_peers")?;
+        let download = state
+            .downloads
+            .iter()
+            .find(|d| d.gid == gid)
+            .ok_or_else(|| anyhow!("GID {gid} is not found"))?;
+        if !download.scenario.is_torrent() || download.status != "active" {
+            return Ok(Vec::new());
+        }
+        Ok(vec![json!({
+            "peerId": "-FL0001-mockpeer0001",
+            "ip": "127.0.0.1",
+            "port": "6881",
+            "downloadSpeed": download.scenario.bytes_per_tick.to_string(),
+            "uploadSpeed": download.scenario.upload_per_tick.to_string(),
+            "seeder": "true",
+        })])
+    }
+
+    async fn tell_all(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        let state = self.rpc("tell_all")?;
+        Ok(state
+            .downloads
+            .iter()
+            .filter_map(|d| parse_snapshot(d.status_json(&state.global_options)))
+            .collect())
+    }
+
+    async fn tell_active(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        let state = self.rpc("tell_active")?;
+        Ok(state
+            .downloads
+            .iter()
+            .filter(|d| d.status == "active")
+            .filter_map(|d| parse_snapshot(d.status_json(&state.global_options)))
+            .collect())
+    }
+
+    async fn change_position(&self, gid: &str, pos: i64, how: &str) -> Result<i64> {
+        let mut state = self.rpc("change_position")?;
+        let slots = state
+            .downloads
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.status == "waiting")
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let mut queue = slots
+            .iter()
+            .map(|index| state.downloads[*index].clone())
+            .collect::<Vec<_>>();
+        let current = queue
+            .iter()
+            .position(|d| d.gid == gid)
+            .ok_or_else(|| anyhow!("GID#{gid} is not in the waiting queue"))?;
+        let last = queue.len() as i64 - 1;
+        let target = match how {
+            "POS_SET" => pos,
+            "POS_CUR" => current as i64 + pos,
+            "POS_END" => last + pos,
+            other => return Err(anyhow!("invalid position mode: {other}")),
+        }
+        .clamp(0, last) as usize;
+        let moved = queue.remove(current);
+        queue.insert(target, moved);
+        for (slot, download) in slots.into_iter().zip(queue) {
+            state.downloads[slot] = download;
+        }
+        Ok(target as i64)
+    }
+
+    async fn change_option(&self, gid: &str, options: Value) -> Result<String> {
+        let mut state = self.rpc("change_option")?;
+        let download = find_mut(&mut state, gid)?;
+        let Value::Object(options) = options else {
+            return Err(anyhow!("options must be an object"));
+        };
+        if let Some(spec) = options.get("select-file").and_then(Value::as_str) {
+            if !download.has_metadata() {
+                return Err(anyhow!("GID#{gid} has no metadata yet"));
+            }
+            let indexes = parse_select_file(spec)?;
+            let count = download.selected.len();
+            if let Some(bad) = indexes.iter().find(|i| **i > count) {
+                return Err(anyhow!("select-file index {bad} is out of range"));
+            }
+            for (index, selected) in download.selected.iter_mut().enumerate() {
+                *selected = indexes.contains(&(index + 1));
+            }
+            download.completed = download.completed.min(download.total_length());
+        }
+        download.options.extend(options);
+        Ok("OK".to_string())
+    }
+
+    async fn change_global_option(&self, options: Value) -> Result<String> {
+        let started = {
+            let mut state = self.rpc("change_global_option")?;
+            if let Value::Object(options) = options {
+                state.global_options.extend(options);
+            }
+            promote_waiting(&mut state)
+        };
+        for (method, gid) in started {
+            self.notify(method, &gid);
+        }
+        Ok("OK".to_string())
+    }
+
+    async fn get_global_stat(&self) -> Result<Value> {
+        let state = self.rpc("get_global_stat")?;
+        let count = |status: &str| {
+            state
+                .downloads
+                .iter()
+                .filter(|d| d.status == status)
+                .count()
+        };
+        let active = state
+            .downloads
+            .iter()
+            .filter(|d| d.status == "active" && d.has_metadata());
+        let (download_speed, upload_speed) = active.fold((0, 0), |(down, up), d| {
+            (
+                down + d.scenario.bytes_per_tick,
+                up + d.scenario.upload_per_tick,
+            )
+        });
+        let stopped = state.downloads.iter().filter(|d| d.is_stopped()).count();
+        Ok(json!({
+            "downloadSpeed": download_speed.to_string(),
+            "uploadSpeed": upload_speed.to_string(),
+            "numActive": count("active").to_string(),
+            "numWaiting": (count("waiting") + count("paused")).to_string(),
+            "numStopped": stopped.to_string(),
+            "numStoppedTotal": stopped.to_string(),
+        }))
+    }
+
+    async fn get_global_option(&self) -> Result<Value> {
+        let state = self.rpc("get_global_option")?;
+        Ok(Value::Object(state.global_options.clone()))
+    }
+
+    async fn get_version(&self) -> Result<Value> {
+        let _state = self.rpc("get_version")?;
+        Ok(json!({
+            "version": "1.37.0-mock",
+            "enabledFeatures": ["BitTorrent", "Metalink", "Async DNS"],
+        }))
+    }
+
+    async fn save_session(&self) -> Result<String> {
+        let _state = self.rpc("save_session")?;
+        Ok("OK".to_string())
+    }
+
+    fn stderr_tail(&self) -> Option<String> {
+        None
+    }
+
+    fn subscribe_notifications(&self) -> Option<broadcast::Receiver<Aria2Notification>> {
+        Some(self.notifications.subscribe())
+    }
+
+    fn notifications_connected(&self) -> bool {
+        self.lock().running
+    }
+
+    fn in_process_mode(&self) -> Option<&'static str> {
+        Some("demo")
+    }
+}
+
+fn mock_endpoint() -> Aria2Endpoint {
+    Aria2Endpoint {
+        endpoint: MOCK_ENDPOINT.to_string(),
+        secret: String::new(),
+        port: 0,
+        compat_mode: false,
+        external: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{MockAria2Engine, MockScenario, parse_select_file};
+    use crate::aria2_manager::Aria2Api;
+
+    async fn started() -> MockAria2Engine {
+        let engine = MockAria2Engine::new();
+        engine.start().await.expect("start mock");
+        engine
+    }
+
+    #[tokio::test]
+    async fn http_download_progresses_per_tick_and_notifies_completion() {
+        let engine = started().await;
+        let mut events = engine.subscribe_notifications().expect("notifications");
+        engine.script("file.bin", MockScenario::http(1000, 400));
+        let gid = engine
+            .add_uri(
+                vec!["https://example.com/file.bin".to_string()],
+                Some(json!({ "dir": "/data" })),
+            )
+            .await
+            .expect("add uri");
+        assert_eq!(gid, "0000000000000001");
+        assert_eq!(
+            events.try_recv().expect("start event").method,
+            "aria2.onDownloadStart"
+        );
+
+        engine.advance(2);
+        let snapshot = engine
+            .tell_snapshot(&gid)
+            .await
+            .expect("tell")
+            .expect("snapshot");
+        assert_eq!(snapshot.status, "active");
+        assert_eq!(snapshot.completed_length, 800);
+        assert_eq!(snapshot.download_speed, 400);
+        assert_eq!(snapshot.files[0].path, "/data/file.bin");
+
+        engine.tick();
+        let snapshot = engine
+            .tell_snapshot(&gid)
+            .await
+            .expect("tell")
+            .expect("snapshot");
+        assert_eq!(snapshot.status, "complete");
+        assert_eq!(snapshot.completed_length, 1000);
+        assert_eq!(
+            events.try_recv().expect("complete event").method,
+            "aria2.onDownloadComplete"
+        );
+    }
+
+    #[tokio::test]
+    async fn magnet_resolves_metadata_before_listing_files() {
+        let engine = started().await;
+        engine.script(
+            "btih:abc",
+            MockScenario::torrent("pack", &[("a.bin", 100), ("extras/b.bin", 900)], 50)
+                .with_metadata_ticks(2)
+                .with_trackers(&["udp://tracker.example:6969/announce"]),
+        );
+        let gid = engine
+            .add_uri(vec!["magnet:?xt=urn:btih:abc".to_string()], None)
+            .await
+            .expect("add magnet");
+
+        engine.tick();
+        let snapshot = engine
+            .tell_snapshot(&gid)
+            .await
+            .expect("tell")
+            .expect("snapshot");
+        assert!(!snapshot.has_metadata);
+        assert_eq!(snapshot.total_length, 0);
+        assert!(
+            engine
+                .change_option(&gid, json!({ "select-file": "1" }))
+                .await
+                .is_err()
+        );
+
+        engine.tick();
+        let status = engine.tell_status(&gid).await.expect("tell status");
+        assert_eq!(status["bittorrent"]["info"]["name"], "pack");
+        assert_eq!(
+            status["bittorrent"]["announceList"][0][0],
+            "udp://tracker.example:6969/announce"
+        );
+        assert_eq!(status["files"][1]["path"], "/tmp/pack/extras/b.bin");
+
+        engine
+            .change_option(&gid, json!({ "select-file": "1" }))
+            .await
+            .expect("select first file");
+        engine.advance(2);
+        let snapshot = engine
+            .tell_snapshot(&gid)
+            .await
+            .expect("tell")
+            .expect("snapshot");
+        assert_eq!(snapshot.total_length, 100);
+        assert_eq!(snapshot.status, "complete");
+        assert!(!snapshot.files[1].selected);
+    }
+
+    #[tokio::test]
+    async fn scripted_failures_report_error_codes_in_order() {
+        let engine = started().await;
+        engine.script(
+            "flaky",
+            MockScenario::http(1000, 300).fail_after(500, "2", "Timeout."),
+        );
+        let failing = engine
+            .add_uri(vec!["https://example.com/flaky".to_string()], None)
+            .await
+            .expect("add failing");
+        let retried = engine
+            .add_uri(vec!["https://example.com/flaky".to_string()], None)
+            .await
+            .expect("add retried");
+        engine.advance(2);
+
+        let snapshot = engine
+            .tell_snapshot(&failing)
+            .await
+            .expect("tell")
+            .expect("snapshot");
+        assert_eq!(snapshot.status, "error");
+        assert_eq!(snapshot.error_code.as_deref(), Some("2"));
+        assert_eq!(snapshot.error_message.as_deref(), Some("Timeout."));
+        assert_eq!(snapshot.completed_length, 500);
+        assert_eq!(engine.status_of(&retried).as_deref(), Some("active"));
+    }
+
+    #[tokio::test]
+    async fn queue_respects_concurrency_and_position_changes() {
+        let engine = started().await;
+        engine
+            .change_global_option(json!({ "max-concurrent-downloads": "1" }))
+            .await
+            .expect("limit concurrency");
+        let mut gids = Vec::new();
+        for name in ["a", "b", "c"] {
+            gids.push(
+                engine
+                    .add_uri(vec![format!("https://example.com/{name}")], None)
+                    .await
+                    .expect("add"),
+            );
+        }
+        assert_eq!(engine.status_of(&gids[0]).as_deref(), Some("active"));
+        assert_eq!(engine.status_of(&gids[1]).as_deref(), Some("waiting"));
+
+        let pos = engine
+            .change_position(&gids[2], 0, "POS_SET")
+            .await
+            .expect("move to front");
+        assert_eq!(pos, 0);
+        engine.pause(&gids[0]).await.expect("pause");
+        assert_eq!(engine.status_of(&gids[2]).as_deref(), Some("active"));
+        assert_eq!(engine.status_of(&gids[1]).as_deref(), Some("waiting"));
+
+        assert!(engine.remove_download_result(&gids[0]).await.is_err());
+        engine.remove(&gids[0], false).await.expect("remove");
+        engine
+            .remove_download_result(&gids[0])
+            .await
+            .expect("remove result");
+        assert_eq!(engine.status_of(&gids[0]), None);
+    }
+
+    #[tokio::test]
+    async fn injected_rpc_failures_and_stopped_engine_surface_as_errors() {
+        let engine = started().await;
+        engine.fail_next("add_uri", "connection reset");
+        let err = engine
+            .add_uri(vec!["https://example.com/x".to_string()], None)
+            .await
+            .expect_err("injected failure");
+        assert!(err.to_string().contains("connection reset"));
+        engine
+            .add_uri(vec!["https://example.com/x".to_string()], None)
+            .await
+            .expect("next call succeeds");
+
+        engine.stop().await.expect("stop");
+        assert!(engine.endpoint().await.is_none());
+        assert!(engine.tell_all().await.is_err());
+        engine.ensure_started().await.expect("restart");
+        assert_eq!(engine.tell_all().await.expect("tell all").len(), 1);
+        assert!(engine.calls().iter().any(|c| c == "add_uri"));
+    }
+
+    #[test]
+    fn select_file_accepts_lists_and_ranges() {
+        assert_eq!(parse_select_file("1,3-5").expect("parse"), vec![1, 3, 4, 5]);
+        assert!(parse_select_file("0").is_err());
+        assert!(parse_select_file("a-b").is_err());
+    }
+
+    #[tokio::test]
+    async fn demo_engine_starts_with_sample_downloads() {
+        let engine = MockAria2Engine::demo();
+        assert_eq!(engine.in_process_mode(), Some("demo"));
+        let snapshots = engine.tell_all().await.expect("tell all");
+        assert_eq!(snapshots.len(), 3);
+        assert!(snapshots.iter().all(|s| s.status == "active"));
+    }
+}
@@ -93,6 +93,8 @@ pub struct TaskFailureReason {
 pub struct Task {
     pub id: String,
     pub aria2_gid: Option<String>,
+    #[serde(default)]
+    pub engine_id: Option<String>, // None = the primary engine
     pub task_type: TaskType,
     pub source: String,
     pub status: TaskStatus,
@@ -120,6 +122,27 @@ pub struct Task {
     pub checksum_status: Option<String>,
     pub created_at: i64,
     pub updated_at: i64,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub start_at: Option<i64>,
+    #[serde(default)]
+    pub window: Option<TaskWindow>,
+    #[serde(default)]
+    pub schedule_held: bool, // paused by its schedule rather than by the user
+    #[serde(default)]
+    pub queue: Option<String>,
+    #[serde(default)]
+    pub queue_held: bool, // paused until its queue has a free slot
+}
+
+/// A recurring time of day a scheduled task may download in.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct TaskWindow {
+    #[serde(default)]
+    pub days: Option<String>, // "1,2,3" (Mon=1..Sun=7), every day when unset
+    pub start: String, // "HH:MM"
+    pub end: String,   // "HH:MM", before `start` to wrap past midnight
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -149,31 +172,145 @@ pub struct AddTaskOptions {
     pub checksum_value: Option<String>,
     #[serde(default)]
     pub headers: Vec<String>,
+    #[serde(default)]
+    pub engine: Option<String>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub on_dependency_failure: Option<String>, // cancel | wait | run
+    #[serde(default)]
+    pub start_at: Option<i64>,
+    #[serde(default)]
+    pub window: Option<TaskWindow>,
+    #[serde(default)]
+    pub queue: Option<String>,
+    #[serde(default)]
+    pub origin: Option<String>, // browser | api | clipboard | ui
+    #[serde(default)]
+    pub collision_policy: Option<String>, // rename | overwrite | skip | resume | ask
+    #[serde(default)]
+    pub duplicate_policy: Option<String>, // warn | skip | redownload | open
+    #[serde(default)]
+    pub url_expansion: Option<String>, // tasks | mirrors | off
+    #[serde(default)]
+    pub mirrors: Vec<String>, // more URIs for the same file
+    #[serde(default)]
+    pub select_file: Option<String>, // aria2 select-file, e.g. "1,3-5"
+    #[serde(default)]
+    pub file_priorities: Vec<TorrentFilePriority>,
+    #[serde(default)]
+    pub metadata_only: Option<bool>, // magnets: fetch the torrent, then wait paused for a file selection
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TorrentFilePriority {
+    pub index: usize,     // 0-based, as in `TorrentInfo.files`
+    pub priority: String, // skip | normal | high
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DownloadDirRule {
     pub enabled: bool,
-    pub matcher: String, // ext | domain | type
+    pub matcher: String, // ext | domain | type | category | regex | glob | size | source
     pub pattern: String,
     pub save_dir: String,
     #[serde(default)]
     pub subdir_by_date: bool,
     #[serde(default)]
     pub subdir_by_domain: bool,
+    #[serde(default)]
+    pub engine: Option<String>,
+    #[serde(default)]
+    pub conditions: Vec<RuleCondition>,
+    #[serde(default)]
+    pub combine: Option<String>, // all | any
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct CategoryRule {
     pub enabled: bool,
-    pub matcher: String, // ext | domain | type
+    pub matcher: String, // ext | domain | type | category | regex | glob | size | source
     pub pattern: String,
     pub category: String,
+    #[serde(default)]
+    pub engine: Option<String>,
+    #[serde(default)]
+    pub conditions: Vec<RuleCondition>,
+    #[serde(default)]
+    pub combine: Option<String>,
+}
+
+/// An extra condition on a rule, checked together with the rule's own
+/// `matcher`/`pattern` as `combine` says: `all` (default) or `any`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct RuleCondition {
+    pub matcher: String,
+    pub pattern: String,
+}
+
+/// Per-task aria2 options applied when a matching task is added. Options set on
+/// the task itself win over the rule.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PolicyRule {
+    pub enabled: bool,
+    pub matcher: String, // ext | domain | type | category | regex | glob | size | source
+    pub pattern: String,
+    #[serde(default)]
+    pub max_download_limit: Option<String>,
+    #[serde(default)]
+    pub split: Option<u32>,
+    #[serde(default)]
+    pub max_connection_per_server: Option<u32>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<String>,
+    #[serde(default)]
+    pub conditions: Vec<RuleCondition>,
+    #[serde(default)]
+    pub combine: Option<String>,
+}
+
+pub const DEFAULT_ENGINE_ID: &str = "local";
+
+/// An additional aria2 reachable over RPC, e.g. a seedbox. Tasks routed to it
+/// keep their gid scoped to this profile id.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct EngineProfile {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub rpc_url: String,
+    #[serde(default)]
+    pub secret: String,
+    #[serde(default)]
+    pub download_dir: Option<String>,
+}
+
+/// A named queue with its own cap on concurrently running tasks.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct TaskQueue {
+    pub name: String,
+    pub max_active: u32,
+    #[serde(default)]
+    pub priority: i32, // higher queues get free slots first and jump aria2's waiting list
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskQueueStatus {
+    pub name: String,
+    pub max_active: u32,
+    pub priority: i32,
+    pub running: u32,
+    pub held: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GlobalSettings {
     pub aria2_bin_path: Option<String>,
+    pub engine_mode: Option<String>, // managed | external | demo | native
+    pub aria2_rpc_url: Option<String>,
+    pub aria2_rpc_secret: Option<String>,
     pub download_dir: Option<String>,
     pub max_concurrent_downloads: Option<u32>,
     pub max_connection_per_server: Option<u32>,
@@ -186,6 +323,8 @@ pub struct GlobalSettings {
     pub download_dir_rules: Vec<DownloadDirRule>,
     #[serde(default)]
     pub category_rules: Vec<CategoryRule>,
+    #[serde(default)]
+    pub policy_rules: Vec<PolicyRule>,
     pub browser_bridge_enabled: Option<bool>,
     pub browser_bridge_port: Option<u16>,
     pub browser_bridge_token: Option<String>,
@@ -201,6 +340,16 @@ pub struct GlobalSettings {
     pub metadata_timeout_secs: Option<u32>,
     pub speed_plan: Option<String>, // JSON array: [{"days":"1,2,3","start":"09:00","end":"18:00","limit":"2M"}]
     pub task_option_presets: Option<String>, // JSON array: [{name, task_type, options}]
+    pub engine_profiles: Option<String>, // JSON array: [{id, name, rpc_url, secret, download_dir}]
+    pub task_queues: Option<String>, // JSON array: [{name, max_active, priority}]
+    pub collision_policy: Option<String>, // rename | overwrite | skip | resume | ask
+    pub duplicate_policy: Option<String>, // warn | skip | redownload | open
+    pub tracker_lists: Option<String>, // JSON array of tracker list URLs (http, https or file)
+    pub tracker_refresh_hours: Option<u32>, // 0 = refresh by hand only
+    pub seeding_rules: Option<String>, // JSON array: [{category, ratio, seed_hours, idle_minutes}]
+    pub max_active_seeds: Option<u32>, // 0 = no limit
+    pub transfer_history_days: Option<u32>, // daily history kept; 0 = forever
+    pub pause_seeding_in_speed_plan: Option<bool>,
     pub post_complete_action: Option<String>, // none | open_dir | open_file
     pub completion_webhook_url: Option<String>,
     pub completion_command: Option<String>,
@@ -221,6 +370,8 @@ pub struct Aria2TaskSnapshot {
     pub completed_length: i64,
     pub download_speed: i64,
     pub upload_speed: i64,
+    #[serde(default)]
+    pub upload_length: i64,
     pub connections: i64,
     pub error_code: Option<String>,
     pub error_message: Option<String>,
@@ -239,6 +390,7 @@ pub struct Aria2FileSnapshot {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Diagnostics {
+    pub engine_mode: String,
     pub rpc_endpoint: String,
     pub rpc_port: Option<u16>,
     pub rpc_secret_set: bool,
@@ -292,6 +444,17 @@ pub struct MediaMergeJob {
     pub updated_at: i64,
 }
 
+/// An add request held back until the task's prerequisites finish.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingSubmission {
+    pub task_id: String,
+    pub kind: String,    // uri | torrent
+    pub payload: String, // the uri, or the base64 torrent
+    pub options: String, // AddTaskOptions as json
+    pub on_dependency_failure: String,
+    pub created_at: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StartupNotice {
     pub level: String, // info | warning | error
@@ -325,6 +488,18 @@ pub struct ImportTaskListResult {
     pub imported_files: usize,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkTaskActionResult {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<BulkTaskFailure>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkTaskFailure {
+    pub task_id: String,
+    pub error: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppUpdateStrategy {
     pub mode: String, // manual_release | tauri_updater_future
@@ -333,10 +508,161 @@ pub struct AppUpdateStrategy {
     pub notes: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TaskMirror {
+    pub uri: String,
+    pub location: Option<String>,
+    pub priority: Option<u32>, // 1 = preferred
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TorrentInfo {
+    pub name: String,
+    pub info_hash_v1: Option<String>,
+    pub info_hash_v2: Option<String>,
+    pub total_length: i64,
+    pub piece_length: i64,
+    pub piece_count: usize,
+    pub private: bool,
+    pub trackers: Vec<String>,
+    pub files: Vec<TorrentInfoFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TorrentInfoFile {
+    pub index: usize,
+    pub path: String, // relative to the torrent's folder, `/`-separated
+    pub length: i64,
+}
+
+/// A subscribed tracker list and how its last refresh went.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TrackerListStatus {
+    pub url: String,
+    pub tracker_count: usize,
+    pub fetched_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// When to stop seeding; a rule without `category` applies to every other task.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct SeedingRule {
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub ratio: Option<f64>,
+    #[serde(default)]
+    pub seed_hours: Option<u32>,
+    #[serde(default)]
+    pub idle_minutes: Option<u32>, // no upload for this long
+}
+
+/// Upload totals survive aria2 dropping the gid, e.g. after a restart or retry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskSeeding {
+    pub task_id: String,
+    pub uploaded_length: i64,
+    pub ratio: f64,
+    pub seeding_since: Option<i64>,
+    pub last_upload_at: Option<i64>,
+    pub held: bool, // paused by the seeding limits rather than by the user
+    pub stopped_reason: Option<String>, // ratio | time | idle | manual
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BandwidthHistoryQuery {
+    #[serde(default)]
+    pub resolution: Option<String>, // minute | hour | day | month; default day
+    #[serde(default)]
+    pub group_by: Option<String>, // task | category | domain; default totals only
+    #[serde(default)]
+    pub from: Option<i64>,
+    #[serde(default)]
+    pub to: Option<i64>,
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
+/// Bytes moved in one history bucket (UTC), for one `key` when grouped.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BandwidthSample {
+    pub bucket_start: i64,
+    pub key: Option<String>,
+    pub downloaded: i64,
+    pub uploaded: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackerHealth {
+    pub uri: String,
+    pub status: String, // ok | unreachable | unknown
+    pub checked_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// An earlier task that fetched the same thing as a new one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateMatch {
+    pub task_id: String,
+    pub matched_by: String, // url | infohash | final_url | name_size
+    pub status: TaskStatus,
+    pub name: Option<String>,
+    pub save_dir: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddTaskOutcome {
+    pub task_id: String,
+    pub action: String, // created | skipped | existing
+    pub duplicate: Option<DuplicateMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BatchAddItem {
+    pub source: String,
+    #[serde(default)]
+    pub options: Option<AddTaskOptions>, // overrides the batch's shared options
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BatchAddRequest {
+    #[serde(default)]
+    pub items: Vec<BatchAddItem>,
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub text: Option<String>, // run through the link parser
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub options: AddTaskOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchAddItemResult {
+    pub source: String,
+    pub task_id: Option<String>,
+    pub action: Option<String>, // created | skipped | existing
+    pub duplicate: Option<DuplicateMatch>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchAddResult {
+    pub items: Vec<BatchAddItemResult>,
+    pub created: usize,
+    pub failed: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SaveDirSuggestion {
     pub save_dir: String,
     pub matched_rule: Option<DownloadDirRule>,
+    pub out: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
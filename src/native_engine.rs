@@ -0,0 +1,1548 @@
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures_util::future::try_join_all;
+use reqwest::{
+    Client, RequestBuilder, StatusCode,
+    header::{CONTENT_DISPOSITION, CONTENT_RANGE, RANGE, REFERER, USER_AGENT},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::{broadcast, watch},
+    time,
+};
+
+use crate::{
+    aria2_manager::{Aria2Api, Aria2Endpoint, Aria2Notification, parse_snapshot},
+    models::Aria2TaskSnapshot,
+};
+
+const NATIVE_ENDPOINT: &str = "native://flamingo";
+const SESSION_FILE: &str = "native-session.json";
+const SIDECAR_SUFFIX: &str = ".flamingo";
+const DEFAULT_MAX_CONCURRENT: usize = 5;
+const DEFAULT_SPLIT: usize = 5;
+const MIN_SEGMENT_SIZE: i64 = 1024 * 1024;
+const SEGMENT_RETRIES: u32 = 3;
+const SIDECAR_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 256;
+
+/// A pure-Rust HTTP(S) downloader that speaks the same [`Aria2Api`] as aria2,
+/// for machines without an `aria2c` binary (`engine_mode=native`).
+///
+/// Each download is split into ranged segments fetched in parallel; progress
+/// lives in a `<file>.flamingo` sidecar so paused or interrupted downloads
+/// resume where they stopped, and unfinished downloads survive restarts
+/// through a session file in the work dir.
+pub struct NativeHttpEngine {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    client: Client,
+    session_file: PathBuf,
+    running: AtomicBool,
+    session_loaded: AtomicBool,
+    next_gid: AtomicU64,
+    jobs: Mutex<Vec<Arc<Job>>>,
+    global_options: Mutex<Map<String, Value>>,
+    global_limiter: RateLimiter,
+    notifications: broadcast::Sender<Aria2Notification>,
+}
+
+struct Job {
+    gid: String,
+    uris: Vec<String>,
+    options: Mutex<Map<String, Value>>,
+    status: Mutex<&'static str>,
+    total: AtomicI64,
+    completed: AtomicI64,
+    connections: AtomicI64,
+    path: Mutex<Option<PathBuf>>,
+    error: Mutex<Option<JobError>>,
+    limiter: RateLimiter,
+    cancel: Mutex<Option<watch::Sender<bool>>>,
+    speed: Mutex<SpeedSample>,
+}
+
+#[derive(Debug, Clone)]
+struct JobError {
+    code: &'static str,
+    message: String,
+}
+
+impl JobError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn from_http(status: StatusCode) -> Self {
+        match status.as_u16() {
+            404 => Self::new("3", "Resource not found"),
+            401 | 403 => Self::new(
+                "24",
+                format!("HTTP authorization failed (status {})", status.as_u16()),
+            ),
+            code => Self::new("22", format!("Unexpected HTTP status {code}")),
+        }
+    }
+
+    fn from_request(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::new("2", "Timeout.")
+        } else {
+            Self::new("6", format!("Network problem: {e}"))
+        }
+    }
+
+    fn from_io(context: &str, e: std::io::Error) -> Self {
+        Self::new("17", format!("{context}: {e}"))
+    }
+}
+
+struct SpeedSample {
+    at: Instant,
+    completed: i64,
+    rate: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Segment {
+    start: i64,
+    end: i64,
+    done: i64,
+}
+
+impl Segment {
+    fn len(&self) -> i64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Progress of one file, stored next to it while it downloads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sidecar {
+    url: String,
+    total_length: i64,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SessionEntry {
+    gid: String,
+    uris: Vec<String>,
+    options: Map<String, Value>,
+    paused: bool,
+}
+
+/// Paces bytes to a bytes-per-second budget; 0 means unlimited.
+struct RateLimiter {
+    rate: AtomicU64,
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            next_free: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+        *self.next_free.lock().expect("limiter mutex poisoned") = Instant::now();
+    }
+
+    async fn acquire(&self, bytes: usize) {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 || bytes == 0 {
+            return;
+        }
+        let wait = {
+            let mut next_free = self.next_free.lock().expect("limiter mutex poisoned");
+            let now = Instant::now();
+            let start = (*next_free).max(now);
+            *next_free = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            start - now
+        };
+        if !wait.is_zero() {
+            time::sleep(wait).await;
+        }
+    }
+}
+
+enum Outcome {
+    Completed,
+    Stopped,
+}
+
+impl NativeHttpEngine {
+    pub fn new(work_dir: &Path) -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CHANNEL_CAPACITY);
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(15))
+            .read_timeout(Duration::from_secs(60))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        let mut global_options = Map::new();
+        global_options.insert(
+            "max-concurrent-downloads".to_string(),
+            json!(DEFAULT_MAX_CONCURRENT.to_string()),
+        );
+        Self {
+            shared: Arc::new(Shared {
+                client,
+                session_file: work_dir.join(SESSION_FILE),
+                running: AtomicBool::new(false),
+                session_loaded: AtomicBool::new(false),
+                next_gid: AtomicU64::new(1),
+                jobs: Mutex::new(Vec::new()),
+                global_options: Mutex::new(global_options),
+                global_limiter: RateLimiter::new(0),
+                notifications,
+            }),
+        }
+    }
+
+    fn ensure_running(&self) -> Result<()> {
+        if !self.shared.running.load(Ordering::SeqCst) {
+            return Err(anyhow!("native engine is not running"));
+        }
+        Ok(())
+    }
+
+    fn job(&self, gid: &str) -> Result<Arc<Job>> {
+        self.ensure_running()?;
+        self.shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .iter()
+            .find(|job| job.gid == gid)
+            .cloned()
+            .ok_or_else(|| anyhow!("GID {gid} is not found"))
+    }
+
+    fn load_session(&self) {
+        if self.shared.session_loaded.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let Ok(raw) = std::fs::read_to_string(&self.shared.session_file) else {
+            return;
+        };
+        let Ok(entries) = serde_json::from_str::<Vec<SessionEntry>>(&raw) else {
+            return;
+        };
+        let mut jobs = self.shared.jobs.lock().expect("jobs mutex poisoned");
+        for entry in entries {
+            let next = u64::from_str_radix(&entry.gid, 16).unwrap_or_default() + 1;
+            self.shared.next_gid.fetch_max(next, Ordering::SeqCst);
+            let job = Job::new(entry.gid, entry.uris, entry.options);
+            if entry.paused {
+                job.set_status("paused");
+            }
+            jobs.push(Arc::new(job));
+        }
+    }
+
+    fn write_session(&self) -> Result<()> {
+        let entries = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .iter()
+            .filter(|job| matches!(job.status(), "active" | "waiting" | "paused"))
+            .map(|job| SessionEntry {
+                gid: job.gid.clone(),
+                uris: job.uris.clone(),
+                options: job.options.lock().expect("options mutex poisoned").clone(),
+                paused: job.status() == "paused",
+            })
+            .collect::<Vec<_>>();
+        if let Some(parent) = self.shared.session_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(
+            &self.shared.session_file,
+            serde_json::to_vec_pretty(&entries)?,
+        )?;
+        Ok(())
+    }
+
+    fn set_status(&self, gid: &str, from: &[&str], to: &'static str) -> Result<String> {
+        let job = self.job(gid)?;
+        let current = job.status();
+        if !from.contains(&current) {
+            return Err(anyhow!("cannot change GID#{gid} from {current} to {to}"));
+        }
+        job.set_status(to);
+        job.cancel();
+        match to {
+            "paused" => self.shared.notify("aria2.onDownloadPause", gid),
+            "removed" => self.shared.notify("aria2.onDownloadStop", gid),
+            _ => {}
+        }
+        self.shared.schedule();
+        Ok(gid.to_string())
+    }
+}
+
+impl Job {
+    fn new(gid: String, uris: Vec<String>, options: Map<String, Value>) -> Self {
+        let limit = options
+            .get("max-download-limit")
+            .and_then(Value::as_str)
+            .map(parse_rate_limit)
+            .unwrap_or_default();
+        Self {
+            gid,
+            uris,
+            options: Mutex::new(options),
+            status: Mutex::new("waiting"),
+            total: AtomicI64::new(0),
+            completed: AtomicI64::new(0),
+            connections: AtomicI64::new(0),
+            path: Mutex::new(None),
+            error: Mutex::new(None),
+            limiter: RateLimiter::new(limit),
+            cancel: Mutex::new(None),
+            speed: Mutex::new(SpeedSample {
+                at: Instant::now(),
+                completed: 0,
+                rate: 0,
+            }),
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        *self.status.lock().expect("status mutex poisoned")
+    }
+
+    fn set_status(&self, status: &'static str) {
+        *self.status.lock().expect("status mutex poisoned") = status;
+    }
+
+    fn cancel(&self) {
+        if let Some(cancel) = self.cancel.lock().expect("cancel mutex poisoned").take() {
+            let _ = cancel.send(true);
+        }
+    }
+
+    fn option(&self, key: &str) -> Option<String> {
+        self.options
+            .lock()
+            .expect("options mutex poisoned")
+            .get(key)
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+    }
+
+    fn download_speed(&self) -> i64 {
+        let completed = self.completed.load(Ordering::Relaxed);
+        let mut sample = self.speed.lock().expect("speed mutex poisoned");
+        let elapsed = sample.at.elapsed();
+        if elapsed >= Duration::from_millis(500) {
+            sample.rate = ((completed - sample.completed).max(0) as f64 / elapsed.as_secs_f64())
+                .round() as i64;
+            sample.at = Instant::now();
+            sample.completed = completed;
+        }
+        if self.status() == "active" {
+            sample.rate
+        } else {
+            0
+        }
+    }
+
+    fn status_json(&self, global_dir: Option<&str>) -> Value {
+        let status = self.status();
+        let path = self.path.lock().expect("path mutex poisoned").clone();
+        let dir = self
+            .option("dir")
+            .or_else(|| global_dir.map(ToString::to_string))
+            .unwrap_or_default();
+        let total = self.total.load(Ordering::Relaxed);
+        let completed = self.completed.load(Ordering::Relaxed);
+        let mut value = json!({
+            "gid": self.gid,
+            "status": status,
+            "totalLength": total.to_string(),
+            "completedLength": completed.to_string(),
+            "uploadLength": "0",
+            "downloadSpeed": self.download_speed().to_string(),
+            "uploadSpeed": "0",
+            "connections": if status == "active" {
+                self.connections.load(Ordering::Relaxed)
+            } else {
+                0
+            }
+            .to_string(),
+            "dir": dir,
+            "files": path.map(|path| vec![json!({
+                "index": "1",
+                "path": path.to_string_lossy(),
+                "length": total.to_string(),
+                "completedLength": completed.to_string(),
+                "selected": "true",
+                "uris": self.uris.iter()
+                    .map(|uri| json!({ "uri": uri, "status": "used" }))
+                    .collect::<Vec<_>>(),
+            })]).unwrap_or_default(),
+        });
+        if let Some(error) = self.error.lock().expect("error mutex poisoned").as_ref() {
+            value["errorCode"] = json!(error.code);
+            value["errorMessage"] = json!(error.message);
+        }
+        value
+    }
+}
+
+impl Shared {
+    fn notify(&self, method: &str, gid: &str) {
+        let _ = self.notifications.send(Aria2Notification {
+            method: method.to_string(),
+            gid: gid.to_string(),
+        });
+    }
+
+    fn global_option(&self, key: &str) -> Option<String> {
+        self.global_options
+            .lock()
+            .expect("global options mutex poisoned")
+            .get(key)
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+    }
+
+    /// Starts waiting jobs until `max-concurrent-downloads` are active.
+    fn schedule(self: &Arc<Self>) {
+        if !self.running.load(Ordering::SeqCst) {
+            return;
+        }
+        let limit = self
+            .global_option("max-concurrent-downloads")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_CONCURRENT)
+            .max(1);
+        let mut started = Vec::new();
+        {
+            let jobs = self.jobs.lock().expect("jobs mutex poisoned");
+            let mut active = jobs.iter().filter(|job| job.status() == "active").count();
+            for job in jobs.iter() {
+                if active >= limit {
+                    break;
+                }
+                if job.status() == "waiting" {
+                    job.set_status("active");
+                    let (tx, rx) = watch::channel(false);
+                    *job.cancel.lock().expect("cancel mutex poisoned") = Some(tx);
+                    active += 1;
+                    started.push((job.clone(), rx));
+                }
+            }
+        }
+        for (job, cancel) in started {
+            self.notify("aria2.onDownloadStart", &job.gid);
+            let shared = self.clone();
+            tokio::spawn(async move {
+                let outcome = run_job(&shared, &job, cancel).await;
+                job.connections.store(0, Ordering::Relaxed);
+                job.cancel.lock().expect("cancel mutex poisoned").take();
+                match outcome {
+                    Ok(Outcome::Completed) if job.status() == "active" => {
+                        job.set_status("complete");
+                        shared.notify("aria2.onDownloadComplete", &job.gid);
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        if job.status() == "active" {
+                            *job.error.lock().expect("error mutex poisoned") = Some(error);
+                            job.set_status("error");
+                            shared.notify("aria2.onDownloadError", &job.gid);
+                        }
+                    }
+                }
+                shared.schedule();
+            });
+        }
+    }
+}
+
+fn apply_request_options(mut request: RequestBuilder, job: &Job) -> RequestBuilder {
+    if let Some(ua) = job.option("user-agent") {
+        request = request.header(USER_AGENT, ua);
+    }
+    if let Some(referer) = job.option("referer") {
+        request = request.header(REFERER, referer);
+    }
+    let headers = job
+        .options
+        .lock()
+        .expect("options mutex poisoned")
+        .get("header")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for line in headers.iter().filter_map(Value::as_str) {
+        if let Some((name, value)) = line.split_once(':') {
+            request = request.header(name.trim(), value.trim());
+        }
+    }
+    request
+}
+
+/// Parses aria2 speed limits such as `512K`, `2M` or `1048576` into bytes per second.
+pub fn parse_rate_limit(value: &str) -> u64 {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => (&value[..index], Some(unit)),
+        _ => (value, None),
+    };
+    let Ok(number) = number.trim().parse::<f64>() else {
+        return 0;
+    };
+    let multiplier = match unit.map(|u| u.to_ascii_uppercase()) {
+        None => 1.0,
+        Some('K') => 1024.0,
+        Some('M') => 1024.0 * 1024.0,
+        Some('G') => 1024.0 * 1024.0 * 1024.0,
+        Some(_) => return 0,
+    };
+    (number * multiplier).max(0.0) as u64
+}
+
+/// Parses a size range such as `100M-2G`, `1G-` or `-500K` into inclusive byte
+/// bounds; a single size means at least that much. `None` if a bound is malformed.
+pub fn parse_size_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let bound = |raw: &str| -> Option<Option<u64>> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Some(None);
+        }
+        let number = raw
+            .strip_suffix(|c: char| matches!(c.to_ascii_uppercase(), 'K' | 'M' | 'G'))
+            .unwrap_or(raw);
+        number.trim().parse::<f64>().ok().filter(|v| *v >= 0.0)?;
+        Some(Some(parse_rate_limit(raw)))
+    };
+    let (min, max) = match value.trim().split_once('-') {
+        Some((min, max)) => (bound(min)?, bound(max)?),
+        None => (bound(value)?, None),
+    };
+    if min.is_none() && max.is_none() {
+        return None;
+    }
+    Some((min, max))
+}
+
+fn filename_from_disposition(value: &str) -> Option<String> {
+    let value = value.trim();
+    let encoded = value.split(';').map(str::trim).find_map(|part| {
+        part.strip_prefix("filename*=")
+            .and_then(|v| v.split_once("''").map(|(_, name)| name.to_string()))
+    });
+    let plain = value.split(';').map(str::trim).find_map(|part| {
+        part.strip_prefix("filename=")
+            .map(|v| v.trim_matches('"').to_string())
+    });
+    encoded
+        .or(plain)
+        .and_then(|name| {
+            Path::new(&name.replace('\\', "/"))
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+        })
+        .filter(|name| !name.is_empty() && name != "." && name != "..")
+}
+
+fn filename_from_url(url: &reqwest::Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back().map(ToString::to_string))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "index.html".to_string())
+}
+
+fn split_segments(total: i64, connections: usize) -> Vec<Segment> {
+    let max_by_size = (total / MIN_SEGMENT_SIZE).max(1) as usize;
+    let count = connections.clamp(1, max_by_size) as i64;
+    let size = total / count;
+    (0..count)
+        .map(|i| Segment {
+            start: i * size,
+            end: if i == count - 1 {
+                total - 1
+            } else {
+                (i + 1) * size - 1
+            },
+            done: 0,
+        })
+        .collect()
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+fn save_sidecar(path: &Path, sidecar: &Sidecar) {
+    if let Ok(bytes) = serde_json::to_vec(sidecar) {
+        let _ = std::fs::write(sidecar_path(path), bytes);
+    }
+}
+
+fn load_sidecar(path: &Path, url: &str, total: i64) -> Option<Vec<Segment>> {
+    let raw = std::fs::read(sidecar_path(path)).ok()?;
+    let sidecar = serde_json::from_slice::<Sidecar>(&raw).ok()?;
+    if sidecar.url != url || sidecar.total_length != total || !path.exists() {
+        return None;
+    }
+    Some(sidecar.segments)
+}
+
+async fn run_job(
+    shared: &Arc<Shared>,
+    job: &Arc<Job>,
+    cancel: watch::Receiver<bool>,
+) -> std::result::Result<Outcome, JobError> {
+    let url = job
+        .uris
+        .first()
+        .cloned()
+        .ok_or_else(|| JobError::new("1", "no uri to download"))?;
+    let probe = apply_request_options(shared.client.get(&url), job)
+        .header(RANGE, "bytes=0-0")
+        .send()
+        .await
+        .map_err(JobError::from_request)?;
+    let status = probe.status();
+    if !status.is_success() {
+        return Err(JobError::from_http(status));
+    }
+    let final_url = probe.url().clone();
+    let ranged_total = (status == StatusCode::PARTIAL_CONTENT)
+        .then(|| {
+            probe
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit_once('/'))
+                .and_then(|(_, total)| total.trim().parse::<i64>().ok())
+        })
+        .flatten();
+    let total = ranged_total.or_else(|| probe.content_length().map(|v| v as i64));
+    let name = job
+        .option("out")
+        .or_else(|| {
+            probe
+                .headers()
+                .get(CONTENT_DISPOSITION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(filename_from_disposition)
+        })
+        .unwrap_or_else(|| filename_from_url(&final_url));
+    drop(probe);
+
+    let dir = job
+        .option("dir")
+        .or_else(|| shared.global_option("dir"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| JobError::new("16", format!("could not create {}: {e}", dir.display())))?;
+    let path = dir.join(&name);
+    *job.path.lock().expect("path mutex poisoned") = Some(path.clone());
+    job.total
+        .store(total.unwrap_or_default(), Ordering::Relaxed);
+
+    match ranged_total {
+        Some(total) if total > 0 => {
+            download_segments(shared, job, final_url.as_str(), &url, &path, total, cancel).await
+        }
+        _ => download_stream(shared, job, final_url.as_str(), &path, cancel).await,
+    }
+}
+
+async fn download_segments(
+    shared: &Arc<Shared>,
+    job: &Arc<Job>,
+    url: &str,
+    source: &str,
+    path: &Path,
+    total: i64,
+    cancel: watch::Receiver<bool>,
+) -> std::result::Result<Outcome, JobError> {
+    let split = job
+        .option("split")
+        .or_else(|| shared.global_option("split"))
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_SPLIT);
+    let per_server = job
+        .option("max-connection-per-server")
+        .or_else(|| shared.global_option("max-connection-per-server"))
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(split);
+    let connections = split.min(per_server.saturating_mul(job.uris.len().max(1)));
+
+    let segments = match load_sidecar(path, source, total) {
+        Some(segments) => segments,
+        None => {
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(path)
+                .await
+                .map_err(|e| JobError::from_io("could not create file", e))?;
+            file.set_len(total as u64)
+                .await
+                .map_err(|e| JobError::from_io("could not allocate file", e))?;
+            split_segments(total, connections)
+        }
+    };
+    job.completed
+        .store(segments.iter().map(|s| s.done).sum(), Ordering::Relaxed);
+    job.connections.store(
+        segments.iter().filter(|s| s.done < s.len()).count() as i64,
+        Ordering::Relaxed,
+    );
+    let sidecar = Sidecar {
+        url: source.to_string(),
+        total_length: total,
+        segments: segments.clone(),
+    };
+    save_sidecar(path, &sidecar);
+    let ctx = SegmentContext {
+        shared,
+        job,
+        url,
+        path,
+        sidecar: Mutex::new((sidecar, Instant::now())),
+        cancel,
+    };
+
+    let workers = segments
+        .iter()
+        .enumerate()
+        .filter(|(_, segment)| segment.done < segment.len())
+        .map(|(index, _)| download_segment(&ctx, index))
+        .collect::<Vec<_>>();
+    let result = try_join_all(workers).await;
+    let state = ctx
+        .sidecar
+        .lock()
+        .expect("sidecar mutex poisoned")
+        .0
+        .clone();
+    match result {
+        Ok(_) if state.segments.iter().all(|s| s.done >= s.len()) => {
+            let _ = fs::remove_file(sidecar_path(path)).await;
+            Ok(Outcome::Completed)
+        }
+        Ok(_) => {
+            save_sidecar(path, &state);
+            Ok(Outcome::Stopped)
+        }
+        Err(error) => {
+            save_sidecar(path, &state);
+            Err(error)
+        }
+    }
+}
+
+struct SegmentContext<'a> {
+    shared: &'a Arc<Shared>,
+    job: &'a Arc<Job>,
+    url: &'a str,
+    path: &'a Path,
+    sidecar: Mutex<(Sidecar, Instant)>,
+    cancel: watch::Receiver<bool>,
+}
+
+impl SegmentContext<'_> {
+    fn cancelled(&self) -> bool {
+        *self.cancel.borrow()
+    }
+
+    fn segment(&self, index: usize) -> Segment {
+        self.sidecar
+            .lock()
+            .expect("sidecar mutex poisoned")
+            .0
+            .segments[index]
+            .clone()
+    }
+
+    fn record(&self, index: usize, written: i64) {
+        self.job.completed.fetch_add(written, Ordering::Relaxed);
+        let mut state = self.sidecar.lock().expect("sidecar mutex poisoned");
+        state.0.segments[index].done += written;
+        if state.1.elapsed() >= SIDECAR_SAVE_INTERVAL {
+            state.1 = Instant::now();
+            save_sidecar(self.path, &state.0);
+        }
+    }
+}
+
+/// Fetches one segment, retrying network failures a few times before giving up.
+async fn download_segment(
+    ctx: &SegmentContext<'_>,
+    index: usize,
+) -> std::result::Result<(), JobError> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(ctx.path)
+        .await
+        .map_err(|e| JobError::from_io("could not open file", e))?;
+    let mut failures = 0;
+    loop {
+        let segment = ctx.segment(index);
+        if segment.done >= segment.len() || ctx.cancelled() {
+            break;
+        }
+        match fetch_range(
+            ctx,
+            &mut file,
+            index,
+            segment.start + segment.done,
+            segment.end,
+        )
+        .await
+        {
+            Ok(()) => failures = 0,
+            Err(error) => {
+                failures += 1;
+                let transient = matches!(error.code, "2" | "6");
+                if !transient || failures > SEGMENT_RETRIES {
+                    return Err(error);
+                }
+                time::sleep(Duration::from_millis(500 * u64::from(failures))).await;
+            }
+        }
+    }
+    ctx.job.connections.fetch_sub(1, Ordering::Relaxed);
+    file.flush()
+        .await
+        .map_err(|e| JobError::from_io("could not write file", e))?;
+    Ok(())
+}
+
+async fn fetch_range(
+    ctx: &SegmentContext<'_>,
+    file: &mut fs::File,
+    index: usize,
+    offset: i64,
+    end: i64,
+) -> std::result::Result<(), JobError> {
+    let mut response = apply_request_options(ctx.shared.client.get(ctx.url), ctx.job)
+        .header(RANGE, format!("bytes={offset}-{end}"))
+        .send()
+        .await
+        .map_err(JobError::from_request)?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(if response.status().is_success() {
+            JobError::new("22", "server stopped honouring range requests")
+        } else {
+            JobError::from_http(response.status())
+        });
+    }
+    file.seek(SeekFrom::Start(offset as u64))
+        .await
+        .map_err(|e| JobError::from_io("could not seek file", e))?;
+    let mut remaining = end - offset + 1;
+    while remaining > 0 {
+        if ctx.cancelled() {
+            return Ok(());
+        }
+        let Some(chunk) = response.chunk().await.map_err(JobError::from_request)? else {
+            return Err(JobError::new(
+                "6",
+                "connection closed before the range finished",
+            ));
+        };
+        let chunk = &chunk[..chunk.len().min(remaining as usize)];
+        ctx.job.limiter.acquire(chunk.len()).await;
+        ctx.shared.global_limiter.acquire(chunk.len()).await;
+        file.write_all(chunk)
+            .await
+            .map_err(|e| JobError::from_io("could not write file", e))?;
+        remaining -= chunk.len() as i64;
+        ctx.record(index, chunk.len() as i64);
+    }
+    Ok(())
+}
+
+/// Servers without range support get one plain stream, restarted from zero on resume.
+async fn download_stream(
+    shared: &Arc<Shared>,
+    job: &Arc<Job>,
+    url: &str,
+    path: &Path,
+    cancel: watch::Receiver<bool>,
+) -> std::result::Result<Outcome, JobError> {
+    let mut response = apply_request_options(shared.client.get(url), job)
+        .send()
+        .await
+        .map_err(JobError::from_request)?;
+    if !response.status().is_success() {
+        return Err(JobError::from_http(response.status()));
+    }
+    if let Some(length) = response.content_length() {
+        job.total.store(length as i64, Ordering::Relaxed);
+    }
+    let mut file = fs::File::create(path)
+        .await
+        .map_err(|e| JobError::from_io("could not create file", e))?;
+    job.completed.store(0, Ordering::Relaxed);
+    job.connections.store(1, Ordering::Relaxed);
+    while let Some(chunk) = response.chunk().await.map_err(JobError::from_request)? {
+        if *cancel.borrow() {
+            return Ok(Outcome::Stopped);
+        }
+        job.limiter.acquire(chunk.len()).await;
+        shared.global_limiter.acquire(chunk.len()).await;
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| JobError::from_io("could not write file", e))?;
+        job.completed
+            .fetch_add(chunk.len() as i64, Ordering::Relaxed);
+    }
+    file.flush()
+        .await
+        .map_err(|e| JobError::from_io("could not write file", e))?;
+    let completed = job.completed.load(Ordering::Relaxed);
+    if job.total.load(Ordering::Relaxed) == 0 {
+        job.total.store(completed, Ordering::Relaxed);
+    }
+    Ok(Outcome::Completed)
+}
+
+#[async_trait]
+impl Aria2Api for NativeHttpEngine {
+    async fn start(&self) -> Result<Aria2Endpoint> {
+        self.load_session();
+        self.shared.running.store(true, Ordering::SeqCst);
+        self.shared.schedule();
+        Ok(native_endpoint())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        if !self.shared.running.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let jobs = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .clone();
+        for job in &jobs {
+            if job.status() == "active" {
+                job.set_status("waiting");
+                job.cancel();
+            }
+        }
+        self.write_session()
+    }
+
+    async fn endpoint(&self) -> Option<Aria2Endpoint> {
+        self.shared
+            .running
+            .load(Ordering::SeqCst)
+            .then(native_endpoint)
+    }
+
+    async fn ensure_started(&self) -> Result<Aria2Endpoint> {
+        self.start().await
+    }
+
+    async fn add_uri(&self, uris: Vec<String>, options: Option<Value>) -> Result<String> {
+        self.ensure_running()?;
+        let uris = uris
+            .into_iter()
+            .map(|uri| uri.trim().to_string())
+            .filter(|uri| !uri.is_empty())
+            .collect::<Vec<_>>();
+        if uris.is_empty() {
+            return Err(anyhow!("add_uri requires at least one uri"));
+        }
+        if let Some(uri) = uris
+            .iter()
+            .find(|uri| !(uri.starts_with("http://") || uri.starts_with("https://")))
+        {
+            return Err(anyhow!(
+                "the native engine only downloads http(s) links, got {uri}"
+            ));
+        }
+        let options = match options {
+            Some(Value::Object(options)) => options,
+            _ => Map::new(),
+        };
+        let gid = format!(
+            "{:016x}",
+            self.shared.next_gid.fetch_add(1, Ordering::SeqCst)
+        );
+        let paused = options.get("pause").and_then(Value::as_str) == Some("true");
+        let job = Arc::new(Job::new(gid.clone(), uris, options));
+        if paused {
+            job.set_status("paused");
+        }
+        self.shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .push(job);
+        self.shared.schedule();
+        Ok(gid)
+    }
+
+    async fn add_torrent(
+        &self,
+        _torrent_base64: String,
+        _uris: Vec<String>,
+        _options: Option<Value>,
+    ) -> Result<String> {
+        Err(anyhow!(
+            "the native engine only downloads http(s) links; use aria2 for torrents"
+        ))
+    }
+
+    async fn pause(&self, gid: &str) -> Result<String> {
+        self.set_status(gid, &["active", "waiting"], "paused")
+    }
+
+    async fn unpause(&self, gid: &str) -> Result<String> {
+        self.set_status(gid, &["paused"], "waiting")
+    }
+
+    async fn pause_all(&self) -> Result<String> {
+        self.ensure_running()?;
+        let jobs = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .clone();
+        for job in jobs
+            .iter()
+            .filter(|job| matches!(job.status(), "active" | "waiting"))
+        {
+            job.set_status("paused");
+            job.cancel();
+            self.shared.notify("aria2.onDownloadPause", &job.gid);
+        }
+        Ok("OK".to_string())
+    }
+
+    async fn unpause_all(&self) -> Result<String> {
+        self.ensure_running()?;
+        let jobs = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .clone();
+        for job in jobs.iter().filter(|job| job.status() == "paused") {
+            job.set_status("waiting");
+        }
+        self.shared.schedule();
+        Ok("OK".to_string())
+    }
+
+    async fn remove(&self, gid: &str, _force: bool) -> Result<String> {
+        self.set_status(gid, &["active", "waiting", "paused"], "removed")
+    }
+
+    async fn remove_download_result(&self, gid: &str) -> Result<String> {
+        let job = self.job(gid)?;
+        if !matches!(job.status(), "complete" | "error" | "removed") {
+            return Err(anyhow!("Could not remove download result of GID#{gid}"));
+        }
+        self.shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .retain(|job| job.gid != gid);
+        Ok("OK".to_string())
+    }
+
+    async fn tell_status(&self, gid: &str) -> Result<Value> {
+        let job = self.job(gid)?;
+        Ok(job.status_json(self.shared.global_option("dir").as_deref()))
+    }
+
+    async fn get_peers(&self, _gid: &str) -> Result<Vec<Value>> {
+        Ok(Vec::new())
+    }
+
+    async fn tell_all(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        self.ensure_running()?;
+        let dir = self.shared.global_option("dir");
+        let jobs = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .clone();
+        Ok(jobs
+            .iter()
+            .filter_map(|job| parse_snapshot(job.status_json(dir.as_deref())))
+            .collect())
+    }
+
+    async fn tell_active(&self) -> Result<Vec<Aria2TaskSnapshot>> {
+        self.ensure_running()?;
+        let dir = self.shared.global_option("dir");
+        let jobs = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .clone();
+        Ok(jobs
+            .iter()
+            .filter(|job| job.status() == "active")
+            .filter_map(|job| parse_snapshot(job.status_json(dir.as_deref())))
+            .collect())
+    }
+
+    async fn change_position(&self, gid: &str, pos: i64, how: &str) -> Result<i64> {
+        self.ensure_running()?;
+        let mut jobs = self.shared.jobs.lock().expect("jobs mutex poisoned");
+        let slots = jobs
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| job.status() == "waiting")
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let mut queue = slots
+            .iter()
+            .map(|index| jobs[*index].clone())
+            .collect::<Vec<_>>();
+        let current = queue
+            .iter()
+            .position(|job| job.gid == gid)
+            .ok_or_else(|| anyhow!("GID#{gid} is not in the waiting queue"))?;
+        let last = queue.len() as i64 - 1;
+        let target = match how {
+            "POS_SET" => pos,
+            "POS_CUR" => current as i64 + pos,
+            "POS_END" => last + pos,
+            other => return Err(anyhow!("invalid position mode: {other}")),
+        }
+        .clamp(0, last) as usize;
+        let moved = queue.remove(current);
+        queue.insert(target, moved);
+        for (slot, job) in slots.into_iter().zip(queue) {
+            jobs[slot] = job;
+        }
+        Ok(target as i64)
+    }
+
+    async fn change_option(&self, gid: &str, options: Value) -> Result<String> {
+        let job = self.job(gid)?;
+        let Value::Object(options) = options else {
+            return Err(anyhow!("options must be an object"));
+        };
+        if let Some(limit) = options.get("max-download-limit").and_then(Value::as_str) {
+            job.limiter.set_rate(parse_rate_limit(limit));
+        }
+        job.options
+            .lock()
+            .expect("options mutex poisoned")
+            .extend(options);
+        Ok("OK".to_string())
+    }
+
+    async fn change_global_option(&self, options: Value) -> Result<String> {
+        self.ensure_running()?;
+        let Value::Object(options) = options else {
+            return Err(anyhow!("options must be an object"));
+        };
+        if let Some(limit) = options
+            .get("max-overall-download-limit")
+            .and_then(Value::as_str)
+        {
+            self.shared.global_limiter.set_rate(parse_rate_limit(limit));
+        }
+        self.shared
+            .global_options
+            .lock()
+            .expect("global options mutex poisoned")
+            .extend(options);
+        self.shared.schedule();
+        Ok("OK".to_string())
+    }
+
+    async fn get_global_stat(&self) -> Result<Value> {
+        self.ensure_running()?;
+        let jobs = self
+            .shared
+            .jobs
+            .lock()
+            .expect("jobs mutex poisoned")
+            .clone();
+        let count = |statuses: &[&str]| {
+            jobs.iter()
+                .filter(|job| statuses.contains(&job.status()))
+                .count()
+                .to_string()
+        };
+        let speed = jobs.iter().map(|job| job.download_speed()).sum::<i64>();
+        Ok(json!({
+            "downloadSpeed": speed.to_string(),
+            "uploadSpeed": "0",
+            "numActive": count(&["active"]),
+            "numWaiting": count(&["waiting", "paused"]),
+            "numStopped": count(&["complete", "error", "removed"]),
+            "numStoppedTotal": count(&["complete", "error", "removed"]),
+        }))
+    }
+
+    async fn get_global_option(&self) -> Result<Value> {
+        self.ensure_running()?;
+        Ok(Value::Object(
+            self.shared
+                .global_options
+                .lock()
+                .expect("global options mutex poisoned")
+                .clone(),
+        ))
+    }
+
+    async fn get_version(&self) -> Result<Value> {
+        self.ensure_running()?;
+        Ok(json!({
+            "version": format!("native-{}", env!("CARGO_PKG_VERSION")),
+            "enabledFeatures": ["HTTPS"],
+        }))
+    }
+
+    async fn save_session(&self) -> Result<String> {
+        self.write_session()?;
+        Ok("OK".to_string())
+    }
+
+    fn stderr_tail(&self) -> Option<String> {
+        None
+    }
+
+    fn subscribe_notifications(&self) -> Option<broadcast::Receiver<Aria2Notification>> {
+        Some(self.shared.notifications.subscribe())
+    }
+
+    fn notifications_connected(&self) -> bool {
+        self.shared.running.load(Ordering::SeqCst)
+    }
+
+    fn in_process_mode(&self) -> Option<&'static str> {
+        Some("native")
+    }
+}
+
+fn native_endpoint() -> Aria2Endpoint {
+    Aria2Endpoint {
+        endpoint: NATIVE_ENDPOINT.to_string(),
+        secret: String::new(),
+        port: 0,
+        compat_mode: false,
+        external: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use serde_json::{Value, json};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+    use uuid::Uuid;
+
+    use super::{
+        MIN_SEGMENT_SIZE, NativeHttpEngine, Segment, Sidecar, parse_rate_limit, sidecar_path,
+        split_segments,
+    };
+    use crate::aria2_manager::Aria2Api;
+
+    type RequestLog = Arc<Mutex<Vec<String>>>;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Serves `/file.bin` with range support, `/plain.bin` without, and 404 otherwise.
+    async fn serve(body: Vec<u8>) -> (String, RequestLog) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let base = format!("http://{}", listener.local_addr().expect("addr"));
+        let body = Arc::new(body);
+        let log: RequestLog = Arc::default();
+        let requests = log.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let body = body.clone();
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(socket);
+                    let mut head = String::new();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).await.unwrap_or(0) == 0 || line == "\r\n" {
+                            break;
+                        }
+                        head.push_str(&line.to_ascii_lowercase());
+                    }
+                    requests.lock().expect("log").push(head.clone());
+                    let path = head
+                        .split_whitespace()
+                        .nth(1)
+                        .unwrap_or_default()
+                        .to_string();
+                    let range = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("range: bytes="))
+                        .and_then(|r| r.trim().split_once('-'))
+                        .map(|(s, e)| {
+                            let start = s.parse::<usize>().unwrap_or(0);
+                            let end = e.parse::<usize>().unwrap_or(body.len() - 1);
+                            (start, end.min(body.len() - 1))
+                        });
+                    let (status, slice, extra) = match (path.as_str(), range) {
+                        ("/file.bin", Some((start, end))) => (
+                            "206 Partial Content",
+                            &body[start..=end],
+                            format!("Content-Range: bytes {start}-{end}/{}\r\n", body.len()),
+                        ),
+                        ("/file.bin" | "/plain.bin", _) => ("200 OK", &body[..], String::new()),
+                        _ => ("404 Not Found", &body[..0], String::new()),
+                    };
+                    let mut socket = reader.into_inner();
+                    let header = format!(
+                        "HTTP/1.1 {status}\r\nContent-Length: {}\r\n{extra}Connection: close\r\n\r\n",
+                        slice.len()
+                    );
+                    let _ = socket.write_all(header.as_bytes()).await;
+                    let _ = socket.write_all(slice).await;
+                });
+            }
+        });
+        (base, log)
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flamingo-native-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        dir
+    }
+
+    async fn wait_for(engine: &NativeHttpEngine, gid: &str) -> Value {
+        for _ in 0..200 {
+            let status = engine.tell_status(gid).await.expect("tell status");
+            if matches!(status["status"].as_str(), Some("complete" | "error")) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        panic!("download {gid} did not finish");
+    }
+
+    async fn started(dir: &Path) -> NativeHttpEngine {
+        let engine = NativeHttpEngine::new(dir);
+        engine.start().await.expect("start");
+        engine
+    }
+
+    #[tokio::test]
+    async fn segmented_download_writes_file_and_sends_task_headers() {
+        let body = payload(3 * MIN_SEGMENT_SIZE as usize + 123);
+        let (base, log) = serve(body.clone()).await;
+        let dir = temp_dir();
+        let engine = started(&dir).await;
+        let gid = engine
+            .add_uri(
+                vec![format!("{base}/file.bin")],
+                Some(json!({
+                    "dir": dir.to_string_lossy(),
+                    "out": "copy.bin",
+                    "split": "3",
+                    "user-agent": "flamingo-test",
+                    "header": ["X-Token: abc"],
+                })),
+            )
+            .await
+            .expect("add uri");
+
+        let status = wait_for(&engine, &gid).await;
+        assert_eq!(status["status"], "complete");
+        assert_eq!(status["totalLength"], body.len().to_string());
+        let path = dir.join("copy.bin");
+        assert_eq!(std::fs::read(&path).expect("read download"), body);
+        assert!(!sidecar_path(&path).exists());
+
+        let requests = log.lock().expect("log").clone();
+        let ranged = requests
+            .iter()
+            .filter(|r| r.contains("range: bytes=") && !r.contains("range: bytes=0-0"))
+            .count();
+        assert_eq!(ranged, 3);
+        assert!(
+            requests
+                .iter()
+                .all(|r| r.contains("user-agent: flamingo-test"))
+        );
+        assert!(requests.iter().all(|r| r.contains("x-token: abc")));
+    }
+
+    #[tokio::test]
+    async fn resumes_from_sidecar_without_refetching_finished_segments() {
+        let body = payload(2 * MIN_SEGMENT_SIZE as usize);
+        let (base, log) = serve(body.clone()).await;
+        let dir = temp_dir();
+        let url = format!("{base}/file.bin");
+        let path = dir.join("file.bin");
+        let half = MIN_SEGMENT_SIZE as usize;
+        let mut partial = body[..half].to_vec();
+        partial.resize(body.len(), 0);
+        std::fs::write(&path, partial).expect("write partial");
+        let sidecar = Sidecar {
+            url: url.clone(),
+            total_length: body.len() as i64,
+            segments: vec![
+                Segment {
+                    start: 0,
+                    end: half as i64 - 1,
+                    done: half as i64,
+                },
+                Segment {
+                    start: half as i64,
+                    end: body.len() as i64 - 1,
+                    done: 0,
+                },
+            ],
+        };
+        std::fs::write(
+            sidecar_path(&path),
+            serde_json::to_vec(&sidecar).expect("encode sidecar"),
+        )
+        .expect("write sidecar");
+
+        let engine = started(&dir).await;
+        let gid = engine
+            .add_uri(vec![url], Some(json!({ "dir": dir.to_string_lossy() })))
+            .await
+            .expect("add uri");
+        assert_eq!(wait_for(&engine, &gid).await["status"], "complete");
+        assert_eq!(std::fs::read(&path).expect("read download"), body);
+        let requests = log.lock().expect("log").clone();
+        assert!(
+            requests
+                .iter()
+                .filter(|r| r.contains("range: bytes="))
+                .all(|r| r.contains("range: bytes=0-0") || r.contains(&format!("bytes={half}-"))),
+            "only the unfinished segment is fetched: {requests:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn servers_without_ranges_stream_and_missing_files_fail_with_aria2_codes() {
+        let body = payload(4096);
+        let (base, _log) = serve(body.clone()).await;
+        let dir = temp_dir();
+        let engine = started(&dir).await;
+        let plain = engine
+            .add_uri(
+                vec![format!("{base}/plain.bin")],
+                Some(json!({ "dir": dir.to_string_lossy() })),
+            )
+            .await
+            .expect("add plain");
+        let missing = engine
+            .add_uri(
+                vec![format!("{base}/missing.bin")],
+                Some(json!({ "dir": dir.to_string_lossy() })),
+            )
+            .await
+            .expect("add missing");
+
+        assert_eq!(wait_for(&engine, &plain).await["status"], "complete");
+        assert_eq!(
+            std::fs::read(dir.join("plain.bin")).expect("read plain"),
+            body
+        );
+        let failed = wait_for(&engine, &missing).await;
+        assert_eq!(failed["status"], "error");
+        assert_eq!(failed["errorCode"], "3");
+
+        assert!(
+            engine
+                .add_uri(vec!["magnet:?xt=urn:btih:abc".to_string()], None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn unfinished_downloads_survive_a_restart_through_the_session_file() {
+        let dir = temp_dir();
+        let engine = started(&dir).await;
+        let gid = engine
+            .add_uri(
+                vec!["http://127.0.0.1:9/never.bin".to_string()],
+                Some(json!({ "dir": dir.to_string_lossy() })),
+            )
+            .await
+            .expect("add uri");
+        engine.pause(&gid).await.expect("pause");
+        engine.stop().await.expect("stop");
+
+        let restarted = started(&dir).await;
+        let status = restarted.tell_status(&gid).await.expect("tell status");
+        assert_eq!(status["status"], "paused");
+        let next = restarted
+            .add_uri(
+                vec!["http://127.0.0.1:9/other.bin".to_string()],
+                Some(json!({ "dir": dir.to_string_lossy() })),
+            )
+            .await
+            .expect("add after restart");
+        assert_ne!(next, gid);
+    }
+
+    #[test]
+    fn rate_limits_and_segments_follow_aria2_conventions() {
+        assert_eq!(parse_rate_limit("0"), 0);
+        assert_eq!(parse_rate_limit("512K"), 512 * 1024);
+        assert_eq!(parse_rate_limit("2M"), 2 * 1024 * 1024);
+        assert_eq!(parse_rate_limit("fast"), 0);
+
+        let segments = split_segments(10 * MIN_SEGMENT_SIZE + 1, 4);
+        assert_eq!(segments.len(), 4);
+        assert_eq!(segments[0].start, 0);
+        assert_eq!(segments[3].end, 10 * MIN_SEGMENT_SIZE);
+        assert_eq!(split_segments(1000, 8).len(), 1);
+    }
+}
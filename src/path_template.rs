@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, Local};
+
+pub const TEMPLATE_VARIABLES: &[&str] = &[
+    "domain",
+    "yyyy",
+    "mm",
+    "dd",
+    "category",
+    "ext",
+    "name",
+    "referer_host",
+    "torrent_name",
+    "counter",
+];
+
+/// Values a save-dir or output-name template can refer to. Missing values
+/// render as nothing.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    pub domain: Option<String>,
+    pub category: Option<String>,
+    pub name: Option<String>,
+    pub ext: Option<String>,
+    pub referer_host: Option<String>,
+    pub torrent_name: Option<String>,
+    pub counter: Option<u64>,
+}
+
+impl TemplateVars {
+    /// `{domain}`, `{name}` and `{ext}` from a URL, `{torrent_name}` from a
+    /// magnet's `dn`.
+    pub fn from_source(source: &str) -> Self {
+        let mut vars = Self::default();
+        let Ok(url) = reqwest::Url::parse(source.trim()) else {
+            return vars;
+        };
+        if url.scheme() == "magnet" {
+            vars.torrent_name = url
+                .query_pairs()
+                .find(|(key, _)| key == "dn")
+                .map(|(_, value)| value.to_string());
+            return vars;
+        }
+        vars.domain = url.host_str().map(|v| v.to_lowercase());
+        let file = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|v| !v.is_empty());
+        if let Some(file) = file {
+            let path = Path::new(file);
+            vars.name = path.file_stem().map(|v| v.to_string_lossy().to_string());
+            vars.ext = path.extension().map(|v| v.to_string_lossy().to_string());
+        }
+        vars
+    }
+
+    fn value(&self, variable: &str, now: &DateTime<Local>) -> Option<String> {
+        match variable {
+            "domain" => self.domain.clone(),
+            "yyyy" => Some(format!("{:04}", now.year())),
+            "mm" => Some(format!("{:02}", now.month())),
+            "dd" => Some(format!("{:02}", now.day())),
+            "category" => self.category.clone(),
+            "ext" => self.ext.clone(),
+            "name" => self.name.clone(),
+            "referer_host" => self.referer_host.clone(),
+            "torrent_name" => self.torrent_name.clone(),
+            "counter" => self.counter.map(|v| v.to_string()),
+            _ => None,
+        }
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+fn parse(template: &str) -> Result<Vec<Piece<'_>>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(anyhow!("unmatched '}}' in template {template}"));
+        }
+        let Some(close) = rest[open..].find('}') else {
+            return Err(anyhow!("unclosed '{{' in template {template}"));
+        };
+        let variable = rest[open + 1..open + close].trim();
+        if !TEMPLATE_VARIABLES.contains(&variable) {
+            return Err(anyhow!(
+                "unknown template variable {{{variable}}}, expected one of {}",
+                TEMPLATE_VARIABLES.join(", ")
+            ));
+        }
+        pieces.push(Piece::Text(&rest[..open]));
+        pieces.push(Piece::Variable(variable));
+        rest = &rest[open + close + 1..];
+    }
+    pieces.push(Piece::Text(rest));
+    Ok(pieces)
+}
+
+pub fn is_template(value: &str) -> bool {
+    value.contains('{')
+}
+
+pub fn validate_template(template: &str) -> Result<()> {
+    parse(template).map(|_| ())
+}
+
+pub fn uses_variable(template: &str, variable: &str) -> bool {
+    parse(template).is_ok_and(|pieces| {
+        pieces
+            .iter()
+            .any(|piece| matches!(piece, Piece::Variable(v) if *v == variable))
+    })
+}
+
+/// Substituted values never add path separators, so a template's own `/`
+/// decides the directory layout.
+pub fn render_template(
+    template: &str,
+    vars: &TemplateVars,
+    now: &DateTime<Local>,
+) -> Result<String> {
+    let mut out = String::new();
+    for piece in parse(template)? {
+        match piece {
+            Piece::Text(text) => out.push_str(text),
+            Piece::Variable(variable) => {
+                out.push_str(&sanitize_value(
+                    &vars.value(variable, now).unwrap_or_default(),
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Renders a directory template, dropping segments left empty by missing values.
+pub fn render_dir_template(
+    template: &str,
+    vars: &TemplateVars,
+    now: &DateTime<Local>,
+) -> Result<String> {
+    let rendered = render_template(template, vars, now)?;
+    Ok(Path::new(&rendered)
+        .components()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Renders an output-name template; `None` when nothing usable is left.
+pub fn render_name_template(
+    template: &str,
+    vars: &TemplateVars,
+    now: &DateTime<Local>,
+) -> Result<Option<String>> {
+    let rendered = render_template(template, vars, now)?;
+    let name = rendered.trim().trim_end_matches(['.', ' ']);
+    Ok((!name.is_empty()).then(|| name.to_string()))
+}
+
+fn sanitize_value(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::{
+        TemplateVars, render_dir_template, render_name_template, uses_variable, validate_template,
+    };
+
+    #[test]
+    fn renders_dirs_and_names_from_source_vars() {
+        let now = Local
+            .with_ymd_and_hms(2026, 3, 7, 12, 0, 0)
+            .single()
+            .expect("local time");
+        let mut vars = TemplateVars::from_source("https://dl.example.com/pub/linux-6.1.tar.gz?x=1");
+        vars.category = Some("kernels".to_string());
+        assert_eq!(vars.name.as_deref(), Some("linux-6.1.tar"));
+        assert_eq!(vars.ext.as_deref(), Some("gz"));
+        assert_eq!(
+            render_dir_template("/archive/{domain}/{yyyy}/{mm}/{category}", &vars, &now)
+                .expect("render"),
+            "/archive/dl.example.com/2026/03/kernels"
+        );
+        // A missing value drops its segment instead of leaving `//`.
+        assert_eq!(
+            render_dir_template("/archive/{referer_host}/{dd}", &vars, &now).expect("render"),
+            "/archive/07"
+        );
+        vars.counter = Some(12);
+        assert_eq!(
+            render_name_template("{counter}-{name}.{ext}", &vars, &now).expect("render"),
+            Some("12-linux-6.1.tar.gz".to_string())
+        );
+
+        let magnet = TemplateVars::from_source("magnet:?xt=urn:btih:abc&dn=Big%2FBuck");
+        assert_eq!(
+            render_name_template("{torrent_name}", &magnet, &now).expect("render"),
+            Some("Big_Buck".to_string())
+        );
+        assert_eq!(
+            render_name_template("{ext}", &magnet, &now).expect("render"),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_variables_and_unbalanced_braces() {
+        assert!(validate_template("/data/{yyyy}/{mm}").is_ok());
+        assert!(
+            validate_template("/data/{year}")
+                .expect_err("unknown variable")
+                .to_string()
+                .contains("unknown template variable {year}")
+        );
+        assert!(validate_template("/data/{yyyy").is_err());
+        assert!(validate_template("/data/yyyy}").is_err());
+        assert!(uses_variable("{counter}-{name}", "counter"));
+        assert!(!uses_variable("{name}", "counter"));
+    }
+}
@@ -0,0 +1,357 @@
+use anyhow::{Result, anyhow};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::models::{TorrentInfo, TorrentInfoFile};
+
+const MAX_DEPTH: usize = 256;
+
+enum Bencode<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Bencode<'a>>),
+    /// Entries in file order plus the raw encoding, which the infohash is taken over.
+    Dict(Vec<(&'a [u8], Bencode<'a>)>, &'a [u8]),
+}
+
+impl<'a> Bencode<'a> {
+    fn get(&self, key: &str) -> Option<&Bencode<'a>> {
+        match self {
+            Bencode::Dict(entries, _) => entries
+                .iter()
+                .find(|(k, _)| *k == key.as_bytes())
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Bencode::Bytes(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<String> {
+        self.bytes()
+            .map(|v| String::from_utf8_lossy(v).into_owned())
+    }
+
+    fn list(&self) -> &[Bencode<'a>] {
+        match self {
+            Bencode::List(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self, depth: usize) -> Result<Bencode<'a>> {
+        if depth > MAX_DEPTH {
+            return Err(anyhow!("torrent nests too deeply"));
+        }
+        let start = self.pos;
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let digits = self.until(b'e')?;
+                let value = std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .ok_or_else(|| anyhow!("invalid integer at byte {start}"))?;
+                Ok(Bencode::Int(value))
+            }
+            b'l' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                while self.peek()? != b'e' {
+                    items.push(self.value(depth + 1)?);
+                }
+                self.pos += 1;
+                Ok(Bencode::List(items))
+            }
+            b'd' => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                while self.peek()? != b'e' {
+                    let key = self.string()?;
+                    entries.push((key, self.value(depth + 1)?));
+                }
+                self.pos += 1;
+                Ok(Bencode::Dict(entries, &self.data[start..self.pos]))
+            }
+            b'0'..=b'9' => Ok(Bencode::Bytes(self.string()?)),
+            other => Err(anyhow!(
+                "unexpected byte {:?} at {start}",
+                char::from(other)
+            )),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let start = self.pos;
+        let length = std::str::from_utf8(self.until(b':')?)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .ok_or_else(|| anyhow!("invalid string length at byte {start}"))?;
+        let end = self
+            .pos
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("string at byte {start} runs past the end"))?;
+        let value = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn until(&mut self, delimiter: u8) -> Result<&'a [u8]> {
+        let rest = &self.data[self.pos..];
+        let length = rest
+            .iter()
+            .position(|b| *b == delimiter)
+            .ok_or_else(|| anyhow!("unterminated value at byte {}", self.pos))?;
+        self.pos += length + 1;
+        Ok(&rest[..length])
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| anyhow!("torrent ends early"))
+    }
+}
+
+/// Reads what a `.torrent` holds without handing it to aria2. File indexes
+/// follow aria2's order, so they can go straight into `select-file`.
+pub fn parse_torrent(data: &[u8]) -> Result<TorrentInfo> {
+    let root = Parser { data, pos: 0 }.value(0)?;
+    let info = root
+        .get("info")
+        .ok_or_else(|| anyhow!("torrent has no info dictionary"))?;
+    let Bencode::Dict(_, info_raw) = info else {
+        return Err(anyhow!("torrent info is not a dictionary"));
+    };
+    let name = info
+        .get("name.utf-8")
+        .or_else(|| info.get("name"))
+        .and_then(Bencode::text)
+        .filter(|v| !v.trim().is_empty())
+        .ok_or_else(|| anyhow!("torrent has no name"))?;
+    let piece_length = info
+        .get("piece length")
+        .and_then(Bencode::int)
+        .filter(|v| *v > 0)
+        .ok_or_else(|| anyhow!("torrent has no piece length"))?;
+    let pieces = info.get("pieces").and_then(Bencode::bytes);
+    let v2 = info.get("meta version").and_then(Bencode::int) == Some(2);
+    if pieces.is_none() && !v2 {
+        return Err(anyhow!("torrent has no pieces"));
+    }
+
+    let mut files = Vec::new();
+    if let Some(length) = info.get("length").and_then(Bencode::int) {
+        files.push((name.clone(), length));
+    } else if let Some(Bencode::List(entries)) = info.get("files") {
+        for entry in entries {
+            let path = entry
+                .get("path.utf-8")
+                .or_else(|| entry.get("path"))
+                .map(|p| {
+                    p.list()
+                        .iter()
+                        .filter_map(Bencode::text)
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+                .filter(|p| !p.is_empty())
+                .ok_or_else(|| anyhow!("torrent file entry without a path"))?;
+            let length = entry
+                .get("length")
+                .and_then(Bencode::int)
+                .ok_or_else(|| anyhow!("torrent file {path} has no length"))?;
+            files.push((path, length));
+        }
+    } else if let Some(tree) = info.get("file tree") {
+        walk_file_tree(tree, &mut Vec::new(), &mut files)?;
+    }
+    if files.is_empty() {
+        return Err(anyhow!("torrent has no files"));
+    }
+    let mut total_length = 0i64;
+    for (path, length) in &files {
+        if *length < 0 {
+            return Err(anyhow!("torrent file {path} has a negative length"));
+        }
+        total_length = total_length
+            .checked_add(*length)
+            .ok_or_else(|| anyhow!("torrent total length overflows"))?;
+    }
+    let files = files
+        .into_iter()
+        .enumerate()
+        .map(|(index, (path, length))| TorrentInfoFile {
+            index,
+            path,
+            length,
+        })
+        .collect::<Vec<_>>();
+
+    let mut trackers = Vec::new();
+    for tier in root.get("announce-list").map(Bencode::list).unwrap_or(&[]) {
+        trackers.extend(tier.list().iter().filter_map(Bencode::text));
+    }
+    trackers.extend(root.get("announce").and_then(Bencode::text));
+    let mut seen = std::collections::HashSet::new();
+    trackers.retain(|t| !t.trim().is_empty() && seen.insert(t.clone()));
+
+    Ok(TorrentInfo {
+        name,
+        info_hash_v1: pieces.map(|_| hex::encode(Sha1::digest(info_raw))),
+        info_hash_v2: v2.then(|| hex::encode(Sha256::digest(info_raw))),
+        total_length,
+        piece_length,
+        piece_count: match pieces {
+            Some(pieces) => pieces.len() / 20,
+            None => (total_length as u64).div_ceil(piece_length as u64) as usize,
+        },
+        private: info.get("private").and_then(Bencode::int) == Some(1),
+        trackers,
+        files,
+    })
+}
+
+/// BitTorrent v2 `file tree`: nested directories, each file ending in a `""` key.
+fn walk_file_tree(
+    node: &Bencode<'_>,
+    prefix: &mut Vec<String>,
+    files: &mut Vec<(String, i64)>,
+) -> Result<()> {
+    let Bencode::Dict(entries, _) = node else {
+        return Ok(());
+    };
+    for (key, child) in entries {
+        if key.is_empty() {
+            let length = child
+                .get("length")
+                .and_then(Bencode::int)
+                .ok_or_else(|| anyhow!("torrent file {} has no length", prefix.join("/")))?;
+            files.push((prefix.join("/"), length));
+            continue;
+        }
+        prefix.push(String::from_utf8_lossy(key).into_owned());
+        walk_file_tree(child, prefix, files)?;
+        prefix.pop();
+    }
+    Ok(())
+}
+
+/// Parses aria2's `select-file` syntax, e.g. `1,3-5`, into 0-based indexes
+/// below `count`.
+pub fn parse_select_file(spec: &str, count: usize) -> Result<Vec<usize>> {
+    let mut indexes = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|v| (1..=count).contains(v))
+                .ok_or_else(|| anyhow!("invalid select-file {spec}: the torrent has {count} files"))
+        };
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(part)?, parse(part)?),
+        };
+        indexes.extend((start..=end).map(|i| i - 1));
+    }
+    indexes.sort_unstable();
+    indexes.dedup();
+    Ok(indexes)
+}
+
+/// The inverse of [`parse_select_file`].
+pub fn select_file_spec(indexes: &[usize]) -> String {
+    indexes
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_select_file, parse_torrent, select_file_spec};
+
+    #[test]
+    fn parses_v1_multi_file_and_v2_torrents() {
+        let v1 = b"d8:announce19:udp://a.example/ann13:announce-listll19:udp://a.example/annel19:udp://b.example/annee4:infod5:filesld6:lengthi100e4:pathl5:a.bineed6:lengthi900e4:pathl6:extras5:b.bineee4:name4:pack12:piece lengthi512e6:pieces40:aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbb7:privatei1eee";
+        let info = parse_torrent(v1).expect("parse v1");
+        assert_eq!(info.name, "pack");
+        assert_eq!(info.total_length, 1000);
+        assert_eq!((info.piece_length, info.piece_count), (512, 2));
+        assert!(info.private);
+        assert_eq!(info.files[1].path, "extras/b.bin");
+        assert_eq!(info.files[1].index, 1);
+        assert_eq!(
+            info.trackers,
+            vec!["udp://a.example/ann", "udp://b.example/ann"]
+        );
+        assert_eq!(info.info_hash_v1.as_deref().map(str::len), Some(40));
+        assert_eq!(info.info_hash_v2, None);
+
+        let v2 = b"d4:infod9:file treed4:docsd5:a.txtd0:d6:lengthi10eeee5:z.bind0:d6:lengthi20eeee12:meta versioni2e4:name3:new12:piece lengthi16384eee";
+        let info = parse_torrent(v2).expect("parse v2");
+        assert_eq!(
+            info.files
+                .iter()
+                .map(|f| f.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["docs/a.txt", "z.bin"]
+        );
+        assert_eq!(info.info_hash_v1, None);
+        assert_eq!(info.info_hash_v2.as_deref().map(str::len), Some(64));
+        assert_eq!(info.piece_count, 1);
+
+        assert!(parse_torrent(b"d4:infod4:name1:xee").is_err());
+        assert!(parse_torrent(b"d4:info").is_err());
+        assert!(parse_torrent(b"9999999999:x").is_err());
+        let negative =
+            b"d4:infod6:lengthi-5e4:name1:x12:piece lengthi512e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        assert!(
+            parse_torrent(negative)
+                .expect_err("negative length")
+                .to_string()
+                .contains("negative")
+        );
+        let overflow = b"d4:infod5:filesld6:lengthi9223372036854775807e4:pathl1:aeed6:lengthi1e4:pathl1:beee4:name1:x12:piece lengthi512e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        assert!(
+            parse_torrent(overflow)
+                .expect_err("overflow")
+                .to_string()
+                .contains("overflows")
+        );
+    }
+
+    #[test]
+    fn select_file_specs_round_trip_within_the_file_count() {
+        assert_eq!(
+            parse_select_file("1,3-4,3", 5).expect("parse"),
+            vec![0, 2, 3]
+        );
+        assert_eq!(select_file_spec(&[0, 2, 3]), "1,3,4");
+        assert!(parse_select_file("6", 5).is_err());
+        assert!(parse_select_file("0", 5).is_err());
+    }
+}
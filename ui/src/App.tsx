@@ -70,6 +70,8 @@ import { useUiViewStore } from './stores/uiViewStore'
 import type {
   AddFormValues,
   AddPresetTaskType,
+  AddTaskOutcome,
+  BatchAddResult,
   BrowserBridgeStatus,
   CategoryRule,
   DownloadRule,
@@ -1854,20 +1856,21 @@ export default function App() {
         save_dir: saveDir || optionPayload.save_dir,
         category: category || optionPayload.category,
       }
+      let created = 0
       for (const candidate of selectedCandidates) {
-        if (candidate.kind === 'magnet') {
-          await api.call('add_magnet', {
-            magnet: candidate.url,
-            options: mergedOptions,
-          })
-        } else {
-          await api.call('add_url', {
-            url: candidate.url,
-            options: mergedOptions,
-          })
-        }
+        const outcome =
+          candidate.kind === 'magnet'
+            ? await api.call<AddTaskOutcome>('add_magnet', {
+                magnet: candidate.url,
+                options: mergedOptions,
+              })
+            : await api.call<AddTaskOutcome>('add_url', {
+                url: candidate.url,
+                options: mergedOptions,
+              })
+        if (outcome.action === 'created') created += 1
       }
-      msg.success(i18nFormat(t('taskAddedCount'), { count: selectedCandidates.length }))
+      msg.success(i18nFormat(t('taskAddedCount'), { count: created }))
       setCandidateReviewOpen(false)
       setAddOpen(false)
       await refresh()
@@ -1896,15 +1899,12 @@ export default function App() {
     const options: Record<string, unknown> = {}
     if (saveDir) options.save_dir = saveDir
     if (category) options.category = category
-    for (const url of urls) {
-      const isMagnet = url.startsWith('magnet:')
-      if (isMagnet) {
-        await api.call('add_magnet', { magnet: url, options })
-      } else {
-        await api.call('add_url', { url, options })
-      }
+    const result = await api.call<BatchAddResult>('add_batch', { request: { urls, options } })
+    msg.success(i18nFormat(t('taskAddedCount'), { count: result.created }))
+    const firstError = result.items.find((item) => item.error)?.error
+    if (firstError) {
+      msg.error(`${t('addFailedPrefix')}: ${firstError}`)
     }
-    msg.success(i18nFormat(t('taskAddedCount'), { count: urls.length }))
     await refresh()
   }, [msg, refresh, t])
 
@@ -1939,13 +1939,14 @@ export default function App() {
             return
           }
         }
-        for (const line of lines) {
-          await api.call('add_url', {
-            url: line,
-            options: optionPayload,
-          })
+        const result = await api.call<BatchAddResult>('add_batch', {
+          request: { urls: lines, options: optionPayload },
+        })
+        const firstError = result.items.find((item) => item.error)?.error
+        if (firstError) {
+          throw new Error(firstError)
         }
-        msg.success(i18nFormat(t('taskAddedCount'), { count: lines.length }))
+        msg.success(i18nFormat(t('taskAddedCount'), { count: result.created }))
       } else if (addType === 'magnet') {
         const lines = magnetValue
           .split(/\r?\n/)
@@ -1963,13 +1964,15 @@ export default function App() {
             return
           }
         }
+        let created = 0
         for (const line of lines) {
-          await api.call('add_magnet', {
+          const outcome = await api.call<AddTaskOutcome>('add_magnet', {
             magnet: line,
             options: optionPayload,
           })
+          if (outcome.action === 'created') created += 1
         }
-        msg.success(i18nFormat(t('taskAddedCount'), { count: lines.length }))
+        msg.success(i18nFormat(t('taskAddedCount'), { count: created }))
       } else {
         if (!addTorrentFile) throw new Error(t('torrentRequired'))
         const buf = await addTorrentFile.arrayBuffer()
@@ -1,11 +1,20 @@
 export type Locale = 'en-US' | 'zh-CN'
 export type ThemeMode = 'system' | 'light' | 'dark'
 export type SectionKey = 'downloading' | 'downloaded' | 'media_discovery' | 'rules'
-export type MatcherType = 'ext' | 'domain' | 'type'
+export type MatcherType =
+  | 'ext'
+  | 'domain'
+  | 'type'
+  | 'category'
+  | 'regex'
+  | 'glob'
+  | 'size'
+  | 'source'
 
 export type Task = {
   id: string
   aria2_gid?: string | null
+  engine_id?: string | null
   task_type?: string
   source: string
   name?: string | null
@@ -27,6 +36,24 @@ export type Task = {
   checksum_status?: string | null
   created_at?: number
   updated_at?: number
+  depends_on?: string[]
+  start_at?: number | null
+  window?: TaskWindow | null
+  schedule_held?: boolean
+  queue?: string | null
+  queue_held?: boolean
+}
+
+export type TaskQueue = {
+  name: string
+  max_active: number
+  priority?: number
+}
+
+export type TaskWindow = {
+  days?: string | null
+  start: string
+  end: string
 }
 
 export type DownloadRule = {
@@ -36,6 +63,14 @@ export type DownloadRule = {
   save_dir: string
   subdir_by_date?: boolean
   subdir_by_domain?: boolean
+  engine?: string | null
+  conditions?: RuleCondition[]
+  combine?: 'all' | 'any' | null
+}
+
+export type RuleCondition = {
+  matcher: MatcherType
+  pattern: string
 }
 
 export type CategoryRule = {
@@ -43,10 +78,29 @@ export type CategoryRule = {
   matcher: MatcherType
   pattern: string
   category: string
+  engine?: string | null
+  conditions?: RuleCondition[]
+  combine?: 'all' | 'any' | null
+}
+
+export type PolicyRule = {
+  enabled: boolean
+  matcher: MatcherType
+  pattern: string
+  max_download_limit?: string | null
+  split?: number | null
+  max_connection_per_server?: number | null
+  user_agent?: string | null
+  headers?: string[]
+  conditions?: RuleCondition[]
+  combine?: 'all' | 'any' | null
 }
 
 export type GlobalSettings = {
   aria2_bin_path?: string | null
+  engine_mode?: 'managed' | 'external' | 'demo' | 'native' | null
+  aria2_rpc_url?: string | null
+  aria2_rpc_secret?: string | null
   download_dir?: string | null
   max_concurrent_downloads?: number | null
   max_connection_per_server?: number | null
@@ -64,6 +118,7 @@ export type GlobalSettings = {
   clipboard_watch_enabled?: boolean | null
   download_dir_rules?: DownloadRule[]
   category_rules?: CategoryRule[]
+  policy_rules?: PolicyRule[]
   retry_max_attempts?: number | null
   retry_backoff_secs?: number | null
   retry_fallback_mirrors?: string | null
@@ -76,6 +131,16 @@ export type GlobalSettings = {
     limit?: string
   }> | null
   task_option_presets?: string | null
+  engine_profiles?: string | null
+  task_queues?: string | null
+  collision_policy?: 'rename' | 'overwrite' | 'skip' | 'resume' | 'ask' | null
+  duplicate_policy?: 'warn' | 'skip' | 'redownload' | 'open' | null
+  tracker_lists?: string | null
+  tracker_refresh_hours?: number | null
+  seeding_rules?: string | null
+  max_active_seeds?: number | null
+  transfer_history_days?: number | null
+  pause_seeding_in_speed_plan?: boolean | null
   post_complete_action?: string | null
   completion_webhook_url?: string | null
   completion_command?: string | null
@@ -160,6 +225,83 @@ export type TaskFile = {
   selected: boolean
 }
 
+export type TorrentInfoFile = {
+  index: number
+  path: string
+  length: number
+}
+
+export type TorrentInfo = {
+  name: string
+  info_hash_v1?: string | null
+  info_hash_v2?: string | null
+  total_length: number
+  piece_length: number
+  piece_count: number
+  private: boolean
+  trackers: string[]
+  files: TorrentInfoFile[]
+}
+
+export type TrackerListStatus = {
+  url: string
+  tracker_count: number
+  fetched_at?: number | null
+  error?: string | null
+}
+
+export type SeedingRule = {
+  category?: string | null
+  ratio?: number | null
+  seed_hours?: number | null
+  idle_minutes?: number | null
+}
+
+export type TaskSeeding = {
+  task_id: string
+  uploaded_length: number
+  ratio: number
+  seeding_since?: number | null
+  last_upload_at?: number | null
+  held: boolean
+  stopped_reason?: 'ratio' | 'time' | 'idle' | 'manual' | null
+}
+
+export type BandwidthHistoryQuery = {
+  resolution?: 'minute' | 'hour' | 'day' | 'month' | null
+  group_by?: 'task' | 'category' | 'domain' | null
+  from?: number | null
+  to?: number | null
+  task_id?: string | null
+  category?: string | null
+  domain?: string | null
+}
+
+export type BandwidthSample = {
+  bucket_start: number
+  key?: string | null
+  downloaded: number
+  uploaded: number
+}
+
+export type TrackerHealth = {
+  uri: string
+  status: 'ok' | 'unreachable' | 'unknown'
+  checked_at?: number | null
+  error?: string | null
+}
+
+export type TorrentFilePriority = {
+  index: number
+  priority: 'skip' | 'normal' | 'high'
+}
+
+export type TaskMirror = {
+  uri: string
+  location?: string | null
+  priority?: number | null
+}
+
 export type MediaMergeJob = {
   task_id: string
   input_url: string
@@ -178,9 +320,38 @@ export type OperationLog = {
   message: string
 }
 
+export type DuplicateMatch = {
+  task_id: string
+  matched_by: 'url' | 'infohash' | 'final_url' | 'name_size'
+  status: string
+  name?: string | null
+  save_dir: string
+}
+
+export type AddTaskOutcome = {
+  task_id: string
+  action: 'created' | 'skipped' | 'existing'
+  duplicate?: DuplicateMatch | null
+}
+
+export type BatchAddItemResult = {
+  source: string
+  task_id?: string | null
+  action?: 'created' | 'skipped' | 'existing' | null
+  duplicate?: DuplicateMatch | null
+  error?: string | null
+}
+
+export type BatchAddResult = {
+  items: BatchAddItemResult[]
+  created: number
+  failed: number
+}
+
 export type SaveDirSuggestion = {
   save_dir: string
   matched_rule?: DownloadRule | null
+  out?: string | null
 }
 
 export type BrowserBridgeStatus = {